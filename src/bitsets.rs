@@ -55,6 +55,12 @@ pub const BB_MAIN_DIAG: Bitboard = 0x8040201008040201;
 /// The squares on the main anti-diagonal (H1-A8).
 pub const BB_MAIN_ANTI_DIAG: Bitboard = 0x0102040810204080;
 
+/// The dark squares (A1, C1, ..., H8).
+pub const BB_DARK_SQUARES: Bitboard = 0xaa55aa55aa55aa55;
+
+/// The light squares (B1, D1, ..., G8).
+pub const BB_LIGHT_SQUARES: Bitboard = !BB_DARK_SQUARES;
+
 
 /// Returns the LSB of a value.
 ///
@@ -307,6 +313,53 @@ pub fn bsf_reset(x: &mut Bitboard) -> Square {
 }
 
 
+/// An iterator over the squares in a bitboard.
+///
+/// The squares are yielded in ascending order. This is a safe
+/// alternative to the `while bb != 0 { let square = bsf_reset(&mut
+/// bb); ... }` loops.
+///
+/// # Examples:
+///
+/// ```rust
+/// # use alcibiades::bitsets::*;
+/// let squares: Vec<_> = Squares::new(0b100101).collect();
+/// assert_eq!(squares, vec![0, 2, 5]);
+/// assert_eq!(Squares::new(0).next(), None);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Squares(Bitboard);
+
+impl Squares {
+    /// Creates a new instance.
+    #[inline]
+    pub fn new(x: Bitboard) -> Squares {
+        Squares(x)
+    }
+}
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    #[inline]
+    fn next(&mut self) -> Option<Square> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(bsf_reset(&mut self.0))
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = pop_count(self.0);
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for Squares {}
+
+
 /// Returns the number of `1`s in the binary representation of a
 /// value.
 ///
@@ -1,7 +1,9 @@
 //! Defines how the chess board is represented in memory.
 
 use std::fmt;
-use utils::parse_fen;
+use bitsets::{Squares, pop_count, gen_shift, BB_DARK_SQUARES, BB_LIGHT_SQUARES, BB_FILE_A,
+              BB_FILE_H};
+use utils::{parse_fen, format_fen, BoardGeometry};
 
 
 /// `WHITE` or `BLACK`.
@@ -51,35 +53,69 @@ pub struct PiecesPlacement {
     pub color: [Bitboard; 2],
 }
 
-impl fmt::Display for PiecesPlacement {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl PiecesPlacement {
+    /// Draws the pieces as a diagram, one line for each rank.
+    ///
+    /// See `Board::render`.
+    pub fn render(&self, style: RenderStyle) -> String {
+        const ASCII: [[char; 7]; 2] = [['K', 'Q', 'R', 'B', 'N', 'P', '.'],
+                                       ['k', 'q', 'r', 'b', 'n', 'p', '.']];
+        const UNICODE: [[char; 7]; 2] = [['♔', '♕', '♖', '♗', '♘', '♙', '·'],
+                                         ['♚', '♛', '♜', '♝', '♞', '♟', '·']];
+        let glyphs = if style.unicode { &UNICODE } else { &ASCII };
+        let file = |j: usize| if style.flipped { 7 - j } else { j };
         let mut s = String::new();
-        for rank in (0..8).rev() {
+        for i in 0..8 {
+            let rank = if style.flipped { i } else { 7 - i };
+            if style.coordinates {
+                s.push_str(&format!("{} ", rank + 1));
+            }
+            for j in 0..8 {
+                let bb = 1 << Board::square(file(j), rank);
+                let piece = (KING..PIECE_NONE)
+                    .find(|&p| bb & self.piece_type[p] != 0)
+                    .unwrap_or(PIECE_NONE);
+                let color = if bb & self.color[WHITE] != 0 { WHITE } else { BLACK };
+                s.push(glyphs[color][piece]);
+            }
             s.push('\n');
-            for file in 0..8 {
-                let square = Board::square(file, rank);
-                let bb = 1 << square;
-                let piece = match bb {
-                    x if x & self.piece_type[KING] != 0 => 'k',
-                    x if x & self.piece_type[QUEEN] != 0 => 'q',
-                    x if x & self.piece_type[ROOK] != 0 => 'r',
-                    x if x & self.piece_type[BISHOP] != 0 => 'b',
-                    x if x & self.piece_type[KNIGHT] != 0 => 'n',
-                    x if x & self.piece_type[PAWN] != 0 => 'p',
-                    _ => '.',
-                };
-                if bb & self.color[WHITE] != 0 {
-                    s.push(piece.to_uppercase().next().unwrap());
-                } else {
-                    s.push(piece);
-                }
+        }
+        if style.coordinates {
+            s.push_str("  ");
+            for j in 0..8 {
+                s.push((b'a' + file(j) as u8) as char);
             }
+            s.push('\n');
         }
-        writeln!(f, "{}", s)
+        s
+    }
+}
+
+impl fmt::Display for PiecesPlacement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\n{}", self.render(RenderStyle::default()))
     }
 }
 
 
+/// Tells how a chess board should be drawn.
+///
+/// See `Board::render`. The default style uses ASCII letters
+/// (uppercase for white, lowercase for black, and `.` for empty
+/// squares), without coordinates, from white's point of view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStyle {
+    /// Use Unicode chess glyphs instead of ASCII letters.
+    pub unicode: bool,
+
+    /// Show rank numbers and file letters.
+    pub coordinates: bool,
+
+    /// Draw the board from black's point of view.
+    pub flipped: bool,
+}
+
+
 /// `QUEENSIDE` or `KINGSIDE`.
 pub type CastlingSide = usize;
 
@@ -106,7 +142,7 @@ pub const KINGSIDE: CastlingSide = 1;
 ///  bit 2 -- if set, black can castle on queen-side;
 ///  bit 3 -- if set, black can castle on king-side.
 /// ```
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy)]
 pub struct CastlingRights(usize);
 
 impl CastlingRights {
@@ -194,13 +230,22 @@ impl fmt::Display for CastlingRights {
     }
 }
 
+impl fmt::Debug for CastlingRights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CastlingRights({})", self)
+    }
+}
+
 
 /// Represents an illegal position error.
 pub struct IllegalBoard;
 
 
 /// Holds a chess position.
-#[derive(Clone, Debug)]
+///
+/// `Board` is displayed as a diagram, followed by its FEN
+/// representation.
+#[derive(Clone)]
 pub struct Board {
     /// The placement of the pieces on the board.
     pub pieces: PiecesPlacement,
@@ -257,6 +302,405 @@ impl Board {
         debug_assert!(square <= 63);
         square >> 3
     }
+
+    /// Returns an iterator over the squares occupied by the pieces of
+    /// a given color and type.
+    #[inline]
+    pub fn pieces_of(&self, color: Color, piece: PieceType) -> Squares {
+        debug_assert!(color <= 1);
+        debug_assert!(piece < PIECE_NONE);
+        Squares::new(self.pieces.color[color] & self.pieces.piece_type[piece])
+    }
+
+    /// Returns the set of squares that are attacked by the pawns of a
+    /// given color.
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// # use alcibiades::*;
+    /// # use alcibiades::squares::*;
+    /// let board = Board::from_fen("k7/8/8/8/8/8/P3P3/7K w - - 0 1").ok().unwrap();
+    /// assert_eq!(board.pawn_attacks(WHITE), 1 << B3 | 1 << D3 | 1 << F3);
+    /// assert_eq!(board.pawn_attacks(BLACK), 0);
+    /// ```
+    #[inline]
+    pub fn pawn_attacks(&self, color: Color) -> Bitboard {
+        debug_assert!(color <= 1);
+        const SHIFTS: [[isize; 2]; 2] = [[7, 9], [-9, -7]];
+        let pawns = self.pieces.color[color] & self.pieces.piece_type[PAWN];
+        gen_shift(pawns & !BB_FILE_A, SHIFTS[color][0]) |
+        gen_shift(pawns & !BB_FILE_H, SHIFTS[color][1])
+    }
+
+    /// Returns the set of squares that are attacked by the pieces and
+    /// pawns of a given color.
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// # use alcibiades::*;
+    /// # use alcibiades::squares::*;
+    /// let board = Board::from_fen("k7/8/8/8/8/8/8/R6K w - - 0 1").ok().unwrap();
+    /// let attacked = board.attacked_squares(WHITE);
+    /// assert!(attacked & 1 << A8 != 0);
+    /// assert!(attacked & 1 << B2 == 0);
+    /// ```
+    #[inline]
+    pub fn attacked_squares(&self, color: Color) -> Bitboard {
+        self.attacks_through(color, self.occupied)
+    }
+
+    /// Returns the set of squares that the enemy king can not move
+    /// to, because they are attacked by the pieces and pawns of a
+    /// given color.
+    ///
+    /// This is the same as `attacked_squares`, except that the enemy
+    /// king is removed from the board first. So, when the enemy king
+    /// is in check by a slider, the squares behind the king on the
+    /// line of the check are attacked too.
+    #[inline]
+    pub fn king_danger_squares(&self, color: Color) -> Bitboard {
+        debug_assert!(color <= 1);
+        let enemy_king = self.pieces.color[1 ^ color] & self.pieces.piece_type[KING];
+        self.attacks_through(color, self.occupied & !enemy_king)
+    }
+
+    /// A helper method for `attacked_squares` and
+    /// `king_danger_squares`. It returns the set of squares that are
+    /// attacked by the pieces and pawns of a given color, on a board
+    /// occupied according to the `occupied` bitboard.
+    fn attacks_through(&self, color: Color, occupied: Bitboard) -> Bitboard {
+        debug_assert!(color <= 1);
+        let geometry = BoardGeometry::get();
+        let mut attacked = self.pawn_attacks(color);
+        for piece in KING..PAWN {
+            for square in self.pieces_of(color, piece) {
+                attacked |= geometry.attacks_from(piece, square, occupied);
+            }
+        }
+        attacked
+    }
+
+    /// Returns if neither side has enough material to checkmate.
+    ///
+    /// This is the case when there are no pawns, queens, or rooks on
+    /// the board, and the only remaining pieces (besides the kings)
+    /// are either a single knight, or bishops that all stand on
+    /// squares of the same color (K vs K, KB vs K, KN vs K, KB vs KB
+    /// with same-colored bishops). No sequence of legal moves can
+    /// lead to a checkmate in such positions.
+    pub fn is_insufficient_material(&self) -> bool {
+        let p = &self.pieces.piece_type;
+        if p[PAWN] | p[QUEEN] | p[ROOK] != 0 {
+            return false;
+        }
+        let knights = p[KNIGHT];
+        let bishops = p[BISHOP];
+        if bishops == 0 {
+            pop_count(knights) <= 1
+        } else {
+            knights == 0 && (bishops & BB_DARK_SQUARES == 0 || bishops & BB_LIGHT_SQUARES == 0)
+        }
+    }
+
+    /// Draws the board as a diagram, one line for each rank.
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// # use alcibiades::*;
+    /// let board = Board::from_fen("k7/8/8/8/8/8/8/6NK w - - 0 1").ok().unwrap();
+    /// let style = RenderStyle {
+    ///     coordinates: true,
+    ///     ..RenderStyle::default()
+    /// };
+    /// assert!(board.render(style).starts_with("8 k.......\n"));
+    /// assert!(board.render(style).ends_with("1 ......NK\n  abcdefgh\n"));
+    /// ```
+    pub fn render(&self, style: RenderStyle) -> String {
+        self.pieces.render(style)
+    }
+
+    /// Returns an iterator over all pieces on the board.
+    ///
+    /// The iterator yields `(color, piece, square)` triples, ordered
+    /// by color (white first), then by piece type (kings first), then
+    /// by square.
+    #[inline]
+    pub fn all_pieces<'a>(&'a self) -> AllPieces<'a> {
+        AllPieces {
+            board: self,
+            color: WHITE,
+            piece: KING,
+            squares: self.pieces_of(WHITE, KING),
+        }
+    }
+
+    /// Returns an iterator over all pieces on the board, in square
+    /// order.
+    ///
+    /// The iterator yields `(square, color, piece)` triples, starting
+    /// from A1 and ending at H8.
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// # use alcibiades::*;
+    /// # use alcibiades::squares::*;
+    /// let board = Board::from_fen("7k/8/8/8/8/8/8/R5NK w - - 0 1").ok().unwrap();
+    /// let pieces: Vec<_> = board.iter_pieces().collect();
+    /// assert_eq!(pieces,
+    ///            vec![(A1, WHITE, ROOK), (G1, WHITE, KNIGHT), (H1, WHITE, KING),
+    ///                 (H8, BLACK, KING)]);
+    /// ```
+    #[inline]
+    pub fn iter_pieces<'a>(&'a self) -> Pieces<'a> {
+        Pieces {
+            board: self,
+            squares: Squares::new(self.pieces.color[WHITE] | self.pieces.color[BLACK]),
+        }
+    }
+}
+
+/// A builder for `Board` instances.
+///
+/// This makes it possible to set up a position square by square,
+/// without writing FEN strings. The builder starts with an empty
+/// board, white to move, no castling rights, and no en-passant
+/// target square. Use `Board::iter_pieces` to iterate over the pieces
+/// of an existing board.
+///
+/// # Examples:
+///
+/// ```rust
+/// # use alcibiades::*;
+/// # use alcibiades::squares::*;
+/// let board = BoardBuilder::new()
+///     .piece(E1, WHITE, KING)
+///     .piece(A1, WHITE, ROOK)
+///     .piece(E8, BLACK, KING)
+///     .castling(WHITE, QUEENSIDE)
+///     .build()
+///     .ok()
+///     .unwrap();
+/// assert_eq!(format!("{:?}", board), "Board(4k3/8/8/8/8/8/8/R3K3 w Q - 0 1)");
+/// ```
+#[derive(Clone, Debug)]
+pub struct BoardBuilder {
+    squares: [Option<(Color, PieceType)>; 64],
+    to_move: Color,
+    castling_rights: CastlingRights,
+    enpassant_square: Option<Square>,
+}
+
+impl BoardBuilder {
+    /// Creates a new instance.
+    pub fn new() -> BoardBuilder {
+        BoardBuilder {
+            squares: [None; 64],
+            to_move: WHITE,
+            castling_rights: CastlingRights::new(0),
+            enpassant_square: None,
+        }
+    }
+
+    /// Places a piece on a given square, replacing the piece that
+    /// was there (if any).
+    pub fn piece(mut self, square: Square, color: Color, piece: PieceType) -> BoardBuilder {
+        assert!(square <= 63);
+        assert!(color <= 1);
+        assert!(piece < PIECE_NONE);
+        self.squares[square] = Some((color, piece));
+        self
+    }
+
+    /// Removes the piece from a given square (if any).
+    pub fn empty(mut self, square: Square) -> BoardBuilder {
+        assert!(square <= 63);
+        self.squares[square] = None;
+        self
+    }
+
+    /// Sets the side to move.
+    pub fn to_move(mut self, color: Color) -> BoardBuilder {
+        assert!(color <= 1);
+        self.to_move = color;
+        self
+    }
+
+    /// Grants a given player the right to castle on a given side.
+    pub fn castling(mut self, player: Color, side: CastlingSide) -> BoardBuilder {
+        self.castling_rights.grant(player, side);
+        self
+    }
+
+    /// Sets the en-passant target square.
+    ///
+    /// This is the square "behind" the pawn that has just made a
+    /// 2-square move.
+    pub fn enpassant_square(mut self, square: Option<Square>) -> BoardBuilder {
+        self.enpassant_square = square;
+        self
+    }
+
+    /// Validates the set up position and returns it as a `Board`.
+    ///
+    /// An error is returned if some player does not have exactly one
+    /// king, if there are pawns on the first or the last rank, if
+    /// some castling right is not supported by the king and rook
+    /// placement, or if the en-passant target square is not behind a
+    /// pawn that could have just made a 2-square move. Note that this
+    /// is not an exhaustive legality check -- for example, the side
+    /// that is not to move may still be in check. (Move generators
+    /// perform the complete check in `MoveGenerator::from_board`.)
+    pub fn build(self) -> Result<Board, IllegalBoard> {
+        let mut pieces = PiecesPlacement {
+            piece_type: [0; 6],
+            color: [0; 2],
+        };
+        for (square, x) in self.squares.iter().enumerate() {
+            if let Some((color, piece)) = *x {
+                pieces.piece_type[piece] |= 1 << square;
+                pieces.color[color] |= 1 << square;
+            }
+        }
+        let is = |square: Square, color: Color, piece: PieceType| {
+            self.squares[square] == Some((color, piece))
+        };
+
+        // Verify the kings and the pawns.
+        for color in 0..2 {
+            if (pieces.piece_type[KING] & pieces.color[color]).count_ones() != 1 {
+                return Err(IllegalBoard);
+            }
+        }
+        if pieces.piece_type[PAWN] & 0xff000000000000ff != 0 {
+            return Err(IllegalBoard);
+        }
+
+        // Verify the castling rights.
+        for color in 0..2 {
+            let rank = if color == WHITE { 0 } else { 7 };
+            for &(side, rook_file) in &[(QUEENSIDE, 0), (KINGSIDE, 7)] {
+                if self.castling_rights.can_castle(color, side) &&
+                   !(is(Board::square(4, rank), color, KING) &&
+                     is(Board::square(rook_file, rank), color, ROOK)) {
+                    return Err(IllegalBoard);
+                }
+            }
+        }
+
+        // Verify the en-passant target square.
+        let enpassant_file = match self.enpassant_square {
+            None => 8,
+            Some(square) => {
+                let them = 1 ^ self.to_move;
+                let (rank, step) = if them == WHITE { (2, 8) } else { (5, -8) };
+                let pawn_square = (square as isize + step) as Square;
+                let orig_square = (square as isize - step) as Square;
+                if square > 63 || Board::rank(square) != rank ||
+                   self.squares[square].is_some() ||
+                   self.squares[orig_square].is_some() ||
+                   !is(pawn_square, them, PAWN) {
+                    return Err(IllegalBoard);
+                }
+                Board::file(square)
+            }
+        };
+
+        Ok(Board {
+               occupied: pieces.color[WHITE] | pieces.color[BLACK],
+               pieces: pieces,
+               to_move: self.to_move,
+               castling_rights: self.castling_rights,
+               enpassant_file: enpassant_file,
+           })
+    }
+}
+
+impl Default for BoardBuilder {
+    fn default() -> BoardBuilder {
+        BoardBuilder::new()
+    }
+}
+
+
+/// An iterator over all pieces on the board (see `Board::all_pieces`).
+#[derive(Clone, Debug)]
+pub struct AllPieces<'a> {
+    board: &'a Board,
+    color: Color,
+    piece: PieceType,
+    squares: Squares,
+}
+
+impl<'a> Iterator for AllPieces<'a> {
+    type Item = (Color, PieceType, Square);
+
+    #[inline]
+    fn next(&mut self) -> Option<(Color, PieceType, Square)> {
+        loop {
+            if let Some(square) = self.squares.next() {
+                return Some((self.color, self.piece, square));
+            }
+            self.piece += 1;
+            if self.piece == PIECE_NONE {
+                self.piece = KING;
+                self.color += 1;
+            }
+            if self.color > BLACK {
+                return None;
+            }
+            self.squares = self.board.pieces_of(self.color, self.piece);
+        }
+    }
+}
+
+
+/// An iterator over all pieces on the board, in square order (see
+/// `Board::iter_pieces`).
+#[derive(Clone, Debug)]
+pub struct Pieces<'a> {
+    board: &'a Board,
+    squares: Squares,
+}
+
+impl<'a> Iterator for Pieces<'a> {
+    type Item = (Square, Color, PieceType);
+
+    #[inline]
+    fn next(&mut self) -> Option<(Square, Color, PieceType)> {
+        self.squares.next().map(|square| {
+            let pieces = &self.board.pieces;
+            let bb = 1 << square;
+            let color = if pieces.color[WHITE] & bb != 0 { WHITE } else { BLACK };
+            let piece = (KING..PIECE_NONE)
+                .find(|&piece| pieces.piece_type[piece] & bb != 0)
+                .unwrap();
+            (square, color, piece)
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.squares.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Pieces<'a> {}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}", self.pieces));
+        writeln!(f, "{}", format_fen(self, 0, 1))
+    }
+}
+
+impl fmt::Debug for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Board({})", format_fen(self, 0, 1))
+    }
 }
 
 
@@ -265,6 +709,62 @@ mod tests {
     use super::*;
     use squares::*;
 
+    #[test]
+    fn piece_iterators() {
+        let b = Board::from_fen("8/8/8/3k4/8/8/2PP4/4K2R w K - 0 1").ok().unwrap();
+        assert_eq!(b.pieces_of(WHITE, PAWN).collect::<Vec<_>>(), vec![C2, D2]);
+        assert_eq!(b.pieces_of(BLACK, KING).len(), 1);
+        assert_eq!(b.pieces_of(BLACK, QUEEN).next(), None);
+        assert_eq!(b.all_pieces().collect::<Vec<_>>(),
+                   vec![(WHITE, KING, E1),
+                        (WHITE, ROOK, H1),
+                        (WHITE, PAWN, C2),
+                        (WHITE, PAWN, D2),
+                        (BLACK, KING, D5)]);
+        assert_eq!(b.iter_pieces().collect::<Vec<_>>(),
+                   vec![(E1, WHITE, KING),
+                        (H1, WHITE, ROOK),
+                        (C2, WHITE, PAWN),
+                        (D2, WHITE, PAWN),
+                        (D5, BLACK, KING)]);
+        let b = Board::from_fen("8/8/8/8/8/8/8/8 w - - 0 1").ok().unwrap();
+        assert_eq!(b.all_pieces().next(), None);
+        assert_eq!(b.iter_pieces().len(), 0);
+    }
+
+    #[test]
+    fn board_builder() {
+        let b = BoardBuilder::new()
+            .piece(E1, WHITE, KING)
+            .piece(H1, WHITE, ROOK)
+            .piece(E8, BLACK, KING)
+            .piece(D5, BLACK, PAWN)
+            .piece(E5, WHITE, PAWN)
+            .piece(A2, WHITE, QUEEN)
+            .empty(A2)
+            .castling(WHITE, KINGSIDE)
+            .enpassant_square(Some(D6))
+            .build()
+            .ok()
+            .unwrap();
+        assert_eq!(format!("{:?}", b), "Board(4k3/8/8/3pP3/8/8/8/4K2R w K d6 0 1)");
+        let b = BoardBuilder::new()
+            .piece(E1, WHITE, KING)
+            .piece(E8, BLACK, KING)
+            .piece(E4, WHITE, PAWN)
+            .to_move(BLACK)
+            .enpassant_square(Some(E3));
+        assert!(b.clone().build().is_ok());
+        assert!(b.clone().enpassant_square(Some(D3)).build().is_err());
+        assert!(b.clone().enpassant_square(Some(E6)).build().is_err());
+        assert!(b.clone().piece(E2, BLACK, KNIGHT).build().is_err());
+        assert!(b.clone().castling(WHITE, QUEENSIDE).build().is_err());
+        assert!(b.clone().piece(A8, WHITE, PAWN).build().is_err());
+        assert!(b.clone().empty(E8).build().is_err());
+        assert!(b.clone().piece(E7, BLACK, KING).build().is_err());
+        assert!(BoardBuilder::new().build().is_err());
+    }
+
     #[test]
     fn castling_rights() {
         let mut c = CastlingRights::new(0b1110);
@@ -272,6 +772,7 @@ mod tests {
         assert_eq!(c.can_castle(WHITE, KINGSIDE), true);
         assert_eq!(c.can_castle(BLACK, QUEENSIDE), true);
         assert_eq!(c.can_castle(BLACK, KINGSIDE), true);
+        assert_eq!(format!("{:?}", c), "CastlingRights(Kqk)");
         c.update(H8, H7);
         assert_eq!(c.can_castle(WHITE, QUEENSIDE), false);
         assert_eq!(c.can_castle(WHITE, KINGSIDE), true);
@@ -282,4 +783,68 @@ mod tests {
         assert_eq!(c.grant(BLACK, KINGSIDE), false);
         assert_eq!(c.value(), 0b1110);
     }
+
+    #[test]
+    fn insufficient_material() {
+        let f = |fen: &str| Board::from_fen(fen).ok().unwrap().is_insufficient_material();
+        assert!(f("8/8/8/3k4/8/8/8/4K3 w - - 0 1"));
+        assert!(f("8/8/8/3k4/8/8/8/4KB2 w - - 0 1"));
+        assert!(f("8/8/8/3k4/8/8/8/4KN2 b - - 0 1"));
+        assert!(f("8/8/8/3k4/8/8/8/n3K3 b - - 0 1"));
+        assert!(f("8/5b2/8/3k4/8/8/8/4KB2 w - - 0 1"));
+        assert!(!f("8/6b1/8/3k4/8/8/8/4KB2 w - - 0 1"));
+        assert!(!f("8/8/8/3k4/8/8/8/3NKN2 w - - 0 1"));
+        assert!(!f("8/8/8/3k4/8/8/8/3NKB2 w - - 0 1"));
+        assert!(!f("8/8/8/3k4/8/8/P7/4K3 w - - 0 1"));
+        assert!(!f("8/8/8/3k4/8/8/8/4K2R w - - 0 1"));
+    }
+
+    #[test]
+    fn attacked_squares() {
+        use bitsets::BB_RANK_1;
+        let b = Board::from_fen("8/8/8/8/4k3/8/1p6/R3K3 b - - 0 1").ok().unwrap();
+        let white = b.attacked_squares(WHITE);
+        assert_eq!(white & BB_RANK_1, BB_RANK_1 & !(1 << A1 | 1 << G1 | 1 << H1));
+        assert_eq!(white & 1 << A8, 1 << A8);
+        assert_eq!(white & 1 << E3, 0);
+        let black = b.attacked_squares(BLACK);
+        assert_eq!(black & BB_RANK_1, 1 << A1 | 1 << C1);
+        assert_eq!(black & 1 << E3, 1 << E3);
+
+        // With the black king removed, the squares behind it are
+        // attacked too.
+        let b = Board::from_fen("8/8/8/8/8/8/8/r3K2k w - - 0 1").ok().unwrap();
+        assert_eq!(b.attacked_squares(BLACK) & 1 << F1, 0);
+        assert_eq!(b.king_danger_squares(BLACK) & 1 << F1, 1 << F1);
+        assert_eq!(b.king_danger_squares(WHITE), b.attacked_squares(WHITE));
+    }
+
+    #[test]
+    fn render_board() {
+        let b = Board::from_fen("k7/8/8/8/8/8/8/6NK w - - 0 1").ok().unwrap();
+        let flipped = RenderStyle {
+            flipped: true,
+            coordinates: true,
+            ..RenderStyle::default()
+        };
+        let lines: Vec<String> = b.render(flipped).lines().map(|x| x.to_string()).collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "1 KN......");
+        assert_eq!(lines[7], "8 .......k");
+        assert_eq!(lines[8], "  hgfedcba");
+        let unicode = RenderStyle {
+            unicode: true,
+            ..RenderStyle::default()
+        };
+        assert!(b.render(unicode).starts_with("♚·······\n"));
+        assert!(b.render(unicode).ends_with("······♘♔\n"));
+    }
+
+    #[test]
+    fn display_board() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let board = Board::from_fen(fen).ok().unwrap();
+        assert_eq!(format!("{:?}", board), format!("Board({})", fen));
+        assert!(format!("{}", board).ends_with(&format!("\n{}\n", fen)));
+    }
 }
@@ -34,7 +34,44 @@
 ///
 /// * `DEPTH_MIN` is the minimum allowed search depth in half-moves (a
 ///   negative number).
-pub type Depth = i8;
+///
+/// `Depth` is wider than the range of allowed depths, so that
+/// calculations like `depth + 1` or `depth - reduction` can not
+/// overflow, even at the limits.
+///
+/// # Fractional depths:
+///
+/// Searches may count the remaining search depth in fractions of a
+/// half-move internally (`ONE_PLY` units make one half-move), so that
+/// extensions and reductions can be finer than one half-move. Such
+/// depths must be converted to whole half-moves (see `whole_plies`)
+/// before they leave the search -- when they are stored in the
+/// transposition table, passed to the quiescence search, or reported.
+pub type Depth = i16;
+
+pub const DEPTH_MIN: Depth = -32;
+pub const DEPTH_MAX: Depth = 127;
+
+/// The number of fractional depth units in one half-move.
+pub const ONE_PLY: Depth = 4;
 
-pub const DEPTH_MIN: i8 = -32;
-pub const DEPTH_MAX: i8 = 95;
+
+/// Converts a fractional depth to whole half-moves, rounding down.
+///
+/// # Example:
+///
+/// ```rust
+/// use alcibiades::*;
+///
+/// assert_eq!(whole_plies(3 * ONE_PLY), 3);
+/// assert_eq!(whole_plies(3 * ONE_PLY - 1), 2);
+/// assert_eq!(whole_plies(-1), -1);
+/// ```
+#[inline]
+pub fn whole_plies(depth: Depth) -> Depth {
+    if depth >= 0 {
+        depth / ONE_PLY
+    } else {
+        -((ONE_PLY - 1 - depth) / ONE_PLY)
+    }
+}
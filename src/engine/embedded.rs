@@ -0,0 +1,368 @@
+//! Implements an engine API that does not use the standard input and
+//! output.
+//!
+//! `run_uci` blocks the current thread reading commands from the
+//! standard input. This is not always possible -- for example, when
+//! the crate is compiled to WebAssembly and driven from JavaScript,
+//! the host calls into the engine and expects the call to return
+//! quickly. `EmbeddedEngine` gives direct access to the engine
+//! instead: the host sets the position, starts the search, and then
+//! polls for replies whenever it has a spare moment.
+//!
+//! `EmbeddedEngine` itself never spawns threads -- it calls the
+//! searcher only from its own methods, on the caller's thread. The
+//! stock searcher (`stock::Deepening`) executes each search in a
+//! separate thread, and therefore can not be used on targets without
+//! thread support, like `wasm32-unknown-unknown`. On such targets,
+//! supply a `DeepeningSearch` implementation that does its work in
+//! small slices (a limited number of nodes, for example) inside
+//! `wait_report`. `poll` calls `wait_report`, and so the whole search
+//! runs on the caller's thread, a slice per `poll` call.
+//!
+//! # Examples:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use alcibiades::engine::engine_default;
+//! use alcibiades::engine::embedded::{GoParams, EngineReply};
+//!
+//! let mut engine = engine_default("My engine", "John Doe").embedded();
+//! engine.set_position("startpos", &["e2e4", "e7e5"]);
+//! engine.go(&GoParams { movetime: Some(1000), ..GoParams::default() });
+//! loop {
+//!     match engine.poll(Duration::from_millis(10)) {
+//!         Some(EngineReply::BestMove { best_move, .. }) => {
+//!             println!("{}", best_move);
+//!             break;
+//!         }
+//!         Some(EngineReply::Info(_)) | None => (),
+//!     }
+//! }
+//! ```
+
+use std::time::Duration;
+use search::DeepeningSearch;
+use time_manager::TimeManager;
+use uci::UciEngine;
+use super::{Engine, EngineBuilder, Variation, ENGINE, register_engine};
+
+pub use uci::{GoParams, EngineReply, InfoItem};
+
+
+/// The FEN for the standard starting position.
+const STARTPOS: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w QKqk - 0 1";
+
+
+/// A chess engine that is driven by direct method calls.
+///
+/// The methods correspond to the UCI commands with the same names,
+/// and the replies that a UCI engine would write to the standard
+/// output are obtained by calling `poll`. Whether the searches are
+/// executed in separate threads depends on the searcher (see the
+/// module-level documentation).
+///
+/// Only one engine can exist at a time. The engine is stopped when
+/// the instance is dropped.
+pub struct EmbeddedEngine<S, T>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    engine: Engine<S, T>,
+}
+
+impl<S, T> EmbeddedEngine<S, T>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    /// Sets a new value for a given configuration option.
+    ///
+    /// Unknown options and invalid values are reported with an
+    /// `"string"` info item.
+    pub fn set_option(&mut self, name: &str, value: &str) {
+        self.engine.set_option(name, value);
+    }
+
+    /// Tells the engine that the next position will be from a
+    /// different game.
+    pub fn new_game(&mut self) {
+        self.engine.new_game();
+    }
+
+    /// Loads a new chess position.
+    ///
+    /// `fen` is the starting position in Forsyth–Edwards notation (or
+    /// `"startpos"`), and `moves` are the moves played from it, in
+    /// long algebraic notation. If the position is illegal, or some of
+    /// the moves can not be played, the previous position stays
+    /// active, and the error is reported with a `"string"` info item.
+    pub fn set_position(&mut self, fen: &str, moves: &[&str]) {
+        let fen = if fen == "startpos" { STARTPOS } else { fen };
+        self.engine.position(fen, &mut moves.iter().cloned());
+    }
+
+    /// Starts thinking on the current position.
+    ///
+    /// This method returns immediately. The progress of the search is
+    /// reported by `poll`, and exactly one `EngineReply::BestMove` is
+    /// returned when the search ends.
+    pub fn go(&mut self, params: &GoParams) {
+        self.engine.go(params);
+    }
+
+    /// Forces the engine to stop thinking and reply with the best move
+    /// it had found.
+    pub fn stop(&mut self) {
+        self.engine.stop();
+    }
+
+    /// Tells the engine that the move it is pondering on was played
+    /// on the board.
+    pub fn ponder_hit(&mut self) {
+        self.engine.ponder_hit();
+    }
+
+    /// Returns the next engine reply, waiting at most `timeout` for it.
+    ///
+    /// This method must be called regularly while the engine is
+    /// thinking, because it is also responsible for stopping the
+    /// search when the time is up. Pass a zero duration to avoid
+    /// blocking.
+    pub fn poll(&mut self, timeout: Duration) -> Option<EngineReply> {
+        self.engine.wait_for_reply(timeout)
+    }
+}
+
+impl<S, T> Drop for EmbeddedEngine<S, T>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    fn drop(&mut self) {
+        self.engine.exit();
+        *ENGINE.lock().unwrap() = None;
+    }
+}
+
+
+impl<S, T> EngineBuilder<S, T>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    /// Creates an engine that is driven by direct method calls,
+    /// instead of UCI commands (see `EmbeddedEngine`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if another engine is already running.
+    pub fn embedded(self) -> EmbeddedEngine<S, T> {
+        register_engine(self.info);
+        Engine::<S, T>::options();
+        EmbeddedEngine { engine: Engine::new(None) }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::TryRecvError;
+    use std::thread::{self, ThreadId};
+    use std::time::Duration;
+    use engine::engine_default;
+    use depth::*;
+    use search::*;
+    use search_node::SearchNode;
+    use stock::{DefaultTtable, DefaultSearchNode, StdTimeManager};
+    use ttable::*;
+    use uci::SetOption;
+    use super::*;
+
+    lazy_static! {
+        /// Only one engine can exist at a time, so the tests take
+        /// turns.
+        static ref ENGINES: Mutex<()> = Mutex::new(());
+    }
+
+    /// A searcher that does not spawn threads. It completes one depth
+    /// of a (fake) search every time `wait_report` is called.
+    struct SingleThreaded {
+        thread: ThreadId,
+        params: Option<SearchParams<DefaultSearchNode>>,
+        depth: RefCell<Depth>,
+        terminated: bool,
+        reports: RefCell<VecDeque<SearchReport<Vec<Variation>>>>,
+    }
+
+    impl DeepeningSearch for SingleThreaded {
+        type Ttable = DefaultTtable;
+
+        type SearchNode = DefaultSearchNode;
+
+        type ReportData = Vec<Variation>;
+
+        fn new(_: Arc<Self::Ttable>) -> Self {
+            SingleThreaded {
+                thread: thread::current().id(),
+                params: None,
+                depth: RefCell::new(0),
+                terminated: false,
+                reports: RefCell::new(VecDeque::new()),
+            }
+        }
+
+        fn start_search(&mut self, params: SearchParams<Self::SearchNode>) {
+            assert_eq!(thread::current().id(), self.thread);
+            self.params = Some(params);
+            self.terminated = false;
+            *self.depth.borrow_mut() = 0;
+        }
+
+        fn wait_report(&self, _: Duration) {
+            assert_eq!(thread::current().id(), self.thread);
+            let params = match self.params {
+                Some(ref p) => p,
+                None => return,
+            };
+            let mut depth = self.depth.borrow_mut();
+            if *depth < params.depth && !self.terminated {
+                *depth += 1;
+                let moves = if params.searchmoves.is_empty() {
+                    params.position.legal_moves()
+                } else {
+                    params.searchmoves.clone()
+                };
+                let done = *depth == params.depth;
+                self.reports
+                    .borrow_mut()
+                    .push_back(SearchReport {
+                                   search_id: params.search_id,
+                                   searched_nodes: *depth as u64,
+                                   depth: *depth,
+                                   value: 0,
+                                   data: vec![Variation {
+                                                  moves: vec![moves[0]],
+                                                  value: 0,
+                                                  bound: BOUND_EXACT,
+                                              }],
+                                   done: done,
+                                   termination: if done {
+                                       Some(TerminationReason::DepthReached)
+                                   } else {
+                                       None
+                                   },
+                                   details: Default::default(),
+                               });
+            } else if self.terminated && *depth < params.depth {
+                *depth = params.depth;
+                self.reports
+                    .borrow_mut()
+                    .push_back(SearchReport {
+                                   search_id: params.search_id,
+                                   searched_nodes: *depth as u64,
+                                   depth: *depth,
+                                   value: 0,
+                                   data: vec![],
+                                   done: true,
+                                   termination: Some(TerminationReason::Terminated),
+                                   details: Default::default(),
+                               });
+            }
+        }
+
+        fn try_recv_report(&mut self) -> Result<SearchReport<Self::ReportData>, TryRecvError> {
+            assert_eq!(thread::current().id(), self.thread);
+            self.reports.borrow_mut().pop_front().ok_or(TryRecvError::Empty)
+        }
+
+        fn send_message(&mut self, msg: &str) {
+            assert_eq!(thread::current().id(), self.thread);
+            if msg == "TERMINATE" {
+                self.terminated = true;
+            }
+        }
+    }
+
+    impl SetOption for SingleThreaded {}
+
+    #[test]
+    fn embedded_engine() {
+        let _turn = ENGINES.lock().unwrap_or_else(|e| e.into_inner());
+        let mut engine = engine_default("Test", "Tester").embedded();
+        let poll_info_string = |engine: &mut EmbeddedEngine<_, _>| loop {
+            match engine.poll(Duration::from_millis(0)) {
+                Some(EngineReply::Info(items)) => {
+                    if let Some(item) = items.into_iter().find(|x| x.info_type == "string") {
+                        return item.data;
+                    }
+                }
+                Some(EngineReply::BestMove { .. }) => panic!("unexpected best move"),
+                None => return String::new(),
+            }
+        };
+        engine.set_position("startpos", &["e2e4", "e7e5", "e1e2", "e8e7"]);
+        assert_eq!(poll_info_string(&mut engine), "");
+        engine.set_position("startpos", &["e2e4", "e2e4"]);
+        assert!(poll_info_string(&mut engine).starts_with("position rejected"));
+        engine.set_option("No Such Option", "1");
+        assert!(poll_info_string(&mut engine).starts_with("unknown option"));
+
+        // The rejected position did not replace the previous one, in
+        // which the white king is on "e2".
+        engine.go(&GoParams {
+                      depth: Some(2),
+                      searchmoves: vec!["e2e3".to_string(), "e2d3".to_string()],
+                      ..GoParams::default()
+                  });
+        let mut infos = 0;
+        loop {
+            match engine.poll(Duration::from_millis(10)) {
+                Some(EngineReply::Info(_)) => infos += 1,
+                Some(EngineReply::BestMove { best_move, .. }) => {
+                    assert!(best_move == "e2e3" || best_move == "e2d3");
+                    break;
+                }
+                None => (),
+            }
+        }
+        assert!(infos > 0);
+        engine.stop();
+        assert!(engine.poll(Duration::from_millis(0)).is_none());
+    }
+
+    #[test]
+    fn single_threaded() {
+        let _turn = ENGINES.lock().unwrap_or_else(|e| e.into_inner());
+        let mut engine = EngineBuilder::<SingleThreaded, StdTimeManager>::new("Test", "Tester")
+            .embedded();
+        engine.set_position("startpos", &["e2e4", "e7e5"]);
+        engine.go(&GoParams {
+                      depth: Some(3),
+                      searchmoves: vec!["g1f3".to_string()],
+                      ..GoParams::default()
+                  });
+
+        // The search advances only when the engine is polled.
+        let mut polls = 0;
+        loop {
+            polls += 1;
+            match engine.poll(Duration::from_millis(0)) {
+                Some(EngineReply::BestMove { best_move, .. }) => {
+                    assert_eq!(best_move, "g1f3");
+                    break;
+                }
+                Some(EngineReply::Info(_)) | None => assert!(polls < 100),
+            }
+        }
+        engine.go(&GoParams { infinite: true, ..GoParams::default() });
+        assert!(engine.poll(Duration::from_millis(0)).is_some());
+        engine.stop();
+        loop {
+            match engine.poll(Duration::from_millis(0)) {
+                Some(EngineReply::BestMove { .. }) => break,
+                Some(EngineReply::Info(_)) => (),
+                None => panic!("no best move"),
+            }
+        }
+    }
+}
@@ -0,0 +1,2804 @@
+//! Implements a generic chess engine.
+
+pub mod embedded;
+
+use std::env;
+use std::mem;
+use std::process;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::fs::{File, OpenOptions};
+use std::marker::PhantomData;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::TryRecvError;
+use std::time::{SystemTime, Duration};
+use std::thread::sleep;
+use std::cmp::{min, max};
+use std::collections::hash_map::Entry;
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::isaac::Isaac64Rng;
+use uci::*;
+use io_log;
+use board::{Board, RenderStyle, WHITE};
+use value::*;
+use depth::*;
+use search::*;
+use ttable::*;
+use moves::Move;
+use evaluator::Evaluator;
+use utils::{parse_san, format_san, format_fen, divide, parse_epd, BENCH_POSITIONS};
+#[cfg(feature = "movegen-reference")]
+use utils::fuzz_move_generator;
+use utils::{GameRecord, GameResult, Experience, ExperienceRecord, OpeningBook, MoveStack};
+use search_node::SearchNode;
+use qsearch::QsearchResult;
+use stock::{StdTimeManager, DefaultSearchExecutor};
+use stock::{TuningRecord, find_scaling_constant, tuning_error, tune_psts};
+use move_generator::MoveGenerator;
+use time_manager::{TimeManager, RemainingTime};
+
+
+/// The minimal transposition table size in Mbytes.
+const HASH_SIZE_MIN: usize = 1;
+
+/// The maximal transposition table size in Mbytes.
+const HASH_SIZE_MAX: usize = 64 * 1024;
+
+/// The default search depth for the "bench" command.
+const BENCH_DEPTH: Depth = 8;
+
+/// The default search time in seconds for the "epd" command.
+const EPD_SECONDS: u64 = 5;
+
+/// The default search depth for the "analyze" command.
+const ANALYZE_DEPTH: Depth = 8;
+
+/// The default number of games for the "selfplay" command.
+const SELFPLAY_GAMES: usize = 10;
+
+/// The default search time per move in milliseconds for the
+/// "selfplay" command.
+const SELFPLAY_MILLIS: u64 = 100;
+
+/// Self-play games that last longer than this number of half-moves
+/// are adjudicated as draws.
+const SELFPLAY_MAX_PLIES: usize = 400;
+
+/// The default maximal number of passes for the "tune" command.
+const TUNE_PASSES: usize = 50;
+
+/// The default number of random games per position for the "fuzz"
+/// command.
+#[cfg(feature = "movegen-reference")]
+const FUZZ_GAMES: usize = 100;
+
+/// The maximal number of half-moves in a "fuzz" game.
+#[cfg(feature = "movegen-reference")]
+const FUZZ_PLIES: usize = 200;
+
+/// The maximal number of entries in the result cache.
+const RESULT_CACHE_SIZE: usize = 8;
+
+/// The number of seconds after which a cached result becomes stale.
+const RESULT_CACHE_TTL_SECS: u64 = 60;
+
+/// The move currently searched at the root is shown only after the
+/// search has been running for this many milliseconds.
+const CURRMOVE_MIN_MILLIS: u64 = 1000;
+
+/// In deterministic mode, time limits are converted to node limits at
+/// this rate.
+const DETERMINISTIC_NODES_PER_MILLI: u64 = 1000;
+
+/// The "Skill Level" at which the engine plays at full strength.
+const SKILL_LEVEL_MAX: u8 = 20;
+
+/// The minimal value for the "UCI_Elo" option.
+const ELO_MIN: i32 = 800;
+
+/// The maximal value for the "UCI_Elo" option. At this rating the
+/// engine plays at full strength.
+const ELO_MAX: i32 = 2800;
+
+/// After leaving the opening book, the engine thinks longer on this
+/// many moves, spending some of the time banked while in the book.
+const BOOK_EXIT_MOVES: usize = 3;
+
+/// On each of the first moves after leaving the opening book, the
+/// engine thinks at least this fraction (`1 / BOOK_EXIT_TIME_DIVISOR`)
+/// of its remaining time.
+const BOOK_EXIT_TIME_DIVISOR: u64 = 16;
+
+/// After playing a book move, the engine pre-warms the transposition
+/// table with a search to this depth.
+const BOOK_PREWARM_DEPTH: Depth = 10;
+
+/// When the strength is limited, for each skill level below the
+/// maximum, the engine may play moves that are this many centipawns
+/// worse than the best move.
+const WEAKNESS_PER_SKILL_LEVEL: Value = 10;
+
+
+struct SearchStatus {
+    pub done: bool,
+    pub depth: Depth,
+    pub value: Value,
+    pub searched_nodes: u64,
+
+    // The selective search depth, or `0` if not available.
+    pub selective_depth: Depth,
+
+    // The duration of the search in milliseconds.
+    pub duration_millis: u64,
+}
+
+impl Default for SearchStatus {
+    fn default() -> Self {
+        SearchStatus {
+            done: false,
+            depth: 0,
+            value: VALUE_UNKNOWN,
+            searched_nodes: 0,
+            selective_depth: 0,
+            duration_millis: 0,
+        }
+    }
+}
+
+
+/// The result of a recent search, kept so that an identical request
+/// can be answered without searching again.
+struct CachedResult {
+    // The hash of the root position, and the moves that were searched.
+    hash: u64,
+    searchmoves: Vec<Move>,
+
+    // The status of the search, and the principal variations from the
+    // deepest completed iteration.
+    status: SearchStatus,
+    variations: Vec<Variation>,
+
+    // When the result was stored.
+    stored_at: SystemTime,
+}
+
+
+enum PlayWhen<S, T>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    TimeManagement(T), // Stop when the time manager says so.
+    MoveTime(u64), // Stop after the given number of milliseconds.
+    Nodes(u64), // Stop when the given number of nodes has been searched.
+    Depth(Depth), // Stop when the given search depth has been completed.
+    Mate(i16), // Stop when a mate in the given number of moves is found.
+    Never(PhantomData<S>), // An infinite search.
+}
+
+
+struct Engine<S, T>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    // The transposition table and the searcher are heavyweight
+    // components. They are constructed lazily (see `prepare`), so
+    // that all options received with "setoption" are taken into
+    // account.
+    tt: Option<Arc<S::Ttable>>,
+    searcher: Option<S>,
+
+    // The preferred size of the transposition table in Mbytes.
+    tt_size_mb: Option<usize>,
+
+    // Whether an option has been changed since the heavyweight
+    // components were constructed.
+    must_rebuild: bool,
+
+    // The changed options that affect the searcher, the time manager,
+    // or the evaluator. They are applied together when the engine
+    // gets ready (see `apply_options`), so that a running search
+    // never sees them change.
+    pending_options: Vec<(&'static str, String)>,
+
+    // The current position, and the FEN and the moves it was created
+    // from. (The position is re-created when the components are
+    // re-built, so that its evaluator gets the new option values.)
+    position: S::SearchNode,
+    position_fen: String,
+    position_moves: Vec<String>,
+
+    queue: VecDeque<EngineReply>,
+
+    // The starting time of the current/last search.
+    started_at: SystemTime,
+
+    // The status of the current/last search.
+    status: SearchStatus,
+
+    // The best line of play from the deepest completed iteration.
+    best_line: Vec<Move>,
+
+    // Whether a best move is still due for the current search.
+    best_move_is_pending: bool,
+
+    // A hard deadline (milliseconds since `deadline_from`) for the
+    // current search. When the deadline is reached the engine plays
+    // the best move it has, even if the search has not yet complied
+    // with the request to terminate.
+    deadline: Option<u64>,
+    deadline_from: SystemTime,
+
+    // Nodes per second statistics.
+    nps_stats: (u64, u64, u64),
+
+    // Helps the engine decide when to show periodic progress reports.
+    silent_since: SystemTime,
+
+    // The move currently searched at the root, and its number.
+    current_move: Option<(Move, usize)>,
+
+    // Whether the engine is thinking in pondering mode at the moment.
+    is_pondering: bool,
+
+    // Tells the engine when it must stop thinking and play the best move.
+    play_when: PlayWhen<S, T>,
+
+    // Whether the mate requested by a "go mate" command has been
+    // proven by the search.
+    mate_is_found: bool,
+
+    // Statistics about the game tree explored by the current/last
+    // search, if the searcher provides them.
+    tree_stats: Option<TreeStats>,
+
+    // The results of the recent searches (the most recent last), and
+    // the key (root position's hash and searched moves) under which
+    // the result of the current search will be cached.
+    result_cache: VecDeque<CachedResult>,
+    result_key: Option<(u64, Vec<Move>)>,
+
+    // The experience loaded from the "Experience File" (together with
+    // the name of the file), and the records from the current game
+    // that have not been appended to the file yet.
+    experience: Option<(String, Experience)>,
+    new_experience: Vec<ExperienceRecord>,
+
+    // The opening book loaded from the "Book File" (together with the
+    // name of the file), and the number of out-of-book moves on which
+    // the engine will still think longer.
+    book: Option<(String, OpeningBook)>,
+    book_exit_moves: usize,
+
+    // The minimal thinking time (in milliseconds since
+    // `deadline_from`) for the current search, and whether the time
+    // manager has already decided that the best move must be played.
+    min_think_millis: u64,
+    time_is_up: bool,
+
+    // Whether the current search only pre-warms the transposition
+    // table. The reports from such a search are not shown.
+    is_prewarming: bool,
+
+    // The supported configuration options. They are used to validate
+    // the values received with "setoption" commands.
+    option_descriptions: Vec<(&'static str, OptionDescription)>,
+}
+
+impl<S, T> UciEngine for Engine<S, T>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    fn name() -> &'static str {
+        ENGINE.lock().unwrap().as_ref().unwrap().name
+    }
+
+    fn author() -> &'static str {
+        ENGINE.lock().unwrap().as_ref().unwrap().author
+    }
+
+    fn options() -> Vec<(&'static str, OptionDescription)> {
+        let hash_size_mb = ENGINE.lock().unwrap().as_ref().unwrap().hash_size_mb;
+
+        // Add up all suported options.
+        let mut options = vec![("Hash",
+                                OptionDescription::Spin {
+                                    min: HASH_SIZE_MIN as i32,
+                                    max: HASH_SIZE_MAX as i32,
+                                    default: hash_size_mb.unwrap_or(16) as i32,
+                                }),
+                               ("Clear Hash", OptionDescription::Button),
+                               ("Move Overhead",
+                                OptionDescription::Spin {
+                                    min: 0,
+                                    max: 5000,
+                                    default: 20,
+                                }),
+                               ("Strict Legality", OptionDescription::Check { default: false }),
+                               ("Debug Stats", OptionDescription::Check { default: false }),
+                               ("Result Cache", OptionDescription::Check { default: false }),
+                               ("Deterministic", OptionDescription::Check { default: false }),
+                               ("UCI_LimitStrength", OptionDescription::Check { default: false }),
+                               ("UCI_Elo",
+                                OptionDescription::Spin {
+                                    min: ELO_MIN,
+                                    max: ELO_MAX,
+                                    default: ELO_MAX,
+                                }),
+                               ("Skill Level",
+                                OptionDescription::Spin {
+                                    min: 0,
+                                    max: SKILL_LEVEL_MAX as i32,
+                                    default: SKILL_LEVEL_MAX as i32,
+                                }),
+                               ("Learning", OptionDescription::Check { default: false }),
+                               ("Experience File",
+                                OptionDescription::String { default: String::new() }),
+                               ("Book File", OptionDescription::String { default: String::new() }),
+                               ("Output Format",
+                                OptionDescription::Combo {
+                                    list: vec!["uci".to_string(), "json".to_string()],
+                                    default: "uci".to_string(),
+                                }),
+                               ("Log File", OptionDescription::String { default: String::new() })];
+        options.extend(S::options());
+        options.extend(T::options());
+
+        // Remove the duplicated options.
+        let mut options_dedup = vec![];
+        let mut prev_name = "";
+        options.sort_by(|a, b| a.0.cmp(&b.0));
+        for o in options.drain(..) {
+            if o.0 == prev_name {
+                continue;
+            }
+            prev_name = o.0;
+            options_dedup.push(o);
+        }
+
+        // Acquire the necessary global locks.
+        let engine_info = ENGINE.lock().unwrap();
+        let mut configuration = ::CONFIGURATION.write().unwrap();
+        let mut changed_defaults = CHANGED_DEFAULTS.write().unwrap();
+        changed_defaults.clear();
+
+        // Inspect each option.
+        for o in options_dedup.iter_mut() {
+            let (name, ref mut description) = *o;
+            let value = description.get_default();
+
+            // Set a new default value for the option if necessary.
+            if let Some(new_default) =
+                engine_info
+                    .as_ref()
+                    .unwrap()
+                    .options
+                    .iter()
+                    .find(|x| x.0 == name) {
+                let new_value = new_default.1;
+                if new_value != value {
+                    assert!(name != "Hash",
+                            "The default value for the Hash option can not be changed.");
+                    description.set_default(new_value);
+
+                    // Remember that the default value has been changed.
+                    changed_defaults.push(*new_default);
+                }
+            }
+
+            // Insert the option into the global configuration table.
+            if let Entry::Vacant(e) = configuration.entry(name) {
+                e.insert(value);
+            }
+        }
+
+        options_dedup
+    }
+
+    fn new(tt_size_mb: Option<usize>) -> Engine<S, T> {
+        const START_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w QKqk - 0 1";
+        let tt_size_mb = tt_size_mb.or(ENGINE.lock().unwrap().as_ref().unwrap().hash_size_mb);
+        let started_at = SystemTime::now();
+        let mut engine = Engine {
+            tt: None,
+            searcher: None,
+            tt_size_mb: tt_size_mb,
+            must_rebuild: true,
+            pending_options: vec![],
+            position: S::SearchNode::from_history(START_FEN, &mut vec![].into_iter())
+                .ok()
+                .unwrap(),
+            position_fen: START_FEN.to_string(),
+            position_moves: vec![],
+            queue: VecDeque::new(),
+            started_at: started_at,
+            status: SearchStatus {
+                done: true,
+                ..Default::default()
+            },
+            best_line: vec![],
+            best_move_is_pending: false,
+            deadline: None,
+            deadline_from: started_at,
+            nps_stats: (0, 0, 0),
+            silent_since: started_at,
+            current_move: None,
+            is_pondering: false,
+            play_when: PlayWhen::Never(PhantomData),
+            mate_is_found: false,
+            tree_stats: None,
+            result_cache: VecDeque::new(),
+            result_key: None,
+            experience: None,
+            new_experience: vec![],
+            book: None,
+            book_exit_moves: 0,
+            min_think_millis: 0,
+            time_is_up: false,
+            is_prewarming: false,
+            option_descriptions: Self::options(),
+        };
+
+        // Set correct value for the "Hash" option.
+        if let Some(v) = tt_size_mb {
+            ::CONFIGURATION
+                .write()
+                .unwrap()
+                .insert("Hash", format!("{}", v));
+        }
+
+        // Issue a "setoption" command for each changed default.
+        for o in CHANGED_DEFAULTS.read().unwrap().iter() {
+            engine.set_option(o.0, o.1);
+        }
+        engine.apply_options();
+
+        engine
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) {
+        // A changed option may change the results of the searches.
+        self.result_cache.clear();
+        let (name, validated) = match self.option_descriptions
+                  .iter()
+                  .find(|x| x.0.to_uppercase() == name.to_uppercase()) {
+            Some(&(x, ref description)) => (x, description.validate(value)),
+            None => {
+                self.queue_info_string(&format!("unknown option: {}", name));
+                return;
+            }
+        };
+        let value = match validated {
+            Ok(ref v) => v.as_str(),
+            Err(e) => {
+                self.queue_info_string(&format!("invalid value for {}: {}", name, e));
+                return;
+            }
+        };
+        match name {
+            "Hash" => {
+                // The transposition table will be re-sized the next
+                // time the engine gets ready. The value has already
+                // been validated against the limits.
+                if let Ok(v) = value.parse::<usize>() {
+                    if Some(v) != self.tt_size_mb {
+                        self.tt_size_mb = Some(v);
+                        self.tt = None;
+                        self.must_rebuild = true;
+                        ::CONFIGURATION.write().unwrap().insert("Hash", format!("{}", v));
+                    }
+                }
+            }
+            "Move Overhead" | "Strict Legality" | "Debug Stats" | "Result Cache" |
+            "Deterministic" | "UCI_LimitStrength" | "UCI_Elo" | "Skill Level" | "Learning" |
+            "Output Format" | "Book File" => {
+                // These options do not require a rebuild.
+                *::CONFIGURATION.write().unwrap().get_mut(name).unwrap() = value.to_string();
+            }
+            "Experience File" => {
+                // The records from the current game belong to the old
+                // file. The new file will be loaded when needed.
+                self.save_experience();
+                *::CONFIGURATION.write().unwrap().get_mut(name).unwrap() = value.to_string();
+            }
+            "Log File" => {
+                if let Err(e) = io_log::set_log_file(value) {
+                    self.queue_info_string(&format!("can not open {}: {}", value, e));
+                    return;
+                }
+                *::CONFIGURATION.write().unwrap().get_mut(name).unwrap() = value.to_string();
+            }
+            "Clear Hash" => {
+                // Empty the table without re-allocating it. If it has
+                // not been allocated yet, there is nothing to do.
+                if let Some(ref tt) = self.tt {
+                    tt.clear();
+                }
+            }
+            _ => {
+                self.pending_options.push((name, value.to_string()));
+                self.must_rebuild = true;
+            }
+        }
+    }
+
+    fn new_game(&mut self) {
+        self.save_experience();
+        self.result_cache.clear();
+        self.book_exit_moves = 0;
+        if let Some(ref tt) = self.tt {
+            tt.clear();
+        }
+    }
+
+    fn position(&mut self, fen: &str, moves: &mut Iterator<Item = &str>) {
+        let mut moves: Vec<String> = moves.map(|m| m.to_string()).collect();
+        let mut result = S::SearchNode::from_history(fen, &mut moves.iter().map(|m| m.as_str()));
+        if result.is_err() {
+            // Some scripts send the moves in Standard Algebraic
+            // Notation (SAN). Try to convert them to long algebraic
+            // notation.
+            if let Some(converted_moves) = convert_san_moves::<S::SearchNode>(fen, &moves) {
+                result = S::SearchNode::from_history(fen,
+                                                     &mut converted_moves
+                                                              .iter()
+                                                              .map(|m| m.as_str()));
+                if result.is_ok() {
+                    self.queue_info_string("moves in SAN have been accepted");
+                    moves = converted_moves;
+                }
+            }
+        }
+        // When the position is rejected, replay the moves one by one,
+        // so that we can tell exactly which one is wrong. In this
+        // case the previous position stays active. When the "Strict
+        // Legality" option is on, the moves are replayed even if the
+        // position has been accepted.
+        let diagnostics = if result.is_err() || ::get_option("Strict Legality") == "true" {
+            match verify_history::<S::SearchNode>(fen, &moves) {
+                Err(s) => Some(s),
+                Ok(_) if result.is_err() => Some("the moves can not be replayed".to_string()),
+                Ok(_) => None,
+            }
+        } else {
+            None
+        };
+        match result {
+            Ok(p) if diagnostics.is_none() => {
+                if p.is_insufficient_material() {
+                    // Let the GUI know that the game can be adjudicated.
+                    self.queue_info_string("draw by insufficient material");
+                }
+                self.position = p;
+                self.position_fen = fen.to_string();
+                self.position_moves = moves;
+            }
+            _ => {
+                let s = diagnostics.unwrap();
+                self.queue_info_string(&format!("position rejected: {}", s));
+            }
+        }
+    }
+
+    fn is_ready(&mut self) {
+        self.prepare();
+    }
+
+    fn go(&mut self, params: &GoParams) {
+        self.terminate();
+        self.prepare();
+        if self.play_from_book(params) {
+            return;
+        }
+
+        // Validate `params.searchmoves`.
+        let searchmoves = {
+            let mut moves = vec![];
+            let legal_moves = self.position.legal_moves();
+            if !params.searchmoves.is_empty() {
+                let mut v = params.searchmoves.clone();
+                v.sort();
+                for m in legal_moves.iter() {
+                    if v.binary_search(&m.notation()).is_ok() {
+                        moves.push(*m);
+                    }
+                }
+            };
+            if moves.is_empty() { legal_moves } else { moves }
+        };
+
+        // In deterministic mode, time limits are converted to node
+        // limits, so that identical inputs always produce identical
+        // results.
+        let deterministic = ::get_option("Deterministic") == "true";
+        let node_limit = if deterministic && params.nodes.is_none() && !params.infinite &&
+                            (params.movetime.is_some() ||
+                             params.depth.is_none() && params.mate.is_none()) {
+            Some(self.deterministic_node_limit(params))
+        } else {
+            params.nodes
+        };
+
+        // Start a new search. When the strength is limited, the
+        // search depth is capped too.
+        let depth = params
+            .depth
+            .map_or(DEPTH_MAX, |x| min(x, DEPTH_MAX as u64) as Depth);
+        let depth = skill_level().map_or(depth, |s| min(depth, 1 + s as Depth / 2));
+        let remaining_time = RemainingTime {
+            white_millis: params.wtime.unwrap_or(300_000),
+            black_millis: params.btime.unwrap_or(300_000),
+            winc_millis: params.winc.unwrap_or(0),
+            binc_millis: params.binc.unwrap_or(0),
+            movestogo: match params.movestogo {
+                Some(0) => None, // Zero moves to go is a nonsense.
+                x => x,
+            },
+        };
+        self.tt.as_ref().unwrap().new_search();
+        self.started_at = SystemTime::now();
+        self.status = Default::default();
+        self.best_line = vec![];
+        self.best_move_is_pending = true;
+        self.nps_stats = (self.nps_stats.0, 0, 0);
+        self.silent_since = self.started_at;
+        self.current_move = None;
+        self.is_pondering = params.ponder;
+        self.mate_is_found = false;
+        self.tree_stats = None;
+        self.deadline = if params.infinite || deterministic {
+            None
+        } else {
+            // Leave a safety margin for communication delays.
+            let overhead = ::get_option("Move Overhead").parse().unwrap_or(0);
+            let (t, inc) = if self.position.board().to_move == WHITE {
+                (params.wtime, params.winc.unwrap_or(0))
+            } else {
+                (params.btime, params.binc.unwrap_or(0))
+            };
+            let hard_limit = t.map(|t| min(t, (t + inc) / 2));
+            match (params.movetime, hard_limit) {
+                (Some(a), Some(b)) => Some(min(a, b)),
+                (a, b) => a.or(b),
+            }
+            .map(|x| x.saturating_sub(overhead))
+        };
+        self.deadline_from = self.started_at;
+        self.play_when = if params.infinite {
+            PlayWhen::Never(PhantomData)
+        } else if params.movetime.is_some() && !deterministic {
+            PlayWhen::MoveTime(params.movetime.unwrap())
+        } else if node_limit.is_some() {
+            PlayWhen::Nodes(node_limit.unwrap())
+        } else if params.depth.is_some() {
+            PlayWhen::Depth(depth)
+        } else if params.mate.is_some() {
+            PlayWhen::Mate(min(params.mate.unwrap(), (DEPTH_MAX + 1) as u64 / 2) as i16)
+        } else {
+            PlayWhen::TimeManagement(T::new(&self.position, &remaining_time))
+        };
+
+        // Right after leaving the opening book, think longer, because
+        // the time that the book has saved is still on the clock.
+        self.min_think_millis = 0;
+        self.time_is_up = false;
+        if let PlayWhen::TimeManagement(_) = self.play_when {
+            if self.book_exit_moves > 0 {
+                self.book_exit_moves -= 1;
+                let t = if self.position.board().to_move == WHITE {
+                    remaining_time.white_millis
+                } else {
+                    remaining_time.black_millis
+                };
+                self.min_think_millis = min(t / BOOK_EXIT_TIME_DIVISOR,
+                                            self.deadline.unwrap_or(t));
+            }
+        }
+
+        // GUIs often re-send the same request after trivial user
+        // interface events. If the result of an identical search is
+        // still available, reply without searching again.
+        // (Cached results expire after some time, so the cache is not
+        // used in deterministic mode.)
+        self.result_key = if ::get_option("Result Cache") == "true" && !params.ponder &&
+                             !deterministic {
+            Some((self.position.hash(), searchmoves.clone()))
+        } else {
+            None
+        };
+        if self.reply_from_cache() {
+            return;
+        }
+        self.consult_experience();
+        self.searcher
+            .as_mut()
+            .unwrap()
+            .start_search(SearchParams {
+                              search_id: 0,
+                              position: self.position.clone(),
+                              depth: depth,
+                              lower_bound: VALUE_MIN,
+                              upper_bound: VALUE_MAX,
+                              searchmoves: searchmoves,
+                              node_limit: node_limit,
+                          });
+    }
+
+    fn ponder_hit(&mut self) {
+        if self.status.done {
+            if self.best_move_is_pending {
+                self.queue_best_move();
+            }
+        } else {
+            self.is_pondering = false;
+            self.deadline_from = SystemTime::now();
+        }
+    }
+
+    fn custom_command(&mut self, name: &str, params: &str) {
+        let mut params = params.split_whitespace();
+        match (name, params.next()) {
+            ("export", Some(kind)) => {
+                // Export evaluator's parameters (the piece-square
+                // tables, for example), so that they can be edited
+                // and loaded back with the corresponding option.
+                let text = match <S::SearchNode as SearchNode>::Evaluator::export(kind) {
+                    Some(text) => text,
+                    None => {
+                        self.queue_info_string(&format!("nothing to export: {}", kind));
+                        return;
+                    }
+                };
+                if let Some(path) = params.next() {
+                    match File::create(path).and_then(|mut f| f.write_all(text.as_bytes())) {
+                        Ok(_) => self.queue_info_string(&format!("exported to {}", path)),
+                        Err(e) => self.queue_info_string(&format!("export failed: {}", e)),
+                    }
+                } else {
+                    for line in text.lines() {
+                        self.queue_info_string(line);
+                    }
+                }
+            }
+            ("memory", None) => {
+                let tt = self.tt.as_ref().map_or(MemoryUsage::default(), |tt| tt.memory_usage());
+                let cache = self.result_cache_memory_usage();
+                for &(component, usage) in &[("tt", tt), ("result cache", cache)] {
+                    self.queue_info_string(&format!("memory {} bytes {} entries {} of {} \
+                                                     fill {} permille",
+                                                    component,
+                                                    usage.bytes,
+                                                    usage.used,
+                                                    usage.capacity,
+                                                    usage.permille()));
+                }
+                self.queue_info_string(&format!("memory total bytes {}", (tt + cache).bytes));
+            }
+            ("d", first) => {
+                // Display the current position. The optional
+                // parameters "unicode", "coordinates", and "flipped"
+                // select the rendering style.
+                let mut style = RenderStyle::default();
+                for param in first.into_iter().chain(params) {
+                    match param {
+                        "unicode" => style.unicode = true,
+                        "coordinates" => style.coordinates = true,
+                        "flipped" => style.flipped = true,
+                        _ => {
+                            self.queue_info_string(&format!("unknown display style: {}", param));
+                            return;
+                        }
+                    }
+                }
+                let p = &self.position;
+                let mut lines: Vec<String> = p.board()
+                    .render(style)
+                    .lines()
+                    .map(|x| x.to_string())
+                    .collect();
+                lines.push(format!("fen {}",
+                                   format_fen(p.board(), p.halfmove_clock(), p.fullmove_number())));
+                lines.push(format!("key {:016x}", p.hash()));
+                for line in lines {
+                    self.queue_info_string(&line);
+                }
+            }
+            ("eval", None) => {
+                // Print the breakdown of the static evaluation (in
+                // centipawns) for the current position.
+                let cp = |v: Value| {
+                    <<S::SearchNode as SearchNode>::Evaluator as Evaluator>::to_centipawns(v)
+                };
+                let board = self.position.board();
+                let terms = self.position.evaluator().evaluate_explained(board);
+                let mut lines = vec![format!("eval {:<16}{:>8}{:>8}{:>8}",
+                                             "term",
+                                             "white",
+                                             "black",
+                                             "total")];
+                for t in terms {
+                    lines.push(format!("eval {:<16}{:>8}{:>8}{:>8}",
+                                       t.name,
+                                       cp(t.white),
+                                       cp(t.black),
+                                       cp(t.white) - cp(t.black)));
+                }
+                let v = self.position.evaluator().evaluate(board);
+                lines.push(format!("eval {:<32}{:>8}",
+                                   "static (white's point of view)",
+                                   cp(if board.to_move == WHITE { v } else { -v })));
+                for line in lines {
+                    self.queue_info_string(&line);
+                }
+            }
+            _ => self.queue_info_string(&format!("unknown command: {}", name)),
+        }
+    }
+
+    fn stop(&mut self) {
+        self.terminate();
+
+        // Ignore "stop" commands for which there is no search to
+        // reply to -- there must be exactly one "bestmove" for each
+        // "go" command.
+        if self.best_move_is_pending {
+            self.queue_best_move();
+        }
+    }
+
+    fn wait_for_reply(&mut self, duration: Duration) -> Option<EngineReply> {
+        if self.queue.is_empty() {
+            let is_thinking = !self.status.done;
+
+            // Wait for the search thread to do some work, and
+            // hopefully update the status. (We must do this even when
+            // the engine is not thinking -- in that case the next
+            // line will just yield the CPU to another process.)
+            self.wait_status_update(duration);
+
+            // See if we must stop thinking and play.
+            if is_thinking && !self.is_pondering &&
+               match self.play_when {
+                   PlayWhen::TimeManagement(_) => self.status.done,
+                   PlayWhen::MoveTime(t) => self.status.done || self.status.duration_millis >= t,
+                   PlayWhen::Nodes(n) => self.status.done || self.status.searched_nodes >= n,
+                   PlayWhen::Depth(d) => self.status.done || self.status.depth >= d,
+                   PlayWhen::Mate(_) => self.status.done || self.mate_is_found,
+                   PlayWhen::Never(_) => false,
+               } {
+                self.stop();
+            } else if is_thinking && !self.is_pondering && self.best_move_is_pending &&
+                      self.deadline_is_reached() {
+                self.enforce_deadline();
+            }
+        }
+
+        self.queue.pop_front()
+    }
+
+    fn json_output(&self) -> bool {
+        ::get_option("Output Format") == "json"
+    }
+
+    fn exit(&mut self) {
+        self.terminate();
+        self.save_experience();
+    }
+}
+
+impl<S, T> Engine<S, T>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    /// Constructs the heavyweight components if they have not been
+    /// constructed yet, or if an option has been changed since then.
+    fn prepare(&mut self) {
+        if !self.must_rebuild {
+            return;
+        }
+        self.terminate();
+        self.apply_options();
+        if self.tt.is_none() {
+            // Make sure that the old table is freed before the new
+            // one is allocated.
+            self.searcher = None;
+            self.tt = Some(Arc::new(S::Ttable::new(self.tt_size_mb)));
+        }
+        self.searcher = Some(S::new(self.tt.as_ref().unwrap().clone()));
+        if let Ok(p) = S::SearchNode::from_history(&self.position_fen,
+                                                   &mut self.position_moves
+                                                            .iter()
+                                                            .map(|m| m.as_str())) {
+            self.position = p;
+        }
+        self.must_rebuild = false;
+    }
+
+    /// Applies the pending options.
+    ///
+    /// The searcher, the time manager, and the evaluator are notified
+    /// first, and then all the accepted values are written to the
+    /// global configuration table at once. The rejected values are
+    /// reported with info strings. Must not be called while a search
+    /// is running.
+    fn apply_options(&mut self) {
+        let mut accepted = vec![];
+        for (name, value) in self.pending_options.drain(..).collect::<Vec<_>>() {
+            match S::set_option(name, &value).and_then(|_| T::set_option(name, &value)) {
+                Ok(()) => accepted.push((name, value)),
+                Err(e) => self.queue_info_string(&format!("can not set {}: {}", name, e)),
+            }
+        }
+        let mut configuration = ::CONFIGURATION.write().unwrap();
+        for (name, value) in accepted {
+            *configuration.get_mut(name).unwrap() = value;
+        }
+    }
+
+    /// Plays the book move for the current position without
+    /// searching, if there is one.
+    ///
+    /// The book is used only when the engine plays a game (that is,
+    /// the search is not limited to a given depth, number of nodes,
+    /// or set of moves). After the book move has been played, the
+    /// transposition table is pre-warmed with a search of the new
+    /// position, which runs until the next command that needs the
+    /// searcher. Returns `true` if a book move has been played.
+    fn play_from_book(&mut self, params: &GoParams) -> bool {
+        if params.infinite || params.ponder || params.depth.is_some() ||
+           params.nodes.is_some() || params.mate.is_some() ||
+           !params.searchmoves.is_empty() {
+            return false;
+        }
+        let file = ::get_option("Book File");
+        if file.is_empty() {
+            return false;
+        }
+        if self.book.as_ref().map_or(true, |x| x.0 != file) {
+            let book = File::open(&file)
+                .and_then(|f| OpeningBook::load::<S::SearchNode, _>(BufReader::new(f)));
+            let book = book.unwrap_or_else(|e| {
+                self.queue_info_string(&format!("can not read {}: {}", file, e));
+                OpeningBook::new()
+            });
+            self.book = Some((file, book));
+        }
+        let book_move = match self.book.as_ref().unwrap().1.probe(self.position.board()) {
+            Some(m) => m.to_string(),
+            None => return false,
+        };
+        let mut moves = self.position_moves.clone();
+        moves.push(book_move.clone());
+        let position = match S::SearchNode::from_history(&self.position_fen,
+                                                         &mut moves.iter().map(|m| m.as_str())) {
+            Ok(p) => p,
+            Err(_) => return false,
+        };
+        self.book_exit_moves = BOOK_EXIT_MOVES;
+        self.best_move_is_pending = false;
+        self.queue_info_string(&format!("book move {}", book_move));
+        self.queue
+            .push_back(EngineReply::BestMove {
+                           best_move: book_move,
+                           ponder_move: None,
+                       });
+
+        // Pre-warm the transposition table.
+        let searchmoves = position.legal_moves();
+        if !searchmoves.is_empty() {
+            self.tt.as_ref().unwrap().new_search();
+            self.status = Default::default();
+            self.play_when = PlayWhen::Never(PhantomData);
+            self.is_prewarming = true;
+            self.searcher
+                .as_mut()
+                .unwrap()
+                .start_search(SearchParams {
+                                  search_id: 0,
+                                  position: position,
+                                  depth: BOOK_PREWARM_DEPTH,
+                                  lower_bound: VALUE_MIN,
+                                  upper_bound: VALUE_MAX,
+                                  searchmoves: searchmoves,
+                                  node_limit: None,
+                              });
+        }
+        true
+    }
+
+    /// Searches each position in `BENCH_POSITIONS` to a given depth.
+    ///
+    /// The transposition table is cleared before each search, so the
+    /// number of searched nodes does not depend on previous searches.
+    /// Returns the total number of searched nodes, and the elapsed
+    /// time in milliseconds.
+    fn bench(&mut self, depth: Depth) -> (u64, u64) {
+        self.terminate();
+        self.prepare();
+        let started_at = SystemTime::now();
+        let mut nodes = 0;
+        for fen in BENCH_POSITIONS.iter() {
+            self.new_game();
+            let position = S::SearchNode::from_history(fen, &mut vec![].into_iter())
+                .ok()
+                .expect("illegal bench position");
+            let searchmoves = position.legal_moves();
+            let searcher = self.searcher.as_mut().unwrap();
+            searcher.start_search(SearchParams {
+                                      search_id: 0,
+                                      position: position,
+                                      depth: depth,
+                                      lower_bound: VALUE_MIN,
+                                      upper_bound: VALUE_MAX,
+                                      searchmoves: searchmoves,
+                                      node_limit: None,
+                                  });
+            loop {
+                searcher.wait_report(Duration::from_millis(100));
+                match searcher.try_recv_report() {
+                    Ok(SearchReport { done: true, searched_nodes, .. }) => {
+                        nodes += searched_nodes;
+                        break;
+                    }
+                    Err(TryRecvError::Disconnected) => break,
+                    _ => continue,
+                }
+            }
+        }
+        let elapsed = started_at.elapsed().unwrap_or(Duration::from_millis(0));
+        (nodes, 1000 * elapsed.as_secs() + (elapsed.subsec_nanos() / 1_000_000) as u64)
+    }
+
+    /// Searches a position to a given depth.
+    ///
+    /// The transposition table is cleared before the search. Returns
+    /// the deepest completed search depth, and the principal
+    /// variation found at this depth (`None` if there are no legal
+    /// moves).
+    fn analyze(&mut self, position: S::SearchNode, depth: Depth) -> Option<(Depth, Variation)> {
+        self.terminate();
+        self.prepare();
+        self.new_game();
+        let searchmoves = position.legal_moves();
+        if searchmoves.is_empty() {
+            return None;
+        }
+        let mut result = None;
+        let searcher = self.searcher.as_mut().unwrap();
+        searcher.start_search(SearchParams {
+                                  search_id: 0,
+                                  position: position,
+                                  depth: depth,
+                                  lower_bound: VALUE_MIN,
+                                  upper_bound: VALUE_MAX,
+                                  searchmoves: searchmoves,
+                                  node_limit: None,
+                              });
+        loop {
+            searcher.wait_report(Duration::from_millis(100));
+            match searcher.try_recv_report() {
+                Ok(SearchReport { done, depth, mut data, .. }) => {
+                    if !data.is_empty() {
+                        result = Some((depth, data.swap_remove(0)));
+                    }
+                    if done {
+                        return result;
+                    }
+                }
+                Err(TryRecvError::Disconnected) => return result,
+                Err(TryRecvError::Empty) => (),
+            }
+        }
+    }
+
+    /// Searches a position for a given time.
+    ///
+    /// The transposition table is cleared before the search. Returns
+    /// the best move found (`None` if there are no legal moves), and
+    /// the number of searched nodes.
+    fn solve(&mut self, position: S::SearchNode, millis: u64) -> (Option<Move>, u64) {
+        self.terminate();
+        self.prepare();
+        self.new_game();
+        let started_at = SystemTime::now();
+        let searchmoves = position.legal_moves();
+        let mut best_move = searchmoves.first().cloned();
+        let mut is_terminated = false;
+        let searcher = self.searcher.as_mut().unwrap();
+        searcher.start_search(SearchParams {
+                                  search_id: 0,
+                                  position: position,
+                                  depth: DEPTH_MAX,
+                                  lower_bound: VALUE_MIN,
+                                  upper_bound: VALUE_MAX,
+                                  searchmoves: searchmoves,
+                                  node_limit: None,
+                              });
+        loop {
+            searcher.wait_report(Duration::from_millis(10));
+            match searcher.try_recv_report() {
+                Ok(SearchReport { done, searched_nodes, data, .. }) => {
+                    if let Some(&m) = data.first().and_then(|v| v.moves.first()) {
+                        best_move = Some(m);
+                    }
+                    if done {
+                        return (best_move, searched_nodes);
+                    }
+                }
+                Err(TryRecvError::Disconnected) => return (best_move, 0),
+                Err(TryRecvError::Empty) => (),
+            }
+            let elapsed = started_at.elapsed().unwrap_or(Duration::from_millis(0));
+            if !is_terminated && elapsed >= Duration::from_millis(millis) {
+                searcher.send_message("TERMINATE");
+                is_terminated = true;
+            }
+        }
+    }
+
+    /// Plays a game of the engine against itself, starting from a
+    /// given position, and searching each move for a given time.
+    ///
+    /// Returns the record of the game, and the reason for its result.
+    fn play_game(&mut self, fen: &str, millis: u64) -> (GameRecord, &'static str) {
+        play_game::<S::SearchNode, _>(fen, |position| {
+            self.solve(position.clone(), millis)
+                .0
+                .expect("no move in a non-final position")
+        })
+    }
+
+    fn queue_info_string(&mut self, s: &str) {
+        self.queue
+            .push_back(EngineReply::Info(vec![InfoItem {
+                                                  info_type: "string".to_string(),
+                                                  data: s.to_string(),
+                                              }]));
+    }
+
+    fn queue_progress_info(&mut self) {
+        let SearchStatus {
+            ref depth,
+            ref searched_nodes,
+            ref duration_millis,
+            ..
+        } = self.status;
+        self.queue
+            .push_back(EngineReply::Info(vec![InfoItem {
+                                                  info_type: "depth".to_string(),
+                                                  data: format!("{}", depth),
+                                              },
+                                              InfoItem {
+                                                  info_type: "time".to_string(),
+                                                  data: format!("{}", duration_millis),
+                                              },
+                                              InfoItem {
+                                                  info_type: "nodes".to_string(),
+                                                  data: format!("{}", searched_nodes),
+                                              },
+                                              InfoItem {
+                                                  info_type: "nps".to_string(),
+                                                  data: format!("{}", self.nps_stats.0),
+                                              }]));
+    }
+
+    /// Shows the principal variations stored in the transposition
+    /// table, when more than one principal variation is requested.
+    ///
+    /// During long multi-PV searches, this keeps the variations shown
+    /// by the GUI up to date between the iterations.
+    fn queue_tt_variations(&mut self) {
+        let count = if self.option_descriptions.iter().any(|x| x.0 == "MultiPV") {
+            ::get_option("MultiPV").parse().unwrap_or(1)
+        } else {
+            1
+        };
+        if count > 1 && self.status.depth > 0 {
+            let variations = self.tt
+                .as_ref()
+                .unwrap()
+                .extract_variations(&self.position, self.status.depth, count);
+            if !variations.is_empty() {
+                self.queue_pv(&variations);
+            }
+        }
+    }
+
+    /// Shows the statistics about the game tree explored by the
+    /// search.
+    fn queue_tree_stats(&mut self) {
+        fn percent(x: u64, total: u64) -> u64 {
+            (100 * x).checked_div(total).unwrap_or(0)
+        }
+
+        let s = match self.tree_stats {
+            Some(s) => s,
+            None => return,
+        };
+        let cutoffs = s.beta_cutoffs.iter().sum();
+        let lines = [format!("tree nodes {} qsearch nodes {} ({}%)",
+                             s.nodes,
+                             s.qsearch_nodes,
+                             percent(s.qsearch_nodes, s.nodes + s.qsearch_nodes)),
+                     format!("tree tt probes {} hits {}%",
+                             s.tt_probes,
+                             percent(s.tt_hits, s.tt_probes)),
+                     format!("tree beta cut-offs {} by move 1 {}% 2 {}% 3 {}% later {}%",
+                             cutoffs,
+                             percent(s.beta_cutoffs[0], cutoffs),
+                             percent(s.beta_cutoffs[1], cutoffs),
+                             percent(s.beta_cutoffs[2], cutoffs),
+                             percent(s.beta_cutoffs[3], cutoffs)),
+                     format!("tree null moves {} cut-offs {}%",
+                             s.null_moves,
+                             percent(s.null_move_cutoffs, s.null_moves)),
+                     format!("tree late move reductions {} re-searches {}%",
+                             s.reductions,
+                             percent(s.reduction_researches, s.reductions))];
+        for line in lines.iter() {
+            self.queue_info_string(line);
+        }
+    }
+
+    fn queue_current_move(&mut self, m: Move, number: usize) {
+        self.queue
+            .push_back(EngineReply::Info(vec![InfoItem {
+                                                  info_type: "currmove".to_string(),
+                                                  data: m.notation(),
+                                              },
+                                              InfoItem {
+                                                  info_type: "currmovenumber".to_string(),
+                                                  data: format!("{}", number),
+                                              }]));
+    }
+
+    fn queue_pv(&mut self, variations: &Vec<Variation>) {
+        let SearchStatus {
+            ref depth,
+            ref searched_nodes,
+            ref selective_depth,
+            ref duration_millis,
+            ..
+        } = self.status;
+        for (i,
+             &Variation {
+                  ref moves,
+                  value,
+                  bound,
+              }) in variations.iter().enumerate() {
+            let score = score_notation::<S::SearchNode>(value, bound);
+            let mut pv = String::new();
+            for m in moves.iter().take(max(0, *depth) as usize) {
+                pv.push_str(&m.notation());
+                pv.push(' ');
+            }
+            let mut info = vec![InfoItem {
+                                    info_type: "depth".to_string(),
+                                    data: format!("{}", depth),
+                                }];
+            if *selective_depth > 0 {
+                info.push(InfoItem {
+                              info_type: "seldepth".to_string(),
+                              data: format!("{}", selective_depth),
+                          });
+            }
+            info.extend(vec![InfoItem {
+                                 info_type: "multipv".to_string(),
+                                 data: format!("{}", i + 1),
+                             },
+                             InfoItem {
+                                 info_type: "score".to_string(),
+                                 data: score,
+                             },
+                             InfoItem {
+                                 info_type: "time".to_string(),
+                                 data: format!("{}", duration_millis),
+                             },
+                             InfoItem {
+                                 info_type: "nodes".to_string(),
+                                 data: format!("{}", searched_nodes),
+                             },
+                             InfoItem {
+                                 info_type: "nps".to_string(),
+                                 data: format!("{}", self.nps_stats.0),
+                             },
+                             InfoItem {
+                                 info_type: "pv".to_string(),
+                                 data: pv,
+                             }]);
+            self.queue.push_back(EngineReply::Info(info));
+        }
+    }
+
+    /// Returns (approximately) the memory used by the result cache.
+    fn result_cache_memory_usage(&self) -> MemoryUsage {
+        let mut bytes = 0;
+        for r in self.result_cache.iter() {
+            bytes += r.searchmoves.capacity() * mem::size_of::<Move>();
+            bytes += r.variations.capacity() * mem::size_of::<Variation>();
+            for v in r.variations.iter() {
+                bytes += v.moves.capacity() * mem::size_of::<Move>();
+            }
+        }
+        MemoryUsage {
+            bytes: self.result_cache.capacity() * mem::size_of::<CachedResult>() + bytes,
+            capacity: RESULT_CACHE_SIZE,
+            used: self.result_cache.len(),
+        }
+    }
+
+    /// Stores the principal variations from the last completed
+    /// iteration of the current search in the result cache.
+    fn store_result(&mut self, variations: &[Variation]) {
+        if let Some((hash, ref searchmoves)) = self.result_key {
+            self.result_cache
+                .retain(|r| r.hash != hash || r.searchmoves != *searchmoves);
+            if self.result_cache.len() >= RESULT_CACHE_SIZE {
+                self.result_cache.pop_front();
+            }
+            self.result_cache
+                .push_back(CachedResult {
+                               hash: hash,
+                               searchmoves: searchmoves.clone(),
+                               status: SearchStatus {
+                                   done: true,
+                                   ..self.status
+                               },
+                               variations: variations.to_vec(),
+                               stored_at: SystemTime::now(),
+                           });
+        }
+    }
+
+    /// Replies to the current "go" command from the result cache, if
+    /// possible.
+    ///
+    /// This is possible when a non-stale result for the same position
+    /// and searched moves is cached, and the limits of the current
+    /// search do not exceed the limits reached by the cached search.
+    /// Searches controlled by the time manager and infinite searches
+    /// are never answered from the cache. Returns `true` if a best
+    /// move has been queued.
+    fn reply_from_cache(&mut self) -> bool {
+        let (hash, searchmoves) = match self.result_key {
+            Some((hash, ref searchmoves)) => (hash, searchmoves.clone()),
+            None => return false,
+        };
+        let ttl = Duration::from_secs(RESULT_CACHE_TTL_SECS);
+        self.result_cache
+            .retain(|r| r.stored_at.elapsed().map(|d| d < ttl).unwrap_or(false));
+        let index = {
+            let play_when = &self.play_when;
+            self.result_cache.iter().position(|r| {
+                r.hash == hash && r.searchmoves == searchmoves &&
+                match *play_when {
+                    PlayWhen::MoveTime(t) => r.status.duration_millis >= t,
+                    PlayWhen::Nodes(n) => r.status.searched_nodes >= n,
+                    PlayWhen::Depth(d) => r.status.depth >= d,
+                    PlayWhen::Mate(m) => proves_mate(&r.variations[0], m),
+                    PlayWhen::TimeManagement(_) |
+                    PlayWhen::Never(_) => false,
+                }
+            })
+        };
+        if let Some(i) = index {
+            let CachedResult { status, variations, .. } = self.result_cache.remove(i).unwrap();
+            self.status = status;
+            self.best_line = variations[0].moves.clone();
+            self.queue_info_string("reply from the result cache");
+            self.queue_pv(&variations);
+            self.queue_best_move();
+            self.store_result(&variations);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns if the hard deadline for the current search has been
+    /// reached.
+    fn deadline_is_reached(&self) -> bool {
+        if let Some(deadline) = self.deadline {
+            let d = self.deadline_from
+                .elapsed()
+                .unwrap_or(Duration::from_millis(0));
+            1000 * d.as_secs() + (d.subsec_nanos() / 1_000_000) as u64 >= deadline
+        } else {
+            false
+        }
+    }
+
+    /// Plays the best move available without waiting for the search
+    /// to terminate.
+    ///
+    /// The search usually checks for termination requests quite
+    /// often, but sometimes it can get stuck for a while (in a long
+    /// quiescence search, for example). In this case we reply with
+    /// the best move from the deepest completed iteration, and let
+    /// the search terminate in the background.
+    fn enforce_deadline(&mut self) {
+        const GRACE_PERIOD_MILLIS: u64 = 5;
+        self.searcher.as_mut().unwrap().send_message("TERMINATE");
+        let started_at = SystemTime::now();
+        while !self.status.done &&
+              started_at.elapsed().unwrap_or(Duration::from_millis(0)) <
+              Duration::from_millis(GRACE_PERIOD_MILLIS) {
+            self.wait_status_update(Duration::from_millis(1));
+        }
+        self.queue_best_move();
+        self.play_when = PlayWhen::Never(PhantomData);
+    }
+
+    fn queue_best_move(&mut self) {
+        // We prefer the best line of play from the deepest completed
+        // iteration, because the transposition table may contain
+        // results from an iteration that has been aborted mid-way.
+        // If no iteration has been completed yet, we fall back to
+        // getting the best line from the transposition table.
+        let pv = self.tt
+            .as_ref()
+            .map_or(vec![], |tt| tt.extract_pv(&self.position).moves);
+        let mut best_line = self.best_line.clone();
+        if best_line.is_empty() {
+            best_line = pv;
+        } else if best_line.len() == 1 && pv.len() > 1 && pv[0] == best_line[0] {
+            // Try to find a ponder move.
+            best_line = pv;
+        }
+        self.remember_experience();
+        if let (Some(skill), Some(&m)) = (skill_level(), best_line.get(0)) {
+            let weaker_move = self.pick_weaker_move(m, skill);
+            if weaker_move != m {
+                best_line = vec![weaker_move];
+            }
+        }
+        self.best_move_is_pending = false;
+        if ::get_option("Debug Stats") == "true" {
+            self.queue_tree_stats();
+        }
+        io_log::log(io_log::NOTE,
+                    &format!("search done: depth {} nodes {} time {} deadline {} value {}",
+                             self.status.depth,
+                             self.status.searched_nodes,
+                             self.status.duration_millis,
+                             self.deadline.map_or("none".to_string(), |d| d.to_string()),
+                             self.status.value));
+        let best_move = if let Some(m) = best_line.get(0) {
+            m.notation()
+        } else {
+            // If we still do not have a best move, we pick the first legal one.
+            self.position
+                .legal_moves()
+                .get(0)
+                .map_or("0000".to_string(), |m| m.notation())
+        };
+        self.queue
+            .push_back(EngineReply::BestMove {
+                           best_move: best_move,
+                           ponder_move: best_line.get(1).map(|m| m.notation()),
+                       });
+    }
+
+    /// Returns the name of the experience file, or `None` if learning
+    /// is disabled.
+    fn experience_file(&self) -> Option<String> {
+        let file = ::get_option("Experience File");
+        if ::get_option("Learning") == "true" && !file.is_empty() {
+            Some(file)
+        } else {
+            None
+        }
+    }
+
+    /// Seeds the transposition table with the best move remembered
+    /// for the root position, if there is one.
+    ///
+    /// The remembered move is stored without a bound, so that it is
+    /// tried first, but the search still has to prove its value.
+    fn consult_experience(&mut self) {
+        let file = match self.experience_file() {
+            Some(f) => f,
+            None => return,
+        };
+        if self.experience.as_ref().map_or(true, |x| x.0 != file) {
+            let experience = match File::open(&file) {
+                Ok(f) => Experience::load(f),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Experience::new()),
+                Err(e) => Err(e),
+            };
+            let experience = experience.unwrap_or_else(|e| {
+                self.queue_info_string(&format!("can not read {}: {}", file, e));
+                Experience::new()
+            });
+            self.experience = Some((file, experience));
+        }
+        let hash = self.position.hash();
+        if let Some(r) = self.experience.as_ref().unwrap().1.get(hash) {
+            let tt = self.tt.as_ref().unwrap();
+            if tt.probe(hash).is_none() && self.position.try_move_digest(r.move_digest).is_some() {
+                type E<S> = <<S as DeepeningSearch>::Ttable as Ttable>::Entry;
+                tt.store(hash,
+                         E::<S>::new(r.value, BOUND_NONE, r.depth).set_move_digest(r.move_digest));
+            }
+        }
+    }
+
+    /// Remembers the result of the current search for the root
+    /// position, if learning is enabled.
+    fn remember_experience(&mut self) {
+        if self.experience_file().is_none() || self.status.depth <= 0 ||
+           self.status.value == VALUE_UNKNOWN {
+            return;
+        }
+        if let Some(&m) = self.best_line.get(0) {
+            let record = ExperienceRecord {
+                hash: self.position.hash(),
+                depth: self.status.depth,
+                value: self.status.value,
+                move_digest: m.digest(),
+            };
+            if let Some((_, ref mut experience)) = self.experience {
+                experience.insert(record);
+            }
+            self.new_experience.push(record);
+        }
+    }
+
+    /// Appends the records from the current game to the experience
+    /// file.
+    fn save_experience(&mut self) {
+        if self.new_experience.is_empty() {
+            return;
+        }
+        let records: Vec<_> = self.new_experience.drain(..).collect();
+        if let Some(file) = self.experience_file() {
+            if let Err(e) = OpenOptions::new()
+                   .read(true)
+                   .write(true)
+                   .create(true)
+                   .truncate(false)
+                   .open(&file)
+                   .and_then(|f| Experience::append(f, &records)) {
+                self.queue_info_string(&format!("can not write {}: {}", file, e));
+            }
+        }
+    }
+
+    /// Picks a move to play instead of `best_move`, when the strength
+    /// is limited to a given skill level.
+    ///
+    /// The best move and the other legal moves (in random order) are
+    /// verified with a very shallow search, and each one gets a
+    /// random bonus between zero and the weakness for the skill
+    /// level. The move with the highest sum is picked. So, the lower
+    /// the skill level is, the more likely the engine is to play
+    /// moves that look worse than the best move, but never moves that
+    /// look much worse than it. The verification stops when the hard
+    /// deadline for the search is reached, so that the move is played
+    /// in time. In deterministic mode, the random bonuses and the
+    /// order of the moves depend only on the position.
+    fn pick_weaker_move(&self, best_move: Move, skill: u8) -> Move {
+        let weakness = WEAKNESS_PER_SKILL_LEVEL * (SKILL_LEVEL_MAX - skill) as Value;
+        let mut rng: Isaac64Rng = if ::get_option("Deterministic") == "true" {
+            SeedableRng::from_seed(&[self.position.hash()][..])
+        } else {
+            thread_rng().gen()
+        };
+        let mut moves: Vec<Move> = self.position
+            .legal_moves()
+            .into_iter()
+            .filter(|&m| m != best_move)
+            .collect();
+        rng.shuffle(&mut moves);
+        let top = verify_move(&self.position, best_move);
+        let mut picked = (best_move, top as isize + rng.gen_range(0, weakness as isize + 1));
+        for m in moves {
+            if self.deadline_is_reached() {
+                break;
+            }
+            let v = verify_move(&self.position, m);
+            let score = v as isize + rng.gen_range(0, weakness as isize + 1);
+            if v >= top - weakness && score > picked.1 {
+                picked = (m, score);
+            }
+        }
+        picked.0
+    }
+
+    /// Converts the time limits of a "go" command to a node limit.
+    ///
+    /// The allotted time is either the given move time, or a fixed
+    /// share of the remaining time on the clock.
+    fn deterministic_node_limit(&self, params: &GoParams) -> u64 {
+        const DEFAULT_MOVESTOGO: u64 = 30;
+        let millis = params.movetime.unwrap_or_else(|| {
+            let (t, inc) = if self.position.board().to_move == WHITE {
+                (params.wtime.unwrap_or(300_000), params.winc.unwrap_or(0))
+            } else {
+                (params.btime.unwrap_or(300_000), params.binc.unwrap_or(0))
+            };
+            let movestogo = match params.movestogo {
+                Some(0) | None => DEFAULT_MOVESTOGO,
+                Some(x) => x,
+            };
+            min(t / 2, t / movestogo + inc)
+        });
+        max(millis, 1).saturating_mul(DETERMINISTIC_NODES_PER_MILLI)
+    }
+
+    fn terminate(&mut self) {
+        if let Some(ref mut searcher) = self.searcher {
+            searcher.send_message("TERMINATE");
+        }
+        while !self.status.done {
+            self.wait_status_update(Duration::from_millis(1000));
+        }
+    }
+
+    fn wait_status_update(&mut self, duration: Duration) {
+        let mut received_report = false;
+        if self.searcher.is_none() {
+            // The engine has not been prepared yet.
+            sleep(duration);
+            return;
+        }
+        self.searcher.as_ref().unwrap().wait_report(duration);
+        while let Ok(r) = self.searcher.as_mut().unwrap().try_recv_report() {
+            received_report = true;
+            if self.is_prewarming {
+                // Nobody waits for the results of this search.
+                self.status.done = r.done;
+                self.is_prewarming = !r.done;
+                continue;
+            }
+            self.process_report(&r);
+            self.inform_time_manager(Some(&r));
+        }
+        if !received_report && !self.status.done {
+            self.inform_time_manager(None);
+        }
+    }
+
+    fn inform_time_manager(&mut self, report: Option<&SearchReport<Vec<Variation>>>) {
+        let elapsed_millis = {
+            let d = self.deadline_from.elapsed().unwrap_or(Duration::from_millis(0));
+            1000 * d.as_secs() + (d.subsec_nanos() / 1_000_000) as u64
+        };
+        if let PlayWhen::TimeManagement(ref mut tm) = self.play_when {
+            let searcher = self.searcher.as_mut().unwrap();
+            if tm.must_play(searcher, report) && !self.is_pondering {
+                self.time_is_up = true;
+            }
+            if self.time_is_up && !self.is_pondering && elapsed_millis >= self.min_think_millis {
+                searcher.send_message("TERMINATE");
+            }
+        }
+    }
+
+    fn process_report(&mut self, report: &SearchReport<Vec<Variation>>) {
+        assert!(!self.status.done);
+        assert!(report.depth >= self.status.depth);
+        assert!(report.searched_nodes >= self.status.searched_nodes);
+        let zero_millis = Duration::from_millis(0);
+        let duration_millis = {
+            let d = self.started_at.elapsed().unwrap_or(zero_millis);
+            1000 * d.as_secs() + (d.subsec_nanos() / 1_000_000) as u64
+        };
+        let completed_depth = report.depth > self.status.depth;
+        if report.details.tree.is_some() {
+            self.tree_stats = report.details.tree;
+        }
+        self.status = SearchStatus {
+            done: report.done,
+            depth: report.depth,
+            value: report.value,
+            searched_nodes: report.searched_nodes,
+            selective_depth: report.details.selective_depth,
+            duration_millis: duration_millis,
+        };
+
+        // Update `self.nps_stats` every 1000 milliseconds.
+        let elapsed_millis = duration_millis - self.nps_stats.2;
+        if elapsed_millis >= 1000 {
+            let nodes = report.searched_nodes - self.nps_stats.1;
+            self.nps_stats = (1000 * nodes / elapsed_millis, report.searched_nodes, duration_millis)
+        }
+
+        // Show the messages from the searcher.
+        for m in report.details.messages.iter() {
+            self.queue_info_string(m);
+        }
+
+        // If principal variations are provided with the report, show them.
+        if !report.data.is_empty() {
+            let best_line = report.data[0].moves.clone();
+            self.best_line = best_line;
+            if let PlayWhen::Mate(m) = self.play_when {
+                self.mate_is_found |= proves_mate(&report.data[0], m);
+            }
+            self.queue_pv(&report.data);
+            self.store_result(&report.data);
+            self.silent_since = SystemTime::now();
+        }
+
+        // If requested, show the aspiration window statistics, and the
+        // transposition table key collisions, for each completed depth.
+        if completed_depth && ::get_option("Debug Stats") == "true" {
+            if let Some(s) = report.details.aspiration {
+                self.queue_info_string(&format!("aspiration depth {} fail highs {} fail lows {} \
+                                                 re-searches {} window {} {}",
+                                                report.depth,
+                                                s.fail_highs,
+                                                s.fail_lows,
+                                                s.researches,
+                                                s.window.0,
+                                                s.window.1));
+            }
+            if let Some((probes, collisions)) = self.tt.as_ref().unwrap().key_collisions() {
+                self.queue_info_string(&format!("tt probes {} key collisions {}",
+                                                probes,
+                                                collisions));
+            }
+        }
+
+        // During long searches, show the move currently searched at
+        // the root.
+        if report.details.current_move != self.current_move {
+            self.current_move = report.details.current_move;
+            if let Some((m, n)) = report.details.current_move {
+                if duration_millis >= CURRMOVE_MIN_MILLIS && !report.done {
+                    self.queue_current_move(m, n);
+                }
+            }
+        }
+
+        // Tell why the search has ended.
+        if let Some(reason) = report.termination {
+            self.queue_info_string(&format!("search ended: {}", reason));
+            if reason == TerminationReason::Failed {
+                // The searcher may be in an inconsistent state, so it
+                // will be re-created before the next search.
+                self.must_rebuild = true;
+            }
+        }
+
+        // If nothing has happened for a while, show progress info.
+        if self.silent_since
+               .elapsed()
+               .unwrap_or(zero_millis)
+               .as_secs() > 10 {
+            self.queue_progress_info();
+            self.queue_tt_variations();
+            self.silent_since = SystemTime::now();
+        }
+    }
+}
+
+
+/// A helper function. It returns the skill level to which the
+/// strength of the engine is limited, or `None` if the engine plays
+/// at full strength.
+///
+/// When the "UCI_LimitStrength" option is on, the skill level is
+/// derived from the "UCI_Elo" option. Otherwise the "Skill Level"
+/// option is used.
+fn skill_level() -> Option<u8> {
+    let skill = if ::get_option("UCI_LimitStrength") == "true" {
+        let elo = ::get_option("UCI_Elo").parse().unwrap_or(ELO_MAX);
+        let elo = min(max(elo, ELO_MIN), ELO_MAX);
+        ((elo - ELO_MIN) * SKILL_LEVEL_MAX as i32 / (ELO_MAX - ELO_MIN)) as u8
+    } else {
+        ::get_option("Skill Level").parse().unwrap_or(SKILL_LEVEL_MAX)
+    };
+    if skill < SKILL_LEVEL_MAX {
+        Some(skill)
+    } else {
+        None
+    }
+}
+
+
+/// A helper function. It returns if a variation proves that the side
+/// to move can give mate in `n` moves or less.
+///
+/// Upper bounds prove nothing, because the mate may actually be
+/// slower than the reported value says.
+fn proves_mate(v: &Variation, n: i16) -> bool {
+    v.bound & BOUND_LOWER != 0 &&
+    mate_distance(v.value).map_or(false, |d| d > 0 && d <= n as isize)
+}
+
+
+/// A helper function. It performs a very shallow search to verify a
+/// move, and returns move's value.
+///
+/// Every possible reply is tried, followed by a quiescence search.
+/// This is enough to detect moves that leave a piece hanging.
+fn verify_move<N: SearchNode>(position: &N, m: Move) -> Value {
+    let mut p = position.clone();
+    if !p.do_move(m) {
+        return VALUE_MIN;
+    }
+    let replies = p.legal_moves();
+    if replies.is_empty() {
+        return -p.evaluate_final();
+    }
+    let mut value = VALUE_MAX;
+    for reply in replies {
+        p.do_move(reply);
+        value = min(value,
+                    p.qsearch(0, VALUE_MIN, VALUE_MAX, VALUE_UNKNOWN).value());
+        p.undo_last_move();
+    }
+    value
+}
+
+
+/// Returns the UCI notation for a score (for example, "cp 35", "mate
+/// -2", or "cp 120 lowerbound").
+fn score_notation<N: SearchNode>(value: Value, bound: BoundType) -> String {
+    fn suffix(bound: BoundType) -> &'static str {
+        match bound {
+            BOUND_UPPER => " upperbound",
+            BOUND_LOWER => " lowerbound",
+            BOUND_EXACT => "",
+            _ => panic!("unexpected bound type"),
+        }
+    }
+
+    match mate_distance(value) {
+        Some(n) => format!("mate {}{}", n, suffix(bound)),
+        None => {
+            match N::Evaluator::to_centipawns(value) {
+                cp if cp <= -CENTIPAWNS_MAX => {
+                    format!("cp {}{}", -CENTIPAWNS_MAX, suffix(bound | BOUND_LOWER))
+                }
+                cp if cp >= CENTIPAWNS_MAX => {
+                    format!("cp {}{}", CENTIPAWNS_MAX, suffix(bound | BOUND_UPPER))
+                }
+                cp => format!("cp {}{}", cp, suffix(bound)),
+            }
+        }
+    }
+}
+
+
+/// A helper function. It converts a sequence of moves, some of which
+/// may be in Standard Algebraic Notation (SAN), to long algebraic
+/// notation.
+///
+/// Returns `None` if some of the moves is illegal or ambiguous.
+fn convert_san_moves<N: SearchNode>(fen: &str, moves: &[String]) -> Option<Vec<String>> {
+    let mut position = match Board::from_fen(fen).ok().map(N::MoveGenerator::from_board) {
+        Some(Ok(p)) => p,
+        _ => return None,
+    };
+    let mut converted_moves: Vec<String> = Vec::with_capacity(moves.len());
+    let mut s = MoveStack::new();
+    for m in moves {
+        s.clear();
+        position.generate_all(&mut s);
+        let mut legal_moves = s.list().to_vec();
+        legal_moves.retain(|&x| if position.do_move(x).is_some() {
+                               position.undo_move(x);
+                               true
+                           } else {
+                               false
+                           });
+        let played = match legal_moves.iter().find(|x| x.notation() == *m) {
+            Some(&x) => x,
+            None => {
+                match parse_san(m, &legal_moves) {
+                    Ok(x) => x,
+                    Err(_) => return None,
+                }
+            }
+        };
+        position.do_move(played);
+        converted_moves.push(played.notation());
+    }
+    Some(converted_moves)
+}
+
+
+/// Verifies that a sequence of moves in long algebraic notation can
+/// be legally played from a given starting position.
+///
+/// Each move is checked against the legal moves in the position, and
+/// then checked again against the move that `try_move_digest`
+/// produces for its digest. Returns a description of the first
+/// discrepancy found.
+fn verify_history<N: SearchNode>(fen: &str, moves: &[String]) -> Result<(), String> {
+    for (i, m) in moves.iter().enumerate() {
+        let p = match N::from_history(fen, &mut moves[..i].iter().map(|x| x.as_str())) {
+            Ok(p) => p,
+            Err(_) if i == 0 => return Err(format!("illegal FEN \"{}\"", fen)),
+            Err(_) => return Err(format!("can not replay the moves before move {}", i + 1)),
+        };
+        let fen = format_fen(p.board(), p.halfmove_clock(), p.fullmove_number());
+        let legal_move = match p.legal_moves().into_iter().find(|x| x.notation() == *m) {
+            Some(x) => x,
+            None => return Err(format!("move {} ({}) is illegal in \"{}\"", i + 1, m, fen)),
+        };
+        if p.try_move_digest(legal_move.digest()) != Some(legal_move) {
+            return Err(format!("move {} ({}) is not recognized from its digest in \"{}\"",
+                               i + 1,
+                               m,
+                               fen));
+        }
+    }
+    if moves.is_empty() && N::from_history(fen, &mut vec![].into_iter()).is_err() {
+        return Err(format!("illegal FEN \"{}\"", fen));
+    }
+    Ok(())
+}
+
+
+/// Runs a UCI protocol server.
+///
+/// "Universal Chess Interface" (UCI) is an open protocol for chess
+/// engines to communicate with other programs including Graphical
+/// User Interfaces (GUI). The protocol is independent of the
+/// operating system. For "Windows", the engine is a normal "exe"
+/// file, either a console or "real" windows application. All
+/// communication is done via standard input and output with text
+/// commands.
+///
+/// If the first command received is "xboard", the engine will speak
+/// the older "Chess Engine Communication Protocol" (CECP) instead.
+///
+/// # Parameters:
+///
+/// * `name` gives the name of the engine.
+///
+/// * `author` gives the name of the author.
+///
+/// * `options` is a vector of (name, value) pairs that override the
+///   default configuration options.
+//
+/// # Type parameters:
+///
+/// * `S` implements game tree searching with iterative deepening. If
+///   principal variations are included in the progress reports from
+///   the search, they will be forwarded to the GUI, and eventually
+///   used to determine the best move.
+///
+///   **Note:** Normally, principal variations (PV) should be sent
+///   only when a new search depth is reached, and possibly when a new
+///   best move is found. Therefore, the majority of the progress
+///   reports will carry an empty `Vec<Variation>` instance. In
+///   multi-PV mode the first slot of the vector is for the best
+///   variation, the second slot is for the second-best variation, and
+///   so forth.
+///
+/// * `T` is responsible for managing engine's thinking time.
+///
+/// # Command line:
+///
+/// Normally, the engine expects no command line arguments. The
+/// exceptions are:
+///
+/// * `perft [FEN] DEPTH` counts the leaf nodes of the move generation
+///   tree of depth `DEPTH` for each legal move in the given position
+///   (the starting position by default), prints the results, and
+///   exits. This can be used to validate the move generator against
+///   known perft values.
+///
+/// * `fuzz [GAMES] [SEED]` plays `GAMES` random games (100 by
+///   default) from each position of the "bench" suite, verifies the
+///   move generator against a reference move generator in every
+///   encountered position (see `fuzz_move_generator`), and exits.
+///   The games are a function of `SEED` (random by default), which
+///   is printed so that failures can be reproduced. Available only
+///   with the "movegen-reference" feature.
+///
+/// * `bench [DEPTH]` searches a fixed suite of positions to a fixed
+///   depth, prints the total number of searched nodes and the speed,
+///   and exits. The node count can be used as a signature of the
+///   search and the evaluation.
+///
+/// * `epd FILE [SECONDS]` searches each position from an EPD test
+///   suite (like "Win At Chess") for `SECONDS` seconds (5 by
+///   default), prints whether the best move found matches the `bm`
+///   and `am` operations of the record, and exits. The number of
+///   solved positions can be used to measure engine's strength.
+///
+/// * `analyze FILE [DEPTH] [FORMAT]` searches each position from
+///   `FILE` (one FEN per line) to depth `DEPTH` (8 by default),
+///   prints the FEN, the best move, the score, the completed depth,
+///   and the principal variation for each position, and exits.
+///   `FORMAT` can be `csv` (the default) or `json` (one object per
+///   line). This is useful for generating training data sets.
+///
+/// * `selfplay [GAMES] [MILLIS] [PGN_FILE]` plays `GAMES` games (10
+///   by default) of the engine against itself, searching each move
+///   for `MILLIS` milliseconds (100 by default), prints the result of
+///   each game and the totals, and exits. The games start from the
+///   positions of the "bench" suite in turn, and are adjudicated by
+///   checkmate, stalemate, threefold repetition, the fifty-move
+///   rule, insufficient material, or as draws after 400
+///   half-moves. If `PGN_FILE` is given, the games are appended to
+///   it in PGN. This is useful for regression-testing evaluation
+///   changes.
+///
+/// * `tune FILE OUTPUT [PASSES]` reads positions with known game
+///   results from `FILE` (see `TuningRecord::parse`), tunes the
+///   piece-square tables so that the static evaluation predicts the
+///   results better (see `tune_psts`), and exits. At most `PASSES`
+///   passes (50 by default) are done. After each pass the tuned
+///   tables are written to `OUTPUT`, so that they can be loaded with
+///   the "PST File" option.
+pub fn run_uci<S, T>(name: &'static str,
+                     author: &'static str,
+                     options: Vec<(&'static str, &'static str)>)
+                     -> !
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    EngineBuilder::<S, T>::new(name, author).options(options).run()
+}
+
+
+/// Runs a UCI protocol server over the given reader and writer.
+///
+/// This is the same as `run_uci`, but instead of the standard input
+/// and output, UCI commands are read from `reader`, and replies are
+/// written to `writer`. Also, instead of exiting the process, this
+/// function returns when the UCI session is closed. This makes it
+/// possible to run a complete engine in-process -- for example, in
+/// integration tests.
+///
+/// Returns `Err` if the handshake was unsuccessful, or if an IO
+/// error occurred.
+///
+/// # Panics
+///
+/// Panics if another engine is already running.
+pub fn run_uci_with_io<S, T, R, W>(name: &'static str,
+                                   author: &'static str,
+                                   options: Vec<(&'static str, &'static str)>,
+                                   reader: R,
+                                   writer: W)
+                                   -> io::Result<()>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>,
+          R: BufRead + Send + 'static,
+          W: Write
+{
+    EngineBuilder::<S, T>::new(name, author).options(options).run_with_io(reader, writer)
+}
+
+
+/// Returns a builder for the default engine.
+///
+/// The default engine is assembled from the "in stock"
+/// implementations of the framework traits (see
+/// `stock::DefaultSearchExecutor`). Individual components can be
+/// swapped by calling the builder's methods.
+///
+/// # Examples:
+///
+/// ```rust,no_run
+/// use alcibiades::engine::engine_default;
+///
+/// engine_default("My engine", "John Doe").hash_size(64).run();
+/// ```
+pub fn engine_default(name: &'static str,
+                      author: &'static str)
+                      -> EngineBuilder<DefaultSearchExecutor, StdTimeManager> {
+    EngineBuilder::new(name, author)
+}
+
+
+/// A builder for UCI chess engines.
+///
+/// `S` is the iterative deepening search, and `T` is the time
+/// manager that the engine will use (see `run_uci`). The type
+/// parameters can be changed with the `search_executor`, `evaluator`,
+/// and `time_manager` methods, so that the engine can be constructed
+/// in one line, without spelling out the whole type of the search
+/// executor.
+pub struct EngineBuilder<S, T> {
+    info: EngineInfo,
+    phantom: PhantomData<fn() -> (S, T)>,
+}
+
+impl<S, T> EngineBuilder<S, T> {
+    /// Creates a new instance.
+    ///
+    /// `name` gives the name of the engine, and `author` gives the
+    /// name of the author.
+    pub fn new(name: &'static str, author: &'static str) -> EngineBuilder<S, T> {
+        EngineBuilder {
+            info: EngineInfo {
+                name: name,
+                author: author,
+                options: vec![],
+                hash_size_mb: None,
+            },
+            phantom: PhantomData,
+        }
+    }
+
+    /// Overrides the default value of a configuration option.
+    pub fn option(mut self, name: &'static str, value: &'static str) -> EngineBuilder<S, T> {
+        self.info.options.push((name, value));
+        self
+    }
+
+    /// Overrides the default values of several configuration options.
+    pub fn options(mut self, options: Vec<(&'static str, &'static str)>) -> EngineBuilder<S, T> {
+        self.info.options.extend(options);
+        self
+    }
+
+    /// Sets the default size of the transposition table in Mbytes.
+    pub fn hash_size(mut self, size_mb: usize) -> EngineBuilder<S, T> {
+        self.info.hash_size_mb = Some(min(max(size_mb, HASH_SIZE_MIN), HASH_SIZE_MAX));
+        self
+    }
+
+    /// Changes the iterative deepening search.
+    pub fn search_executor<U>(self) -> EngineBuilder<U, T> {
+        EngineBuilder {
+            info: self.info,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Changes the search executor to the default one, working with
+    /// the given static evaluator.
+    pub fn evaluator<E: Evaluator>(self) -> EngineBuilder<DefaultSearchExecutor<E>, T> {
+        self.search_executor()
+    }
+
+    /// Changes the time manager.
+    pub fn time_manager<U>(self) -> EngineBuilder<S, U> {
+        EngineBuilder {
+            info: self.info,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S, T> EngineBuilder<S, T>
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    /// Runs a UCI protocol server (see `run_uci`).
+    ///
+    /// This function never returns. It terminates the process with a
+    /// non-zero exit code on failure.
+    pub fn run(self) -> ! {
+        let args: Vec<String> = env::args().skip(1).collect();
+        match args.first().map(|a| a.as_str()) {
+            Some("perft") => {
+                let args = &args[1..];
+                process::exit(run_perft::<<S::SearchNode as SearchNode>::MoveGenerator>(args));
+            }
+            #[cfg(feature = "movegen-reference")]
+            Some("fuzz") => {
+                let args = &args[1..];
+                process::exit(run_fuzz::<<S::SearchNode as SearchNode>::MoveGenerator>(args));
+            }
+            Some("bench") => process::exit(run_bench::<S, T>(self.info, &args[1..])),
+            Some("epd") => process::exit(run_epd::<S, T>(self.info, &args[1..])),
+            Some("analyze") => process::exit(run_analyze::<S, T>(self.info, &args[1..])),
+            Some("selfplay") => process::exit(run_selfplay::<S, T>(self.info, &args[1..])),
+            Some("tune") => process::exit(run_tune::<S, T>(self.info, &args[1..])),
+            _ => (),
+        }
+        process::exit(match self.run_with_io(BufReader::new(io::stdin()), io::stdout()) {
+                          Ok(_) => 0,
+                          Err(_) => 1,
+                      });
+    }
+
+    /// Runs a UCI protocol server over the given reader and writer
+    /// (see `run_uci_with_io`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if another engine is already running.
+    pub fn run_with_io<R, W>(self, reader: R, writer: W) -> io::Result<()>
+        where R: BufRead + Send + 'static,
+              W: Write
+    {
+        register_engine(self.info);
+        let result = run_engine_with_io::<Engine<S, T>, R, W>(reader, writer);
+        *ENGINE.lock().unwrap() = None;
+        result
+    }
+}
+
+
+/// Ensures that the engine is not already running, and registers it.
+fn register_engine(info: EngineInfo) {
+    let mut engine = ENGINE.lock().unwrap();
+    assert!(engine.is_none(), "two engines can not run in parallel");
+    *engine = Some(info);
+}
+
+
+/// Runs the "perft" command line command, and returns the exit code.
+fn run_perft<T: MoveGenerator>(args: &[String]) -> i32 {
+    const STARTPOS: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let (fen, depth) = match args.split_last() {
+        Some((depth, fen)) if fen.is_empty() => (STARTPOS.to_string(), depth.as_str()),
+        Some((depth, fen)) => (fen.join(" "), depth.as_str()),
+        None => (String::new(), ""),
+    };
+    let position = Board::from_fen(&fen).ok().and_then(|b| T::from_board(b).ok());
+    match (position, depth.parse::<Depth>()) {
+        (Some(mut position), Ok(depth)) if depth >= 1 && depth < DEPTH_MAX => {
+            let started_at = SystemTime::now();
+            let mut counts: Vec<(String, u64)> = divide(&mut position, depth)
+                .into_iter()
+                .map(|(m, n)| (m.notation(), n))
+                .collect();
+            counts.sort();
+            for &(ref m, n) in counts.iter() {
+                println!("{}: {}", m, n);
+            }
+            let nodes: u64 = counts.iter().map(|&(_, n)| n).sum();
+            let elapsed = started_at.elapsed().unwrap_or(Duration::from_millis(0));
+            let millis = 1000 * elapsed.as_secs() + (elapsed.subsec_nanos() / 1_000_000) as u64;
+            println!("");
+            println!("Nodes searched: {}", nodes);
+            println!("Time: {} ms ({} nps)", millis, 1000 * nodes / max(millis, 1));
+            0
+        }
+        _ => {
+            let _ = writeln!(io::stderr(), "usage: perft [FEN] DEPTH");
+            2
+        }
+    }
+}
+
+
+/// Runs the "fuzz" command line command, and returns the exit code.
+#[cfg(feature = "movegen-reference")]
+fn run_fuzz<T: MoveGenerator>(args: &[String]) -> i32 {
+    let games = args.first().map_or(Ok(FUZZ_GAMES), |s| s.parse::<usize>());
+    let seed = args.get(1).map_or(Ok(thread_rng().gen()), |s| s.parse::<u64>());
+    let (games, seed) = match (games, seed) {
+        (Ok(games), Ok(seed)) if games >= 1 => (games, seed),
+        _ => {
+            let _ = writeln!(io::stderr(), "usage: fuzz [GAMES] [SEED]");
+            return 2;
+        }
+    };
+    println!("Seed: {}", seed);
+    let mut count = 0;
+    for fen in BENCH_POSITIONS.iter() {
+        let position = Board::from_fen(fen).ok().and_then(|b| T::from_board(b).ok()).unwrap();
+        match fuzz_move_generator(&position, games, FUZZ_PLIES, seed) {
+            Ok(n) => count += n,
+            Err(e) => {
+                println!("{}", e);
+                return 1;
+            }
+        }
+    }
+    println!("Verified positions: {}", count);
+    0
+}
+
+
+/// Runs the "bench" command line command, and returns the exit code.
+fn run_bench<S, T>(info: EngineInfo, args: &[String]) -> i32
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    match args.first().map_or(Ok(BENCH_DEPTH), |d| d.parse::<Depth>()) {
+        Ok(depth) if depth >= 1 && depth < DEPTH_MAX => {
+            register_engine(info);
+            Engine::<S, T>::options();
+            let mut engine = Engine::<S, T>::new(None);
+            let (nodes, millis) = engine.bench(depth);
+            engine.exit();
+            *ENGINE.lock().unwrap() = None;
+            println!("Nodes searched: {}", nodes);
+            println!("Time: {} ms ({} nps)", millis, 1000 * nodes / max(millis, 1));
+            0
+        }
+        _ => {
+            let _ = writeln!(io::stderr(), "usage: bench [DEPTH]");
+            2
+        }
+    }
+}
+
+
+/// Runs the "epd" command line command, and returns the exit code.
+fn run_epd<S, T>(info: EngineInfo, args: &[String]) -> i32
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    let seconds = args.get(1).map_or(Ok(EPD_SECONDS), |s| s.parse::<u64>());
+    let file = args.first().and_then(|f| File::open(f).ok());
+    let (file, seconds) = match (file, seconds) {
+        (Some(file), Ok(seconds)) if seconds >= 1 => (file, seconds),
+        _ => {
+            let _ = writeln!(io::stderr(), "usage: epd FILE [SECONDS]");
+            return 2;
+        }
+    };
+    register_engine(info);
+    Engine::<S, T>::options();
+    let mut engine = Engine::<S, T>::new(None);
+    let (mut solved, mut total) = (0, 0);
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let id = format!("line {}", n + 1);
+        let record = parse_epd(&line).ok();
+        let position = record
+            .as_ref()
+            .and_then(|r| S::SearchNode::from_history(&r.fen, &mut vec![].into_iter()).ok());
+        let (record, position) = match (record, position) {
+            (Some(r), Some(p)) => (r, p),
+            _ => {
+                println!("{}: illegal EPD record", id);
+                continue;
+            }
+        };
+        let id = record.id().map_or(id, |x| x.to_string());
+        let legal_moves = position.legal_moves();
+        let best_moves = record.best_moves(&legal_moves);
+        let avoid_moves = record.avoid_moves(&legal_moves);
+        if best_moves.is_empty() && avoid_moves.is_empty() {
+            println!("{}: no best moves or moves to avoid", id);
+            continue;
+        }
+        total += 1;
+        let (best_move, _) = engine.solve(position.clone(), 1000 * seconds);
+        let is_solved = best_move.map_or(false, |m| {
+            (best_moves.is_empty() || best_moves.contains(&m)) && !avoid_moves.contains(&m)
+        });
+        if is_solved {
+            solved += 1;
+        }
+        let mut expected = vec![];
+        for &(opcode, ref moves) in &[("bm", best_moves), ("am", avoid_moves)] {
+            if !moves.is_empty() {
+                let moves: Vec<String> = moves.iter().map(|&m| format_san(&position, m)).collect();
+                expected.push(format!("{} {}", opcode, moves.join(" ")));
+            }
+        }
+        println!("{}: {} {} ({})",
+                 id,
+                 best_move.map_or("-".to_string(), |m| format_san(&position, m)),
+                 if is_solved { "ok" } else { "FAILED" },
+                 expected.join(", "));
+    }
+    engine.exit();
+    *ENGINE.lock().unwrap() = None;
+    println!("");
+    println!("Solved: {} of {}", solved, total);
+    0
+}
+
+
+/// Runs the "selfplay" command line command, and returns the exit
+/// code.
+fn run_selfplay<S, T>(info: EngineInfo, args: &[String]) -> i32
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    let games = args.first().map_or(Ok(SELFPLAY_GAMES), |s| s.parse::<usize>());
+    let millis = args.get(1).map_or(Ok(SELFPLAY_MILLIS), |s| s.parse::<u64>());
+    let pgn_file = args.get(2).map(|f| OpenOptions::new().create(true).append(true).open(f));
+    let (games, millis, mut pgn_file) = match (games, millis, pgn_file) {
+        (Ok(games), Ok(millis), None) if games >= 1 && millis >= 1 => (games, millis, None),
+        (Ok(games), Ok(millis), Some(Ok(f))) if games >= 1 && millis >= 1 => {
+            (games, millis, Some(f))
+        }
+        _ => {
+            let _ = writeln!(io::stderr(), "usage: selfplay [GAMES] [MILLIS] [PGN_FILE]");
+            return 2;
+        }
+    };
+    register_engine(info);
+    Engine::<S, T>::options();
+    let mut engine = Engine::<S, T>::new(None);
+    let (mut white_wins, mut black_wins, mut draws) = (0, 0, 0);
+    for n in 0..games {
+        let fen = BENCH_POSITIONS[n % BENCH_POSITIONS.len()];
+        let (record, reason) = engine.play_game(fen, millis);
+        match record.result {
+            GameResult::WhiteWins => white_wins += 1,
+            GameResult::BlackWins => black_wins += 1,
+            _ => draws += 1,
+        }
+        println!("Game {}: {} ({}, {} half-moves)",
+                 n + 1,
+                 record.result.notation(),
+                 reason,
+                 record.moves.len());
+        if let Some(ref mut f) = pgn_file {
+            if let Err(e) = record
+                   .to_pgn::<S::SearchNode>()
+                   .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "illegal game"))
+                   .and_then(|pgn| writeln!(f, "{}", pgn)) {
+                let _ = writeln!(io::stderr(), "can not write game {}: {}", n + 1, e);
+            }
+        }
+    }
+    engine.exit();
+    *ENGINE.lock().unwrap() = None;
+    println!("");
+    println!("White wins: {}, black wins: {}, draws: {}", white_wins, black_wins, draws);
+    0
+}
+
+
+/// Runs the "analyze" command line command, and returns the exit code.
+fn run_analyze<S, T>(info: EngineInfo, args: &[String]) -> i32
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    let depth = args.get(1).map_or(Ok(ANALYZE_DEPTH), |s| s.parse::<Depth>());
+    let json = match args.get(2).map(|s| s.as_str()) {
+        None | Some("csv") => Some(false),
+        Some("json") => Some(true),
+        _ => None,
+    };
+    let file = args.first().and_then(|f| File::open(f).ok());
+    let (file, depth, json) = match (file, depth, json) {
+        (Some(file), Ok(depth), Some(json)) if depth >= 1 && depth < DEPTH_MAX => {
+            (file, depth, json)
+        }
+        _ => {
+            let _ = writeln!(io::stderr(), "usage: analyze FILE [DEPTH] [csv|json]");
+            return 2;
+        }
+    };
+    register_engine(info);
+    Engine::<S, T>::options();
+    let mut engine = Engine::<S, T>::new(None);
+    if !json {
+        println!("fen,best_move,score,depth,pv");
+    }
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let fen = line.trim();
+        if fen.is_empty() || fen.starts_with('#') {
+            continue;
+        }
+        let position = match S::SearchNode::from_history(fen, &mut vec![].into_iter()) {
+            Ok(p) => p,
+            Err(_) => {
+                let _ = writeln!(io::stderr(), "line {}: illegal position", n + 1);
+                continue;
+            }
+        };
+        let (best_move, score, depth, pv) = match engine.analyze(position.clone(), depth) {
+            Some((depth, v)) => {
+                (v.moves.first().map_or(String::new(), |m| m.notation()),
+                 score_notation::<S::SearchNode>(v.value, v.bound),
+                 depth,
+                 v.moves.iter().map(|m| m.notation()).collect())
+            }
+            None => {
+                let value = position.evaluate_final();
+                (String::new(), score_notation::<S::SearchNode>(value, BOUND_EXACT), 0, vec![])
+            }
+        };
+        if json {
+            let pv: Vec<String> = pv.iter().map(|m| json_string(m)).collect();
+            println!("{{\"fen\":{},\"best_move\":{},\"score\":{},\"depth\":{},\"pv\":[{}]}}",
+                     json_string(fen),
+                     json_string(&best_move),
+                     json_string(&score),
+                     depth,
+                     pv.join(","));
+        } else {
+            println!("{},{},{},{},{}", fen, best_move, score, depth, pv.join(" "));
+        }
+    }
+    engine.exit();
+    *ENGINE.lock().unwrap() = None;
+    0
+}
+
+
+/// Runs the "tune" command line command, and returns the exit code.
+fn run_tune<S, T>(info: EngineInfo, args: &[String]) -> i32
+    where S: DeepeningSearch<ReportData = Vec<Variation>>,
+          T: TimeManager<S>
+{
+    type E<N> = <N as SearchNode>::Evaluator;
+    let passes = args.get(2).map_or(Ok(TUNE_PASSES), |s| s.parse::<usize>());
+    let file = args.first().and_then(|f| File::open(f).ok());
+    let (file, output, passes) = match (file, args.get(1), passes) {
+        (Some(file), Some(output), Ok(passes)) if passes >= 1 => (file, output, passes),
+        _ => {
+            let _ = writeln!(io::stderr(), "usage: tune FILE OUTPUT [PASSES]");
+            return 2;
+        }
+    };
+    let mut records = vec![];
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match TuningRecord::parse(&line) {
+            Some(r) => records.push(r),
+            None => println!("line {}: illegal record", n + 1),
+        }
+    }
+    if records.is_empty() {
+        let _ = writeln!(io::stderr(), "no records found");
+        return 1;
+    }
+
+    // Apply the configured options (the "PST File" option, for
+    // example) before tuning.
+    register_engine(info);
+    Engine::<S, T>::options();
+    let mut engine = Engine::<S, T>::new(None);
+    let k = find_scaling_constant::<E<S::SearchNode>>(&records);
+    println!("Records: {}, scaling constant: {:.3}, error: {:.6}",
+             records.len(),
+             k,
+             tuning_error::<E<S::SearchNode>>(&records, k));
+    let mut write_error = None;
+    tune_psts::<E<S::SearchNode>, _>(&records, k, passes, |pass, error, psts| {
+        println!("Pass {}: error {:.6}", pass, error);
+        let result = File::create(output).and_then(|mut f| f.write_all(psts.to_text().as_bytes()));
+        write_error = result.err();
+        write_error.is_none()
+    });
+    engine.exit();
+    *ENGINE.lock().unwrap() = None;
+    if let Some(e) = write_error {
+        let _ = writeln!(io::stderr(), "can not write {}: {}", output, e);
+        return 1;
+    }
+    println!("");
+    println!("Tuned tables written to {}", output);
+    0
+}
+
+
+/// A helper function for `Engine::play_game`. It plays a game from a
+/// given position, choosing the moves with `choose_move`.
+///
+/// Returns the record of the game, and the reason for its result.
+///
+/// Before each move the search node is rebuilt from the played moves
+/// with `from_history`. (A search node on which a repetition, or a
+/// rule-50 draw has been played has no legal moves, so it can not be
+/// simply advanced with `do_move`.)
+fn play_game<N, F>(fen: &str, mut choose_move: F) -> (GameRecord, &'static str)
+    where N: SearchNode,
+          F: FnMut(&N) -> Move
+{
+    let mut record = GameRecord::new(fen);
+    let mut moves: Vec<String> = vec![];
+    let mut halfmove_clock = None;
+    loop {
+        let position = N::from_history(fen, &mut moves.iter().map(|s| s.as_str()))
+            .ok()
+            .expect("illegal self-play position");
+        let clock = *halfmove_clock.get_or_insert(position.halfmove_clock() as usize);
+        if let Some((result, reason)) = adjudicate(&position, clock) {
+            record.result = result;
+            return (record, reason);
+        }
+        if record.moves.len() >= SELFPLAY_MAX_PLIES {
+            record.result = GameResult::Draw;
+            return (record, "too many moves");
+        }
+        let m = choose_move(&position);
+        record.push(m.digest(), VALUE_UNKNOWN);
+        moves.push(m.notation());
+        halfmove_clock = Some(if m.is_pawn_advance_or_capure() {
+                                  0
+                              } else {
+                                  clock + 1
+                              });
+    }
+}
+
+
+/// A helper function for `play_game`. It returns the result of a
+/// game, and the reason for it, if the game is over in the given
+/// position.
+///
+/// `halfmove_clock` is passed separately, because search nodes do
+/// not count beyond 99 half-moves.
+fn adjudicate<N: SearchNode>(position: &N,
+                             halfmove_clock: usize)
+                             -> Option<(GameResult, &'static str)> {
+    let no_legal_moves = position.legal_moves().is_empty();
+    if no_legal_moves && position.is_check() {
+        return Some(if position.board().to_move == WHITE {
+                        (GameResult::BlackWins, "checkmate")
+                    } else {
+                        (GameResult::WhiteWins, "checkmate")
+                    });
+    }
+    if halfmove_clock >= 100 {
+        return Some((GameResult::Draw, "fifty-move rule"));
+    }
+    if position.is_insufficient_material() {
+        return Some((GameResult::Draw, "insufficient material"));
+    }
+    let hashes = position.history_hashes();
+    if let Some(last) = hashes.last() {
+        if hashes.iter().filter(|&h| h == last).count() >= 3 {
+            return Some((GameResult::Draw, "threefold repetition"));
+        }
+    }
+    if no_legal_moves {
+        return Some((GameResult::Draw, "stalemate"));
+    }
+    None
+}
+
+
+struct EngineInfo {
+    name: &'static str,
+    author: &'static str,
+    options: Vec<(&'static str, &'static str)>,
+    hash_size_mb: Option<usize>,
+}
+
+
+lazy_static! {
+    static ref ENGINE: Mutex<Option<EngineInfo>> = Mutex::new(None);
+    static ref CHANGED_DEFAULTS: RwLock<Vec<(&'static str, &'static str)>> = RwLock::new(vec![]);
+}
+
+
+impl OptionDescription {
+    /// Returns the default value of the option, as it would be sent
+    /// with a "setoption" command.
+    pub fn get_default(&self) -> String {
+        match *self {
+            OptionDescription::Check { default: true } => "true".to_string(),
+            OptionDescription::Check { default: false } => "false".to_string(),
+            OptionDescription::Spin { default: ref v, .. } => format!("{}", v),
+            OptionDescription::Combo { default: ref v, .. } => v.clone(),
+            OptionDescription::String { default: ref v, .. } => v.clone(),
+            OptionDescription::Button => "".to_string(),
+        }
+    }
+
+    fn set_default(&mut self, value: &str) {
+        match *self {
+            OptionDescription::Check { default: ref mut v } => {
+                *v = match value.to_lowercase().as_str() {
+                    "true" => true,
+                    "false" => false,
+                    _ => *v,
+                }
+            }
+            OptionDescription::Spin { default: ref mut v, .. } => {
+                *v = value.parse::<i32>().unwrap_or(*v)
+            }
+            OptionDescription::Combo { default: ref mut v, .. } => *v = value.to_string(),
+            OptionDescription::String { default: ref mut v, .. } => *v = value.to_string(),
+            OptionDescription::Button => (),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use search_node::SearchNode;
+    use utils::parse_san;
+    use stock::{StdSearchNode, StdQsearch, StdMoveGenerator, SimpleEvaluator};
+    type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+
+    #[test]
+    fn verify_move() {
+        use super::verify_move;
+        let p = P::from_history("4k3/8/2p5/3p4/8/8/3Q4/4K3 w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let moves = p.legal_moves();
+        let blunder = verify_move(&p, parse_san("Qxd5", &moves).ok().unwrap());
+        let safe = verify_move(&p, parse_san("Ke2", &moves).ok().unwrap());
+        assert!(blunder < safe - 500);
+    }
+
+    #[test]
+    fn verify_history() {
+        use super::verify_history;
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let moves = |s: &str| -> Vec<String> { s.split_whitespace().map(String::from).collect() };
+        assert!(verify_history::<P>(fen, &moves("")).is_ok());
+        assert!(verify_history::<P>(fen, &moves("e2e4 e7e5 g1f3")).is_ok());
+        assert!(verify_history::<P>("8/8/8/8/8/8/8/8 w - - 0 1", &moves("")).is_err());
+        let e = verify_history::<P>(fen, &moves("e2e4 e7e5 e1g1")).err().unwrap();
+        assert!(e.starts_with("move 3 (e1g1) is illegal"), "{}", e);
+        assert!(e.contains("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"));
+    }
+
+    #[test]
+    fn convert_san_moves() {
+        use super::convert_san_moves;
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let moves = |s: &str| -> Vec<String> { s.split_whitespace().map(String::from).collect() };
+        assert_eq!(convert_san_moves::<P>(fen, &moves("e4 e7e5 Nf3!? Nc6")),
+                   Some(moves("e2e4 e7e5 g1f3 b8c6")));
+        assert_eq!(convert_san_moves::<P>(fen, &moves("Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1 Ng8 e4")),
+                   Some(moves("g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1 f6g8 e2e4")));
+        assert_eq!(convert_san_moves::<P>(fen, &moves("e4 e5 Ke3")), None);
+        assert_eq!(convert_san_moves::<P>("8/8/8/8/8/8/8/8 w - - 0 1", &moves("e4")), None);
+    }
+
+    #[test]
+    fn score_notation() {
+        use super::score_notation;
+        use value::*;
+        use ttable::*;
+        assert_eq!(score_notation::<P>(0, BOUND_EXACT), "cp 0");
+        assert_eq!(score_notation::<P>(VALUE_MAX - 1, BOUND_EXACT), "mate 1");
+        assert_eq!(score_notation::<P>(-VALUE_MAX + 2, BOUND_LOWER), "mate -1 lowerbound");
+    }
+
+    #[test]
+    fn adjudicate() {
+        use super::adjudicate;
+        use utils::GameResult;
+        let position = |fen: &str, moves: &str| {
+            P::from_history(fen, &mut moves.split_whitespace()).ok().unwrap()
+        };
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(adjudicate(&position(fen, ""), 0).is_none());
+        assert_eq!(adjudicate(&position(fen, "f2f3 e7e5 g2g4 d8h4"), 0),
+                   Some((GameResult::BlackWins, "checkmate")));
+        assert_eq!(adjudicate(&position("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", ""), 0),
+                   Some((GameResult::Draw, "stalemate")));
+        let p = position("7k/8/6K1/8/8/8/8/R7 w - - 99 80", "a1a2");
+        assert_eq!(adjudicate(&p, 99), None);
+        assert_eq!(adjudicate(&p, 100), Some((GameResult::Draw, "fifty-move rule")));
+        assert_eq!(adjudicate(&position("7k/8/6K1/8/8/8/8/7N w - - 0 1", ""), 0),
+                   Some((GameResult::Draw, "insufficient material")));
+        let p = position(fen, "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1");
+        assert!(adjudicate(&p, 7).is_none());
+        let p = position(fen, "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(adjudicate(&p, 8), Some((GameResult::Draw, "threefold repetition")));
+    }
+
+    #[test]
+    fn play_game() {
+        use super::play_game;
+        use utils::GameResult;
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut moves = "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1 f6g8 g1f3".split_whitespace();
+        let (record, reason) = play_game::<P, _>(fen, |position| {
+            let notation = moves.next().unwrap();
+            position.legal_moves().into_iter().find(|m| m.notation() == notation).unwrap()
+        });
+        assert_eq!(reason, "threefold repetition");
+        assert_eq!(record.result, GameResult::Draw);
+        assert_eq!(record.moves.len(), 8);
+
+        // The fifty-move rule.
+        let fen = "7k/8/6K1/8/8/8/8/R7 w - - 96 80";
+        let mut moves = "a1a2 h8g8 a2a1 g8h8 a1a2".split_whitespace();
+        let (_, reason) = play_game::<P, _>(fen, |position| {
+            let notation = moves.next().unwrap();
+            position.legal_moves().into_iter().find(|m| m.notation() == notation).unwrap()
+        });
+        assert_eq!(reason, "fifty-move rule");
+    }
+}
@@ -1,9 +1,11 @@
 //! Defines the `Evaluator` trait.
 
-use uci::SetOption;
-use board::Board;
-use moves::Move;
+use std::ops::{Add, Sub, Neg, AddAssign, SubAssign};
+use uci::{SetOption, OptionDescription};
+use board::*;
+use moves::*;
 use value::*;
+use bitsets::*;
 
 
 /// A trait used to statically evaluate positions.
@@ -60,6 +62,54 @@ pub trait Evaluator: Clone + SetOption + Send + 'static {
     /// whether it is safe to try a null move.
     fn is_zugzwangy(&self, position: &Board) -> bool;
 
+    /// Returns a breakdown of the evaluation of the position to which
+    /// the evaluator is currently bound.
+    ///
+    /// `position` points to the position to which the evaluator is
+    /// currently bound.
+    ///
+    /// This is useful for debugging -- it shows why the evaluator
+    /// likes (or dislikes) a position. The sum of `white - black` for
+    /// all returned terms should be (approximately) equal to the
+    /// value returned by `evaluate`, from white's point of view. The
+    /// default implementation returns a single term that gives the
+    /// whole evaluation to white.
+    fn evaluate_explained(&self, position: &Board) -> Vec<EvalTerm> {
+        let v = self.evaluate(position);
+        vec![EvalTerm {
+                 name: "Evaluation",
+                 white: if position.to_move == WHITE { v } else { -v },
+                 black: 0,
+             }]
+    }
+
+    /// Converts an evaluation value to centipawns.
+    ///
+    /// `value` is between `VALUE_EVAL_MIN` and `VALUE_EVAL_MAX`. The
+    /// search works with the values returned by `evaluate`, but the
+    /// GUI expects to receive centipawns. Evaluators that use a
+    /// different internal scale must override this method. The
+    /// default implementation returns `value` unchanged.
+    #[inline]
+    fn to_centipawns(value: Value) -> i32
+        where Self: Sized
+    {
+        value as i32
+    }
+
+    /// Returns evaluator's parameters of a given kind as text, or
+    /// `None` if the evaluator has no such parameters.
+    ///
+    /// Engines use this method to implement the "export" command
+    /// (for example, "export psts" exports the piece-square tables).
+    /// The default implementation returns `None`.
+    #[allow(unused_variables)]
+    fn export(kind: &str) -> Option<String>
+        where Self: Sized
+    {
+        None
+    }
+
     /// Updates evaluator's state to keep up with a move that will be
     /// played.
     ///
@@ -98,3 +148,285 @@ pub trait Evaluator: Clone + SetOption + Send + 'static {
     #[allow(unused_variables)]
     fn undone_move(&mut self, position: &Board, m: Move) {}
 }
+
+
+/// A term in the breakdown of an evaluation.
+///
+/// See `Evaluator::evaluate_explained`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalTerm {
+    /// The name of the term (for example, "Material").
+    pub name: &'static str,
+
+    /// The contribution of white's pieces, from white's point of view.
+    pub white: Value,
+
+    /// The contribution of black's pieces, from black's point of view.
+    pub black: Value,
+}
+
+
+/// A pair of middlegame and endgame evaluation scores.
+///
+/// Most evaluation terms change their importance as the game
+/// progresses. For example, a centralized king is a liability in the
+/// middlegame, but an asset in the endgame. Tapered evaluation deals
+/// with this by calculating two separate scores for each term -- one
+/// for the middlegame (`mg`) and one for the endgame (`eg`), and then
+/// interpolating between them according to the current game phase
+/// (see `TaperedScore::interpolate`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaperedScore {
+    /// The middlegame score.
+    pub mg: i32,
+
+    /// The endgame score.
+    pub eg: i32,
+}
+
+impl TaperedScore {
+    /// Creates a new instance.
+    #[inline]
+    pub fn new(mg: i32, eg: i32) -> TaperedScore {
+        TaperedScore { mg: mg, eg: eg }
+    }
+
+    /// Interpolates between the middlegame and the endgame scores.
+    ///
+    /// `phase` should be between `0` (a pure endgame) and `PHASE_MAX`
+    /// (a full-material middlegame). Bigger values are treated as
+    /// `PHASE_MAX`. The result is clamped to the range between
+    /// `VALUE_EVAL_MIN` and `VALUE_EVAL_MAX`.
+    #[inline]
+    pub fn interpolate(&self, phase: u8) -> Value {
+        let phase = if phase > PHASE_MAX { PHASE_MAX } else { phase } as i32;
+        let max = PHASE_MAX as i32;
+        let v = (self.mg * phase + self.eg * (max - phase)) / max;
+        if v > VALUE_EVAL_MAX as i32 {
+            VALUE_EVAL_MAX
+        } else if v < VALUE_EVAL_MIN as i32 {
+            VALUE_EVAL_MIN
+        } else {
+            v as Value
+        }
+    }
+}
+
+impl Add for TaperedScore {
+    type Output = TaperedScore;
+
+    #[inline]
+    fn add(self, other: TaperedScore) -> TaperedScore {
+        TaperedScore::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl Sub for TaperedScore {
+    type Output = TaperedScore;
+
+    #[inline]
+    fn sub(self, other: TaperedScore) -> TaperedScore {
+        TaperedScore::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for TaperedScore {
+    type Output = TaperedScore;
+
+    #[inline]
+    fn neg(self) -> TaperedScore {
+        TaperedScore::new(-self.mg, -self.eg)
+    }
+}
+
+impl AddAssign for TaperedScore {
+    #[inline]
+    fn add_assign(&mut self, other: TaperedScore) {
+        self.mg += other.mg;
+        self.eg += other.eg;
+    }
+}
+
+impl SubAssign for TaperedScore {
+    #[inline]
+    fn sub_assign(&mut self, other: TaperedScore) {
+        self.mg -= other.mg;
+        self.eg -= other.eg;
+    }
+}
+
+
+/// The game phase of a position with full material (a middlegame).
+///
+/// The game phase is calculated from the remaining material on the
+/// board. Each knight and bishop contributes `1`, each rook
+/// contributes `2`, and each queen contributes `4`. Pawns and kings
+/// do not contribute. Thus, the starting position has a game phase of
+/// `PHASE_MAX`, and a position with only kings and pawns has a game
+/// phase of `0`.
+pub const PHASE_MAX: u8 = 24;
+
+/// The contribution of each piece type to the game phase.
+pub const PHASE_WEIGHTS: [u8; 8] = [0, 4, 2, 1, 1, 0, 0, 0];
+
+
+/// Calculates the game phase of a given position.
+///
+/// The returned value is between `0` (a pure endgame) and
+/// `PHASE_MAX` (a full-material middlegame).
+pub fn game_phase(position: &Board) -> u8 {
+    let mut phase = 0;
+    for piece in QUEEN..PAWN {
+        phase += PHASE_WEIGHTS[piece] as usize * pop_count(position.pieces.piece_type[piece]);
+    }
+    if phase > PHASE_MAX as usize {
+        PHASE_MAX
+    } else {
+        phase as u8
+    }
+}
+
+
+/// Returns by how much the (uncapped) game phase will increase when a
+/// given move is played.
+///
+/// The returned value is negative for captures, and positive for
+/// promotions to a piece. This function is useful for evaluators that
+/// want to update the game phase incrementally.
+#[inline]
+pub fn game_phase_delta(m: Move) -> i16 {
+    let mut delta = -(PHASE_WEIGHTS[m.captured_piece()] as i16);
+    if m.move_type() == MOVE_PROMOTION {
+        delta += PHASE_WEIGHTS[Move::piece_from_aux_data(m.aux_data())] as i16;
+    }
+    delta
+}
+
+
+/// A trait for tapered evaluation terms.
+///
+/// A tapered evaluation term calculates a middlegame and an endgame
+/// score for a position (see `TaperedScore`). The scores from all
+/// terms are summed up, and then interpolated by the current game
+/// phase. The in-stock `TaperedEvaluator` turns any tapered
+/// evaluation term into an `Evaluator`. Several terms can be combined
+/// into one by putting them in a tuple -- `(A, B)` is a tapered
+/// evaluation term whose score is the sum of the scores of `A` and
+/// `B`.
+///
+/// Similarly to `Evaluator`, evaluation terms are bound to a
+/// particular chess position, and can update their state
+/// incrementally, following the current line of play.
+pub trait TaperedTerm: Clone + SetOption + Send + 'static {
+    /// Creates a new instance bound to a given position.
+    fn new(position: &Board) -> Self;
+
+    /// Returns the middlegame and endgame scores for the position to
+    /// which the term is currently bound.
+    ///
+    /// The scores are from the point of view of the side to move.
+    fn score(&self, position: &Board) -> TaperedScore;
+
+    /// Returns a breakdown of the score for the position to which the
+    /// term is currently bound.
+    ///
+    /// Each returned triple contains the name of a sub-term, the
+    /// scores for white's pieces (from white's point of view), and
+    /// the scores for black's pieces (from black's point of
+    /// view). The default implementation returns a single sub-term
+    /// named "Other" that gives the whole score to white.
+    fn explain(&self, position: &Board) -> Vec<(&'static str, TaperedScore, TaperedScore)> {
+        let score = self.score(position);
+        let white = if position.to_move == WHITE { score } else { -score };
+        vec![("Other", white, TaperedScore::default())]
+    }
+
+    /// Returns term's parameters of a given kind as text, or `None`
+    /// if the term has no such parameters (see `Evaluator::export`).
+    #[allow(unused_variables)]
+    fn export(kind: &str) -> Option<String> {
+        None
+    }
+
+    /// Updates term's state to keep up with a move that will be
+    /// played.
+    #[inline]
+    #[allow(unused_variables)]
+    fn will_do_move(&mut self, position: &Board, m: Move) {}
+
+    /// Updates term's state to keep up with a move that was played.
+    #[inline]
+    #[allow(unused_variables)]
+    fn done_move(&mut self, position: &Board, m: Move) {}
+
+    /// Updates term's state to keep up with a move that will be taken
+    /// back.
+    #[inline]
+    #[allow(unused_variables)]
+    fn will_undo_move(&mut self, position: &Board, m: Move) {}
+
+    /// Updates term's state in accordance with a move that was taken
+    /// back.
+    #[inline]
+    #[allow(unused_variables)]
+    fn undone_move(&mut self, position: &Board, m: Move) {}
+}
+
+
+impl<A: TaperedTerm, B: TaperedTerm> SetOption for (A, B) {
+    fn options() -> Vec<(&'static str, OptionDescription)> {
+        let mut options = A::options();
+        options.extend(B::options());
+        options
+    }
+
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        try!(A::set_option(name, value));
+        B::set_option(name, value)
+    }
+}
+
+impl<A: TaperedTerm, B: TaperedTerm> TaperedTerm for (A, B) {
+    fn new(position: &Board) -> Self {
+        (A::new(position), B::new(position))
+    }
+
+    #[inline]
+    fn score(&self, position: &Board) -> TaperedScore {
+        self.0.score(position) + self.1.score(position)
+    }
+
+    fn explain(&self, position: &Board) -> Vec<(&'static str, TaperedScore, TaperedScore)> {
+        let mut terms = self.0.explain(position);
+        terms.extend(self.1.explain(position));
+        terms
+    }
+
+    fn export(kind: &str) -> Option<String> {
+        A::export(kind).or_else(|| B::export(kind))
+    }
+
+    #[inline]
+    fn will_do_move(&mut self, position: &Board, m: Move) {
+        self.0.will_do_move(position, m);
+        self.1.will_do_move(position, m);
+    }
+
+    #[inline]
+    fn done_move(&mut self, position: &Board, m: Move) {
+        self.0.done_move(position, m);
+        self.1.done_move(position, m);
+    }
+
+    #[inline]
+    fn will_undo_move(&mut self, position: &Board, m: Move) {
+        self.0.will_undo_move(position, m);
+        self.1.will_undo_move(position, m);
+    }
+
+    #[inline]
+    fn undone_move(&mut self, position: &Board, m: Move) {
+        self.0.undone_move(position, m);
+        self.1.undone_move(position, m);
+    }
+}
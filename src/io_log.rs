@@ -0,0 +1,134 @@
+//! Implements logging of the communication with the GUI.
+//!
+//! When a log file is set (see `set_log_file`), every command
+//! received from the GUI and every reply sent to it is appended to
+//! the file, together with a timestamp. This helps tournament
+//! operators to debug time losses and protocol issues.
+
+use std::io;
+use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+lazy_static! {
+    static ref LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+}
+
+
+/// Tags the commands received from the GUI.
+pub const RECEIVED: &'static str = "<<";
+
+/// Tags the replies sent to the GUI.
+pub const SENT: &'static str = ">>";
+
+/// Tags the notes made by the engine itself (search summaries, for
+/// example).
+pub const NOTE: &'static str = "##";
+
+
+/// Starts appending log records to a given file.
+///
+/// If `path` is an empty string, logging is stopped.
+pub fn set_log_file(path: &str) -> io::Result<()> {
+    let file = if path.is_empty() {
+        None
+    } else {
+        Some(try!(OpenOptions::new().create(true).append(true).open(path)))
+    };
+    *LOG_FILE.lock().unwrap() = file;
+    Ok(())
+}
+
+
+/// Appends a record to the log file, if there is one.
+///
+/// Each record takes one line, consisting of a timestamp (seconds
+/// since the Unix epoch, with millisecond precision), a tag
+/// (`RECEIVED`, `SENT`, or `NOTE`), and the logged text. Write errors
+/// are ignored.
+pub fn log(tag: &str, text: &str) {
+    if let Some(ref mut file) = *LOG_FILE.lock().unwrap() {
+        let t = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let _ = writeln!(file,
+                         "{}.{:03} {} {}",
+                         t.as_secs(),
+                         t.subsec_nanos() / 1_000_000,
+                         tag,
+                         text.trim_end());
+    }
+}
+
+
+/// A writer that logs every line written through it.
+///
+/// The lines are logged with the `SENT` tag.
+pub struct LogWriter<W: Write> {
+    inner: W,
+    line: Vec<u8>,
+}
+
+
+impl<W: Write> LogWriter<W> {
+    /// Creates a new instance, wrapping a given writer.
+    pub fn new(inner: W) -> LogWriter<W> {
+        LogWriter {
+            inner: inner,
+            line: vec![],
+        }
+    }
+}
+
+
+impl<W: Write> Write for LogWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = try!(self.inner.write(buf));
+        for &b in buf[..n].iter() {
+            if b == b'\n' {
+                log(SENT, &String::from_utf8_lossy(&self.line));
+                self.line.clear();
+            } else {
+                self.line.push(b);
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn log_writer() {
+        let path = env::temp_dir().join("alcibiades_io_log_test.log");
+        let path = path.to_str().unwrap();
+        fs::remove_file(path).ok();
+        let mut output = vec![];
+        {
+            let mut w = LogWriter::new(&mut output);
+            write!(w, "not logged\n").unwrap();
+            set_log_file(path).unwrap();
+            log(RECEIVED, "isready\n");
+            write!(w, "ready").unwrap();
+            write!(w, "ok\nbestmove").unwrap();
+            set_log_file("").unwrap();
+            write!(w, " e2e4\n").unwrap();
+        }
+        assert_eq!(output, b"not logged\nreadyok\nbestmove e2e4\n".to_vec());
+        let mut s = String::new();
+        File::open(path).unwrap().read_to_string(&mut s).unwrap();
+        let lines: Vec<&str> = s.lines().map(|l| l.splitn(2, ' ').nth(1).unwrap()).collect();
+        assert_eq!(lines, vec!["<< isready", ">> readyok"]);
+        fs::remove_file(path).ok();
+    }
+}
@@ -42,7 +42,7 @@
 //!
 //! ```toml
 //! [dependencies]
-//! alcibiades = "0.3.0"
+//! alcibiades = "0.4.0"
 //! ```
 //!
 //! and this to your crate root:
@@ -67,7 +67,17 @@
 //! ```
 //!
 //! This engine is assembled from the "in stock" implementations of
-//! the different framework traits.
+//! the different framework traits. The same engine can be constructed
+//! in one line, with its components swapped as needed:
+//!
+//! ```rust,no_run
+//! extern crate alcibiades;
+//! use alcibiades::prelude::*;
+//!
+//! fn main() {
+//!     engine_default("My engine", "John Doe").hash_size(64).run();
+//! }
+//! ```
 //!
 //! In reality, you will probably want to write your own
 //! implementations for some of the framework traits. Thanks to Rust's
@@ -87,6 +97,7 @@ extern crate libc;
 extern crate regex;
 extern crate rand;
 
+pub mod prelude;
 pub mod utils;
 pub mod engine;
 pub mod stock;
@@ -106,6 +117,8 @@ mod move_generator;
 mod qsearch;
 mod time_manager;
 mod uci;
+mod xboard;
+mod io_log;
 
 pub use board::*;
 pub use moves::*;
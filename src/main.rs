@@ -0,0 +1,16 @@
+//! The default chess engine, assembled from the "in stock"
+//! implementations of the framework traits.
+//!
+//! All the work is done by the library -- this is only a thin
+//! wrapper around `engine::engine_default`, so that the crate can be
+//! installed and used as a ready-made UCI engine.
+
+extern crate alcibiades;
+
+use alcibiades::engine::engine_default;
+
+fn main() {
+    engine_default(concat!("Alcibiades ", env!("CARGO_PKG_VERSION")),
+                   "Evgeni Pandurski")
+        .run();
+}
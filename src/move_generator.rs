@@ -1,6 +1,5 @@
 //! Defines the `MoveGenerator` trait.
 
-use std::mem::uninitialized;
 use std::cmp::max;
 use uci::SetOption;
 use board::*;
@@ -19,10 +18,12 @@ use utils::BoardGeometry;
 ///
 /// * Find which pieces and pawns attack a given square.
 ///
-/// * Find if the side to move is in check.
+/// * Find if the side to move is in check, and which pieces give
+///   check or are pinned.
 ///
-/// * Generate all legal moves, or a subset of all legal moves in the
-///   current position.
+/// * Generate all legal moves (possibly with some pseudo-legal
+///   king's moves), only the strictly legal moves, or a subset of all
+///   legal moves in the current position.
 ///
 /// * Generate a null move.
 ///
@@ -72,6 +73,22 @@ pub trait MoveGenerator: Clone + SetOption + Send + 'static {
     /// Returns if the side to move is in check.
     fn is_check(&self) -> bool;
 
+    /// Returns a bitboard with all enemy pieces and pawns that attack
+    /// the king of the side to move.
+    fn checkers(&self) -> Bitboard {
+        BoardGeometry::get().checkers(self.board())
+    }
+
+    /// Returns a bitboard with all pinned pieces and pawns belonging
+    /// to the side to move.
+    ///
+    /// A piece is pinned when it can not move off the line between
+    /// its king and an enemy slider without exposing the king to
+    /// check.
+    fn pinned(&self) -> Bitboard {
+        BoardGeometry::get().pinned(self.board())
+    }
+
     /// Generates all legal moves, possibly including some
     /// pseudo-legal moves too.
     ///
@@ -96,6 +113,36 @@ pub trait MoveGenerator: Clone + SetOption + Send + 'static {
     /// legal, except it might leave the king in check.
     fn generate_all<T: AddMove>(&self, moves: &mut T);
 
+    /// Generates all legal moves, and only legal moves.
+    ///
+    /// The moves are added to `moves`. This method generates the same
+    /// moves as `generate_all`, except that king's moves to squares
+    /// under attack are left out. This is convenient for perft, user
+    /// interfaces, and other users that need a list of strictly legal
+    /// moves, but is slower, because the set of squares attacked by
+    /// the enemy has to be found up front.
+    ///
+    /// The initial move score for the generated moves is
+    /// *unspecified*.
+    ///
+    /// **Note:** The default implementation tries each king's move
+    /// on a copy of the position. Implementations should override it
+    /// with something faster.
+    fn generate_legal<T: AddMove>(&self, moves: &mut T) {
+        let mut all_moves = Vec::new();
+        self.generate_all(&mut all_moves);
+        let mut position = self.clone();
+        for m in all_moves {
+            if m.played_piece() == KING {
+                if position.do_move(m).is_none() {
+                    continue;
+                }
+                position.undo_move(m);
+            }
+            moves.add_move(m);
+        }
+    }
+
     /// Generates moves for the quiescence search.
     ///
     /// The moves are added to `moves`. This method always generates a
@@ -110,6 +157,34 @@ pub trait MoveGenerator: Clone + SetOption + Send + 'static {
     ///   can be omitted for speed.
     fn generate_forcing<T: AddMove>(&self, generate_checks: bool, moves: &mut T);
 
+    /// Generates the pseudo-legal moves that `generate_forcing`
+    /// leaves out.
+    ///
+    /// The moves are added to `moves`. The moves generated by this
+    /// method, together with the moves generated by
+    /// `generate_forcing(false, ..)`, are exactly the moves generated
+    /// by `generate_all`:
+    ///
+    /// * If the king is in check, no moves are generated.
+    ///
+    /// * Otherwise, all moves that are not tactical (see
+    ///   `Move::is_tactical`) are generated.
+    ///
+    /// **Note:** The default implementation calls `generate_all` and
+    /// filters out the tactical moves. Implementations should
+    /// override it with something faster.
+    fn generate_quiet<T: AddMove>(&self, moves: &mut T) {
+        if !self.is_check() {
+            let mut all_moves = Vec::new();
+            self.generate_all(&mut all_moves);
+            for m in all_moves {
+                if !m.is_tactical() {
+                    moves.add_move(m);
+                }
+            }
+        }
+    }
+
     /// Returns a null move.
     ///
     /// "Null move" is a pseudo-move that changes only the side to
@@ -164,27 +239,35 @@ pub trait MoveGenerator: Clone + SetOption + Send + 'static {
     /// on the board, and therefore the returned value might be
     /// incorrect.
     ///
+    /// Pawns that capture on the last rank during the exchange are
+    /// assumed to promote to queens. For en-passant captures, the
+    /// captured pawn is removed from its square, so that the sliders
+    /// behind it can enter the exchange.
+    ///
     /// The move passed to this method must have been generated by
     /// `generate_all`, `generate_forcing`, `try_move_digest`, or
     /// `null_move` methods for the current position on the board.
     fn evaluate_move(&self, m: Move) -> Value {
         debug_assert!(m.played_piece() < PIECE_NONE);
         debug_assert!(m.captured_piece() <= PIECE_NONE);
-        const PIECE_VALUES: [Value; 8] = [10000, 975, 500, 325, 325, 100, 0, 0];
 
         unsafe {
             let mut piece = m.played_piece();
             let captured_piece = m.captured_piece();
+            let move_type = m.move_type();
 
             // Try not to waste CPU cycles when the played piece is
             // less valuable than the captured piece.
-            if piece > captured_piece {
+            if piece > captured_piece && move_type != MOVE_PROMOTION {
                 return *PIECE_VALUES.get_unchecked(captured_piece);
             }
 
             // This is the square on which all the action takes place.
             let exchange_square = m.dest_square();
 
+            // Pawns that capture on this square get promoted.
+            let is_promotion_square = (1 << exchange_square) & (BB_RANK_1 | BB_RANK_8) != 0;
+
             let color: &[Bitboard; 2] = &self.board().pieces.color;
             let piece_type: &[Bitboard; 6] = &self.board().pieces.piece_type;
             let file_sliders = piece_type[QUEEN] | piece_type[ROOK];
@@ -194,17 +277,48 @@ pub trait MoveGenerator: Clone + SetOption + Send + 'static {
                 .squares_behind_blocker
                 .get_unchecked(exchange_square);
 
+            // Returns the sliders that attack the exchange square
+            // through a given vacated square.
+            let xray_attackers = |vacated_square: Square| -> Bitboard {
+                let behind = self.board().occupied & *behind_blocker.get_unchecked(vacated_square);
+                if behind & (file_sliders | diag_sliders) == 0 {
+                    return 0;
+                }
+                match behind & file_sliders &
+                      geometry.attacks_from_unsafe(ROOK, exchange_square, behind) {
+                    0 => {
+                        // Not a file slider, possibly a diagonal slider.
+                        behind & diag_sliders &
+                        geometry.attacks_from_unsafe(BISHOP, exchange_square, behind)
+                    }
+                    bb => {
+                        // A file slider.
+                        bb
+                    }
+                }
+            };
+
             // These variables (along with `piece`) will be updated on each capture:
             let mut us = self.board().to_move;
             let mut depth = 0;
             let mut orig_square_bb = 1 << m.orig_square();
             let mut attackers_and_defenders = self.attacks_to(exchange_square);
+            if move_type == MOVE_ENPASSANT {
+                // The captured pawn is not on the exchange square, but
+                // it leaves its square vacant.
+                let captured_square = if us == WHITE {
+                    exchange_square - 8
+                } else {
+                    exchange_square + 8
+                };
+                attackers_and_defenders |= xray_attackers(captured_square);
+            }
 
             // The `gain` array will hold the total material gained at
             // each `depth`, from the viewpoint of the side that made the
             // last capture (`us`).
-            let mut gain: [Value; 34] = uninitialized();
-            gain[0] = if m.move_type() == MOVE_PROMOTION {
+            let mut gain: [Value; 34] = [0; 34];
+            gain[0] = if move_type == MOVE_PROMOTION {
                 piece = Move::piece_from_aux_data(m.aux_data());
                 PIECE_VALUES[captured_piece] + PIECE_VALUES[piece] - PIECE_VALUES[PAWN]
             } else {
@@ -220,13 +334,15 @@ pub trait MoveGenerator: Clone + SetOption + Send + 'static {
                 let speculative_gain: &mut Value = gain.get_unchecked_mut(depth + 1);
                 *speculative_gain = *PIECE_VALUES.get_unchecked(piece) - current_gain;
 
-                if max(-current_gain, *speculative_gain) < 0 {
+                if max(-current_gain, *speculative_gain) < 0 && !is_promotion_square {
                     // The side that made the last capture wins even if
                     // the captured piece happens to be defended. So, we
                     // stop here to save precious CPU cycles. Note that
                     // here we may happen to return an incorrect SEE
                     // value, but the sign will be correct, which is by
-                    // far the most important information.
+                    // far the most important information. (This does
+                    // not hold when the captured piece can be
+                    // recaptured by a promoting pawn.)
                     break;
                 }
 
@@ -235,22 +351,8 @@ pub trait MoveGenerator: Clone + SetOption + Send + 'static {
 
                 // Consider adding new attackers/defenders, now that
                 // capturing piece's origin square is vacant.
-                let behind = self.board().occupied &
-                             *behind_blocker.get_unchecked(bsf(orig_square_bb));
-                if behind & (file_sliders | diag_sliders) != 0 && piece != KING {
-                    attackers_and_defenders |=
-                        match behind & file_sliders &
-                              geometry.attacks_from_unsafe(ROOK, exchange_square, behind) {
-                            0 => {
-                                // Not a file slider, possibly a diagonal slider.
-                                behind & diag_sliders &
-                                geometry.attacks_from_unsafe(BISHOP, exchange_square, behind)
-                            }
-                            bb => {
-                                // A file slider.
-                                bb
-                            }
-                        };
+                if piece != KING {
+                    attackers_and_defenders |= xray_attackers(bsf(orig_square_bb));
                 }
 
                 // Change the side to move.
@@ -266,6 +368,12 @@ pub trait MoveGenerator: Clone + SetOption + Send + 'static {
                             depth += 1;
                             piece = p;
                             orig_square_bb = lsb(bb);
+                            if piece == PAWN && is_promotion_square {
+                                // The pawn gets promoted to a queen.
+                                piece = QUEEN;
+                                *gain.get_unchecked_mut(depth) += PIECE_VALUES[QUEEN] -
+                                                                  PIECE_VALUES[PAWN];
+                            }
                             continue 'exchange;
                         }
                     }
@@ -2,6 +2,8 @@
 
 use std::fmt;
 use board::*;
+use squares::*;
+use ranks::*;
 
 
 /// `MOVE_ENPASSANT`, `MOVE_PROMOTION`, `MOVE_CASTLING`, or `MOVE_NORMAL`.
@@ -33,7 +35,6 @@ pub const MOVE_NORMAL: MoveType = 3;
 /// promotion; `2`) castling; `3`) normal move. "Aux data" encodes the
 /// type of the promoted piece if the move type is pawn promotion,
 /// otherwise it is zero.
-#[derive(Debug)]
 #[derive(Clone, Copy)]
 #[derive(PartialEq, Eq)]
 pub struct MoveDigest(u16);
@@ -48,6 +49,21 @@ impl MoveDigest {
         MoveDigest(0)
     }
 
+    /// Creates a new instance from a raw `u16` value.
+    ///
+    /// This is useful when move digests are deserialized. Note that
+    /// the returned digest may not correspond to any legal move.
+    #[inline]
+    pub fn new(value: u16) -> MoveDigest {
+        MoveDigest(value)
+    }
+
+    /// Returns the contained raw value.
+    #[inline]
+    pub fn value(&self) -> u16 {
+        self.0
+    }
+
     /// Returns the move type.
     #[inline]
     pub fn move_type(&self) -> MoveType {
@@ -102,6 +118,32 @@ impl MoveDigest {
     pub fn is_null(&self) -> bool {
         self.orig_square() == self.dest_square() && self.move_type() == MOVE_NORMAL
     }
+
+    /// Returns if the digest can possibly encode a legal move (or a
+    /// null move) in some position, or is `MoveDigest::invalid()`.
+    ///
+    /// This is useful for detecting corrupted move digests. Note that
+    /// the method checks only that the origin square, the destination
+    /// square, the move type, and the aux data fit together.
+    pub fn is_well_formed(&self) -> bool {
+        if *self == MoveDigest::invalid() {
+            return true;
+        }
+        let (orig, dest) = (self.orig_square(), self.dest_square());
+        let ranks = (Board::rank(orig), Board::rank(dest));
+        let file_distance = (Board::file(orig) as isize - Board::file(dest) as isize).abs();
+        match self.move_type() {
+            MOVE_PROMOTION => {
+                (ranks == (RANK_7, RANK_8) || ranks == (RANK_2, RANK_1)) && file_distance <= 1
+            }
+            _ if self.aux_data() != 0 => false,
+            MOVE_ENPASSANT => {
+                (ranks == (RANK_5, RANK_6) || ranks == (RANK_4, RANK_3)) && file_distance == 1
+            }
+            MOVE_CASTLING => (orig == E1 || orig == E8) && (dest == orig - 2 || dest == orig + 2),
+            _ => true,
+        }
+    }
 }
 
 impl fmt::Display for MoveDigest {
@@ -110,18 +152,25 @@ impl fmt::Display for MoveDigest {
     }
 }
 
+impl fmt::Debug for MoveDigest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MoveDigest({})", self.notation())
+    }
+}
+
 
 /// Represents a move on the chessboard.
 ///
-/// `Move` is a `u64` number. It contains 3 types of information:
+/// `Move` is an immutable `u32` number. It contains 2 types of
+/// information:
 ///
 /// 1. Information about the played move itself.
 ///
 /// 2. Information needed so as to be able to undo the move and
 ///    restore the board into the exact same state as before.
 ///
-/// 3. Move ordering info -- moves with higher move score are tried
-///    first.
+/// Move ordering scores are not part of the move -- they are kept
+/// separately (see `utils::MoveStack`).
 ///
 /// Bits 0-15 contain the whole information about the move
 /// itself. This is called **"move digest"** and is laid out the
@@ -160,16 +209,11 @@ impl fmt::Display for MoveDigest {
 /// contains pushed pawn's file (a value between 0 and 7). Otherwise
 /// it contains `8`. "Castling rights" holds the castling rights
 /// before the move was played. When "Captured piece" is stored, its
-/// bits are inverted, so that MVV-LVA (Most valuable victim -- least
-/// valuable aggressor) move ordering is followed for moves that have
-/// the same "score".
-///
-/// Bits 32-63 contain the "score" field, which is used to influence
-/// move ordering.
-#[derive(Debug)]
+/// bits are inverted, so that comparing two moves follows MVV-LVA
+/// (Most valuable victim -- least valuable aggressor) move ordering.
 #[derive(Clone, Copy)]
 #[derive(PartialOrd, Ord, PartialEq, Eq)]
-pub struct Move(u64);
+pub struct Move(u32);
 
 impl Move {
     /// Creates a new instance.
@@ -181,8 +225,7 @@ impl Move {
                captured_piece: PieceType,
                played_piece: PieceType,
                castling_rights: CastlingRights,
-               enpassant_file: usize,
-               score: u32)
+               enpassant_file: usize)
                -> Move {
         debug_assert!(move_type <= 0x11);
         debug_assert!(played_piece < PIECE_NONE);
@@ -195,17 +238,16 @@ impl Move {
         debug_assert!(orig_square != dest_square ||
                       move_type == MOVE_NORMAL && captured_piece == PIECE_NONE);
 
-        Move(// Most probably constants:
-             (score as u64) << SHIFT_SCORE |
-             (move_type << SHIFT_MOVE_TYPE | aux_data << SHIFT_AUX_DATA) as u64 |
-             (
-                 // Sorted by increasing likelihood of being changed in a cycle:
-                 castling_rights.value() << SHIFT_CASTLING_RIGHTS |
-                 enpassant_file << SHIFT_ENPASSANT_FILE |
-                 played_piece << SHIFT_PIECE |
-                 orig_square << SHIFT_ORIG_SQUARE |
-                 (!captured_piece & 7) << SHIFT_CAPTURED_PIECE |
-                 dest_square << SHIFT_DEST_SQUARE) as u64)
+        Move((// Most probably constants:
+              move_type << SHIFT_MOVE_TYPE | aux_data << SHIFT_AUX_DATA |
+
+              // Sorted by increasing likelihood of being changed in a cycle:
+              castling_rights.value() << SHIFT_CASTLING_RIGHTS |
+              enpassant_file << SHIFT_ENPASSANT_FILE |
+              played_piece << SHIFT_PIECE |
+              orig_square << SHIFT_ORIG_SQUARE |
+              (!captured_piece & 7) << SHIFT_CAPTURED_PIECE |
+              dest_square << SHIFT_DEST_SQUARE) as u32)
     }
 
     /// Creates an invalid move instance.
@@ -216,7 +258,7 @@ impl Move {
     /// available.
     #[inline]
     pub fn invalid() -> Move {
-        Move(((!PIECE_NONE & 7) << SHIFT_CAPTURED_PIECE | KING << SHIFT_PIECE) as u64)
+        Move(((!PIECE_NONE & 7) << SHIFT_CAPTURED_PIECE | KING << SHIFT_PIECE) as u32)
     }
 
     /// Decodes the promoted piece type from the raw value returned by
@@ -230,20 +272,6 @@ impl Move {
         QUEEN + pp_code
     }
 
-    /// Assigns a new score for the move.
-    #[inline]
-    pub fn set_score(&mut self, score: u32) {
-        const MASK_SCORE: u64 = (::std::u32::MAX as u64) << SHIFT_SCORE;
-        self.0 &= !MASK_SCORE;
-        self.0 |= (score as u64) << SHIFT_SCORE;
-    }
-
-    /// Returns the assigned move score.
-    #[inline]
-    pub fn score(&self) -> u32 {
-        (self.0 >> SHIFT_SCORE) as u32
-    }
-
     /// Returns the move type.
     #[inline]
     pub fn move_type(&self) -> MoveType {
@@ -335,6 +363,20 @@ impl Move {
         (v & MASK_PIECE | C) ^ (v & MASK_CAPTURED_PIECE | P) >= MASK_PIECE
     }
 
+    /// Returns `true` if the move is a capture or a pawn promotion to
+    /// queen, `false` otherwise.
+    ///
+    /// Captures that promote a pawn to a piece other than queen are
+    /// not considered tactical.
+    #[inline]
+    pub fn is_tactical(&self) -> bool {
+        if self.move_type() == MOVE_PROMOTION {
+            self.aux_data() == 0
+        } else {
+            self.captured_piece() < PIECE_NONE
+        }
+    }
+
     /// Returns if the move is a null move.
     ///
     /// "Null move" is a pseudo-move that changes nothing on the board
@@ -355,6 +397,22 @@ impl fmt::Display for Move {
     }
 }
 
+impl fmt::Debug for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const MOVE_TYPES: [&'static str; 4] = ["enpassant", "promotion", "castling", "normal"];
+        const PIECES: [&'static str; 7] = ["king", "queen", "rook", "bishop", "knight", "pawn",
+                                           "none"];
+        f.debug_struct("Move")
+            .field("notation", &format_args!("{}", self.notation()))
+            .field("move_type", &format_args!("{}", MOVE_TYPES[self.move_type()]))
+            .field("played_piece", &format_args!("{}", PIECES[self.played_piece()]))
+            .field("captured_piece", &format_args!("{}", PIECES[self.captured_piece()]))
+            .field("castling_rights", &self.castling_rights())
+            .field("enpassant_file", &self.enpassant_file())
+            .finish()
+    }
+}
+
 
 /// A trait for adding moves to move containers.
 pub trait AddMove {
@@ -371,7 +429,6 @@ impl AddMove for Vec<Move> {
 
 
 // Field shifts
-const SHIFT_SCORE: usize = 32;
 const SHIFT_CAPTURED_PIECE: usize = 27;
 const SHIFT_PIECE: usize = 24;
 const SHIFT_CASTLING_RIGHTS: usize = 20;
@@ -402,12 +459,12 @@ mod tests {
     #[test]
     fn moves() {
         let cr = CastlingRights::new(0b1011);
-        let mut m = Move::new(MOVE_NORMAL, E2, E4, 0, PIECE_NONE, PAWN, cr, 8, 0);
-        let m1 = Move::new(MOVE_NORMAL, F3, E4, 0, KNIGHT, PAWN, cr, 8, ::std::u32::MAX);
-        let m2 = Move::new(MOVE_NORMAL, F3, E4, 0, PIECE_NONE, KING, cr, 8, 0);
-        let m3 = Move::new(MOVE_PROMOTION, F2, F1, 1, PIECE_NONE, PAWN, cr, 8, 0);
-        let m4 = Move::new(MOVE_NORMAL, F2, E3, 0, KNIGHT, BISHOP, cr, 8, 0);
-        let m5 = Move::new(MOVE_NORMAL, F2, F2, 0, PIECE_NONE, KING, cr, 8, 0);
+        let m = Move::new(MOVE_NORMAL, E2, E4, 0, PIECE_NONE, PAWN, cr, 8);
+        let m1 = Move::new(MOVE_NORMAL, F3, E4, 0, KNIGHT, PAWN, cr, 8);
+        let m2 = Move::new(MOVE_NORMAL, F3, E4, 0, PIECE_NONE, KING, cr, 8);
+        let m3 = Move::new(MOVE_PROMOTION, F2, F1, 1, PIECE_NONE, PAWN, cr, 8);
+        let m4 = Move::new(MOVE_NORMAL, F2, E3, 0, KNIGHT, BISHOP, cr, 8);
+        let m5 = Move::new(MOVE_NORMAL, F2, F2, 0, PIECE_NONE, KING, cr, 8);
         assert!(m1 > m);
         assert!(m2 < m);
         assert_eq!(m.move_type(), MOVE_NORMAL);
@@ -427,12 +484,6 @@ mod tests {
         assert_eq!(Move::piece_from_aux_data(3), KNIGHT);
         let m_copy = m;
         assert_eq!(m, m_copy);
-        assert_eq!(m.score(), 0);
-        m.set_score(::std::u32::MAX);
-        assert_eq!(m.score(), ::std::u32::MAX);
-        assert!(m > m_copy);
-        m.set_score(0);
-        assert_eq!(m.score(), 0);
         assert!(m.is_pawn_advance_or_capure());
         assert!(m1.is_pawn_advance_or_capure());
         assert!(!m2.is_pawn_advance_or_capure());
@@ -447,5 +498,29 @@ mod tests {
         assert_eq!(m.digest().orig_square(), m.orig_square());
         assert_eq!(m.digest().dest_square(), m.dest_square());
         assert_eq!(m.digest().aux_data(), m.aux_data());
+        for x in [m, m1, m2, m3, m4, m5, Move::invalid()].iter() {
+            assert!(x.digest().is_well_formed());
+        }
+        let digest = |move_type: MoveType, orig: Square, dest: Square, aux_data: u16| {
+            MoveDigest::new((move_type << SHIFT_MOVE_TYPE | orig << SHIFT_ORIG_SQUARE |
+                             dest << SHIFT_DEST_SQUARE) as u16 | aux_data)
+        };
+        assert!(digest(MOVE_CASTLING, E8, G8, 0).is_well_formed());
+        assert!(!digest(MOVE_CASTLING, E1, E4, 0).is_well_formed());
+        assert!(digest(MOVE_ENPASSANT, D5, E6, 0).is_well_formed());
+        assert!(!digest(MOVE_ENPASSANT, D5, D6, 0).is_well_formed());
+        assert!(!digest(MOVE_PROMOTION, E6, E7, 0).is_well_formed());
+        assert!(!digest(MOVE_NORMAL, E2, E4, 1).is_well_formed());
+    }
+
+    #[test]
+    fn debug_move() {
+        let cr = CastlingRights::new(0b1011);
+        let m = Move::new(MOVE_PROMOTION, F2, F1, 1, KNIGHT, PAWN, cr, 8);
+        assert_eq!(format!("{:?}", m.digest()), "MoveDigest(f2f1r)");
+        assert_eq!(format!("{:?}", m),
+                   "Move { notation: f2f1r, move_type: promotion, played_piece: pawn, \
+                    captured_piece: knight, castling_rights: CastlingRights(QKk), \
+                    enpassant_file: 8 }");
     }
 }
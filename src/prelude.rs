@@ -0,0 +1,25 @@
+//! Re-exports the most commonly used items.
+//!
+//! The framework traits and types, the "in stock" implementations,
+//! and the functions that run the engine can be imported at once:
+//!
+//! ```rust,no_run
+//! use alcibiades::prelude::*;
+//!
+//! engine_default("My engine", "John Doe").evaluator::<SimpleEvaluator>().run();
+//! ```
+
+pub use board::*;
+pub use moves::*;
+pub use value::*;
+pub use depth::*;
+pub use evaluator::*;
+pub use search_node::*;
+pub use search::*;
+pub use ttable::*;
+pub use move_generator::*;
+pub use qsearch::*;
+pub use time_manager::*;
+pub use uci::{SetOption, OptionDescription};
+pub use stock::*;
+pub use engine::{engine_default, run_uci, run_uci_with_io, EngineBuilder};
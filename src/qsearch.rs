@@ -1,6 +1,7 @@
 //! Defines the `Qsearch` trait.
 
 use uci::SetOption;
+use board::Bitboard;
 use value::*;
 use depth::*;
 use move_generator::MoveGenerator;
@@ -49,6 +50,17 @@ pub struct QsearchParams<'a, T: MoveGenerator + 'a> {
     /// Saves the re-calculation if position's static evaluation is
     /// already available.
     pub static_eval: Value,
+
+    /// Squares on which recaptures must be considered.
+    ///
+    /// Usually this is the destination square of the last move
+    /// played in the main search, or `0` if there is no such move.
+    /// During the first ply of the quiescence search, captures on
+    /// those squares will be tried even when the static exchange
+    /// evaluation says they are losing. This improves the horizon
+    /// behavior, correcting SEE errors due to pinned and overloaded
+    /// pieces.
+    pub recapture_squares: Bitboard,
 }
 
 
@@ -70,6 +82,14 @@ pub trait QsearchResult: Clone {
 
     /// Retruns the number of positions searched to calculate the evaluation.
     fn searched_nodes(&self) -> u64;
+
+    /// Returns the length (in half-moves) of the longest line of play
+    /// examined to calculate the evaluation.
+    ///
+    /// The default implementation returns `0`.
+    fn depth(&self) -> Depth {
+        0
+    }
 }
 
 
@@ -1,8 +1,11 @@
 //! Defines search-related types and traits.
 
+use std::fmt;
+use std::ops::Add;
 use std::thread;
 use std::time::Duration;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use std::sync::mpsc::{Sender, Receiver, TryRecvError};
 use uci::SetOption;
 use moves::Move;
@@ -57,10 +60,101 @@ pub struct SearchParams<T: SearchNode> {
     /// The behavior of the search is *undefined* if the root position
     /// is not final, but `searchmoves` is empty.
     pub searchmoves: Vec<Move>,
+
+    /// The maximal number of positions to search, or `None` if there
+    /// is no limit.
+    ///
+    /// When the limit is reached, the search should end as soon as
+    /// possible, and its last report should give
+    /// `TerminationReason::NodeLimitReached`. Searches that delegate
+    /// their work to other searches should pass on the remaining part
+    /// of the limit (see `remaining_nodes`).
+    pub node_limit: Option<u64>,
+}
+
+
+impl<T: SearchNode> SearchParams<T> {
+    /// Returns the part of the node limit that remains after a given
+    /// number of positions have been searched.
+    ///
+    /// # Examples:
+    ///
+    /// ```rust
+    /// # use alcibiades::*;
+    /// # use alcibiades::stock::*;
+    /// # type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+    /// let position = P::from_history("8/8/8/8/8/8/8/K6k w - - 0 1", &mut vec![].into_iter())
+    ///     .ok()
+    ///     .unwrap();
+    /// let mut params = SearchParams {
+    ///     search_id: 0,
+    ///     searchmoves: position.legal_moves(),
+    ///     position: position,
+    ///     depth: 5,
+    ///     lower_bound: VALUE_MIN,
+    ///     upper_bound: VALUE_MAX,
+    ///     node_limit: Some(1000),
+    /// };
+    /// assert_eq!(params.remaining_nodes(300), Some(700));
+    /// assert_eq!(params.remaining_nodes(3000), Some(0));
+    /// params.node_limit = None;
+    /// assert_eq!(params.remaining_nodes(300), None);
+    /// ```
+    pub fn remaining_nodes(&self, searched_nodes: u64) -> Option<u64> {
+        self.node_limit.map(|n| n.saturating_sub(searched_nodes))
+    }
+}
+
+
+/// The reason why a search has ended.
+///
+/// This allows frontends to tell why the search ended, without
+/// inferring it from the reported value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminationReason {
+    /// The requested search depth has been reached.
+    DepthReached,
+
+    /// The search has been terminated by a `TERMINATE` message.
+    Terminated,
+
+    /// The node limit for the search has been reached.
+    NodeLimitReached,
+
+    /// A forced mate (for either side) has been found.
+    MateFound,
+
+    /// The root position is a draw (stalemate, for example).
+    DrawForced,
+
+    /// There is only one move to consider at the root.
+    SingleMove,
+
+    /// The search thread has panicked. The results from the
+    /// unfinished iteration are lost.
+    Failed,
+}
+
+
+impl fmt::Display for TerminationReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+                        TerminationReason::DepthReached => "depth reached",
+                        TerminationReason::Terminated => "terminated",
+                        TerminationReason::NodeLimitReached => "node limit reached",
+                        TerminationReason::MateFound => "mate found",
+                        TerminationReason::DrawForced => "draw forced",
+                        TerminationReason::SingleMove => "single move",
+                        TerminationReason::Failed => "search failed",
+                    })
+    }
 }
 
 
 /// A progress report from a search.
+///
+/// `SearchReport` is displayed as a one-line summary (the auxiliary
+/// data is omitted).
 #[derive(Clone, Debug)]
 pub struct SearchReport<T> {
     /// The ID assigned to the search.
@@ -101,6 +195,15 @@ pub struct SearchReport<T> {
     /// Should be `false` for all reports except the last one.
     pub done: bool,
 
+    /// The reason why the search has ended.
+    ///
+    /// Should be `None` for all reports except the last one, which
+    /// may provide it.
+    pub termination: Option<TerminationReason>,
+
+    /// Optional details about the progress of the search.
+    pub details: SearchDetails,
+
     /// Auxiliary data.
     ///
     /// For example, this may contain calculated principal
@@ -109,6 +212,167 @@ pub struct SearchReport<T> {
 }
 
 
+/// Optional details about the progress of a search.
+///
+/// All the details are optional, so searches that can not provide
+/// some of them should leave them at their default values. To stay
+/// compatible with future versions, in which more details may be
+/// added, construct instances with `..Default::default()`.
+///
+/// # Examples:
+///
+/// ```rust
+/// # use alcibiades::*;
+/// let details = SearchDetails {
+///     selective_depth: 12,
+///     ..Default::default()
+/// };
+/// assert!(details.tree.is_none());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SearchDetails {
+    /// The maximal distance from the root (in half-moves) reached by
+    /// the search so far, including extensions and quiescence search,
+    /// or `0` if not available.
+    ///
+    /// Deepening searches should give the selective depth of the
+    /// current iteration.
+    pub selective_depth: Depth,
+
+    /// Statistics about the aspiration windows used by the search,
+    /// or `None` if not available.
+    ///
+    /// Searchers that do not use aspiration windows should send
+    /// `None`.
+    pub aspiration: Option<AspirationStats>,
+
+    /// Statistics about the game tree explored by the search so far,
+    /// or `None` if not available.
+    ///
+    /// Depth-first searches may send `None` in all reports except the
+    /// last one. Deepening searches should send the statistics for
+    /// all the depth-first searches they have executed.
+    pub tree: Option<TreeStats>,
+
+    /// The move that is currently being searched at the root
+    /// position, and its number in the order of searching (starting
+    /// from `1`), or `None` if not available.
+    ///
+    /// GUIs use this to show the progress of long iterations.
+    pub current_move: Option<(Move, usize)>,
+
+    /// Messages for the user (for example, about a search trace file
+    /// that can not be written), which have not been sent with the
+    /// previous reports.
+    ///
+    /// The engine shows them as info strings.
+    pub messages: Vec<String>,
+}
+
+
+/// Statistics about the aspiration windows used by a search.
+///
+/// This can be used to diagnose badly sized aspiration windows: too
+/// narrow windows cause many re-searches, too wide windows cause few
+/// beta cut-offs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AspirationStats {
+    /// The number of aspirated searches that failed high.
+    pub fail_highs: usize,
+
+    /// The number of aspirated searches that failed low.
+    pub fail_lows: usize,
+
+    /// The number of re-searches with widened aspiration windows.
+    pub researches: usize,
+
+    /// The current aspiration window (alpha, beta).
+    pub window: (Value, Value),
+}
+
+
+/// Statistics about the game tree explored by a search.
+///
+/// This can be used to guide the tuning of the search. For example,
+/// in a well ordered game tree the majority of the beta cut-offs are
+/// caused by the first tried move, and only few of the late move
+/// reductions need to be re-searched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// The number of positions searched by the main search.
+    pub nodes: u64,
+
+    /// The number of positions searched by the quiescence search.
+    pub qsearch_nodes: u64,
+
+    /// The number of transposition table probes.
+    pub tt_probes: u64,
+
+    /// The number of transposition table probes that found an entry.
+    pub tt_hits: u64,
+
+    /// The number of beta cut-offs caused by the first, the second,
+    /// the third, and all the later tried moves.
+    pub beta_cutoffs: [u64; 4],
+
+    /// The number of tried null moves.
+    pub null_moves: u64,
+
+    /// The number of null moves that caused a beta cut-off.
+    pub null_move_cutoffs: u64,
+
+    /// The number of late moves searched with a reduced depth.
+    pub reductions: u64,
+
+    /// The number of late moves that had to be re-searched with a
+    /// full depth.
+    pub reduction_researches: u64,
+}
+
+impl Add for TreeStats {
+    type Output = TreeStats;
+
+    fn add(self, other: TreeStats) -> TreeStats {
+        let mut beta_cutoffs = self.beta_cutoffs;
+        for (x, y) in beta_cutoffs.iter_mut().zip(other.beta_cutoffs.iter()) {
+            *x += *y;
+        }
+        TreeStats {
+            nodes: self.nodes + other.nodes,
+            qsearch_nodes: self.qsearch_nodes + other.qsearch_nodes,
+            tt_probes: self.tt_probes + other.tt_probes,
+            tt_hits: self.tt_hits + other.tt_hits,
+            beta_cutoffs: beta_cutoffs,
+            null_moves: self.null_moves + other.null_moves,
+            null_move_cutoffs: self.null_move_cutoffs + other.null_move_cutoffs,
+            reductions: self.reductions + other.reductions,
+            reduction_researches: self.reduction_researches + other.reduction_researches,
+        }
+    }
+}
+
+
+impl<T> fmt::Display for SearchReport<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f,
+                    "search {}: depth {}, nodes {}",
+                    self.search_id,
+                    self.depth,
+                    self.searched_nodes));
+        if self.value != VALUE_UNKNOWN {
+            try!(write!(f, ", value {}", self.value));
+        }
+        if self.done {
+            try!(write!(f, ", done"));
+        }
+        if let Some(reason) = self.termination {
+            try!(write!(f, " ({})", reason));
+        }
+        Ok(())
+    }
+}
+
+
 /// A trait for executing iterative deepening searches.
 ///
 /// Chess programs must rely on some type of search in order to play
@@ -262,19 +526,21 @@ pub trait Search: SetOption {
     /// * `messages` gives the receiving-half of control messages'
     ///   channel.
     ///
-    ///   Control messages' format is not specified, but the
-    ///   implementation **must** meet the following requirements:
-    ///
-    ///   * Unrecognized messages are ignored.
+    ///   Control messages' format is not specified. Unrecognized
+    ///   messages must be ignored.
     ///
-    ///   * The message `"TERMINATE"` is recognized as a request to
-    ///     terminate the search.
+    /// * `terminated` gives a flag that becomes `true` when the search
+    ///   must be terminated.
     ///
-    ///   * Receiving two or more termination requests does not cause
-    ///     problems.
+    ///   The search thread must check the flag very often (for
+    ///   example, once for every searched position), so that it stops
+    ///   within a fraction of a millisecond. Polling `messages` is
+    ///   not fast enough for this, because the search thread can not
+    ///   afford to do it often.
     fn spawn(params: SearchParams<Self::SearchNode>,
              tt: Arc<Self::Ttable>,
              reports: Sender<SearchReport<Self::ReportData>>,
-             messages: Receiver<String>)
+             messages: Receiver<String>,
+             terminated: Arc<AtomicBool>)
              -> thread::JoinHandle<Value>;
 }
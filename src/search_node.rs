@@ -1,12 +1,14 @@
 //! Defines the `SearchNode` trait.
 
 use uci::SetOption;
-use board::{Board, IllegalBoard};
+use board::{Board, Bitboard, IllegalBoard};
 use moves::{Move, MoveDigest, AddMove};
 use depth::*;
 use value::*;
 use evaluator::Evaluator;
+use move_generator::MoveGenerator;
 use qsearch::QsearchResult;
+use utils::BoardGeometry;
 
 
 /// A trait for chess positions -- a convenient interface for the
@@ -25,18 +27,24 @@ use qsearch::QsearchResult;
 /// 3. Quiescence search.
 /// 4. 50 move rule awareness.
 /// 5. Threefold/twofold repetition detection.
+/// 6. Insufficient material detection.
 ///
 /// **Important note:** Repeating positions are considered a draw
 /// after the first repetition, not after the second one as the chess
 /// rules prescribe. In order to compensate for that,
 /// `SearchNode::from_history` "forgets" all positions that have
 /// occurred exactly once. Also, the newly created instance is never
-/// deemed a draw due to repetition or rule-50.
+/// deemed a draw due to repetition, rule-50, or insufficient
+/// material.
 pub trait SearchNode: Clone + SetOption + Send + 'static {
     /// The type of static evaluator that the implementation works
     /// with.
     type Evaluator: Evaluator;
 
+    /// The type of move generator that the implementation works
+    /// with.
+    type MoveGenerator: MoveGenerator<Evaluator = Self::Evaluator>;
+
     /// The type of result object that `qsearch` returns.
     type QsearchResult: QsearchResult;
 
@@ -66,9 +74,60 @@ pub trait SearchNode: Clone + SetOption + Send + 'static {
     /// Starts at 1 and is incremented after black's move.
     fn fullmove_number(&self) -> u16;
 
+    /// Returns the number of half-moves played from the beginning of
+    /// the game to the last piece capture or pawn advance.
+    ///
+    /// Together with `halfmove_clock`, this is useful for displaying
+    /// how many moves remain until the game can be drawn by rule-50.
+    fn ply_of_last_irreversible(&self) -> u16 {
+        let ply = 2 * (self.fullmove_number() - 1) + self.board().to_move as u16;
+        ply.saturating_sub(self.halfmove_clock() as u16)
+    }
+
+    /// Returns the hash values for the boards that have occurred
+    /// since the last piece capture or pawn advance.
+    ///
+    /// The hashes are ordered from the earliest to the current board,
+    /// and equal values indicate repeated boards. This allows
+    /// external code (match managers, GUIs) to adjudicate draws by
+    /// repetition. Boards that occurred before the initial position
+    /// passed to `from_history` are unknown, and therefore omitted.
+    ///
+    /// The default implementation returns an empty vector, which
+    /// means that the implementation does not track the history.
+    fn history_hashes(&self) -> Vec<u64> {
+        vec![]
+    }
+
+    /// Returns if neither side has enough material to checkmate.
+    ///
+    /// See `Board::is_insufficient_material` for details.
+    fn is_insufficient_material(&self) -> bool {
+        self.board().is_insufficient_material()
+    }
+
     /// Returns if the side to move is in check.
     fn is_check(&self) -> bool;
 
+    /// Returns a bitboard with all enemy pieces and pawns that attack
+    /// the king of the side to move.
+    fn checkers(&self) -> Bitboard {
+        BoardGeometry::get().checkers(self.board())
+    }
+
+    /// Returns a bitboard with all pinned pieces and pawns belonging
+    /// to the side to move.
+    fn pinned(&self) -> Bitboard {
+        BoardGeometry::get().pinned(self.board())
+    }
+
+    /// Returns if the last played move gave check.
+    ///
+    /// If the last played move is unknown, `false` is returned.
+    fn last_move_gave_check(&self) -> bool {
+        self.last_move() != Move::invalid() && self.is_check()
+    }
+
     /// Returns a reference to a static evaluator bound to the current
     /// position.
     fn evaluator(&self) -> &Self::Evaluator;
@@ -77,7 +136,8 @@ pub trait SearchNode: Clone + SetOption + Send + 'static {
     ///
     /// In final positions this method will return the correct value
     /// of the position (`0` for a draw, `VALUE_MIN` for a
-    /// checkmate). A position is guaranteed to be final if
+    /// checkmate). Implementations may evaluate draws with a small
+    /// bias (contempt) instead of `0`. A position is guaranteed to be final if
     /// `generate_moves` method generates no legal moves. (It may
     /// generate some pseudo-legal moves, but if none of them is
     /// legal, then the position is final.)
@@ -159,6 +219,40 @@ pub trait SearchNode: Clone + SetOption + Send + 'static {
     /// rule-50 positions.
     fn generate_moves<T: AddMove>(&self, moves: &mut T);
 
+    /// Generates the first stage of all pseudo-legal moves -- the
+    /// captures and the pawn promotions to queen.
+    ///
+    /// Generating moves in stages saves time when one of the first
+    /// tried moves causes a beta cut-off. This method and
+    /// `generate_quiet_moves` generate disjoint sets of moves, and
+    /// together they generate exactly the moves that
+    /// `generate_moves` would generate. If the king is in check, all
+    /// moves are generated by this method.
+    fn generate_captures<T: AddMove>(&self, moves: &mut T) {
+        let mut all_moves = Vec::new();
+        self.generate_moves(&mut all_moves);
+        let is_check = self.is_check();
+        for m in all_moves {
+            if is_check || m.is_tactical() {
+                moves.add_move(m);
+            }
+        }
+    }
+
+    /// Generates the second stage of all pseudo-legal moves -- the
+    /// moves that `generate_captures` leaves out.
+    fn generate_quiet_moves<T: AddMove>(&self, moves: &mut T) {
+        if !self.is_check() {
+            let mut all_moves = Vec::new();
+            self.generate_moves(&mut all_moves);
+            for m in all_moves {
+                if !m.is_tactical() {
+                    moves.add_move(m);
+                }
+            }
+        }
+    }
+
     /// Verifies if the supplied move digest represents a proper move.
     ///
     /// If a move `m` exists that would be generated by
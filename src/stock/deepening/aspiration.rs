@@ -1,9 +1,11 @@
 //! Implements `Aspiration`.
 
 use super::{bogus_params, contains_dups};
+use super::trace::{trace, TraceEvent};
 use std::cmp::{min, max};
 use std::time::Duration;
 use std::sync::Arc;
+use std::sync::atomic::AtomicIsize;
 use std::sync::mpsc::TryRecvError;
 use uci::{SetOption, OptionDescription};
 use moves::Move;
@@ -11,7 +13,9 @@ use value::*;
 use depth::*;
 use ttable::*;
 use search_node::SearchNode;
-use search::{SearchParams, SearchReport};
+use search::{SearchParams, SearchReport, SearchDetails, TerminationReason, AspirationStats,
+             TreeStats};
+use utils::Tunable;
 
 // In this module we use the `DeepeningSearch` trait for depth-first
 // searches too, so we rename it to avoid confusion.
@@ -27,12 +31,45 @@ fn initial_window() -> isize {
 }
 
 
+/// When an aspirated search fails, the aspiration window is widened
+/// by this many eighths of its current half-width.
+static WINDOW_GROWTH: Tunable = Tunable {
+    name: "Tune Aspiration Window Growth",
+    min: 1,
+    max: 16,
+    default: 3,
+    value: AtomicIsize::new(3),
+};
+
+
+/// The outcome of a completed aspirated search.
+///
+/// This can be used to calculate the initial aspiration window for a
+/// subsequent (deeper) search from the same position.
+#[derive(Clone, Copy, Debug)]
+pub struct AspirationState {
+    /// The search depth.
+    pub depth: Depth,
+
+    /// The value found by the search.
+    pub value: Value,
+
+    /// Whether `value` is exact, an upper bound, or a lower bound.
+    pub bound: BoundType,
+
+    /// The half-width of the aspiration window when the search ended.
+    pub delta: isize,
+}
+
+
 /// Executes searches with aspiration windows.
-pub struct Aspiration<T: SearchExecutor> {
+pub struct Aspiration<T: SearchExecutor<ReportData = Vec<Move>>> {
     tt: Arc<T::Ttable>,
     params: SearchParams<T::SearchNode>,
     search_is_terminated: bool,
     previously_searched_nodes: u64,
+    previous_tree_stats: TreeStats,
+    selective_depth: Depth,
 
     // The real work will be handed over to `searcher`.
     searcher: T,
@@ -53,13 +90,31 @@ pub struct Aspiration<T: SearchExecutor> {
     // Indicates that the aspirated search will most probably fail high.
     expected_to_fail_high: bool,
 
+    // The depth of the last started aspirated search.
+    searched_depth: Depth,
+
+    // If set, the initial aspiration window for the next search will
+    // be calculated from this, instead of from the transposition
+    // table.
+    state_hint: Option<AspirationState>,
+
+    // Statistics about the aspiration windows of the current search.
+    stats: AspirationStats,
+
+    // The principal variation found by the last completed aspirated
+    // search.
+    pv: Vec<Move>,
+
+    // Messages for the user that will be sent with the next report.
+    messages: Vec<String>,
+
     /// `Aspiration` has a special mode which can be used to implement
     /// late move reductions.
     pub lmr_mode: bool,
 }
 
 
-impl<T: SearchExecutor> SearchExecutor for Aspiration<T> {
+impl<T: SearchExecutor<ReportData = Vec<Move>>> SearchExecutor for Aspiration<T> {
     type Ttable = T::Ttable;
 
     type SearchNode = T::SearchNode;
@@ -72,6 +127,8 @@ impl<T: SearchExecutor> SearchExecutor for Aspiration<T> {
             params: bogus_params(),
             search_is_terminated: false,
             previously_searched_nodes: 0,
+            previous_tree_stats: TreeStats::default(),
+            selective_depth: 0,
             lmr_mode: false,
             searcher: T::new(tt),
             value: VALUE_UNKNOWN,
@@ -79,6 +136,11 @@ impl<T: SearchExecutor> SearchExecutor for Aspiration<T> {
             beta: VALUE_MAX,
             delta: 0,
             expected_to_fail_high: false,
+            searched_depth: 0,
+            state_hint: None,
+            stats: AspirationStats::default(),
+            pv: vec![],
+            messages: vec![],
         }
     }
 
@@ -92,7 +154,11 @@ impl<T: SearchExecutor> SearchExecutor for Aspiration<T> {
         self.params = params;
         self.search_is_terminated = false;
         self.previously_searched_nodes = 0;
+        self.previous_tree_stats = TreeStats::default();
+        self.selective_depth = 0;
         self.value = VALUE_UNKNOWN;
+        self.stats = AspirationStats::default();
+        self.pv.clear();
         self.calc_initial_aspiration_window();
         self.start_aspirated_search();
     }
@@ -102,9 +168,18 @@ impl<T: SearchExecutor> SearchExecutor for Aspiration<T> {
             searched_nodes,
             depth,
             value,
+            data,
             done,
+            termination,
+            details,
             ..
         } = try!(self.searcher.try_recv_report());
+        if done &&
+           (termination == Some(TerminationReason::NodeLimitReached) ||
+            termination == Some(TerminationReason::Failed)) {
+            self.search_is_terminated = true;
+        }
+        self.selective_depth = max(self.selective_depth, details.selective_depth);
         let mut report = SearchReport {
             search_id: self.params.search_id,
             searched_nodes: self.previously_searched_nodes + searched_nodes,
@@ -112,18 +187,33 @@ impl<T: SearchExecutor> SearchExecutor for Aspiration<T> {
             value: self.value,
             data: vec![],
             done: done,
+            termination: termination,
+            details: SearchDetails {
+                selective_depth: self.selective_depth,
+                tree: details.tree.map(|t| self.previous_tree_stats + t),
+                current_move: details.current_move,
+                messages: details.messages,
+                ..Default::default()
+            },
         };
+        if let (true, Some(t)) = (done, report.details.tree) {
+            self.previous_tree_stats = t;
+        }
         if done && !self.search_is_terminated {
             self.previously_searched_nodes = report.searched_nodes;
             self.value = value;
+            self.pv = data;
             if self.widen_aspiration_window(value) {
                 self.start_aspirated_search();
                 report.done = false;
+                report.termination = None;
             } else {
                 report.depth = depth;
             }
             report.value = value;
         }
+        report.details.aspiration = Some(self.stats);
+        report.details.messages.extend(self.messages.drain(..));
         Ok(report)
     }
 
@@ -140,7 +230,7 @@ impl<T: SearchExecutor> SearchExecutor for Aspiration<T> {
 }
 
 
-impl<T: SearchExecutor> SetOption for Aspiration<T> {
+impl<T: SearchExecutor<ReportData = Vec<Move>>> SetOption for Aspiration<T> {
     fn options() -> Vec<(&'static str, OptionDescription)> {
         let mut options = vec![("Initial Aspiration Window",
                                 OptionDescription::Spin {
@@ -148,29 +238,72 @@ impl<T: SearchExecutor> SetOption for Aspiration<T> {
                                     max: 10000,
                                     default: 16,
                                 })];
+        options.extend(Tunable::options(&[&WINDOW_GROWTH]));
         options.extend(T::options());
         options
     }
 
-    fn set_option(name: &str, value: &str) {
-        T::set_option(name, value)
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        if Tunable::set_option(&[&WINDOW_GROWTH], name, value) {
+            Ok(())
+        } else {
+            T::set_option(name, value)
+        }
     }
 }
 
 
-impl<T: SearchExecutor> Aspiration<T> {
+impl<T: SearchExecutor<ReportData = Vec<Move>>> Aspiration<T> {
+    /// Sets the state from which the initial aspiration window for
+    /// the next search will be calculated.
+    ///
+    /// This is useful when the same position is searched repeatedly
+    /// with different windows (for example, the root moves in
+    /// multi-PV mode), so that the transposition table does not
+    /// contain reliable information. The hint is consumed by the next
+    /// call to `start_search`.
+    pub fn set_state_hint(&mut self, state: Option<AspirationState>) {
+        self.state_hint = state;
+    }
+
+    /// Returns the principal variation found by the last completed
+    /// search.
+    ///
+    /// The principal variation may be incomplete, or even empty.
+    pub fn pv(&self) -> &[Move] {
+        &self.pv
+    }
+
+    /// Returns the outcome of the last completed search.
+    pub fn state(&self) -> AspirationState {
+        AspirationState {
+            depth: self.searched_depth,
+            value: self.value,
+            bound: match self.value {
+                v if v <= self.alpha => BOUND_UPPER,
+                v if v >= self.beta => BOUND_LOWER,
+                _ => BOUND_EXACT,
+            },
+            delta: self.delta,
+        }
+    }
+
     fn start_aspirated_search(&mut self) {
         let depth = if self.lmr_mode && self.expected_to_fail_high && self.params.depth > 0 {
             self.params.depth - 1
         } else {
             self.params.depth
         };
+        self.searched_depth = depth;
+        self.stats.window = (self.alpha, self.beta);
+        let node_limit = self.params.remaining_nodes(self.previously_searched_nodes);
         self.searcher
             .start_search(SearchParams {
                               search_id: 0,
                               depth: depth,
                               lower_bound: self.alpha,
                               upper_bound: self.beta,
+                              node_limit: node_limit,
                               ..self.params.clone()
                           });
     }
@@ -184,13 +317,26 @@ impl<T: SearchExecutor> Aspiration<T> {
             ..
         } = self.params;
         let (mut a, mut b) = (VALUE_MIN, VALUE_MAX);
-        if let Some(e) = self.tt.probe(self.params.position.hash()) {
-            if e.depth() >= 4 && e.depth() + 2 >= self.params.depth {
-                let v = e.value() as isize;
-                if e.bound() & BOUND_LOWER != 0 {
+        let previous = match self.state_hint.take() {
+            Some(s) => {
+                // Start with a window which is between the initial
+                // window and the final window of the previous search.
+                self.delta = max(self.delta, (self.delta + s.delta) / 2);
+                Some((s.depth, s.value, s.bound))
+            }
+            None => {
+                self.tt
+                    .probe(self.params.position.hash())
+                    .map(|e| (e.depth(), e.value(), e.bound()))
+            }
+        };
+        if let Some((depth, value, bound)) = previous {
+            if depth >= 4 && depth + 2 >= self.params.depth {
+                let v = value as isize;
+                if bound & BOUND_LOWER != 0 {
                     a = max(v - self.delta, VALUE_MIN as isize) as Value;
                 }
-                if e.bound() & BOUND_UPPER != 0 {
+                if bound & BOUND_UPPER != 0 {
                     b = min(v + self.delta, VALUE_MAX as isize) as Value;
                 }
                 debug_assert!(a < b);
@@ -221,19 +367,44 @@ impl<T: SearchExecutor> Aspiration<T> {
            self.lmr_mode && self.expected_to_fail_high && v < upper_bound {
             // Failed low -- reduce alpha.
             self.alpha = max(v as isize - self.delta, lower_bound as isize) as Value;
+            self.stats.fail_lows += 1;
+            if !self.lmr_mode {
+                let event = TraceEvent::FailLow {
+                    depth: self.params.depth,
+                    value: v,
+                    alpha: self.alpha,
+                    beta: self.beta,
+                };
+                if let Err(e) = trace(event) {
+                    self.messages.push(e);
+                }
+            }
         } else if self.beta < upper_bound && self.beta <= v && v < upper_bound {
             // Failed high -- raise beta.
             self.beta = min(v as isize + self.delta, upper_bound as isize) as Value;
+            self.stats.fail_highs += 1;
+            if !self.lmr_mode {
+                let event = TraceEvent::FailHigh {
+                    depth: self.params.depth,
+                    value: v,
+                    alpha: self.alpha,
+                    beta: self.beta,
+                };
+                if let Err(e) = trace(event) {
+                    self.messages.push(e);
+                }
+            }
         } else {
             return false;
         }
         self.expected_to_fail_high = false;
+        self.stats.researches += 1;
         self.increase_delta();
         true
     }
 
     fn increase_delta(&mut self) {
-        self.delta += 3 * self.delta / 8;
+        self.delta += WINDOW_GROWTH.get() * self.delta / 8;
         if self.delta > 64 * initial_window() {
             self.delta = 1_000_000;
         }
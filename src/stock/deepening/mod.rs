@@ -3,12 +3,18 @@
 
 mod aspiration;
 mod multipv;
+mod trace;
 
 use self::multipv::Multipv;
+use self::trace::TraceEvent;
+use std::mem;
+use std::any::Any;
 use std::thread;
+use std::cmp::min;
 use std::time::Duration;
 use std::cell::RefCell;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Sender, Receiver, TryRecvError};
 use regex::Regex;
 use uci::{SetOption, OptionDescription};
@@ -17,7 +23,8 @@ use value::*;
 use depth::*;
 use ttable::*;
 use search_node::SearchNode;
-use search::{Search, SearchParams, SearchReport};
+use qsearch::QsearchResult;
+use search::{Search, SearchParams, SearchReport, SearchDetails, TerminationReason, TreeStats};
 
 // In this module we use the `DeepeningSearch` trait for depth-first
 // searches too, so we rename it to avoid confusion.
@@ -28,6 +35,12 @@ use search::DeepeningSearch as SearchExecutor;
 /// Executes searches with iterative deepening, aspiration windows,
 /// multi-PV, and "searchmoves".
 ///
+/// `T` can be any `Search` implementation, working with any
+/// `SearchNode` implementation (`T::SearchNode`). Nothing here is tied
+/// to the "in stock" search node, so an engine that brings its own
+/// position representation can still reuse the whole search stack --
+/// `Deepening<SimpleSearch<StdTtable<StdTtableEntry>, MyNode>>`.
+///
 /// *Iterative deepening* works as follows: A depth-first search is
 /// executed with a depth of one ply, then the depth is incremented
 /// and another search is executed. This process is repeated until the
@@ -60,10 +73,60 @@ use search::DeepeningSearch as SearchExecutor;
 ///
 /// **Important note:** `Deepening` requires a proper transposition
 /// table to do its work. It can not work with `DummyTtable`.
-pub struct Deepening<T: Search> {
+///
+/// # Search traces
+///
+/// The root-level decisions made during the search (best move, value,
+/// and searched nodes for each completed depth, and failed aspirated
+/// searches) can be recorded to a file, and later replayed in "check
+/// mode", which reports the first divergence from the recorded trace
+/// with `SearchDetails::messages`. This is controlled by the "Search
+/// Trace Mode" (`Off`, `Record`, or `Check`) and "Search Trace File"
+/// configuration options.
+///
+/// # Avoiding shuffling
+///
+/// When the engine is winning, but the evaluation has not changed
+/// during the last few root positions while the rule-50 counter
+/// grew, it is probably shuffling pieces without making progress. In
+/// this case a small bonus is added to the values of the root moves
+/// that reset the rule-50 counter (pawn advances and captures), so
+/// that the engine converts its advantage instead of drifting into a
+/// draw. This can be disabled with the "Avoid Shuffling"
+/// configuration option.
+///
+/// # Avoiding repetitions
+///
+/// When the engine is winning, a small penalty is subtracted from
+/// the values of the root moves that lead to boards which already
+/// occurred earlier in the game (including the history passed by the
+/// GUI), so that the opponent is not given easy chances to claim a
+/// draw by repetition. This can be disabled with the "Avoid
+/// Repetitions" configuration option.
+///
+/// # Verifying the best move
+///
+/// When the search is terminated before the last iteration has been
+/// completed, the best move from the deepest completed iteration is
+/// verified with a very shallow search. If it looks much worse than
+/// the best move from an earlier iteration (this may happen when the
+/// best move has just changed after an aspiration failure), the final
+/// report carries the principal variations from the earlier
+/// iteration, so that the engine plays its best move instead.
+///
+/// # Principal variations
+///
+/// When `T::ReportData` is `Vec<Move>`, the reports from `T` are
+/// expected to carry the principal variation found by the search.
+/// Report data of any other type is ignored, and the principal
+/// variations are taken from the transposition table only.
+pub struct Deepening<T: Search>
+    where T::ReportData: 'static
+{
     params: SearchParams<T::SearchNode>,
     search_is_terminated: bool,
     previously_searched_nodes: u64,
+    previous_tree_stats: TreeStats,
 
     // The real work will be handed over to `multipv`.
     multipv: Multipv<ThreadExecutor<T>>,
@@ -76,10 +139,25 @@ pub struct Deepening<T: Search> {
 
     // The depth at which the search are likely to be terminated.
     depth_target: Depth,
+
+    // The rule-50 counters and the values for the recently searched
+    // root positions.
+    root_history: Vec<(u8, Value)>,
+
+    // The principal variations from the deepest completed iteration,
+    // and from the deepest completed iteration that had a different
+    // best move.
+    variations: Vec<Variation>,
+    previous_variations: Vec<Variation>,
+
+    // Messages for the user that will be sent with the next report.
+    messages: Vec<String>,
 }
 
 
-impl<T: Search> SearchExecutor for Deepening<T> {
+impl<T: Search> SearchExecutor for Deepening<T>
+    where T::ReportData: 'static
+{
     type Ttable = T::Ttable;
 
     type SearchNode = T::SearchNode;
@@ -91,10 +169,15 @@ impl<T: Search> SearchExecutor for Deepening<T> {
             params: bogus_params(),
             search_is_terminated: false,
             previously_searched_nodes: 0,
+            previous_tree_stats: TreeStats::default(),
             multipv: Multipv::new(tt),
             depth: 0,
             value: VALUE_UNKNOWN,
             depth_target: DEPTH_MAX,
+            root_history: vec![],
+            variations: vec![],
+            previous_variations: vec![],
+            messages: vec![],
         }
     }
 
@@ -105,12 +188,31 @@ impl<T: Search> SearchExecutor for Deepening<T> {
         debug_assert!(params.upper_bound <= VALUE_MAX);
         debug_assert!(params.lower_bound < params.upper_bound);
         debug_assert!(!contains_dups(&params.searchmoves));
+        if self.value != VALUE_UNKNOWN {
+            self.root_history.push((self.params.position.halfmove_clock(), self.value));
+        }
         self.params = params;
         self.search_is_terminated = false;
         self.previously_searched_nodes = 0;
+        self.previous_tree_stats = TreeStats::default();
         self.depth = 0;
         self.value = VALUE_UNKNOWN;
         self.depth_target = DEPTH_MAX;
+        self.variations = vec![];
+        self.previous_variations = vec![];
+        self.multipv.progress_bonus = if self.is_shuffling() {
+            PROGRESS_BONUS
+        } else {
+            0
+        };
+        self.multipv.repetition_penalty = if self.is_winning() {
+            REPETITION_PENALTY
+        } else {
+            0
+        };
+        if let Err(e) = trace::start_search(self.params.position.hash(), self.params.depth) {
+            self.messages.push(e);
+        }
         self.search_next_depth();
     }
 
@@ -121,8 +223,15 @@ impl<T: Search> SearchExecutor for Deepening<T> {
             value,
             data,
             done,
+            termination,
+            details,
             ..
         } = try!(self.multipv.try_recv_report());
+        if done &&
+           (termination == Some(TerminationReason::NodeLimitReached) ||
+            termination == Some(TerminationReason::Failed)) {
+            self.search_is_terminated = true;
+        }
         if value != VALUE_UNKNOWN {
             self.value = value;
         }
@@ -137,18 +246,45 @@ impl<T: Search> SearchExecutor for Deepening<T> {
             value: self.value,
             data: vec![],
             done: done,
+            termination: termination,
+            details: SearchDetails {
+                tree: details.tree.map(|t| self.previous_tree_stats + t),
+                ..details
+            },
         };
+        if let (true, Some(t)) = (done, report.details.tree) {
+            self.previous_tree_stats = t;
+        }
         if done && !self.search_is_terminated {
             debug_assert_eq!(depth, self.depth + 1);
             report.depth = depth;
             report.data.extend(self.multipv.extract_variations());
+            if let Some(v) = report.data.first() {
+                let event = TraceEvent::Iteration {
+                    depth: depth,
+                    best_move: v.moves.first().cloned().unwrap_or(Move::invalid()),
+                    value: v.value,
+                    searched_nodes: report.searched_nodes,
+                };
+                if let Err(e) = trace::trace(event) {
+                    self.messages.push(e);
+                }
+            }
+            self.remember_variations(&report.data);
             self.previously_searched_nodes = report.searched_nodes;
             self.depth = depth;
             if depth < self.params.depth {
                 self.search_next_depth();
                 report.done = false;
+                report.termination = None;
+            } else {
+                report.termination = Some(self.termination_reason());
             }
+        } else if done && self.best_move_is_blunder() {
+            // The last iteration has not been completed.
+            report.data = self.previous_variations.clone();
         }
+        report.details.messages.extend(self.messages.drain(..));
         Ok(report)
     }
 
@@ -177,66 +313,228 @@ impl<T: Search> SearchExecutor for Deepening<T> {
 }
 
 
-impl<T: Search> SetOption for Deepening<T> {
+impl<T: Search> SetOption for Deepening<T>
+    where T::ReportData: 'static
+{
     fn options() -> Vec<(&'static str, OptionDescription)> {
-        Multipv::<ThreadExecutor<T>>::options()
+        let mut options = vec![("Avoid Shuffling", OptionDescription::Check { default: true }),
+                               ("Avoid Repetitions", OptionDescription::Check { default: true })];
+        options.extend(trace::options());
+        options.extend(Multipv::<ThreadExecutor<T>>::options());
+        options
     }
 
-    fn set_option(name: &str, value: &str) {
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
         Multipv::<ThreadExecutor<T>>::set_option(name, value)
     }
 }
 
 
-impl<T: Search> Deepening<T> {
+impl<T: Search> Deepening<T>
+    where T::ReportData: 'static
+{
+    /// Returns if the engine seems to be shuffling pieces in a won
+    /// position.
+    ///
+    /// This is the case when the rule-50 counter has grown during
+    /// each of the last `SHUFFLING_ROOTS` searched root positions
+    /// (including the current one), and the values for those
+    /// positions were winning, but did not change significantly.
+    fn is_shuffling(&mut self) -> bool {
+        if ::get_option("Avoid Shuffling") != "true" {
+            return false;
+        }
+        let n = self.root_history.len();
+        if n > SHUFFLING_ROOTS {
+            self.root_history.drain(..n - SHUFFLING_ROOTS);
+        }
+        if self.root_history.len() < SHUFFLING_ROOTS {
+            return false;
+        }
+        let mut clocks: Vec<u8> = self.root_history.iter().map(|x| x.0).collect();
+        clocks.push(self.params.position.halfmove_clock());
+        let values: Vec<Value> = self.root_history.iter().map(|x| x.1).collect();
+        let min_value = *values.iter().min().unwrap();
+        let max_value = *values.iter().max().unwrap();
+        clocks.windows(2).all(|w| w[0] < w[1]) && min_value >= SHUFFLING_MIN_VALUE &&
+        max_value <= VALUE_EVAL_MAX && max_value - min_value <= SHUFFLING_MAX_VALUE_CHANGE
+    }
+
+    /// Returns if the engine was winning in the previously searched
+    /// root position, so that repetitions should be avoided.
+    fn is_winning(&self) -> bool {
+        if ::get_option("Avoid Repetitions") != "true" {
+            return false;
+        }
+        match self.root_history.last() {
+            Some(&(_, v)) => v >= REPETITION_MIN_VALUE && v <= VALUE_EVAL_MAX,
+            None => false,
+        }
+    }
+
+    /// Remembers the principal variations from a completed iteration.
+    fn remember_variations(&mut self, variations: &[Variation]) {
+        let best_move = |v: &[Variation]| v.first().and_then(|x| x.moves.first()).cloned();
+        let new_best_move = best_move(variations);
+        if new_best_move.is_some() && new_best_move != best_move(&self.variations) {
+            self.previous_variations = mem::replace(&mut self.variations, variations.to_vec());
+        } else if !variations.is_empty() {
+            self.variations = variations.to_vec();
+        }
+    }
+
+    /// Returns if the best move from the deepest completed iteration
+    /// looks much worse than the best move from the deepest completed
+    /// iteration that had a different best move.
+    fn best_move_is_blunder(&self) -> bool {
+        const BLUNDER_MARGIN: isize = 300;
+        let best_move = |v: &[Variation]| v.first().and_then(|x| x.moves.first()).cloned();
+        match (best_move(&self.variations), best_move(&self.previous_variations)) {
+            (Some(m), Some(previous)) => {
+                let position = &self.params.position;
+                (verify_move(position, m) as isize) <
+                (verify_move(position, previous) as isize) - BLUNDER_MARGIN
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the reason why a search that has reached the
+    /// requested depth has ended.
+    fn termination_reason(&self) -> TerminationReason {
+        match self.params.searchmoves.len() {
+            0 if self.value > VALUE_MIN => TerminationReason::DrawForced,
+            0 => TerminationReason::MateFound,
+            1 => TerminationReason::SingleMove,
+            _ if mate_distance(self.value).is_some() => TerminationReason::MateFound,
+            _ => TerminationReason::DepthReached,
+        }
+    }
+
     fn search_next_depth(&mut self) {
+        let node_limit = self.params.remaining_nodes(self.previously_searched_nodes);
         self.multipv
             .start_search(SearchParams {
                               search_id: 0,
                               depth: self.depth + 1,
+                              node_limit: node_limit,
                               ..self.params.clone()
                           });
     }
 }
 
 
+/// The number of recently searched root positions that are inspected
+/// to detect shuffling.
+const SHUFFLING_ROOTS: usize = 6;
+
+/// The minimal value (in centipawns) for a position to be considered
+/// as won, when detecting shuffling.
+const SHUFFLING_MIN_VALUE: Value = 150;
+
+/// The maximal change in the value (in centipawns) during the
+/// inspected root positions, for the evaluation to be considered as
+/// flat.
+const SHUFFLING_MAX_VALUE_CHANGE: Value = 30;
+
+/// The bonus (in centipawns) for the root moves that reset the
+/// rule-50 counter, when shuffling is detected.
+const PROGRESS_BONUS: Value = 15;
+
+/// The minimal value (in centipawns) for a position to be considered
+/// as won, when avoiding repetitions.
+const REPETITION_MIN_VALUE: Value = 100;
+
+/// The penalty (in centipawns) for the root moves that lead to
+/// previously encountered boards, when the engine is winning.
+const REPETITION_PENALTY: Value = 20;
+
+
+/// A helper function. It performs a very shallow search to verify a
+/// move, and returns move's value.
+///
+/// Every possible reply is tried, followed by a quiescence search.
+/// This is enough to detect moves that leave a piece hanging.
+fn verify_move<N: SearchNode>(position: &N, m: Move) -> Value {
+    let mut p = position.clone();
+    if !p.do_move(m) {
+        return VALUE_MIN;
+    }
+    let replies = p.legal_moves();
+    if replies.is_empty() {
+        return -p.evaluate_final();
+    }
+    let mut value = VALUE_MAX;
+    for reply in replies {
+        p.do_move(reply);
+        value = min(value,
+                    p.qsearch(0, VALUE_MIN, VALUE_MAX, VALUE_UNKNOWN).value());
+        p.undo_last_move();
+    }
+    value
+}
+
+
 /// A helper type. It turns a `Search` into `SearchExecutor`.
+///
+/// `"TERMINATE"` messages set the termination flag of the search
+/// thread. All other messages are sent to the search thread. If the
+/// search thread panics, a final report with
+/// `TerminationReason::Failed` is produced in place of the one that
+/// the thread did not send, so that the search does not hang. The
+/// data carried by the reports is converted to a principal variation
+/// (see `report_data_to_pv`).
 struct ThreadExecutor<T: Search> {
     tt: Arc<T::Ttable>,
+    search_id: usize,
     messages_tx: Sender<String>,
+    terminated: Arc<AtomicBool>,
     reports_rx: Receiver<SearchReport<T::ReportData>>,
     reports_tx: Sender<SearchReport<T::ReportData>>,
     pending_report: RefCell<Option<SearchReport<T::ReportData>>>,
     handle: Option<thread::JoinHandle<Value>>,
+    searched_nodes: u64,
+    has_failed: bool,
 }
 
-impl<T: Search> SearchExecutor for ThreadExecutor<T> {
+impl<T: Search> SearchExecutor for ThreadExecutor<T>
+    where T::ReportData: 'static
+{
     type Ttable = T::Ttable;
 
     type SearchNode = T::SearchNode;
 
-    type ReportData = T::ReportData;
+    type ReportData = Vec<Move>;
 
     fn new(tt: Arc<Self::Ttable>) -> Self {
         let (reports_tx, reports_rx) = channel();
         Self {
             tt: tt,
+            search_id: 0,
             messages_tx: channel().0,
+            terminated: Arc::new(AtomicBool::new(false)),
             reports_rx: reports_rx,
             reports_tx: reports_tx,
             pending_report: RefCell::new(None),
             handle: None,
+            searched_nodes: 0,
+            has_failed: false,
         }
     }
 
     fn start_search(&mut self, params: SearchParams<Self::SearchNode>) {
         let (messages_tx, messages_rx) = channel();
         self.messages_tx = messages_tx;
+        self.terminated = Arc::new(AtomicBool::new(false));
         self.handle.take().and_then(|h| h.join().ok());
+        self.has_failed = false;
+        self.searched_nodes = 0;
+        self.search_id = params.search_id;
         self.handle = Some(T::spawn(params,
                                     self.tt.clone(),
                                     self.reports_tx.clone(),
-                                    messages_rx));
+                                    messages_rx,
+                                    self.terminated.clone()));
     }
 
     fn wait_report(&self, timeout_after: Duration) {
@@ -247,15 +545,54 @@ impl<T: Search> SearchExecutor for ThreadExecutor<T> {
     }
 
     fn try_recv_report(&mut self) -> Result<SearchReport<Self::ReportData>, TryRecvError> {
-        self.pending_report
+        // The reports that a panicked search thread has managed to
+        // send are received first.
+        if self.handle.as_ref().map_or(false, |h| h.is_finished()) &&
+           self.handle.take().unwrap().join().is_err() {
+            self.has_failed = true;
+        }
+        let report = self.pending_report
             .borrow_mut()
             .take()
             .ok_or(TryRecvError::Empty)
-            .or_else(|_| self.reports_rx.try_recv())
+            .or_else(|_| self.reports_rx.try_recv());
+        match report {
+            Err(TryRecvError::Empty) if self.has_failed => {
+                self.has_failed = false;
+                Ok(SearchReport {
+                       search_id: self.search_id,
+                       searched_nodes: self.searched_nodes,
+                       depth: 0,
+                       value: VALUE_UNKNOWN,
+                       data: vec![],
+                       done: true,
+                       termination: Some(TerminationReason::Failed),
+                       details: Default::default(),
+                   })
+            }
+            Ok(r) => {
+                self.searched_nodes = r.searched_nodes;
+                Ok(SearchReport {
+                       search_id: r.search_id,
+                       searched_nodes: r.searched_nodes,
+                       depth: r.depth,
+                       value: r.value,
+                       data: report_data_to_pv(r.data),
+                       done: r.done,
+                       termination: r.termination,
+                       details: r.details,
+                   })
+            }
+            Err(e) => Err(e),
+        }
     }
 
     fn send_message(&mut self, msg: &str) {
-        self.messages_tx.send(msg.to_string()).ok();
+        if msg == "TERMINATE" {
+            self.terminated.store(true, Ordering::Relaxed);
+        } else {
+            self.messages_tx.send(msg.to_string()).ok();
+        }
     }
 }
 
@@ -264,12 +601,21 @@ impl<T: Search> SetOption for ThreadExecutor<T> {
         T::options()
     }
 
-    fn set_option(name: &str, value: &str) {
-        T::set_option(name, value);
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        T::set_option(name, value)
     }
 }
 
 
+/// A helper function. It returns the principal variation carried by
+/// search report's data, or an empty list if the data is not a list
+/// of moves.
+fn report_data_to_pv<D: 'static>(data: D) -> Vec<Move> {
+    let data: Box<Any> = Box::new(data);
+    data.downcast::<Vec<Move>>().map(|pv| *pv).unwrap_or(vec![])
+}
+
+
 /// A helper function. It returns bogus search parameters.
 fn bogus_params<T: SearchNode>() -> SearchParams<T> {
     const FEN: &'static str = "7k/8/8/8/8/8/8/7K w - - 0 1";
@@ -282,6 +628,7 @@ fn bogus_params<T: SearchNode>() -> SearchParams<T> {
         lower_bound: VALUE_MIN,
         upper_bound: VALUE_MAX,
         searchmoves: vec![Move::invalid()],
+        node_limit: None,
     }
 }
 
@@ -305,3 +652,209 @@ fn contains_same_moves(list1: &Vec<Move>, list2: &Vec<Move>) -> bool {
     list2.sort();
     list1 == list2
 }
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc::{Sender, Receiver};
+    use uci::SetOption;
+    use moves::Move;
+    use value::*;
+    use ttable::*;
+    use search_node::SearchNode;
+    use search::*;
+    use stock::{StdTtable, StdTtableEntry, StdSearchNode, StdQsearch, StdMoveGenerator,
+                SimpleEvaluator, SimpleSearch};
+    use super::Deepening;
+
+    type Tt = StdTtable<StdTtableEntry>;
+    type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+
+    /// Starts a search for each of the given root positions in turn,
+    /// pretending that the value found for the root position is the
+    /// given one. Only the rule-50 counter changes between the root
+    /// positions.
+    fn search_roots(roots: &[(u8, Value)]) -> Deepening<SimpleSearch<Tt, P>> {
+        for (name, description) in Deepening::<SimpleSearch<Tt, P>>::options() {
+            ::CONFIGURATION.write().unwrap().entry(name).or_insert(description.get_default());
+        }
+        let mut search = Deepening::<SimpleSearch<Tt, P>>::new(Arc::new(Tt::new(None)));
+        for &(halfmove_clock, value) in roots {
+            let fen = format!("7k/8/8/8/8/8/8/R6K w - - {} 60", halfmove_clock);
+            let position = P::from_history(&fen, &mut vec![].into_iter()).ok().unwrap();
+            search.start_search(SearchParams {
+                                    search_id: 0,
+                                    depth: 1,
+                                    lower_bound: VALUE_MIN,
+                                    upper_bound: VALUE_MAX,
+                                    searchmoves: position.legal_moves(),
+                                    node_limit: None,
+                                    position: position,
+                                });
+            search.value = value;
+        }
+        search.send_message("TERMINATE");
+        search
+    }
+
+    /// A search that panics when asked to search deeper than one
+    /// half-move.
+    struct CrashingSearch;
+
+    impl SetOption for CrashingSearch {}
+
+    impl Search for CrashingSearch {
+        type Ttable = Tt;
+        type SearchNode = P;
+        type ReportData = Vec<Move>;
+
+        fn spawn(params: SearchParams<P>,
+                 tt: Arc<Tt>,
+                 reports: Sender<SearchReport<Vec<Move>>>,
+                 messages: Receiver<String>,
+                 terminated: Arc<AtomicBool>)
+                 -> thread::JoinHandle<Value> {
+            if params.depth <= 1 {
+                SimpleSearch::<Tt, P>::spawn(params, tt, reports, messages, terminated)
+            } else {
+                thread::spawn(|| panic!("the search has crashed"))
+            }
+        }
+    }
+
+    #[test]
+    fn search_failure() {
+        for (name, description) in Deepening::<CrashingSearch>::options() {
+            ::CONFIGURATION.write().unwrap().entry(name).or_insert(description.get_default());
+        }
+        let position = P::from_history("7k/8/8/8/8/8/8/R6K w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let mut search = Deepening::<CrashingSearch>::new(Arc::new(Tt::new(None)));
+        search.start_search(SearchParams {
+                                search_id: 1,
+                                depth: 5,
+                                lower_bound: VALUE_MIN,
+                                upper_bound: VALUE_MAX,
+                                searchmoves: position.legal_moves(),
+                                node_limit: None,
+                                position: position,
+                            });
+        let mut variations = vec![];
+        let report = loop {
+            search.wait_report(Duration::from_millis(10));
+            if let Ok(r) = search.try_recv_report() {
+                if r.done {
+                    break r;
+                }
+                if !r.data.is_empty() {
+                    variations = r.data;
+                }
+            }
+        };
+
+        // The results from the first iteration are not lost.
+        assert_eq!(report.search_id, 1);
+        assert_eq!(report.termination, Some(TerminationReason::Failed));
+        assert_eq!(report.depth, 1);
+        assert!(report.value != VALUE_UNKNOWN);
+        assert!(!variations.is_empty());
+    }
+
+    #[test]
+    fn report_data_to_pv() {
+        use super::report_data_to_pv;
+        let pv = vec![Move::invalid()];
+        assert_eq!(report_data_to_pv(pv.clone()), pv);
+        assert!(report_data_to_pv(()).is_empty());
+        assert!(report_data_to_pv(vec![0u8]).is_empty());
+    }
+
+    #[test]
+    fn verify_best_move() {
+        use utils::parse_san;
+        use super::verify_move;
+        let position = P::from_history("4k3/8/2p5/3p4/8/8/3Q4/4K3 w - - 0 1",
+                                       &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let moves = position.legal_moves();
+        let blunder = parse_san("Qxd5", &moves).ok().unwrap();
+        let safe = parse_san("Ke2", &moves).ok().unwrap();
+        assert!(verify_move(&position, blunder) < verify_move(&position, safe) - 500);
+
+        let variation = |m: Move| {
+            Variation {
+                moves: vec![m],
+                value: 0,
+                bound: BOUND_EXACT,
+            }
+        };
+        let mut search = Deepening::<SimpleSearch<Tt, P>>::new(Arc::new(Tt::new(None)));
+        search.params.position = position;
+        search.remember_variations(&[variation(safe)]);
+        search.remember_variations(&[variation(safe)]);
+        assert!(!search.best_move_is_blunder());
+        search.remember_variations(&[variation(blunder)]);
+        assert_eq!(search.previous_variations[0].moves, vec![safe]);
+        assert!(search.best_move_is_blunder());
+        search.remember_variations(&[variation(safe)]);
+        assert!(!search.best_move_is_blunder());
+    }
+
+    #[test]
+    fn avoid_shuffling() {
+        use super::{SHUFFLING_ROOTS, PROGRESS_BONUS};
+        let progress_bonus = |roots: &[(u8, Value)]| search_roots(roots).multipv.progress_bonus;
+        let flat: Vec<(u8, Value)> = (0..SHUFFLING_ROOTS as u8 + 1)
+            .map(|i| (10 + i, 300))
+            .collect();
+        assert_eq!(progress_bonus(&flat[..SHUFFLING_ROOTS]), 0);
+        assert_eq!(progress_bonus(&flat), PROGRESS_BONUS);
+
+        // The rule-50 counter has been reset.
+        let roots = [(10, 300), (11, 300), (12, 300), (0, 300), (1, 300), (2, 300), (3, 300),
+                     (4, 300), (5, 300)];
+        assert_eq!(progress_bonus(&roots), 0);
+        let mut roots = roots.to_vec();
+        roots.push((6, 300));
+        assert_eq!(progress_bonus(&roots), PROGRESS_BONUS);
+
+        // The value has changed, or is not winning.
+        let mut roots = flat.clone();
+        roots[3].1 = 340;
+        assert_eq!(progress_bonus(&roots), 0);
+        roots[3].1 = 320;
+        assert_eq!(progress_bonus(&roots), PROGRESS_BONUS);
+        let roots: Vec<(u8, Value)> = flat.iter().map(|&(c, _)| (c, 100)).collect();
+        assert_eq!(progress_bonus(&roots), 0);
+        let roots: Vec<(u8, Value)> = flat.iter().map(|&(c, _)| (c, VALUE_MAX - 10)).collect();
+        assert_eq!(progress_bonus(&roots), 0);
+
+        ::CONFIGURATION.write().unwrap().insert("Avoid Shuffling", "false".to_string());
+        let bonus = progress_bonus(&flat);
+        ::CONFIGURATION.write().unwrap().insert("Avoid Shuffling", "true".to_string());
+        assert_eq!(bonus, 0);
+    }
+
+    #[test]
+    fn avoid_repetitions() {
+        use super::REPETITION_PENALTY;
+        let penalty = |roots: &[(u8, Value)]| search_roots(roots).multipv.repetition_penalty;
+        assert_eq!(penalty(&[(10, 0)]), 0);
+        assert_eq!(penalty(&[(10, 150), (11, 0)]), REPETITION_PENALTY);
+        assert_eq!(penalty(&[(10, 150), (0, 0)]), REPETITION_PENALTY);
+        assert_eq!(penalty(&[(10, 150), (11, 50), (12, 0)]), 0);
+        assert_eq!(penalty(&[(10, -150), (11, 0)]), 0);
+        assert_eq!(penalty(&[(10, VALUE_MAX - 10), (11, 0)]), 0);
+
+        ::CONFIGURATION.write().unwrap().insert("Avoid Repetitions", "false".to_string());
+        let p = penalty(&[(10, 150), (11, 0)]);
+        ::CONFIGURATION.write().unwrap().insert("Avoid Repetitions", "true".to_string());
+        assert_eq!(p, 0);
+    }
+}
@@ -1,7 +1,7 @@
 //! Implements `Multipv`.
 
 use super::{bogus_params, contains_dups};
-use super::aspiration::Aspiration;
+use super::aspiration::{Aspiration, AspirationState};
 use std::cmp::{min, max};
 use std::time::Duration;
 use std::sync::Arc;
@@ -13,7 +13,8 @@ use depth::*;
 use ttable::*;
 use evaluator::Evaluator;
 use search_node::SearchNode;
-use search::{SearchParams, SearchReport};
+use search::{SearchParams, SearchReport, SearchDetails, TerminationReason, AspirationStats,
+             TreeStats};
 
 // In this module we use the `DeepeningSearch` trait for depth-first
 // searches too, so we rename it to avoid confusion.
@@ -28,11 +29,13 @@ use search::DeepeningSearch as SearchExecutor;
 /// vector sorted by descending move strength. This allows the
 /// iterative deepening routine to improve `searchmoves`' order on
 /// each iteration.
-pub struct Multipv<T: SearchExecutor> {
+pub struct Multipv<T: SearchExecutor<ReportData = Vec<Move>>> {
     tt: Arc<T::Ttable>,
     params: SearchParams<T::SearchNode>,
     search_is_terminated: bool,
     previously_searched_nodes: u64,
+    previous_tree_stats: TreeStats,
+    selective_depth: Depth,
 
     // The real work will be handed over to `searcher`.
     searcher: Aspiration<T>,
@@ -49,10 +52,41 @@ pub struct Multipv<T: SearchExecutor> {
 
     // The values for the corresponding moves in `self.params.searchmoves`.
     values: Vec<Value>,
+
+    // The outcomes of the last completed searches for the root moves,
+    // and the hash of the root position. Each root move maintains its
+    // own aspiration window, so that re-searches for an unstable line
+    // do not widen the windows for the stable lines.
+    aspiration_states: Vec<(Move, AspirationState)>,
+    aspiration_states_hash: u64,
+
+    // Statistics about the aspiration windows for the root moves
+    // searched so far.
+    aspiration_stats: AspirationStats,
+
+    // The principal variations found for the root moves searched so
+    // far.
+    pvs: Vec<(Move, Vec<Move>)>,
+
+    /// A bonus (in centipawns) that will be added to the values of
+    /// the root moves that reset the rule-50 counter (pawn advances
+    /// and captures). When this is not zero, a genuine multi-PV
+    /// search is always performed, so that the bonus can be applied.
+    pub progress_bonus: Value,
+
+    /// A penalty (in centipawns) that will be subtracted from the
+    /// values of the root moves that lead to boards which already
+    /// occurred earlier in the game. When this is not zero, and such
+    /// moves exist, a genuine multi-PV search is performed, so that
+    /// the penalty can be applied.
+    pub repetition_penalty: Value,
+
+    // The root moves that lead to previously encountered boards.
+    repeating_moves: Vec<Move>,
 }
 
 
-impl<T: SearchExecutor> SearchExecutor for Multipv<T> {
+impl<T: SearchExecutor<ReportData = Vec<Move>>> SearchExecutor for Multipv<T> {
     type Ttable = T::Ttable;
 
     type SearchNode = T::SearchNode;
@@ -65,11 +99,20 @@ impl<T: SearchExecutor> SearchExecutor for Multipv<T> {
             params: bogus_params(),
             search_is_terminated: false,
             previously_searched_nodes: 0,
+            previous_tree_stats: TreeStats::default(),
+            selective_depth: 0,
             searcher: Aspiration::new(tt),
             variation_count: 1,
             all_moves_are_considered: true,
             current_move_index: 0,
             values: vec![VALUE_MIN],
+            aspiration_states: vec![],
+            aspiration_states_hash: 0,
+            aspiration_stats: AspirationStats::default(),
+            pvs: vec![],
+            progress_bonus: 0,
+            repetition_penalty: 0,
+            repeating_moves: vec![],
         }
     }
 
@@ -86,15 +129,25 @@ impl<T: SearchExecutor> SearchExecutor for Multipv<T> {
         self.params = params;
         self.search_is_terminated = false;
         self.previously_searched_nodes = 0;
+        self.previous_tree_stats = TreeStats::default();
+        self.selective_depth = 0;
         self.variation_count = min(n, max(1, ::get_option("MultiPV").parse().unwrap_or(0)));
-        if n == 0 || self.variation_count == 1 && self.all_moves_are_considered {
+        self.repeating_moves = if self.repetition_penalty != 0 {
+            repeating_moves(&self.params.position, &self.params.searchmoves)
+        } else {
+            vec![]
+        };
+        if n == 0 ||
+           self.variation_count == 1 && self.all_moves_are_considered &&
+           self.progress_bonus == 0 && self.repeating_moves.is_empty() {
             // A plain aspiration search.
             //
             // A search is not a genuine multi-PV search if all legal
             // moves in the root position are being considered, and
             // the number of best lines of play that should be
             // calculated is one or zero. In those cases we fall-back
-            // to a plain aspiration search.
+            // to a plain aspiration search (unless progress bonus or
+            // repetition penalty should be applied).
             debug_assert!(self.variation_count <= 1);
             self.searcher.lmr_mode = false;
             self.searcher.start_search(self.params.clone());
@@ -102,8 +155,15 @@ impl<T: SearchExecutor> SearchExecutor for Multipv<T> {
             // A genuine multi-PV search.
             debug_assert!(self.variation_count >= 1);
             self.searcher.lmr_mode = true;
+            let hash = self.params.position.hash();
+            if hash != self.aspiration_states_hash {
+                self.aspiration_states.clear();
+                self.aspiration_states_hash = hash;
+            }
             self.current_move_index = 0;
             self.values = vec![VALUE_MIN; n];
+            self.aspiration_stats = AspirationStats::default();
+            self.pvs.clear();
             self.search_current_move();
         }
     }
@@ -114,8 +174,19 @@ impl<T: SearchExecutor> SearchExecutor for Multipv<T> {
                 searched_nodes,
                 value,
                 done,
+                termination,
+                details,
                 ..
             } = try!(self.searcher.try_recv_report());
+            if done &&
+               (termination == Some(TerminationReason::NodeLimitReached) ||
+                termination == Some(TerminationReason::Failed)) {
+                self.search_is_terminated = true;
+            }
+            // The root moves are searched from the child positions.
+            self.selective_depth = max(self.selective_depth, details.selective_depth + 1);
+            let aspiration = details.aspiration
+                .map(|s| add_root_move_stats(self.aspiration_stats, s));
             let mut report = SearchReport {
                 search_id: self.params.search_id,
                 searched_nodes: self.previously_searched_nodes + searched_nodes,
@@ -123,16 +194,47 @@ impl<T: SearchExecutor> SearchExecutor for Multipv<T> {
                 value: VALUE_UNKNOWN,
                 data: vec![],
                 done: done,
+                termination: if done {
+                    termination.or(Some(TerminationReason::Terminated))
+                } else {
+                    None
+                },
+                details: SearchDetails {
+                    selective_depth: self.selective_depth,
+                    aspiration: aspiration,
+                    tree: details.tree.map(|t| self.previous_tree_stats + t),
+                    current_move: self.params
+                        .searchmoves
+                        .get(self.current_move_index)
+                        .map(|&m| (m, self.current_move_index + 1)),
+                    messages: details.messages,
+                },
             };
+            if let (true, Some(t)) = (done, report.details.tree) {
+                self.previous_tree_stats = t;
+            }
             if done && !self.search_is_terminated {
                 self.previously_searched_nodes = report.searched_nodes;
+                if let Some(s) = aspiration {
+                    self.aspiration_stats = s;
+                }
                 self.params.position.undo_last_move();
-                self.advance_current_move(-value);
+                let m = self.params.searchmoves[self.current_move_index];
+                let v = add_bonus(-value, self.bonus(m));
+                self.pvs.push((m, self.searcher.pv().to_vec()));
+                let state = self.searcher.state();
+                match self.aspiration_states.iter_mut().find(|x| x.0 == m) {
+                    Some(x) => x.1 = state,
+                    None => self.aspiration_states.push((m, state)),
+                }
+                self.advance_current_move(v);
                 if self.search_current_move() {
                     report.done = false;
+                    report.termination = None;
                 } else {
+                    report.termination = Some(TerminationReason::DepthReached);
                     report.depth = self.params.depth;
-                    report.value = self.values[0];
+                    report.value = self.best_value();
                     report.data = self.params.searchmoves.clone();
                 }
             }
@@ -155,7 +257,7 @@ impl<T: SearchExecutor> SearchExecutor for Multipv<T> {
 }
 
 
-impl<T: SearchExecutor> SetOption for Multipv<T> {
+impl<T: SearchExecutor<ReportData = Vec<Move>>> SetOption for Multipv<T> {
     fn options() -> Vec<(&'static str, OptionDescription)> {
         let mut options = vec![("MultiPV",
                                 OptionDescription::Spin {
@@ -167,13 +269,13 @@ impl<T: SearchExecutor> SetOption for Multipv<T> {
         options
     }
 
-    fn set_option(name: &str, value: &str) {
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
         Aspiration::<T>::set_option(name, value)
     }
 }
 
 
-impl<T: SearchExecutor> Multipv<T> {
+impl<T: SearchExecutor<ReportData = Vec<Move>>> Multipv<T> {
     /// Returns the best lines of play so far.
     pub fn extract_variations(&mut self) -> Vec<Variation> {
         let mut variations = vec![];
@@ -183,8 +285,9 @@ impl<T: SearchExecutor> Multipv<T> {
                     .iter()
                     .take(self.variation_count) {
                 let p = &mut self.params.position;
+                let pv = self.pvs.iter().find(|x| x.0 == *m).map_or(&[][..], |x| &x.1);
                 assert!(p.do_move(*m));
-                let mut v = self.tt.extract_pv(p);
+                let mut v = extract_pv(&*self.tt, p, pv);
                 p.undo_last_move();
                 v.moves.insert(0, *m);
                 v.value = -v.value;
@@ -197,7 +300,7 @@ impl<T: SearchExecutor> Multipv<T> {
             }
         } else if self.variation_count != 0 {
             debug_assert_eq!(self.variation_count, 1);
-            variations.push(self.tt.extract_pv(&self.params.position));
+            variations.push(extract_pv(&*self.tt, &self.params.position, self.searcher.pv()));
         }
         variations
     }
@@ -207,6 +310,9 @@ impl<T: SearchExecutor> Multipv<T> {
             let alpha = self.values[self.variation_count - 1];
             if alpha < self.params.upper_bound {
                 let m = self.params.searchmoves[self.current_move_index];
+                let alpha = add_bonus(alpha, -self.bonus(m));
+                let state = self.aspiration_states.iter().find(|x| x.0 == m).map(|x| x.1);
+                self.searcher.set_state_hint(state);
                 assert!(self.params.position.do_move(m));
                 self.previously_searched_nodes += 1;
                 self.searcher
@@ -216,6 +322,8 @@ impl<T: SearchExecutor> Multipv<T> {
                                       lower_bound: -self.params.upper_bound,
                                       upper_bound: -max(alpha, self.params.lower_bound),
                                       searchmoves: self.params.position.legal_moves(),
+                                      node_limit: self.params
+                                          .remaining_nodes(self.previously_searched_nodes),
                                       ..self.params.clone()
                                   });
                 return true;
@@ -227,7 +335,7 @@ impl<T: SearchExecutor> Multipv<T> {
 
     fn write_reslut_to_tt(&self) {
         if self.all_moves_are_considered {
-            let value = self.values[0];
+            let value = self.best_value();
             let bound = match value {
                 v if v <= self.params.lower_bound => BOUND_UPPER,
                 v if v >= self.params.upper_bound => BOUND_LOWER,
@@ -261,4 +369,178 @@ impl<T: SearchExecutor> Multipv<T> {
     fn runs_genuine_multipv_search(&self) -> bool {
         self.searcher.lmr_mode
     }
+
+    /// Returns the progress bonus (reduced by the repetition penalty)
+    /// for a given root move.
+    #[inline]
+    fn bonus(&self, m: Move) -> Value {
+        let mut bonus = 0;
+        if m.is_pawn_advance_or_capure() {
+            bonus += self.progress_bonus;
+        }
+        if self.repeating_moves.contains(&m) {
+            bonus -= self.repetition_penalty;
+        }
+        bonus
+    }
+
+    /// Returns the value of the best move, without the progress bonus
+    /// and the repetition penalty.
+    #[inline]
+    fn best_value(&self) -> Value {
+        add_bonus(self.values[0], -self.bonus(self.params.searchmoves[0]))
+    }
+}
+
+
+/// A helper function. It returns the principal variation for a given
+/// position.
+///
+/// `pv` should contain the moves found by the search. Moves from the
+/// transposition table are used only to continue the principal
+/// variation beyond `pv` (which ends where the search depth has been
+/// exhausted, or where the search has used the transposition table
+/// instead of searching), or when `pv` is empty or invalid.
+fn extract_pv<T: Ttable, N: SearchNode>(tt: &T, position: &N, pv: &[Move]) -> Variation {
+    let mut variation = tt.extract_pv(position);
+    if !pv.is_empty() {
+        let mut p = position.clone();
+        let mut moves = Vec::with_capacity(32);
+        for m in pv {
+            match p.try_move_digest(m.digest()) {
+                Some(m) if p.do_move(m) => moves.push(m),
+                _ => return variation,
+            }
+        }
+        moves.extend(tt.extract_pv(&p).moves);
+        variation.moves = moves;
+    }
+    variation
+}
+
+
+/// A helper function. It adds the statistics for the search of a
+/// root move to `stats`.
+///
+/// Root moves are searched from the opponent's perspective, so their
+/// fail highs are our fail lows, and vice versa.
+#[inline]
+fn add_root_move_stats(stats: AspirationStats, s: AspirationStats) -> AspirationStats {
+    AspirationStats {
+        fail_highs: stats.fail_highs + s.fail_lows,
+        fail_lows: stats.fail_lows + s.fail_highs,
+        researches: stats.researches + s.researches,
+        window: (-s.window.1, -s.window.0),
+    }
+}
+
+
+/// A helper function. It returns the moves among `searchmoves` that
+/// lead to boards which already occurred earlier in the game
+/// (including the boards that occurred before the root position).
+fn repeating_moves<N: SearchNode>(position: &N, searchmoves: &[Move]) -> Vec<Move> {
+    let mut p = position.clone();
+    searchmoves
+        .iter()
+        .cloned()
+        .filter(|&m| {
+            if !p.do_move(m) {
+                return false;
+            }
+            let mut history = p.history_hashes();
+            p.undo_last_move();
+            let board_hash = history.pop().unwrap();
+            history.contains(&board_hash)
+        })
+        .collect()
+}
+
+
+/// A helper function. It adds `bonus` to `value`, but only if `value`
+/// is not a checkmate value.
+#[inline]
+fn add_bonus(value: Value, bonus: Value) -> Value {
+    if VALUE_EVAL_MIN <= value && value <= VALUE_EVAL_MAX {
+        max(VALUE_EVAL_MIN, min(value + bonus, VALUE_EVAL_MAX))
+    } else {
+        value
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{Multipv, repeating_moves};
+    use super::super::ThreadExecutor;
+    use uci::SetOption;
+    use ttable::Ttable;
+    use value::*;
+    use search::SearchParams;
+    use search::DeepeningSearch as SearchExecutor;
+    use search_node::*;
+    use stock::{StdTtable, StdTtableEntry, StdSearchNode, StdQsearch, StdMoveGenerator,
+                SimpleEvaluator, SimpleSearch};
+    type Tt = StdTtable<StdTtableEntry>;
+    type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+
+    #[test]
+    fn repeating_moves_test() {
+        let moves = vec!["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6"];
+        let p = P::from_history("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                                &mut moves.into_iter())
+            .ok()
+            .unwrap();
+        let searchmoves = p.legal_moves();
+        let repeating: Vec<String> = repeating_moves(&p, &searchmoves)
+            .iter()
+            .map(|m| m.notation())
+            .collect();
+        assert_eq!(repeating, vec!["f3g1"]);
+        let p = P::from_history("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                                &mut vec!["g1f3", "g8f6"].into_iter())
+            .ok()
+            .unwrap();
+        let searchmoves = p.legal_moves();
+        assert!(repeating_moves(&p, &searchmoves).is_empty());
+    }
+
+    #[test]
+    fn repetition_penalty() {
+        type M = Multipv<ThreadExecutor<SimpleSearch<Tt, P>>>;
+        for (name, description) in M::options() {
+            ::CONFIGURATION.write().unwrap().entry(name).or_insert(description.get_default());
+        }
+        let moves = vec!["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6"];
+        let p = P::from_history("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                                &mut moves.into_iter())
+            .ok()
+            .unwrap();
+        let searchmoves = p.legal_moves();
+        let find = |notation: &str| *searchmoves.iter().find(|m| m.notation() == notation).unwrap();
+        let (repeating, quiet, advance) = (find("f3g1"), find("b1c3"), find("e2e4"));
+        let params = SearchParams {
+            search_id: 0,
+            depth: 1,
+            lower_bound: VALUE_MIN,
+            upper_bound: VALUE_MAX,
+            searchmoves: searchmoves.clone(),
+            node_limit: None,
+            position: p.clone(),
+        };
+        let mut multipv = M::new(Arc::new(Tt::new(None)));
+        multipv.start_search(params.clone());
+        multipv.send_message("TERMINATE");
+        assert!(multipv.repeating_moves.is_empty());
+        assert_eq!(multipv.bonus(repeating), 0);
+
+        multipv.repetition_penalty = 20;
+        multipv.progress_bonus = 15;
+        multipv.start_search(params.clone());
+        multipv.send_message("TERMINATE");
+        assert_eq!(multipv.repeating_moves, vec![repeating]);
+        assert_eq!(multipv.bonus(repeating), -20);
+        assert_eq!(multipv.bonus(quiet), 0);
+        assert_eq!(multipv.bonus(advance), 15);
+    }
 }
@@ -0,0 +1,248 @@
+//! Implements search traces.
+//!
+//! A search trace is a text file which contains the sequence of
+//! root-level decisions made during one or more searches -- one
+//! decision per line. Search traces can be recorded, and later
+//! replayed in "check mode". In check mode, the decisions made by the
+//! current search are compared with the recorded ones, and the first
+//! divergence is reported. Given identical settings and a
+//! deterministic configuration (one search thread, fixed search
+//! depth, cleared transposition table), this is a practical tool for
+//! bisecting unintended behavior changes across refactors.
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use uci::OptionDescription;
+use moves::Move;
+use value::*;
+use depth::*;
+
+
+/// A root-level decision made during a search.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent {
+    /// A new search has been started.
+    Search { hash: u64, depth: Depth },
+
+    /// An aspirated search failed low, and the aspiration window was
+    /// widened.
+    FailLow { depth: Depth, value: Value, alpha: Value, beta: Value },
+
+    /// An aspirated search failed high, and the aspiration window was
+    /// widened.
+    FailHigh { depth: Depth, value: Value, alpha: Value, beta: Value },
+
+    /// A deepening iteration has been completed.
+    Iteration { depth: Depth, best_move: Move, value: Value, searched_nodes: u64 },
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TraceEvent::Search { hash, depth } => write!(f, "search {:016x} {}", hash, depth),
+            TraceEvent::FailLow { depth, value, alpha, beta } => {
+                write!(f, "fail-low {} {} {} {}", depth, value, alpha, beta)
+            }
+            TraceEvent::FailHigh { depth, value, alpha, beta } => {
+                write!(f, "fail-high {} {} {} {}", depth, value, alpha, beta)
+            }
+            TraceEvent::Iteration { depth, best_move, value, searched_nodes } => {
+                write!(f,
+                       "iteration {} {} {} {}",
+                       depth,
+                       best_move.notation(),
+                       value,
+                       searched_nodes)
+            }
+        }
+    }
+}
+
+
+/// Returns the descriptions of the configuration options related to
+/// search traces.
+pub fn options() -> Vec<(&'static str, OptionDescription)> {
+    vec![("Search Trace Mode",
+          OptionDescription::Combo {
+              list: vec!["Off".to_string(), "Record".to_string(), "Check".to_string()],
+              default: "Off".to_string(),
+          }),
+         ("Search Trace File", OptionDescription::String { default: String::new() })]
+}
+
+
+/// Adds an event to the search trace (if tracing is enabled).
+///
+/// Returns an error message if the event can not be written (in
+/// record mode), or differs from the recorded one (in check mode). In
+/// this case tracing stops until the next search is started.
+pub fn trace(event: TraceEvent) -> Result<(), String> {
+    let mut t = TRACE.lock().unwrap();
+    let result = match *t {
+        Some(ref mut x) => x.add(&event),
+        None => Ok(()),
+    };
+    if result.is_err() {
+        *t = None;
+    }
+    result
+}
+
+
+/// Starts tracing a new search.
+///
+/// Tracing is configured by the "Search Trace Mode" and "Search Trace
+/// File" options. When tracing is enabled, this function adds a
+/// `TraceEvent::Search` event to the search trace.
+///
+/// Returns an error message if the search trace file can not be
+/// created (in record mode), or can not be read (in check mode). In
+/// this case tracing is disabled for the search.
+pub fn start_search(hash: u64, depth: Depth) -> Result<(), String> {
+    {
+        let mode = ::get_option("Search Trace Mode");
+        let path = ::get_option("Search Trace File");
+        let mut t = TRACE.lock().unwrap();
+        let reopen = match *t {
+            Some(ref x) => x.mode != mode || x.path != path,
+            None => true,
+        };
+        if reopen {
+            *t = None;
+            match SearchTrace::open(&mode, &path) {
+                Ok(x) => *t = x,
+                Err(e) => return Err(format!("can not open the search trace file {}: {}", path, e)),
+            }
+        }
+    }
+    trace(TraceEvent::Search {
+              hash: hash,
+              depth: depth,
+          })
+}
+
+
+/// A helper type. It records or checks a search trace.
+struct SearchTrace {
+    mode: String,
+    path: String,
+    file: Option<File>,
+    expected: VecDeque<String>,
+    line_number: usize,
+}
+
+impl SearchTrace {
+    fn open(mode: &str, path: &str) -> io::Result<Option<SearchTrace>> {
+        if path.is_empty() {
+            return Ok(None);
+        }
+        let mut t = SearchTrace {
+            mode: mode.to_string(),
+            path: path.to_string(),
+            file: None,
+            expected: VecDeque::new(),
+            line_number: 0,
+        };
+        match mode {
+            "Record" => {
+                t.file = Some(try!(File::create(path)));
+            }
+            "Check" => {
+                let f = try!(File::open(path));
+                t.expected = try!(BufReader::new(f).lines().collect());
+            }
+            _ => return Ok(None),
+        }
+        Ok(Some(t))
+    }
+
+    fn add(&mut self, event: &TraceEvent) -> Result<(), String> {
+        let line = event.to_string();
+        self.line_number += 1;
+        if let Some(ref mut f) = self.file {
+            return writeln!(f, "{}", line)
+                .map_err(|e| format!("can not write the search trace file: {}", e));
+        }
+        match self.expected.pop_front() {
+            Some(ref x) if *x == line => Ok(()),
+            Some(x) => {
+                Err(format!("search trace diverges at line {}: expected \"{}\", got \"{}\"",
+                            self.line_number,
+                            x,
+                            line))
+            }
+            None => {
+                Err(format!("search trace diverges at line {}: expected end of trace, got \"{}\"",
+                            self.line_number,
+                            line))
+            }
+        }
+    }
+}
+
+
+lazy_static! {
+    static ref TRACE: Mutex<Option<SearchTrace>> = Mutex::new(None);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn record_and_check() {
+        let events = vec![TraceEvent::Search {
+                              hash: 0xabc,
+                              depth: 3,
+                          },
+                          TraceEvent::FailHigh {
+                              depth: 1,
+                              value: 50,
+                              alpha: 10,
+                              beta: 40,
+                          },
+                          TraceEvent::Iteration {
+                              depth: 1,
+                              best_move: Move::invalid(),
+                              value: 50,
+                              searched_nodes: 1000,
+                          }];
+        assert_eq!(events[0].to_string(), "search 0000000000000abc 3");
+        assert_eq!(events[1].to_string(), "fail-high 1 50 10 40");
+
+        let path = env::temp_dir().join("alcibiades_search_trace_test.txt");
+        let path = path.to_str().unwrap();
+        let mut t = SearchTrace::open("Record", path).unwrap().unwrap();
+        for e in events.iter() {
+            assert!(t.add(e).is_ok());
+        }
+        drop(t);
+
+        let mut t = SearchTrace::open("Check", path).unwrap().unwrap();
+        for e in events.iter() {
+            assert!(t.add(e).is_ok());
+        }
+        assert_eq!(t.add(&events[0]).err().unwrap(),
+                   format!("search trace diverges at line 4: expected end of trace, got \"{}\"",
+                           events[0]));
+
+        let mut t = SearchTrace::open("Check", path).unwrap().unwrap();
+        assert!(t.add(&events[0]).is_ok());
+        assert_eq!(t.add(&events[2]).err().unwrap(),
+                   format!("search trace diverges at line 2: expected \"{}\", got \"{}\"",
+                           events[1],
+                           events[2]));
+
+        assert!(SearchTrace::open("Off", path).unwrap().is_none());
+        assert!(SearchTrace::open("Record", "").unwrap().is_none());
+        let missing = env::temp_dir().join("alcibiades_missing_dir").join("trace.txt");
+        assert!(SearchTrace::open("Check", missing.to_str().unwrap()).is_err());
+        assert!(SearchTrace::open("Record", missing.to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,438 @@
+//! Implements `EndgameEvaluator` and exact recognizers for trivial
+//! endings.
+
+use std::cmp::{min, max};
+use uci::{SetOption, OptionDescription};
+use moves::Move;
+use board::*;
+use value::*;
+use ranks::*;
+use bitsets::*;
+use evaluator::{Evaluator, EvalTerm};
+use utils::BoardGeometry;
+
+
+/// The value of a position that is known to be won, but where the
+/// mate is still far away.
+pub const VALUE_KNOWN_WIN: Value = 5000;
+
+
+/// An evaluator that recognizes trivial endings, and evaluates all
+/// other positions with `E`.
+///
+/// When there are at most five pieces on the board (kings
+/// included), `evaluate_endgame` is consulted first. This gives exact
+/// values for king and pawn versus king endings, helps the search to
+/// find the mate in king, bishop and knight versus king endings, and
+/// evaluates some well-known draws as such.
+#[derive(Clone)]
+pub struct EndgameEvaluator<E: Evaluator> {
+    fallback: E,
+}
+
+impl<E: Evaluator> SetOption for EndgameEvaluator<E> {
+    fn options() -> Vec<(&'static str, OptionDescription)> {
+        E::options()
+    }
+
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        E::set_option(name, value)
+    }
+}
+
+impl<E: Evaluator> Evaluator for EndgameEvaluator<E> {
+    fn new(position: &Board) -> EndgameEvaluator<E> {
+        EndgameEvaluator { fallback: E::new(position) }
+    }
+
+    #[inline]
+    fn evaluate(&self, position: &Board) -> Value {
+        if pop_count(position.occupied) <= 5 {
+            if let Some(v) = evaluate_endgame(position) {
+                return v;
+            }
+        }
+        self.fallback.evaluate(position)
+    }
+
+    fn evaluate_explained(&self, position: &Board) -> Vec<EvalTerm> {
+        if let Some(v) = evaluate_endgame(position) {
+            vec![EvalTerm {
+                     name: "Endgame",
+                     white: if position.to_move == WHITE { v } else { -v },
+                     black: 0,
+                 }]
+        } else {
+            self.fallback.evaluate_explained(position)
+        }
+    }
+
+    #[inline]
+    fn is_zugzwangy(&self, position: &Board) -> bool {
+        self.fallback.is_zugzwangy(position)
+    }
+
+    fn export(kind: &str) -> Option<String> {
+        E::export(kind)
+    }
+
+    #[inline]
+    fn to_centipawns(value: Value) -> i32 {
+        E::to_centipawns(value)
+    }
+
+    #[inline]
+    fn will_do_move(&mut self, position: &Board, m: Move) {
+        self.fallback.will_do_move(position, m);
+    }
+
+    #[inline]
+    fn done_move(&mut self, position: &Board, m: Move) {
+        self.fallback.done_move(position, m);
+    }
+
+    #[inline]
+    fn will_undo_move(&mut self, position: &Board, m: Move) {
+        self.fallback.will_undo_move(position, m);
+    }
+
+    #[inline]
+    fn undone_move(&mut self, position: &Board, m: Move) {
+        self.fallback.undone_move(position, m);
+    }
+}
+
+
+/// Evaluates a position if it is a recognized trivial ending.
+///
+/// Returns `None` if the ending is not recognized. The recognized
+/// endings are:
+///
+/// * King and pawn versus king -- the exact result is taken from a
+///   bitbase (see `kpk_is_win`).
+///
+/// * King, bishop and knight versus king -- the weak king is driven
+///   to a corner of the bishop's color.
+///
+/// * King and queen (or rook) versus king -- the weak king is driven
+///   to the edge of the board.
+///
+/// * Draws: king versus king with at most one minor piece, two
+///   knights versus a bare king, and the equal endings of a minor
+///   piece versus a minor piece, rook versus rook, and queen versus
+///   queen (with no pawns).
+///
+/// The returned value is from the point of view of the side to move.
+pub fn evaluate_endgame(position: &Board) -> Option<Value> {
+    let pieces = &position.pieces;
+    let count = |color: Color, piece: PieceType| {
+        pop_count(pieces.color[color] & pieces.piece_type[piece])
+    };
+    let minors = |color: Color| count(color, BISHOP) + count(color, KNIGHT);
+    let majors = |color: Color| count(color, QUEEN) + count(color, ROOK);
+    let total = |color: Color| pop_count(pieces.color[color]) - 1;
+
+    // Find the strong side. (The weak side may have pieces only in
+    // the drawish endings.)
+    let strong = if total(WHITE) >= total(BLACK) { WHITE } else { BLACK };
+    let weak = 1 ^ strong;
+    let sign = if position.to_move == strong { 1 } else { -1 };
+
+    // Draws.
+    if pieces.piece_type[PAWN] == 0 {
+        let is_draw = match (total(strong), total(weak)) {
+            (0, 0) => true,
+            (1, 0) => minors(strong) == 1,
+            (2, 0) => count(strong, KNIGHT) == 2,
+            (1, 1) => {
+                minors(strong) == 1 && minors(weak) == 1 ||
+                count(strong, ROOK) == 1 && count(weak, ROOK) == 1 ||
+                count(strong, QUEEN) == 1 && count(weak, QUEEN) == 1
+            }
+            _ => false,
+        };
+        if is_draw {
+            return Some(0);
+        }
+    }
+    if total(weak) != 0 {
+        return None;
+    }
+    let strong_king = bsf(pieces.color[strong] & pieces.piece_type[KING]);
+    let weak_king = bsf(pieces.color[weak] & pieces.piece_type[KING]);
+
+    // King and pawn versus king.
+    if total(strong) == 1 && count(strong, PAWN) == 1 {
+        let pawn = bsf(pieces.piece_type[PAWN]);
+        let (sk, sp, wk) = if strong == WHITE {
+            (strong_king, pawn, weak_king)
+        } else {
+            (strong_king ^ 0b111000, pawn ^ 0b111000, weak_king ^ 0b111000)
+        };
+        return Some(if kpk_is_win(sk, sp, wk, position.to_move == strong) {
+                        sign * (VALUE_KNOWN_WIN + 10 * Board::rank(sp) as Value)
+                    } else {
+                        0
+                    });
+    }
+
+    // King, bishop and knight versus king.
+    if total(strong) == 2 && count(strong, BISHOP) == 1 && count(strong, KNIGHT) == 1 {
+        let corners = if pieces.piece_type[BISHOP] & BB_DARK_SQUARES != 0 {
+            [0, 63]
+        } else {
+            [7, 56]
+        };
+        let corner_distance = min(distance(weak_king, corners[0]),
+                                  distance(weak_king, corners[1]));
+        return Some(sign *
+                    (VALUE_KNOWN_WIN - 20 * corner_distance as Value -
+                     10 * distance(strong_king, weak_king) as Value));
+    }
+
+    // King and queen (or rook) versus king.
+    if majors(strong) >= 1 && count(strong, PAWN) == 0 {
+        let file = Board::file(weak_king) as isize;
+        let rank = Board::rank(weak_king) as isize;
+        let center_distance = max(max(3 - file, file - 4), max(3 - rank, rank - 4)) as Value;
+        return Some(sign *
+                    (VALUE_KNOWN_WIN + PIECE_VALUES[QUEEN] * count(strong, QUEEN) as Value +
+                     PIECE_VALUES[ROOK] * count(strong, ROOK) as Value +
+                     20 * center_distance -
+                     10 * distance(strong_king, weak_king) as Value));
+    }
+    None
+}
+
+
+/// Returns if a king and pawn versus king position is a win for the
+/// strong side.
+///
+/// The squares are given from the strong side's point of view (the
+/// pawn moves towards the 8th rank). `strong_to_move` tells whether
+/// the strong side is to move. The results are computed by
+/// retrograde analysis when this function is called for the first
+/// time.
+pub fn kpk_is_win(strong_king: Square,
+                  pawn: Square,
+                  weak_king: Square,
+                  strong_to_move: bool)
+                  -> bool {
+    let (strong_king, pawn, weak_king) = if Board::file(pawn) > 3 {
+        (strong_king ^ 0b111, pawn ^ 0b111, weak_king ^ 0b111)
+    } else {
+        (strong_king, pawn, weak_king)
+    };
+    let to_move = if strong_to_move { WHITE } else { BLACK };
+    KPK_BITBASE[kpk_index(to_move, strong_king, weak_king, pawn)] == KPK_WIN
+}
+
+
+lazy_static! {
+    static ref KPK_BITBASE: Vec<u8> = compute_kpk_bitbase();
+}
+
+
+const KPK_INVALID: u8 = 0;
+const KPK_UNKNOWN: u8 = 1;
+const KPK_DRAW: u8 = 2;
+const KPK_WIN: u8 = 4;
+
+
+/// A helper function. It returns the index in the KPK bitbase for a
+/// given position.
+///
+/// The pawn must be on files "a" to "d".
+#[inline]
+fn kpk_index(to_move: Color, wk: Square, bk: Square, pawn: Square) -> usize {
+    debug_assert!(Board::file(pawn) <= 3);
+    ((to_move * 64 + wk) * 64 + bk) * 32 + Board::rank(pawn) * 4 + Board::file(pawn)
+}
+
+
+/// A helper function. It computes the KPK bitbase.
+///
+/// White has the pawn. All positions are classified as invalid,
+/// drawn, or won for white. Unresolved positions are repeatedly
+/// classified by looking at the positions reachable in one move,
+/// until nothing changes. The positions that remain unresolved are
+/// drawn.
+fn compute_kpk_bitbase() -> Vec<u8> {
+    let geometry = BoardGeometry::get();
+    let king_moves = |square: Square| Squares::new(geometry.attacks_from(KING, square, 0));
+    let pawn_attacks = |pawn: Square| geometry.pawn_attacks[WHITE][pawn];
+    let mut db = vec![KPK_INVALID; 2 * 64 * 64 * 32];
+
+    // Initial classification.
+    for to_move in WHITE..BLACK + 1 {
+        for wk in 0..64 {
+            for bk in 0..64 {
+                for rank in RANK_2..RANK_8 {
+                    for file in 0..4 {
+                        let pawn = Board::square(file, rank);
+                        if wk == bk || wk == pawn || bk == pawn || distance(wk, bk) <= 1 ||
+                           to_move == WHITE && pawn_attacks(pawn) & 1 << bk != 0 {
+                            continue;
+                        }
+                        let queening_square = pawn + 8;
+                        db[kpk_index(to_move, wk, bk, pawn)] =
+                            if to_move == WHITE && rank == RANK_7 && wk != queening_square &&
+                               bk != queening_square &&
+                               (distance(bk, queening_square) > 1 ||
+                                distance(wk, queening_square) == 1) {
+                                KPK_WIN
+                            } else if to_move == BLACK && distance(bk, pawn) == 1 &&
+                                      distance(wk, pawn) > 1 {
+                                KPK_DRAW
+                            } else {
+                                KPK_UNKNOWN
+                            };
+                    }
+                }
+            }
+        }
+    }
+
+    // Repeated classification.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for to_move in WHITE..BLACK + 1 {
+            for wk in 0..64 {
+                for bk in 0..64 {
+                    for rank in RANK_2..RANK_8 {
+                        for file in 0..4 {
+                            let pawn = Board::square(file, rank);
+                            let index = kpk_index(to_move, wk, bk, pawn);
+                            if db[index] != KPK_UNKNOWN {
+                                continue;
+                            }
+                            let mut results = 0;
+                            if to_move == WHITE {
+                                for to in king_moves(wk) {
+                                    if to != pawn && distance(to, bk) > 1 {
+                                        results |= db[kpk_index(BLACK, to, bk, pawn)];
+                                    }
+                                }
+                                if rank < RANK_7 && pawn + 8 != wk && pawn + 8 != bk {
+                                    results |= db[kpk_index(BLACK, wk, bk, pawn + 8)];
+                                    if rank == RANK_2 && pawn + 16 != wk && pawn + 16 != bk {
+                                        results |= db[kpk_index(BLACK, wk, bk, pawn + 16)];
+                                    }
+                                }
+                                db[index] = if results & KPK_WIN != 0 {
+                                    KPK_WIN
+                                } else if results & KPK_UNKNOWN == 0 {
+                                    KPK_DRAW
+                                } else {
+                                    KPK_UNKNOWN
+                                };
+                            } else {
+                                for to in king_moves(bk) {
+                                    if distance(to, wk) > 1 && pawn_attacks(pawn) & 1 << to == 0 {
+                                        results |= if to == pawn {
+                                            KPK_DRAW
+                                        } else {
+                                            db[kpk_index(WHITE, wk, to, pawn)]
+                                        };
+                                    }
+                                }
+                                db[index] = if results == 0 {
+                                    if pawn_attacks(pawn) & 1 << bk != 0 {
+                                        KPK_WIN
+                                    } else {
+                                        KPK_DRAW
+                                    }
+                                } else if results & KPK_DRAW != 0 {
+                                    KPK_DRAW
+                                } else if results & KPK_UNKNOWN == 0 {
+                                    KPK_WIN
+                                } else {
+                                    KPK_UNKNOWN
+                                };
+                            }
+                            changed |= db[index] != KPK_UNKNOWN;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    db
+}
+
+
+/// A helper function. It returns the number of king moves between
+/// two squares.
+#[inline]
+fn distance(a: Square, b: Square) -> usize {
+    let files = (Board::file(a) as isize - Board::file(b) as isize).abs();
+    let ranks = (Board::rank(a) as isize - Board::rank(b) as isize).abs();
+    max(files, ranks) as usize
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use squares::*;
+    use stock::SimpleEvaluator;
+
+    type E = EndgameEvaluator<SimpleEvaluator>;
+
+    fn eval(fen: &str) -> Option<Value> {
+        evaluate_endgame(&Board::from_fen(fen).ok().unwrap())
+    }
+
+    #[test]
+    fn kpk() {
+        // The king on the 6th rank, in front of its pawn.
+        assert!(kpk_is_win(E6, E5, E8, true));
+        assert!(kpk_is_win(E6, E5, E8, false));
+
+        // The opposition.
+        assert!(!kpk_is_win(E5, E4, E7, true));
+        assert!(kpk_is_win(E5, E4, E7, false));
+
+        // The rook pawn.
+        assert!(!kpk_is_win(B6, A5, A8, true));
+
+        // The square of the pawn.
+        assert!(kpk_is_win(H1, A5, F5, false));
+        assert!(!kpk_is_win(H1, A5, E5, false));
+        assert!(kpk_is_win(H1, A5, E5, true));
+        assert!(kpk_is_win(A1, H5, C5, false));
+
+        // The opposition from both points of view.
+        assert_eq!(eval("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), Some(0));
+        assert!(eval("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1").unwrap() <= -VALUE_KNOWN_WIN);
+        assert_eq!(eval("8/8/8/4p3/4k3/8/4K3/8 b - - 0 1"), Some(0));
+        assert!(eval("8/8/8/4p3/4k3/8/4K3/8 w - - 0 1").unwrap() <= -VALUE_KNOWN_WIN);
+    }
+
+    #[test]
+    fn endgames() {
+        assert_eq!(eval("8/8/8/4k3/8/8/3NK3/8 w - - 0 1"), Some(0));
+        assert_eq!(eval("8/8/8/4k3/8/8/3NK1N1/8 b - - 0 1"), Some(0));
+        assert_eq!(eval("8/8/2r5/4k3/8/8/3RK3/8 b - - 0 1"), Some(0));
+        assert_eq!(eval("8/8/2r5/4k3/8/8/3QK3/8 b - - 0 1"), None);
+        assert_eq!(eval("8/8/2n5/4k3/8/8/3BK3/8 w - - 0 1"), Some(0));
+        assert_eq!(eval("8/8/8/4k3/1p6/8/3BK1N1/8 w - - 0 1"), None);
+
+        // Drive the weak king to the right corner.
+        let near = eval("7k/8/8/8/8/8/3BK1N1/8 w - - 0 1").unwrap();
+        let far = eval("k7/8/8/8/8/8/3BK1N1/8 w - - 0 1").unwrap();
+        assert!(near > far && far > VALUE_KNOWN_WIN - 250);
+        assert_eq!(eval("k7/8/8/8/8/8/3BK1N1/8 b - - 0 1"), Some(-far));
+
+        // Drive the weak king to the edge.
+        let edge = eval("k7/8/8/8/8/8/4K3/7R w - - 0 1").unwrap();
+        let center = eval("8/8/8/8/3k4/8/4K3/7R w - - 0 1").unwrap();
+        assert!(edge > center && center > VALUE_KNOWN_WIN);
+
+        let b = Board::from_fen("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1").ok().unwrap();
+        assert_eq!(E::new(&b).evaluate(&b), 0);
+        let b = Board::from_fen("4k3/8/4K3/4P3/8/8/8/1R6 b - - 0 1").ok().unwrap();
+        assert!(E::new(&b).evaluate(&b) < -400);
+    }
+}
@@ -9,7 +9,17 @@ mod std_qsearch;
 mod std_move_generator;
 mod std_time_manager;
 mod simple_evaluator;
+mod tapered_evaluator;
+mod tapered_pst;
+mod tapered_king_safety;
+mod tapered_passed_pawns;
+mod tapered_mobility;
+mod endgames;
+mod nnue_evaluator;
+mod tuning;
 mod deepening;
+#[cfg(feature = "eval-plugin")]
+mod plugin_evaluator;
 
 pub use self::std_ttable::*;
 pub use self::std_ttable_entry::*;
@@ -20,4 +30,27 @@ pub use self::std_qsearch::*;
 pub use self::std_move_generator::*;
 pub use self::std_time_manager::*;
 pub use self::simple_evaluator::*;
+pub use self::tapered_evaluator::*;
+pub use self::tapered_pst::*;
+pub use self::tapered_king_safety::*;
+pub use self::tapered_passed_pawns::*;
+pub use self::tapered_mobility::*;
+pub use self::endgames::*;
+pub use self::nnue_evaluator::*;
+pub use self::tuning::*;
 pub use self::deepening::*;
+#[cfg(feature = "eval-plugin")]
+pub use self::plugin_evaluator::*;
+
+
+/// The transposition table used by the default engine.
+pub type DefaultTtable = StdTtable<StdTtableEntry>;
+
+/// The search node used by the default engine, working with the
+/// static evaluator `E`.
+pub type DefaultSearchNode<E = SimpleEvaluator> = StdSearchNode<StdQsearch<StdMoveGenerator<E>>>;
+
+/// The iterative deepening search used by the default engine,
+/// working with the static evaluator `E`.
+pub type DefaultSearchExecutor<E = SimpleEvaluator> = Deepening<SimpleSearch<DefaultTtable,
+                                                                             DefaultSearchNode<E>>>;
@@ -0,0 +1,382 @@
+//! Implements `NnueEvaluator` and `NnueNetwork`.
+
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::sync::{Arc, RwLock};
+use uci::{SetOption, OptionDescription};
+use moves::Move;
+use board::*;
+use value::*;
+use bitsets::*;
+use evaluator::{Evaluator, EvalTerm};
+
+
+/// The number of input features of a network.
+///
+/// There is one feature for each combination of color, piece type,
+/// and square.
+pub const NNUE_INPUTS: usize = 2 * 6 * 64;
+
+/// The maximal size of the hidden layer of a network.
+pub const NNUE_HIDDEN_MAX: usize = 4096;
+
+/// The accumulated values are clipped to the range `0..NNUE_CLIP_MAX`
+/// before being fed to the output layer.
+pub const NNUE_CLIP_MAX: i32 = 255;
+
+/// The output of a network is divided by this number to obtain the
+/// evaluation in centipawns.
+pub const NNUE_OUTPUT_DIVISOR: i64 = 255 * 64;
+
+
+/// A small neural network that evaluates chess positions.
+///
+/// The network has a single hidden layer, which is computed twice --
+/// once from white's perspective, and once from black's perspective.
+/// The hidden layer of each perspective is the sum of hidden biases
+/// and the input weights of the pieces on the board, so it can be
+/// updated cheaply when a move is played or taken back (see
+/// `NnueEvaluator`). The two hidden layers are clipped to the range
+/// `0..NNUE_CLIP_MAX` and fed to a linear output layer -- the side
+/// to move's layer first.
+///
+/// Networks are loaded from files with a simple format (all integers
+/// are little-endian):
+///
+/// * Bytes 0-7: the ASCII string `ALCNNUE1`.
+///
+/// * Bytes 8-11: the size `H` of the hidden layer, as a 32-bit
+///   unsigned integer (between `1` and `NNUE_HIDDEN_MAX`).
+///
+/// * `NNUE_INPUTS * H` 16-bit signed integers: the input weights,
+///   feature by feature. Feature `(6 * color + piece) * 64 + square`
+///   gives the weights for a piece of color `color` (`0` for our,
+///   `1` for their), type `piece` (`0` for king, `1` for queen, and
+///   so on, up to `5` for pawn), placed at square `square` (`A1` is
+///   `0`, `B1` is `1`, `A2` is `8`, and so on). Black's perspective
+///   sees the board flipped vertically.
+///
+/// * `H` 16-bit signed integers: the hidden biases.
+///
+/// * `2 * H` 16-bit signed integers: the output weights.
+///
+/// * A 32-bit signed integer: the output bias.
+///
+/// The output of the network, divided by `NNUE_OUTPUT_DIVISOR`, is
+/// the evaluation in centipawns from the point of view of the side to
+/// move.
+pub struct NnueNetwork {
+    hidden_size: usize,
+    input_weights: Vec<i16>,
+    hidden_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl NnueNetwork {
+    /// Loads a network from a file.
+    pub fn load(path: &str) -> io::Result<NnueNetwork> {
+        let mut bytes = vec![];
+        try!(try!(File::open(path)).read_to_end(&mut bytes));
+        NnueNetwork::from_bytes(&bytes)
+    }
+
+    /// Creates a network from the contents of a network file.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<NnueNetwork> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        if bytes.len() < 12 || &bytes[..8] != b"ALCNNUE1" {
+            return Err(invalid("not a network file"));
+        }
+        let hidden_size = read_u32(&bytes[8..]) as usize;
+        if hidden_size == 0 || hidden_size > NNUE_HIDDEN_MAX {
+            return Err(invalid("invalid hidden layer size"));
+        }
+        if bytes.len() != 12 + 2 * (NNUE_INPUTS + 3) * hidden_size + 4 {
+            return Err(invalid("invalid file length"));
+        }
+        let mut weights = bytes[12..bytes.len() - 4]
+            .chunks(2)
+            .map(|x| (x[0] as u16 | (x[1] as u16) << 8) as i16);
+        Ok(NnueNetwork {
+               hidden_size: hidden_size,
+               input_weights: weights.by_ref().take(NNUE_INPUTS * hidden_size).collect(),
+               hidden_biases: weights.by_ref().take(hidden_size).collect(),
+               output_weights: weights.collect(),
+               output_bias: read_u32(&bytes[bytes.len() - 4..]) as i32,
+           })
+    }
+
+    /// Returns the size of the hidden layer.
+    pub fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    /// Adds (or subtracts) the input weights of a feature to the
+    /// hidden layer of a given perspective.
+    #[inline]
+    fn update(&self,
+              layer: &mut [i32],
+              perspective: Color,
+              piece: (Color, PieceType, Square),
+              add: bool) {
+        let (color, piece, square) = piece;
+        let feature = if perspective == WHITE {
+            (6 * color + piece) * 64 + square
+        } else {
+            (6 * (1 ^ color) + piece) * 64 + (square ^ 0b111000)
+        };
+        let weights = &self.input_weights[feature * self.hidden_size..];
+        for (x, &w) in layer.iter_mut().zip(weights) {
+            if add {
+                *x += w as i32;
+            } else {
+                *x -= w as i32;
+            }
+        }
+    }
+
+    /// Computes the output of the network, given the hidden layers of
+    /// both perspectives.
+    fn output(&self, us: &[i32], them: &[i32]) -> i64 {
+        let inputs = us.iter().chain(them.iter());
+        inputs.zip(self.output_weights.iter())
+            .fold(self.output_bias as i64, |acc, (&x, &w)| {
+                acc + (x.max(0).min(NNUE_CLIP_MAX) * w as i32) as i64
+            })
+    }
+}
+
+
+/// An evaluator that uses a neural network (see `NnueNetwork`).
+///
+/// The network is selected by the "NNUEFile" configuration option,
+/// which should contain the path to the network file. When no network
+/// is selected, or the network file can not be loaded, the evaluation
+/// falls back to `E`.
+///
+/// The hidden layers of the network are updated incrementally, with
+/// the difference between the old and the new placement of the
+/// pieces, whenever a move is played or taken back.
+#[derive(Clone)]
+pub struct NnueEvaluator<E: Evaluator> {
+    network: Option<Arc<NnueNetwork>>,
+    layers: [Vec<i32>; 2],
+    pieces: PiecesPlacement,
+    fallback: E,
+}
+
+impl<E: Evaluator> SetOption for NnueEvaluator<E> {
+    fn options() -> Vec<(&'static str, OptionDescription)> {
+        let mut options = vec![("NNUEFile", OptionDescription::String { default: String::new() })];
+        options.extend(E::options());
+        options
+    }
+
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        if name == "NNUEFile" {
+            let network = if value.is_empty() {
+                Ok(None)
+            } else {
+                NnueNetwork::load(value).map(|n| Some(Arc::new(n)))
+            };
+            match network {
+                Ok(n) => *NETWORK.write().unwrap() = n,
+                Err(e) => {
+                    *NETWORK.write().unwrap() = None;
+                    return Err(format!("can not load {}: {}", value, e));
+                }
+            }
+        }
+        E::set_option(name, value)
+    }
+}
+
+impl<E: Evaluator> Evaluator for NnueEvaluator<E> {
+    fn new(position: &Board) -> NnueEvaluator<E> {
+        let network = NETWORK.read().unwrap().clone();
+        let mut layers = [vec![], vec![]];
+        if let Some(ref n) = network {
+            for (perspective, layer) in layers.iter_mut().enumerate() {
+                layer.extend(n.hidden_biases.iter().map(|&x| x as i32));
+                for piece in position.all_pieces() {
+                    n.update(layer, perspective, piece, true);
+                }
+            }
+        }
+        NnueEvaluator {
+            network: network,
+            layers: layers,
+            pieces: position.pieces.clone(),
+            fallback: E::new(position),
+        }
+    }
+
+    fn evaluate(&self, position: &Board) -> Value {
+        if let Some(ref n) = self.network {
+            let us = position.to_move;
+            let v = n.output(&self.layers[us], &self.layers[1 ^ us]) / NNUE_OUTPUT_DIVISOR;
+            if v > VALUE_EVAL_MAX as i64 {
+                VALUE_EVAL_MAX
+            } else if v < VALUE_EVAL_MIN as i64 {
+                VALUE_EVAL_MIN
+            } else {
+                v as Value
+            }
+        } else {
+            self.fallback.evaluate(position)
+        }
+    }
+
+    fn evaluate_explained(&self, position: &Board) -> Vec<EvalTerm> {
+        if self.network.is_some() {
+            let v = self.evaluate(position);
+            vec![EvalTerm {
+                     name: "NNUE",
+                     white: if position.to_move == WHITE { v } else { -v },
+                     black: 0,
+                 }]
+        } else {
+            self.fallback.evaluate_explained(position)
+        }
+    }
+
+    #[inline]
+    fn is_zugzwangy(&self, position: &Board) -> bool {
+        self.fallback.is_zugzwangy(position)
+    }
+
+    fn export(kind: &str) -> Option<String> {
+        E::export(kind)
+    }
+
+    #[inline]
+    fn will_do_move(&mut self, position: &Board, m: Move) {
+        self.pieces = position.pieces.clone();
+        self.fallback.will_do_move(position, m);
+    }
+
+    #[inline]
+    fn done_move(&mut self, position: &Board, m: Move) {
+        self.update_layers(position);
+        self.fallback.done_move(position, m);
+    }
+
+    #[inline]
+    fn will_undo_move(&mut self, position: &Board, m: Move) {
+        self.pieces = position.pieces.clone();
+        self.fallback.will_undo_move(position, m);
+    }
+
+    #[inline]
+    fn undone_move(&mut self, position: &Board, m: Move) {
+        self.update_layers(position);
+        self.fallback.undone_move(position, m);
+    }
+}
+
+impl<E: Evaluator> NnueEvaluator<E> {
+    /// A helper method. It updates the hidden layers with the
+    /// difference between the remembered and the current placement of
+    /// the pieces.
+    fn update_layers(&mut self, position: &Board) {
+        if let Some(ref n) = self.network {
+            let old = &self.pieces;
+            let new = &position.pieces;
+            for color in WHITE..BLACK + 1 {
+                for piece in KING..PIECE_NONE {
+                    let old_bb = old.color[color] & old.piece_type[piece];
+                    let new_bb = new.color[color] & new.piece_type[piece];
+                    for (bb, add) in [(old_bb & !new_bb, false), (new_bb & !old_bb, true)].iter() {
+                        for square in Squares::new(*bb) {
+                            for perspective in WHITE..BLACK + 1 {
+                                n.update(&mut self.layers[perspective],
+                                         perspective,
+                                         (color, piece, square),
+                                         *add);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+
+lazy_static! {
+    static ref NETWORK: RwLock<Option<Arc<NnueNetwork>>> = RwLock::new(None);
+}
+
+
+#[inline]
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use move_generator::*;
+    use stock::{SimpleEvaluator, StdMoveGenerator};
+    use utils::MoveStack;
+
+    type E = NnueEvaluator<SimpleEvaluator>;
+
+    fn network_bytes(hidden_size: usize) -> Vec<u8> {
+        let mut bytes = b"ALCNNUE1".to_vec();
+        bytes.extend([hidden_size as u8, 0, 0, 0].iter());
+        let n = (NNUE_INPUTS + 3) * hidden_size;
+        for i in 0..n {
+            let w = ((i * 7919) % 61) as i16 - 20;
+            bytes.extend([w as u8, (w >> 8) as u8].iter());
+        }
+        bytes.extend([100, 0, 0, 0].iter());
+        bytes
+    }
+
+    #[test]
+    fn from_bytes() {
+        let n = NnueNetwork::from_bytes(&network_bytes(3)).ok().unwrap();
+        assert_eq!(n.hidden_size(), 3);
+        assert_eq!(n.output_weights.len(), 6);
+        assert_eq!(n.output_bias, 100);
+        assert!(NnueNetwork::from_bytes(&network_bytes(3)[1..]).is_err());
+        assert!(NnueNetwork::from_bytes(&network_bytes(0)).is_err());
+        let mut bytes = network_bytes(3);
+        bytes.push(0);
+        assert!(NnueNetwork::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn incremental_update() {
+        let path = env::temp_dir().join("alcibiades_nnue_test.bin");
+        let path = path.to_str().unwrap();
+        File::create(path).unwrap().write_all(&network_bytes(8)).unwrap();
+        E::set_option("NNUEFile", path).unwrap();
+        fs::remove_file(path).ok();
+        let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        let b = Board::from_fen(fen).ok().unwrap();
+        assert!(E::new(&b).network.is_some());
+        let mut g = StdMoveGenerator::<E>::from_board(b).ok().unwrap();
+        let mut s = MoveStack::new();
+        g.generate_all(&mut s);
+        while let Some(m) = s.pop() {
+            if g.do_move(m).is_some() {
+                let e = E::new(g.board());
+                assert_eq!(g.evaluator().layers, e.layers);
+                assert_eq!(g.evaluator().evaluate(g.board()), e.evaluate(g.board()));
+                g.undo_move(m);
+            }
+        }
+        let e = E::new(g.board());
+        assert_eq!(g.evaluator().layers, e.layers);
+        let b = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").ok().unwrap();
+        let flipped = Board::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1").ok().unwrap();
+        assert_eq!(E::new(&b).evaluate(&b), E::new(&flipped).evaluate(&flipped));
+    }
+}
@@ -0,0 +1,235 @@
+//! Implements `PluginEvaluator`.
+//!
+//! This module is available only when the crate is compiled with the
+//! `eval-plugin` feature enabled.
+
+use std::ffi::CString;
+use std::sync::RwLock;
+use libc;
+use uci::{SetOption, OptionDescription};
+use moves::Move;
+use board::*;
+use value::*;
+use evaluator::{Evaluator, EvalTerm};
+
+
+/// The type of the evaluation function exported by plugins.
+///
+/// The function receives a pointer to a serialized position (see
+/// `serialize_board`), and returns its evaluation in centipawns, from
+/// the point of view of the side to move.
+pub type PluginEvalFn = extern "C" fn(board_bytes: *const u8) -> i32;
+
+
+/// The length of a serialized position in bytes.
+pub const PLUGIN_BOARD_BYTES: usize = 68;
+
+
+/// An evaluator that delegates the evaluation to an external shared
+/// library (a plugin).
+///
+/// The plugin is selected by the "EvalPlugin" configuration option,
+/// which should contain the path to the shared library. The library
+/// must export a C ABI function named `eval` with the signature
+/// `int32_t eval(const uint8_t *board_bytes)`. (See `PluginEvalFn`
+/// and `serialize_board`.) When no plugin is selected, or the plugin
+/// can not be loaded, the evaluation falls back to `E`.
+///
+/// This allows researchers to iterate on evaluation functions written
+/// in other languages without recompiling the engine.
+///
+/// **Important note:** Loading a plugin executes foreign code in the
+/// engine's process, and there is no way to verify that this code is
+/// well behaved. The `eval` function must be thread-safe (it will be
+/// called concurrently from different search threads), must not
+/// unwind or retain the passed pointer, and must return a value
+/// between `VALUE_EVAL_MIN` and `VALUE_EVAL_MAX` (values outside this
+/// range are clamped). Loaded libraries are never unloaded, because
+/// existing evaluator instances may still be calling them.
+#[derive(Clone)]
+pub struct PluginEvaluator<E: Evaluator> {
+    plugin: Option<PluginEvalFn>,
+    fallback: E,
+}
+
+impl<E: Evaluator> SetOption for PluginEvaluator<E> {
+    fn options() -> Vec<(&'static str, OptionDescription)> {
+        let mut options = vec![("EvalPlugin", OptionDescription::String { default: String::new() })];
+        options.extend(E::options());
+        options
+    }
+
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        if name == "EvalPlugin" {
+            let plugin = if value.is_empty() {
+                None
+            } else {
+                unsafe { load_plugin(value) }
+            };
+            *PLUGIN.write().unwrap() = plugin;
+            if !value.is_empty() && plugin.is_none() {
+                return Err(format!("can not load {}", value));
+            }
+        }
+        E::set_option(name, value)
+    }
+}
+
+impl<E: Evaluator> Evaluator for PluginEvaluator<E> {
+    fn new(position: &Board) -> PluginEvaluator<E> {
+        PluginEvaluator {
+            plugin: *PLUGIN.read().unwrap(),
+            fallback: E::new(position),
+        }
+    }
+
+    fn evaluate(&self, position: &Board) -> Value {
+        if let Some(eval) = self.plugin {
+            let bytes = serialize_board(position);
+            let v = eval(bytes.as_ptr());
+            if v > VALUE_EVAL_MAX as i32 {
+                VALUE_EVAL_MAX
+            } else if v < VALUE_EVAL_MIN as i32 {
+                VALUE_EVAL_MIN
+            } else {
+                v as Value
+            }
+        } else {
+            self.fallback.evaluate(position)
+        }
+    }
+
+    fn evaluate_explained(&self, position: &Board) -> Vec<EvalTerm> {
+        if self.plugin.is_some() {
+            let v = self.evaluate(position);
+            vec![EvalTerm {
+                     name: "Plugin",
+                     white: if position.to_move == WHITE { v } else { -v },
+                     black: 0,
+                 }]
+        } else {
+            self.fallback.evaluate_explained(position)
+        }
+    }
+
+    #[inline]
+    fn is_zugzwangy(&self, position: &Board) -> bool {
+        self.fallback.is_zugzwangy(position)
+    }
+
+    fn export(kind: &str) -> Option<String> {
+        E::export(kind)
+    }
+
+    #[inline]
+    fn will_do_move(&mut self, position: &Board, m: Move) {
+        self.fallback.will_do_move(position, m);
+    }
+
+    #[inline]
+    fn done_move(&mut self, position: &Board, m: Move) {
+        self.fallback.done_move(position, m);
+    }
+
+    #[inline]
+    fn will_undo_move(&mut self, position: &Board, m: Move) {
+        self.fallback.will_undo_move(position, m);
+    }
+
+    #[inline]
+    fn undone_move(&mut self, position: &Board, m: Move) {
+        self.fallback.undone_move(position, m);
+    }
+}
+
+
+/// Serializes a position to the format that plugins expect.
+///
+/// The format is as follows (all integers are little-endian):
+///
+/// * Bytes 0-47: six 64-bit bitboards, one for each piece type -- in
+///   this order: king, queen, rook, bishop, knight, pawn. Bit `N` is
+///   set when square `N` is occupied by a piece of the given type
+///   (`A1` is `0`, `B1` is `1`, `A2` is `8`, and so on).
+///
+/// * Bytes 48-63: two 64-bit bitboards with the pieces of each color
+///   -- white, then black.
+///
+/// * Byte 64: the side to move (`0` for white, `1` for black).
+///
+/// * Byte 65: the castling rights -- bit `0` for white queen-side,
+///   bit `1` for white king-side, bit `2` for black queen-side, bit
+///   `3` for black king-side.
+///
+/// * Byte 66: the file of the en-passant target square (`0` for file
+///   A, `7` for file H), or a value bigger than `7` if there is no
+///   en-passant target square.
+///
+/// * Byte 67: reserved (always zero).
+pub fn serialize_board(position: &Board) -> [u8; PLUGIN_BOARD_BYTES] {
+    let mut bytes = [0u8; PLUGIN_BOARD_BYTES];
+    let bitboards = position.pieces.piece_type.iter().chain(position.pieces.color.iter());
+    for (i, bb) in bitboards.enumerate() {
+        for j in 0..8 {
+            bytes[8 * i + j] = (bb >> (8 * j)) as u8;
+        }
+    }
+    bytes[64] = position.to_move as u8;
+    bytes[65] = position.castling_rights.value() as u8;
+    bytes[66] = position.enpassant_file as u8;
+    bytes
+}
+
+
+lazy_static! {
+    static ref PLUGIN: RwLock<Option<PluginEvalFn>> = RwLock::new(None);
+}
+
+
+/// A helper function. It loads the shared library at `path`, and
+/// returns its `eval` function.
+unsafe fn load_plugin(path: &str) -> Option<PluginEvalFn> {
+    let path = match CString::new(path) {
+        Ok(x) => x,
+        Err(_) => return None,
+    };
+    let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW);
+    if handle.is_null() {
+        return None;
+    }
+    let symbol = libc::dlsym(handle, b"eval\0".as_ptr() as *const libc::c_char);
+    if symbol.is_null() {
+        libc::dlclose(handle);
+        return None;
+    }
+    Some(::std::mem::transmute::<*mut libc::c_void, PluginEvalFn>(symbol))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stock::SimpleEvaluator;
+
+    #[test]
+    fn serialize() {
+        let b = Board::from_fen("4k3/8/8/8/4Pp2/8/8/R3K3 b Q e3 0 1").ok().unwrap();
+        let bytes = serialize_board(&b);
+        assert_eq!(bytes[0..8], [1 << 4, 0, 0, 0, 0, 0, 0, 1 << 4]);
+        assert_eq!(bytes[16], 1);
+        assert_eq!(bytes[40..48], [0, 0, 0, 1 << 4 | 1 << 5, 0, 0, 0, 0]);
+        assert_eq!(bytes[64], 1);
+        assert_eq!(bytes[65], 1);
+        assert_eq!(bytes[66], 4);
+        assert_eq!(bytes[67], 0);
+    }
+
+    #[test]
+    fn fallback() {
+        let b = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").ok().unwrap();
+        PluginEvaluator::<SimpleEvaluator>::set_option("EvalPlugin", "/nonexistent/plugin.so");
+        let e = PluginEvaluator::<SimpleEvaluator>::new(&b);
+        assert!(e.plugin.is_none());
+        assert_eq!(e.evaluate(&b), SimpleEvaluator::new(&b).evaluate(&b));
+    }
+}
@@ -1,21 +1,44 @@
 //! Implements the `Evaluator` trait.
 
-use uci::SetOption;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use uci::{SetOption, OptionDescription};
 use moves::*;
 use board::*;
 use value::*;
-use evaluator::Evaluator;
+use evaluator::{Evaluator, EvalTerm};
 use bitsets::*;
 
 
 /// A simple evaluator that adds a random number to the available
 /// material.
+///
+/// The random number is a pseudo-random function of the position, so
+/// the evaluation is reproducible. Different values for the "Random
+/// Seed" option give different random numbers.
 #[derive(Clone)]
 pub struct SimpleEvaluator {
     material: Value,
 }
 
-impl SetOption for SimpleEvaluator {}
+impl SetOption for SimpleEvaluator {
+    fn options() -> Vec<(&'static str, OptionDescription)> {
+        vec![("Random Seed",
+              OptionDescription::Spin {
+                  min: 0,
+                  max: i32::MAX,
+                  default: 0,
+              })]
+    }
+
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        if name == "Random Seed" {
+            if let Ok(v) = value.parse::<usize>() {
+                RANDOM_SEED.store(v, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+}
 
 impl Evaluator for SimpleEvaluator {
     fn new(position: &Board) -> SimpleEvaluator {
@@ -44,9 +67,31 @@ impl Evaluator for SimpleEvaluator {
 
     #[inline]
     fn evaluate(&self, position: &Board) -> Value {
-        let k = (position.occupied >> 32 ^ position.occupied) as u32;
-        let random_number = (k.wrapping_mul(2654435769) >> 27) as Value;
-        self.material + random_number
+        self.material + random_number(position)
+    }
+
+    fn evaluate_explained(&self, position: &Board) -> Vec<EvalTerm> {
+        let material = |color: Color| {
+            let mut material = 0;
+            for piece in QUEEN..PIECE_NONE {
+                let occupied = position.pieces.piece_type[piece] & position.pieces.color[color];
+                material += PIECE_VALUES[piece] * pop_count(occupied) as Value;
+            }
+            material
+        };
+        // The random number is a bonus for the side to move.
+        let mut random = [0; 2];
+        random[position.to_move] = random_number(position);
+        vec![EvalTerm {
+                 name: "Material",
+                 white: material(WHITE),
+                 black: material(BLACK),
+             },
+             EvalTerm {
+                 name: "Random",
+                 white: random[WHITE],
+                 black: random[BLACK],
+             }]
     }
 
     #[allow(unused_variables)]
@@ -60,6 +105,18 @@ impl Evaluator for SimpleEvaluator {
 const PIECE_VALUES: [Value; 8] = [10000, 975, 500, 325, 325, 100, 0, 0];
 
 
+/// The value of the "Random Seed" option.
+static RANDOM_SEED: AtomicUsize = AtomicUsize::new(0);
+
+
+#[inline]
+fn random_number(position: &Board) -> Value {
+    let seed = RANDOM_SEED.load(Ordering::Relaxed) as u32;
+    let k = (position.occupied >> 32 ^ position.occupied) as u32 ^ seed;
+    (k.wrapping_mul(2654435769) >> 27) as Value
+}
+
+
 #[inline]
 fn gained_material(m: Move) -> Value {
     if m.move_type() == MOVE_PROMOTION {
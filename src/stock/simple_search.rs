@@ -1,9 +1,10 @@
 //! Implements `SimpleSearch`.
 
 use std::mem;
-use std::cmp::max;
+use std::cmp::{min, max};
 use std::thread;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicIsize, AtomicBool, Ordering};
 use std::sync::mpsc::{Sender, Receiver};
 use std::marker::PhantomData;
 use std::ops::Deref;
@@ -17,7 +18,8 @@ use search::*;
 use search_node::SearchNode;
 use evaluator::Evaluator;
 use qsearch::QsearchResult;
-use utils::MoveStack;
+use ranks::*;
+use utils::{MoveStack, Tunable};
 
 
 /// Executes depth-first alpha-beta searches with null move pruning
@@ -29,15 +31,47 @@ use utils::MoveStack;
 /// bound) and the maximum score that the minimizing player is assured
 /// of (upper bound) respectively.
 ///
+/// *Reverse futility pruning* (also known as static null move
+/// pruning) returns early from shallow non-PV nodes whose static
+/// evaluation exceeds beta by a depth-dependent margin.
+///
 /// *Null move pruning* is a method to reduce the search space by
 /// trying a "null" or "passing" move, then seeing if the score of the
 /// subtree search is still high enough to cause a beta cutoff. Nodes
 /// are saved by reducing the depth of the subtree under the null
 /// move.
 ///
+/// *ProbCut* searches the good captures at a reduced depth, with beta
+/// raised by a margin. If one of them fails high, a full-depth search
+/// would most probably fail high too, so the node is cut off.
+///
 /// *Late move reductions* save search space by reducing the search
 /// depth for moves that are ordered closer to the end (likely
-/// fail-low nodes).
+/// fail-low nodes). The reduction is smaller in the nodes that the
+/// transposition table remembers as former PV nodes.
+///
+/// *Killer moves* and *history heuristic* are used to order the quiet
+/// moves (see `KillerTable` and `HistoryTable`).
+///
+/// *Internal iterative deepening* is used at PV nodes for which the
+/// transposition table does not have a move -- a reduced-depth search
+/// is performed first, so as to find a good move to try first.
+///
+/// *Search extensions* increase the search depth by one half-move for
+/// checks, for singular moves (hash moves that are much better than
+/// all the alternatives), and for pawn pushes to the 7th rank. To
+/// avoid search explosion, the number of extensions of each kind
+/// along a line of play is limited.
+///
+/// *Mate distance pruning* cuts off the lines that can not lead to a
+/// faster checkmate than an already found one.
+///
+/// *The transposition table* values are not trusted when the
+/// fifty-move rule falls within their search horizon (see
+/// `Ttable::probe_with_clock`).
+///
+/// *The principal variation* is collected in a triangular PV table
+/// during the search, and is sent with the last progress report.
 ///
 /// **Important note:** `SimpleSearch` ignores the `searchmoves`
 /// search parameter. It always analyses all legal moves in the root
@@ -55,12 +89,13 @@ impl<T, N> Search for SimpleSearch<T, N>
 
     type SearchNode = N;
 
-    type ReportData = ();
+    type ReportData = Vec<Move>;
 
     fn spawn(params: SearchParams<Self::SearchNode>,
              tt: Arc<Self::Ttable>,
              reports_tx: Sender<SearchReport<Self::ReportData>>,
-             messages_rx: Receiver<String>)
+             _: Receiver<String>,
+             terminated: Arc<AtomicBool>)
              -> thread::JoinHandle<Value> {
         assert!(params.depth >= 0, "depth must be at least 0.");
         debug_assert!(params.depth <= DEPTH_MAX);
@@ -76,6 +111,7 @@ impl<T, N> Search for SimpleSearch<T, N>
                 depth,
                 lower_bound,
                 upper_bound,
+                node_limit,
                 ..
             } = params;
             let report = SearchReport {
@@ -83,30 +119,39 @@ impl<T, N> Search for SimpleSearch<T, N>
                 searched_nodes: 0,
                 depth: 0,
                 value: VALUE_UNKNOWN,
-                data: (),
+                data: vec![],
                 done: false,
+                termination: None,
+                details: Default::default(),
             };
-            let mut reporting = |searched_nodes| {
+            let mut reporting = |searched_nodes, selective_depth, current_move| {
                 reports_tx
                     .send(SearchReport {
-                              searched_nodes,
-                              ..report
+                              searched_nodes: searched_nodes,
+                              details: SearchDetails {
+                                  selective_depth: selective_depth,
+                                  current_move: current_move,
+                                  ..Default::default()
+                              },
+                              ..report.clone()
                           })
                     .ok();
-                if let Ok(msg) = messages_rx.try_recv() {
-                    msg == "TERMINATE"
-                } else {
-                    false
-                }
+                false
             };
             let mut move_stack = MoveStack::new();
             let mut search =
                 SearchRunner::new(position, tt.deref(), &mut move_stack, &mut reporting);
-            let (depth, value) = if let Ok(v) =
-                search.run(lower_bound, upper_bound, depth, Move::invalid()) {
-                (depth, v)
+            search.set_termination_flag(terminated);
+            if let Some(n) = node_limit {
+                search.set_node_limit(n);
+            }
+            let (depth, value, termination, pv) = if let Ok(v) =
+                search.run(lower_bound, upper_bound, depth) {
+                (depth, v, TerminationReason::DepthReached, search.pv().to_vec())
+            } else if node_limit.map_or(false, |n| search.node_count() >= n) {
+                (0, VALUE_UNKNOWN, TerminationReason::NodeLimitReached, vec![])
             } else {
-                (0, VALUE_UNKNOWN)
+                (0, VALUE_UNKNOWN, TerminationReason::Terminated, vec![])
             };
             reports_tx
                 .send(SearchReport {
@@ -114,6 +159,13 @@ impl<T, N> Search for SimpleSearch<T, N>
                           depth: depth,
                           value: value,
                           done: true,
+                          termination: Some(termination),
+                          details: SearchDetails {
+                              selective_depth: search.selective_depth(),
+                              tree: Some(search.tree_stats()),
+                              ..Default::default()
+                          },
+                          data: pv,
                           ..report
                       })
                 .ok();
@@ -124,11 +176,17 @@ impl<T, N> Search for SimpleSearch<T, N>
 
 impl<T: Ttable, N: SearchNode> SetOption for SimpleSearch<T, N> {
     fn options() -> Vec<(&'static str, OptionDescription)> {
-        N::options()
+        let mut options = Tunable::options(&tunable_params());
+        options.extend(N::options());
+        options
     }
 
-    fn set_option(name: &str, value: &str) {
-        N::set_option(name, value);
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        if Tunable::set_option(&tunable_params(), name, value) {
+            Ok(())
+        } else {
+            N::set_option(name, value)
+        }
     }
 }
 
@@ -144,12 +202,20 @@ struct SearchRunner<'a, T, N>
 {
     tt: &'a T,
     killers: KillerTable,
+    history: HistoryTable,
+    extensions: [usize; 3],
     position: N,
     moves: &'a mut MoveStack,
-    state_stack: Vec<NodeState>,
+    stack: SearchStack,
+    pv: PvTable,
     reported_nodes: u64,
     unreported_nodes: u64,
-    report_function: &'a mut FnMut(u64) -> bool,
+    node_limit: u64,
+    terminated: Arc<AtomicBool>,
+    selective_depth: Depth,
+    root_move: Option<(Move, usize)>,
+    stats: TreeStats,
+    report_function: &'a mut FnMut(u64, Depth, Option<(Move, usize)>) -> bool,
 }
 
 impl<'a, T, N> SearchRunner<'a, T, N>
@@ -160,26 +226,52 @@ impl<'a, T, N> SearchRunner<'a, T, N>
     ///
     /// `report_function` should be a function that registers the
     /// search progress. It will be called with the number of searched
-    /// positions from the beginning of the search to this moment. The
-    /// function should return `true` if the search should be
-    /// terminated, otherwise it should return `false`.
+    /// positions from the beginning of the search to this moment, the
+    /// selective search depth, and the move currently being searched
+    /// at the root (together with its number). The function should
+    /// return `true` if the search should be terminated, otherwise it
+    /// should return `false`.
     pub fn new(root: N,
                tt: &'a T,
                move_stack: &'a mut MoveStack,
-               report_function: &'a mut FnMut(u64) -> bool)
+               report_function: &'a mut FnMut(u64, Depth, Option<(Move, usize)>) -> bool)
                -> SearchRunner<'a, T, N> {
         SearchRunner {
             tt: tt,
             killers: KillerTable::new(),
+            history: HistoryTable::new(),
+            extensions: [0; 3],
             position: root,
             moves: move_stack,
-            state_stack: Vec::with_capacity(32),
+            stack: SearchStack::new(),
+            pv: PvTable::new(),
             reported_nodes: 0,
             unreported_nodes: 0,
+            node_limit: u64::MAX,
+            terminated: Arc::new(AtomicBool::new(false)),
+            selective_depth: 0,
+            root_move: None,
+            stats: TreeStats::default(),
             report_function: report_function,
         }
     }
 
+    /// Limits the number of positions that will be searched.
+    ///
+    /// When the limit is reached, `run` fails as if the search were
+    /// terminated.
+    pub fn set_node_limit(&mut self, node_limit: u64) {
+        self.node_limit = node_limit;
+    }
+
+    /// Sets a flag that terminates the search when it becomes `true`.
+    ///
+    /// The flag is checked for every searched position, so `run`
+    /// fails almost immediately after the flag is set.
+    pub fn set_termination_flag(&mut self, terminated: Arc<AtomicBool>) {
+        self.terminated = terminated;
+    }
+
     /// Performs a game tree search and returns the result.
     ///
     /// `alpha` and `beta` together give the interval within which an
@@ -188,9 +280,9 @@ impl<'a, T, N> SearchRunner<'a, T, N>
     /// this interval, this method may return a value that is closer
     /// to the the interval bounds than the exact evaluation, but
     /// always staying on the correct side of the interval. `depth` is
-    /// the desired search depth in half-moves. `last_move` should be
-    /// the move that led to the current position, or `Move::invalid()`
-    /// if the last move is unknown.
+    /// the desired search depth in half-moves. The move that led to
+    /// the current position is taken from the search stack (at the
+    /// root it is unknown).
     ///
     /// **Important note**: This method may leave un-restored move
     /// lists in `move_stack` (see the parametes passed to
@@ -198,11 +290,24 @@ impl<'a, T, N> SearchRunner<'a, T, N>
     /// stack to be restored to the state it had when the search
     /// instance was created.
     pub fn run(&mut self,
-               mut alpha: Value, // lower bound
+               alpha: Value, // lower bound
                beta: Value, // upper bound
-               depth: Depth,
-               last_move: Move)
+               depth: Depth)
                -> Result<Value, TerminatedSearch> {
+        self.search(alpha, beta, depth * ONE_PLY)
+    }
+
+    /// A helper method for `run`. It does the real work.
+    ///
+    /// Here `depth` is given in fractional units (see `ONE_PLY`), so
+    /// that the search depth can be reduced or extended by fractions
+    /// of a half-move. All the helper methods below use fractional
+    /// depths too.
+    fn search(&mut self,
+              mut alpha: Value, // lower bound
+              beta: Value, // upper bound
+              depth: Depth)
+              -> Result<Value, TerminatedSearch> {
         // This implementation performs a modified alpha-beta search.
         // It uses zero window searches with reduced depth for late
         // moves.
@@ -221,29 +326,52 @@ impl<'a, T, N> SearchRunner<'a, T, N>
         // respectively.
 
         debug_assert!(alpha < beta);
+        let is_pv = alpha + 1 < beta;
         let mut value = VALUE_UNKNOWN;
 
-        if let Some(v) = try!(self.node_begin(alpha, beta, depth, last_move)) {
+        if let Some(v) = try!(self.node_begin(alpha, beta, depth)) {
             // We already have the final result.
             value = v;
 
         } else {
             // Initial guests.
-            debug_assert!(depth > 0);
+            debug_assert!(depth >= ONE_PLY);
             let mut bound = BOUND_EXACT;
             let mut best_move = Move::invalid();
+            try!(self.internal_iterative_deepening(alpha, beta, depth));
+            let singular_move = try!(self.singular_move(depth));
 
             // Try moves.
-            while let Some(m) = self.do_move() {
-                try!(self.report_progress(1));
+            let mut move_number = 0;
+            while let Some((m, move_score)) = self.do_move() {
+                self.tt.prefetch(self.position.hash());
+                move_number += 1;
+                if self.stack.ply() == 0 {
+                    self.root_move = Some((m, move_number));
+                    self.unreported_nodes += 1;
+                    try!(self.flush_progress());
+                } else {
+                    try!(self.report_progress(1));
+                }
+                self.stack.current_mut().current_move = m;
+
+                // Decide whether to extend the search.
+                let extension = self.extension(m, singular_move, depth);
+                let depth = if let Some(kind) = extension {
+                    self.extensions[kind] += 1;
+                    depth + ONE_PLY
+                } else {
+                    depth
+                };
 
                 // Make a recursive call.
-                let mut v = if m.score() > REDUCTION_THRESHOLD {
+                let (child_alpha, child_beta) = child_window(alpha, beta);
+                let v = if move_score > REDUCTION_THRESHOLD {
                     // The moves that have good chances to cause a
                     // beta cut-off we analyze with a full depth and
                     // fully open window (alpha, beta). We hope that
                     // at least one of these moves will raise `alpha`.
-                    -try!(self.run(-beta, -alpha, depth - 1, m))
+                    -try!(self.search(child_alpha, child_beta, depth - ONE_PLY))
                 } else {
                     // For the rest of the moves we first try to prove
                     // that they are not better than our current best
@@ -252,22 +380,34 @@ impl<'a, T, N> SearchRunner<'a, T, N>
                     // 1). Only if it seems that the move is better
                     // than our current best move, we do a full-depth,
                     // full-window search.
-                    match -try!(self.run(-alpha - 1, -alpha, depth - 2, m)) {
-                        v if v <= alpha => v,
-                        _ => -try!(self.run(-beta, -alpha, depth - 1, m)),
+                    let mut reduction = LATE_MOVE_REDUCTION.get() as Depth;
+                    if self.stack.current().was_pv {
+                        reduction = max(0, reduction - FORMER_PV_REDUCTION_RELIEF);
+                    }
+                    let reduced_depth = depth - ONE_PLY - reduction;
+                    let (null_alpha, null_beta) = child_window(alpha, alpha + 1);
+                    self.stats.reductions += 1;
+                    match -try!(self.search(null_alpha, null_beta, reduced_depth)) {
+                        v if adjust_mate_value(v) <= alpha => v,
+                        _ => {
+                            self.stats.reduction_researches += 1;
+                            -try!(self.search(child_alpha, child_beta, depth - ONE_PLY))
+                        }
                     }
                 };
                 self.undo_move();
+                if let Some(kind) = extension {
+                    self.extensions[kind] -= 1;
+                }
                 debug_assert!(v > VALUE_UNKNOWN);
 
                 // Increase/decrease the value for a checkmate by one
                 // on every half-move. This way the engine will seek
-                // for the fastest checkmate possible.
-                if v < VALUE_EVAL_MIN - 1 {
-                    v += 1;
-                } else if v > VALUE_EVAL_MAX + 1 {
-                    v -= 1;
-                }
+                // for the fastest checkmate possible. Note that
+                // checkmate values are always relative to the current
+                // node, and therefore can be stored in (and retrieved
+                // from) the transposition table without adjustment.
+                let v = adjust_mate_value(v);
 
                 // See how good this move was.
                 if v >= beta {
@@ -278,7 +418,10 @@ impl<'a, T, N> SearchRunner<'a, T, N>
                     best_move = m;
                     value = v;
                     bound = BOUND_LOWER;
+                    self.stats.beta_cutoffs[min(move_number, 4) - 1] += 1;
+                    self.pv.update(self.stack.ply(), m);
                     self.register_killer_move(m);
+                    self.history.register(self.position.board().to_move, m, whole_plies(depth));
                     break;
                 }
                 if v > value {
@@ -287,6 +430,7 @@ impl<'a, T, N> SearchRunner<'a, T, N>
                     value = v;
                     bound = if v > alpha {
                         alpha = v;
+                        self.pv.update(self.stack.ply(), m);
                         BOUND_EXACT
                     } else {
                         BOUND_UPPER
@@ -301,7 +445,7 @@ impl<'a, T, N> SearchRunner<'a, T, N>
             }
 
             // Store the result to the transposition table.
-            self.store(value, bound, depth, best_move);
+            self.store(value, bound, depth, best_move, is_pv);
         }
 
         self.node_end();
@@ -314,6 +458,33 @@ impl<'a, T, N> SearchRunner<'a, T, N>
         self.reported_nodes + self.unreported_nodes
     }
 
+    /// Returns the maximal distance from the root (in half-moves)
+    /// reached by the search so far, including quiescence search.
+    #[inline]
+    pub fn selective_depth(&self) -> Depth {
+        self.selective_depth
+    }
+
+    /// Returns statistics about the game tree explored so far.
+    pub fn tree_stats(&self) -> TreeStats {
+        TreeStats {
+            nodes: self.node_count() - self.stats.qsearch_nodes,
+            ..self.stats
+        }
+    }
+
+    /// Returns the principal variation found by the last call to
+    /// `run`.
+    ///
+    /// The returned variation may be shorter than the search depth
+    /// (for example, when the result for some of its positions was
+    /// taken from the transposition table), or even empty (when no
+    /// move raised `alpha` at the root).
+    #[inline]
+    pub fn pv(&self) -> &[Move] {
+        self.pv.get(0)
+    }
+
     /// A helper method for `run`. Each call to `run` begins with a
     /// call to `node_begin`.
     ///
@@ -322,16 +493,19 @@ impl<'a, T, N> SearchRunner<'a, T, N>
     ///
     /// 1. Checks if the transposition table has the result.
     /// 2. On leaf nodes, performs quiescence search.
-    /// 3. Performs null move pruning if possible.
+    /// 3. Performs reverse futility pruning, null move pruning, and
+    ///    ProbCut if possible.
     fn node_begin(&mut self,
                   alpha: Value,
                   beta: Value,
-                  depth: Depth,
-                  last_move: Move)
+                  depth: Depth)
                   -> Result<Option<Value>, TerminatedSearch> {
         // Probe the transposition table.
         let hash = self.position.hash();
-        let (entry, static_eval) = if let Some(e) = self.tt.probe(hash) {
+        let entry = self.tt.probe_with_clock(hash, self.position.halfmove_clock());
+        self.stats.tt_probes += 1;
+        self.stats.tt_hits += entry.is_some() as u64;
+        let (entry, static_eval) = if let Some(e) = entry {
             match e.static_eval() {
                 VALUE_UNKNOWN => {
                     (e,
@@ -347,17 +521,24 @@ impl<'a, T, N> SearchRunner<'a, T, N>
                 .evaluate(self.position.board());
             (T::Entry::new(0, BOUND_NONE, 0).set_static_eval(v), v)
         };
-        self.state_stack
-            .push(NodeState {
-                      phase: NodePhase::Pristine,
-                      hash_move_digest: entry.move_digest(),
-                      static_eval: static_eval,
-                      is_check: unsafe { mem::uninitialized() }, // We will initialize this soon!
-                      killer: None,
-                  });
+        self.stack.push(entry.move_digest(), static_eval);
+        self.stack.current_mut().was_pv = entry.is_pv();
+        self.pv.clear(self.stack.ply());
+        let ply = self.stack.ply() as Depth;
+        self.selective_depth = max(self.selective_depth, ply);
+
+        // Mate distance pruning. Even if we checkmate the opponent on
+        // the next move, the value for the node will not be bigger
+        // than `VALUE_MAX - 1`. If `alpha` is that high already, this
+        // line is longer than an already found checkmate, and there
+        // is no point in searching it. (The root node is always
+        // searched, so that a best move is found.)
+        if ply > 0 && alpha >= VALUE_MAX - 1 {
+            return Ok(Some(alpha));
+        }
 
         // Check if the TT entry gives the result.
-        if entry.depth() >= depth {
+        if entry.depth() * ONE_PLY >= depth {
             let value = entry.value();
             let bound = entry.bound();
             if (value >= beta && bound & BOUND_LOWER != 0) ||
@@ -368,8 +549,11 @@ impl<'a, T, N> SearchRunner<'a, T, N>
         };
 
         // On leaf nodes, do quiescence search.
-        if depth <= 0 {
+        if depth < ONE_PLY {
+            let depth = whole_plies(depth);
             let result = self.position.qsearch(depth, alpha, beta, static_eval);
+            self.selective_depth = max(self.selective_depth, ply + result.depth());
+            self.stats.qsearch_nodes += result.searched_nodes();
             try!(self.report_progress(result.searched_nodes()));
             let bound = if result.value() >= beta {
                 BOUND_LOWER
@@ -384,37 +568,56 @@ impl<'a, T, N> SearchRunner<'a, T, N>
             return Ok(Some(result.value()));
         }
 
-        // Save the current move list and other info that we will need
-        // at later phases.
+        // Open a new move list frame, and save other info that we will
+        // need at later phases.
         {
-            self.moves.save();
-            let state = self.state_stack.last_mut().unwrap();
+            self.moves.open_frame(MOVES_PER_PLY);
+            let state = self.stack.current_mut();
             state.phase = NodePhase::ConsideredNullMove;
             state.is_check = self.position.is_check();
         }
 
+        // Consider reverse futility pruning. At shallow non-PV nodes,
+        // if the static evaluation exceeds `beta` by a margin that
+        // grows with the remaining depth, we assume that at least one
+        // of the moves will preserve the advantage. This is not safe
+        // when in check, in positions prone to zugzwang, or when
+        // `beta` is a checkmate value.
+        if depth <= REVERSE_FUTILITY_MAX_DEPTH && alpha + 1 == beta &&
+           !self.stack.current().is_check && beta < VALUE_EVAL_MAX &&
+           static_eval as isize -
+           REVERSE_FUTILITY_MARGIN.get() * depth as isize / ONE_PLY as isize >=
+           beta as isize &&
+           {
+               let p = &self.position;
+               !p.evaluator().is_zugzwangy(p.board())
+           } {
+            return Ok(Some(beta));
+        }
+
         // Consider null move pruning. In positions that are not prone
         // to zugzwang, we attempt to reduce the search space by
         // trying a "null" or "passing" move, then seeing if the score
         // of the sub-tree search is still high enough to cause a beta
         // cutoff. Nodes are saved by reducing the depth of the
         // sub-tree under the null move.
-        if !last_move.is_null() && static_eval >= beta &&
+        if self.stack.current().null_move_allowed && static_eval >= beta &&
            {
                let p = &self.position;
                !p.evaluator().is_zugzwangy(p.board())
            } {
             // Calculate the reduced depth.
-            let reduced_depth = if depth > 7 {
-                depth - NULL_MOVE_REDUCTION - 1
+            let r = NULL_MOVE_REDUCTION.get() as Depth * ONE_PLY;
+            let reduced_depth = if depth > 7 * ONE_PLY {
+                depth - r - ONE_PLY
             } else {
-                depth - NULL_MOVE_REDUCTION
+                depth - r
             };
 
             // Check if the TT indicates that trying a null move is
             // futile. We rely on the fact that if no normal move can
             // reach `beta`, a null move will not do it either.
-            if entry.depth() >= max(0, reduced_depth) && entry.value() < beta &&
+            if entry.depth() * ONE_PLY >= max(0, reduced_depth) && entry.value() < beta &&
                entry.bound() & BOUND_UPPER != 0 {
                 return Ok(None);
             }
@@ -422,50 +625,217 @@ impl<'a, T, N> SearchRunner<'a, T, N>
             // Play a null move and search.
             let m = self.position.null_move();
             if self.position.do_move(m) {
-                let value = -try!(self.run(-beta, -alpha, max(0, reduced_depth - 1), m));
+                self.tt.prefetch(self.position.hash());
+                self.stack.current_mut().current_move = m;
+                self.stats.null_moves += 1;
+                let value = -try!(self.search(-beta, -alpha, max(0, reduced_depth - ONE_PLY)));
                 self.position.undo_last_move();
                 if value >= beta {
+                    self.stats.null_move_cutoffs += 1;
                     // The result we are about to return is more or
                     // less a lie (because of the depth reduction),
                     // and therefore we better tell a smaller lie and
                     // return `beta` here instead of `value`.
                     self.tt
                         .store(hash,
-                               T::Entry::new(beta, BOUND_LOWER, depth)
+                               T::Entry::new(beta, BOUND_LOWER, whole_plies(depth))
                                    .set_static_eval(static_eval));
                     return Ok(Some(beta));
                 }
             }
         }
 
+        // Consider ProbCut.
+        if alpha + 1 == beta && !self.stack.current().is_check &&
+           try!(self.probcut(beta, depth, static_eval)) {
+            return Ok(Some(beta));
+        }
+
         // Well, we do not know the value yet.
         Ok(None)
     }
 
+    /// A helper method for `node_begin`. It performs ProbCut, and
+    /// returns `true` if the node can be cut off.
+    ///
+    /// Only the captures whose static exchange evaluation can bring
+    /// the static evaluation over the raised beta are tried. When
+    /// one of them fails high at the reduced depth, the result is
+    /// stored in the transposition table.
+    fn probcut(&mut self,
+               beta: Value,
+               depth: Depth,
+               static_eval: Value)
+               -> Result<bool, TerminatedSearch> {
+        let raised_beta = beta as isize + PROBCUT_MARGIN.get();
+        if depth < PROBCUT_MIN_DEPTH || beta <= VALUE_EVAL_MIN ||
+           raised_beta >= VALUE_EVAL_MAX as isize {
+            return Ok(false);
+        }
+        let raised_beta = raised_beta as Value;
+        let hash = self.position.hash();
+
+        // The generated moves are removed from the move list before
+        // returning, so that `do_move` starts with an empty list.
+        debug_assert!(self.moves.list().is_empty());
+        self.position.generate_captures(self.moves);
+        let mut is_cut = false;
+        while let Some(m) = self.moves.pull_best() {
+            if (static_eval as isize + self.position.evaluate_move(m) as isize) <
+               raised_beta as isize {
+                continue;
+            }
+            if self.position.do_move(m) {
+                try!(self.report_progress(1));
+                self.tt.prefetch(self.position.hash());
+                self.stack.current_mut().current_move = m;
+                let reduced_depth = depth - PROBCUT_REDUCTION;
+                let v = -try!(self.search(-raised_beta, -raised_beta + 1, reduced_depth));
+                self.position.undo_last_move();
+                if v >= raised_beta {
+                    self.tt
+                        .store(hash,
+                               T::Entry::new(raised_beta,
+                                             BOUND_LOWER,
+                                             whole_plies(reduced_depth + ONE_PLY))
+                                   .set_move_digest(m.digest())
+                                   .set_static_eval(static_eval));
+                    is_cut = true;
+                    break;
+                }
+            }
+        }
+        self.moves.clear();
+        Ok(is_cut)
+    }
+
+    /// A helper method for `run`. It performs internal iterative
+    /// deepening.
+    ///
+    /// When the transposition table has no move for a PV node (a node
+    /// searched with a non-null window), a reduced-depth search is
+    /// performed, and the best move found by it becomes the hash move.
+    ///
+    /// The reduced-depth search is performed on the current ply, so
+    /// the killer moves and the principal variation that it finds are
+    /// recorded for the current node.
+    fn internal_iterative_deepening(&mut self,
+                                    alpha: Value,
+                                    beta: Value,
+                                    depth: Depth)
+                                    -> Result<(), TerminatedSearch> {
+        if depth < IID_MIN_DEPTH || alpha + 1 == beta ||
+           self.stack.current().hash_move_digest != MoveDigest::invalid() {
+            return Ok(());
+        }
+
+        // The reduced-depth search pushes its own frame for the
+        // current node, so we temporarily take ours off the stack.
+        let frame = self.stack.take();
+        let result = self.search(alpha, beta, depth - IID_REDUCTION.get() as Depth * ONE_PLY);
+        self.stack.put_back(frame);
+        try!(result);
+        if let Some(e) = self.tt.probe(self.position.hash()) {
+            self.stack.current_mut().hash_move_digest = e.move_digest();
+        }
+        Ok(())
+    }
+
+    /// A helper method for `run`. It checks if the hash move is
+    /// singular, and if so, returns it.
+    ///
+    /// A move is singular when the transposition table gives a lower
+    /// bound for its value, and all other moves fail low with respect
+    /// to a window a margin below that bound, when searched with a
+    /// reduced depth.
+    fn singular_move(&mut self, depth: Depth) -> Result<Option<Move>, TerminatedSearch> {
+        if depth < SINGULAR_EXTENSION_MIN_DEPTH ||
+           self.extensions[EXTENSION_SINGULAR] >= EXTENSIONS_MAX[EXTENSION_SINGULAR] {
+            return Ok(None);
+        }
+        let margin = SINGULAR_EXTENSION_MARGIN.get() as Value;
+        let entry = self.tt.probe_with_clock(self.position.hash(), self.position.halfmove_clock());
+        let (hash_move, beta) = match entry {
+            Some(e) if e.depth() * ONE_PLY >= depth - 3 * ONE_PLY &&
+                       e.bound() & BOUND_LOWER != 0 &&
+                       VALUE_EVAL_MIN + margin <= e.value() &&
+                       e.value() <= VALUE_EVAL_MAX => {
+                match self.position.try_move_digest(e.move_digest()) {
+                    Some(m) => (m, e.value() - margin),
+                    None => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        // Search all other moves with a null window (beta - 1, beta).
+        // The generated moves are removed from the move list before
+        // returning, so that `do_move` starts with an empty list.
+        debug_assert!(self.moves.list().is_empty());
+        self.position.generate_moves(self.moves);
+        self.stack.current_mut().excluded_move = hash_move;
+        let mut is_singular = true;
+        while let Some(m) = self.moves.pop() {
+            if m != self.stack.current().excluded_move && self.position.do_move(m) {
+                try!(self.report_progress(1));
+                self.stack.current_mut().current_move = m;
+                let v = -try!(self.search(-beta, -beta + 1, depth / 2 - ONE_PLY));
+                self.position.undo_last_move();
+                if v >= beta {
+                    is_singular = false;
+                    break;
+                }
+            }
+        }
+        self.moves.clear();
+        self.stack.current_mut().excluded_move = Move::invalid();
+        Ok(if is_singular { Some(hash_move) } else { None })
+    }
+
+    /// A helper method for `run`. It decides whether the search
+    /// should be extended for the move `m`, which has just been
+    /// played. Returns the kind of the extension, or `None`.
+    fn extension(&self, m: Move, singular_move: Option<Move>, depth: Depth) -> Option<usize> {
+        // Make sure that the maximum search depth is not exceeded.
+        if self.stack.len() + whole_plies(depth) as usize >= DEPTH_MAX as usize {
+            return None;
+        }
+        let mut is_applicable = [false; 3];
+        is_applicable[EXTENSION_CHECK] = self.position.is_check();
+        is_applicable[EXTENSION_SINGULAR] = Some(m) == singular_move;
+        is_applicable[EXTENSION_PAWN_PUSH] = m.played_piece() == PAWN &&
+                                             match Board::rank(m.dest_square()) {
+            RANK_7 => self.position.board().to_move == BLACK,
+            RANK_2 => self.position.board().to_move == WHITE,
+            _ => false,
+        };
+        (0..3).find(|&kind| is_applicable[kind] && self.extensions[kind] < EXTENSIONS_MAX[kind])
+    }
+
     /// A helper method for `run`. Each call to `run` ends with a call
     /// to `node_end`.
     #[inline]
     fn node_end(&mut self) {
-        // Restore the move list from the previous ply (half-move) and
-        // pop the state stack.
-        if let NodePhase::Pristine = self.state_stack.last().unwrap().phase {
-            // For pristine nodes we have not saved a new move list,
-            // so we should not call `restore`.
+        // Close the move list frame for this ply (half-move) and pop
+        // the state stack.
+        if let NodePhase::Pristine = self.stack.current().phase {
+            // For pristine nodes we have not opened a new frame, so
+            // we should not call `close_frame`.
         } else {
-            self.moves.restore();
+            self.moves.close_frame();
         }
-        self.state_stack.pop();
+        self.stack.pop();
 
         // Killer moves for distant plys are gradually becoming
         // outdated, so we should downgrade them.
-        let downgraded_ply = self.state_stack.len() + KILLERS_DOWNGRADE_DISTANCE;
+        let downgraded_ply = self.stack.len() + KILLERS_DOWNGRADE_DISTANCE;
         if downgraded_ply < DEPTH_MAX as usize {
             self.killers.downgrade(downgraded_ply);
         }
     }
 
     /// A helper method for `run`. It plays the next legal move in the
-    /// current position and returns it.
+    /// current position and returns it, together with its move score.
     ///
     /// Each call to `do_move` for the same position will play and
     /// return a different move. When all legal moves has been played,
@@ -474,10 +844,9 @@ impl<'a, T, N> SearchRunner<'a, T, N>
     /// try to be efficient, for example it will generate the list of
     /// all pseudo-legal moves at the last possible moment.
     #[inline]
-    fn do_move(&mut self) -> Option<Move> {
-        debug_assert!(self.state_stack.len() > 0);
-        let ply = self.state_stack.len() - 1;
-        let state = &mut self.state_stack[ply];
+    fn do_move(&mut self) -> Option<(Move, u32)> {
+        let ply = self.stack.ply();
+        let state = self.stack.current_mut();
         debug_assert!(if let NodePhase::Pristine = state.phase {
                           false
                       } else {
@@ -488,18 +857,18 @@ impl<'a, T, N> SearchRunner<'a, T, N>
         // Try the hash move.
         if let NodePhase::ConsideredNullMove = state.phase {
             state.phase = NodePhase::TriedHashMove;
-            if let Some(mut m) = self.position.try_move_digest(state.hash_move_digest) {
+            if let Some(m) = self.position.try_move_digest(state.hash_move_digest) {
                 if self.position.do_move(m) {
-                    m.set_score(MOVE_SCORE_MAX);
-                    return Some(m);
+                    return Some((m, MOVE_SCORE_MAX));
                 }
             }
         }
 
-        // Generate all pseudo-legal moves.
+        // Generate the captures and the pawn promotions to queen. (The
+        // quiet moves will be generated later, only if needed.)
         if let NodePhase::TriedHashMove = state.phase {
-            state.phase = NodePhase::GeneratedMoves;
-            self.position.generate_moves(self.moves);
+            state.phase = NodePhase::GeneratedCaptures;
+            self.position.generate_captures(self.moves);
 
             // Remove the already tried hash move from the list.
             if state.hash_move_digest != MoveDigest::invalid() {
@@ -508,7 +877,8 @@ impl<'a, T, N> SearchRunner<'a, T, N>
 
             // Set move scores to captures and pawn promotions to
             // queen according to their static exchange evaluation.
-            for m in self.moves.list_mut().iter_mut() {
+            for i in 0..self.moves.list().len() {
+                let m = self.moves.list()[i];
                 let move_score = if m.move_type() == MOVE_PROMOTION {
                     if m.aux_data() == 0 {
                         MOVE_SCORE_MAX - 1
@@ -516,7 +886,7 @@ impl<'a, T, N> SearchRunner<'a, T, N>
                         0
                     }
                 } else if m.captured_piece() < PIECE_NONE {
-                    match self.position.evaluate_move(*m) {
+                    match self.position.evaluate_move(m) {
                         see if see > 0 => MOVE_SCORE_MAX - 1,
                         see if see == 0 => MOVE_SCORE_MAX - 2,
                         _ => 0,
@@ -524,84 +894,105 @@ impl<'a, T, N> SearchRunner<'a, T, N>
                 } else {
                     0
                 };
-                m.set_score(move_score);
+                self.moves.scores_mut()[i] = move_score;
             }
         }
 
-        // Try the generated moves.
-        while let Some(mut m) = if let NodePhase::TriedLosingCaptures = state.phase {
-                  // After we have tried the losing captures, we try the
-                  // rest of the moves in the order in which they reside in
-                  // the move stack, because at this stage the probability
-                  // of cut-off is low, so the move ordering is not
-                  // important.
-                  self.moves.pop()
-              } else {
-                  self.moves.pull_best()
-              } {
-            // First -- the winning and even captures and promotions
-            // to queen.
-            if let NodePhase::GeneratedMoves = state.phase {
-                if m.score() > REDUCTION_THRESHOLD {
+        // First -- the winning and even captures and promotions to
+        // queen.
+        if let NodePhase::GeneratedCaptures = state.phase {
+            while let Some((m, move_score)) = self.moves.pull_best_scored() {
+                if move_score > REDUCTION_THRESHOLD {
                     if self.position.do_move(m) {
-                        return Some(m);
+                        return Some((m, move_score));
                     }
                     continue;
                 }
-                state.phase = NodePhase::TriedWinningMoves;
+                self.moves.push_scored(m, move_score);
+                break;
             }
+            state.phase = NodePhase::TriedWinningMoves;
+            state.killers = self.killers.get(ply);
+        }
 
-            // Second -- the killer moves. We try two killer moves in
-            // two sequential iterations of the loop. `state.killer`
-            // remembers where we are.
-            if let NodePhase::TriedWinningMoves = state.phase {
-                self.moves.add_move(m);
-                let killer = if let Some(k2) = state.killer {
+        // Second -- the killer moves. We try two killer moves in two
+        // sequential calls. The quiet moves have not been generated
+        // yet, so the killers that are not in the list are verified
+        // with `try_move_digest`.
+        loop {
+            let killer = match state.phase {
+                NodePhase::TriedWinningMoves => {
+                    state.phase = NodePhase::TriedFirstKiller;
+                    state.killers.0
+                }
+                NodePhase::TriedFirstKiller => {
                     state.phase = NodePhase::TriedKillerMoves;
-                    k2
-                } else {
-                    let (k1, k2) = self.killers.get(ply);
-                    state.killer = Some(k2);
-                    k1
-                };
-                if killer != MoveDigest::invalid() {
-                    if let Some(mut m) = self.moves.pull_move(killer) {
-                        if self.position.do_move(m) {
-                            m.set_score(MOVE_SCORE_MAX);
-                            return Some(m);
-                        }
-                    }
+                    state.killers.1
                 }
+                _ => break,
+            };
+            if killer == MoveDigest::invalid() || killer == state.hash_move_digest {
                 continue;
             }
+            let m = match self.moves.pull_move(killer) {
+                None if !state.is_check => {
+                    match self.position.try_move_digest(killer) {
+                        Some(m) if !m.is_tactical() => m,
+                        _ => continue,
+                    }
+                }
+                None => continue,
+                Some(m) => m,
+            };
+            if self.position.do_move(m) {
+                return Some((m, MOVE_SCORE_MAX));
+            }
+        }
 
-            // Third -- the losing captures.
-            if let NodePhase::TriedKillerMoves = state.phase {
+        // Third -- the losing captures.
+        if let NodePhase::TriedKillerMoves = state.phase {
+            while let Some((m, move_score)) = self.moves.pull_best_scored() {
                 if m.captured_piece() < PIECE_NONE {
                     if self.position.do_move(m) {
-                        m.set_score(MOVE_SCORE_MAX);
-                        return Some(m);
+                        return Some((m, MOVE_SCORE_MAX));
                     }
                     continue;
                 }
-                state.phase = NodePhase::TriedLosingCaptures;
-                self.moves.add_move(m);
+                self.moves.push_scored(m, move_score);
+                break;
+            }
+            state.phase = NodePhase::TriedLosingCaptures;
+
+            // Generate the quiet moves, and remove the already tried
+            // hash move and killer moves from the list.
+            self.position.generate_quiet_moves(self.moves);
+            for &digest in &[state.hash_move_digest, state.killers.0, state.killers.1] {
+                if digest != MoveDigest::invalid() {
+                    self.moves.pull_move(digest);
+                }
+            }
 
-                // TODO: Pull selected quiet moves to the top of the
-                // move stack here, using the history
-                // heuristics.
-                continue;
+            // Set move scores to the remaining quiet moves according
+            // to the history heuristic.
+            let us = self.position.board().to_move;
+            for i in 0..self.moves.list().len() {
+                let m = self.moves.list()[i];
+                self.moves.scores_mut()[i] = self.history.get(us, m);
             }
+        }
 
-            // Fourth -- the remaining quiet moves.
+        // Fourth -- the remaining quiet moves. (The history heuristic
+        // scores are used only for ordering, so we return a zero
+        // score to allow depth reductions.)
+        while let Some(m) = self.moves.pull_best() {
             if self.position.do_move(m) {
                 if state.is_check || self.position.is_check() || m.move_type() == MOVE_PROMOTION {
                     // When evading check, giving check, or promoting
                     // a pawn -- set a high move score to avoid search
                     // depth reductions.
-                    m.set_score(MOVE_SCORE_MAX);
+                    return Some((m, MOVE_SCORE_MAX));
                 }
-                return Some(m);
+                return Some((m, 0));
             }
         }
         None
@@ -616,13 +1007,22 @@ impl<'a, T, N> SearchRunner<'a, T, N>
 
     /// A helper method for `run`. It stores the updated node
     /// information in the transposition table.
+    ///
+    /// `is_pv` tells whether the node has been searched with a
+    /// non-null window (a PV node).
     #[inline]
-    fn store(&mut self, value: Value, bound: BoundType, depth: Depth, best_move: Move) {
+    fn store(&mut self,
+             value: Value,
+             bound: BoundType,
+             depth: Depth,
+             best_move: Move,
+             is_pv: bool) {
         self.tt
             .store(self.position.hash(),
-                   T::Entry::new(value, bound, depth)
+                   T::Entry::new(value, bound, whole_plies(depth))
                        .set_move_digest(best_move.digest())
-                       .set_static_eval(self.state_stack.last().unwrap().static_eval));
+                       .set_static_eval(self.stack.current().static_eval)
+                       .set_pv_flag(is_pv));
     }
 
     /// A helper method for `run`. It reports search progress.
@@ -638,12 +1038,25 @@ impl<'a, T, N> SearchRunner<'a, T, N>
             NODE_COUNT_REPORT_INTERVAL
         };
         self.unreported_nodes += new_nodes;
+        if self.node_count() >= self.node_limit || self.terminated.load(Ordering::Relaxed) {
+            return Err(TerminatedSearch);
+        }
         if self.unreported_nodes >= node_count_report_interval {
-            self.reported_nodes += self.unreported_nodes;
-            self.unreported_nodes = 0;
-            if (*self.report_function)(self.reported_nodes) {
-                return Err(TerminatedSearch);
-            }
+            try!(self.flush_progress());
+        }
+        Ok(())
+    }
+
+    /// A helper method for `run`. It reports search progress
+    /// immediately.
+    ///
+    /// This is used when a new move starts being searched at the
+    /// root, so that the move can be shown without delay.
+    fn flush_progress(&mut self) -> Result<(), TerminatedSearch> {
+        self.reported_nodes += self.unreported_nodes;
+        self.unreported_nodes = 0;
+        if (*self.report_function)(self.reported_nodes, self.selective_depth, self.root_move) {
+            return Err(TerminatedSearch);
         }
         Ok(())
     }
@@ -652,7 +1065,7 @@ impl<'a, T, N> SearchRunner<'a, T, N>
     /// caused a beta cut-off (a killer move).
     #[inline]
     fn register_killer_move(&mut self, m: Move) {
-        self.killers.register(self.state_stack.len() - 1, m);
+        self.killers.register(self.stack.ply(), m);
     }
 }
 
@@ -661,6 +1074,11 @@ impl<'a, T, N> SearchRunner<'a, T, N>
 const MOVE_SCORE_MAX: u32 = ::std::u32::MAX;
 
 
+/// The number of moves for which space is reserved in the move stack
+/// at each ply. (Most positions have fewer legal moves.)
+const MOVES_PER_PLY: usize = 64;
+
+
 /// The number of nodes that will be searched without reporting search
 /// progress.
 ///
@@ -671,7 +1089,31 @@ const NODE_COUNT_REPORT_INTERVAL: u64 = 15000;
 
 /// The number of half-moves with which the search depth will be
 /// reduced when trying null moves.
-const NULL_MOVE_REDUCTION: i8 = 3;
+static NULL_MOVE_REDUCTION: Tunable = Tunable {
+    name: "Tune Null Move Reduction",
+    min: 1,
+    max: 6,
+    default: 3,
+    value: AtomicIsize::new(3),
+};
+
+
+/// The number of fractional depth units (see `ONE_PLY`) with which
+/// the search depth will be additionally reduced for the moves that
+/// are searched at reduced depth.
+static LATE_MOVE_REDUCTION: Tunable = Tunable {
+    name: "Tune Late Move Reduction",
+    min: 0,
+    max: 3 * ONE_PLY as isize,
+    default: ONE_PLY as isize,
+    value: AtomicIsize::new(ONE_PLY as isize),
+};
+
+
+/// In former PV nodes, late move reductions are smaller by this many
+/// fractional depth units (see `ONE_PLY`). Moves in those nodes are
+/// more likely to turn out better than expected.
+const FORMER_PV_REDUCTION_RELIEF: Depth = ONE_PLY / 2;
 
 
 /// Moves with move scores higher than this number will be searched at
@@ -680,6 +1122,95 @@ const NULL_MOVE_REDUCTION: i8 = 3;
 const REDUCTION_THRESHOLD: u32 = 0;
 
 
+/// The maximum remaining depth at which reverse futility pruning is
+/// performed.
+const REVERSE_FUTILITY_MAX_DEPTH: Depth = 3 * ONE_PLY;
+
+
+/// For reverse futility pruning, the static evaluation must exceed
+/// beta by this margin for each half-move of remaining depth.
+static REVERSE_FUTILITY_MARGIN: Tunable = Tunable {
+    name: "Tune Reverse Futility Margin",
+    min: 0,
+    max: 500,
+    default: 150,
+    value: AtomicIsize::new(150),
+};
+
+
+/// The minimum search depth at which ProbCut is performed.
+const PROBCUT_MIN_DEPTH: Depth = 5 * ONE_PLY;
+
+
+/// The search depth will be reduced by this much for ProbCut
+/// searches.
+const PROBCUT_REDUCTION: Depth = 4 * ONE_PLY;
+
+
+/// For ProbCut, the reduced-depth search must fail high with respect
+/// to beta raised by this margin.
+static PROBCUT_MARGIN: Tunable = Tunable {
+    name: "Tune ProbCut Margin",
+    min: 0,
+    max: 500,
+    default: 200,
+    value: AtomicIsize::new(200),
+};
+
+
+/// The minimum search depth at which internal iterative deepening is
+/// performed.
+const IID_MIN_DEPTH: Depth = 5 * ONE_PLY;
+
+
+/// The number of half-moves with which the search depth will be
+/// reduced for internal iterative deepening.
+static IID_REDUCTION: Tunable = Tunable {
+    name: "Tune IID Reduction",
+    min: 1,
+    max: 4,
+    default: 2,
+    value: AtomicIsize::new(2),
+};
+
+
+/// Extension kinds -- checks, singular moves, and pawn pushes to the
+/// 7th rank.
+const EXTENSION_CHECK: usize = 0;
+const EXTENSION_SINGULAR: usize = 1;
+const EXTENSION_PAWN_PUSH: usize = 2;
+
+
+/// The maximum number of extensions of each kind along a line of play.
+const EXTENSIONS_MAX: [usize; 3] = [8, 2, 4];
+
+
+/// The minimum search depth at which a singular move is looked for.
+const SINGULAR_EXTENSION_MIN_DEPTH: Depth = 6 * ONE_PLY;
+
+
+/// All moves except the singular move must fail low with respect to
+/// the value of the singular move reduced by this margin.
+static SINGULAR_EXTENSION_MARGIN: Tunable = Tunable {
+    name: "Tune Singular Extension Margin",
+    min: 0,
+    max: 500,
+    default: 50,
+    value: AtomicIsize::new(50),
+};
+
+
+/// Returns the search parameters that can be tuned at runtime.
+fn tunable_params() -> [&'static Tunable; 6] {
+    [&NULL_MOVE_REDUCTION,
+     &LATE_MOVE_REDUCTION,
+     &REVERSE_FUTILITY_MARGIN,
+     &PROBCUT_MARGIN,
+     &IID_REDUCTION,
+     &SINGULAR_EXTENSION_MARGIN]
+}
+
+
 /// When this distance in half-moves is reached, the old killer moves
 /// will be downgraded. This affects for how long the successful old
 /// killer moves are kept.
@@ -691,20 +1222,168 @@ enum NodePhase {
     Pristine,
     ConsideredNullMove,
     TriedHashMove,
-    GeneratedMoves,
+    GeneratedCaptures,
     TriedWinningMoves,
+    TriedFirstKiller,
     TriedKillerMoves,
     TriedLosingCaptures,
 }
 
 
 /// Holds information about the state of a node in the search tree.
-struct NodeState {
+struct SearchFrame {
     phase: NodePhase,
     hash_move_digest: MoveDigest,
     static_eval: Value,
     is_check: bool,
-    killer: Option<MoveDigest>,
+
+    // The killer moves tried in this node.
+    killers: (MoveDigest, MoveDigest),
+
+    // The move that is currently being searched, or
+    // `Move::invalid()`.
+    current_move: Move,
+
+    // A move that must not be searched (the hash move, while checking
+    // if it is singular), or `Move::invalid()`.
+    excluded_move: Move,
+
+    // Whether null move pruning is allowed. Two null moves in a row
+    // are not allowed.
+    null_move_allowed: bool,
+
+    // Whether the transposition table says that the node has been
+    // searched as a PV node before.
+    was_pv: bool,
+}
+
+
+/// Holds a frame for each node in the current line of play.
+///
+/// Each call to `SearchRunner::run` pushes a new frame at the
+/// beginning, and pops it at the end. So, the search heuristics can
+/// look not only at the current node, but also at the nodes that led
+/// to it.
+struct SearchStack {
+    frames: Vec<SearchFrame>,
+}
+
+impl SearchStack {
+    /// Creates a new (empty) instance.
+    fn new() -> SearchStack {
+        SearchStack { frames: Vec::with_capacity(DEPTH_MAX as usize + 1) }
+    }
+
+    /// Pushes a new frame for a node with a given hash move and
+    /// static evaluation.
+    #[inline]
+    fn push(&mut self, hash_move_digest: MoveDigest, static_eval: Value) {
+        // The new node is reached by the move currently being
+        // searched in the node below it.
+        let last_move = self.frames.last().map_or(Move::invalid(), |f| f.current_move);
+        self.frames
+            .push(SearchFrame {
+                      phase: NodePhase::Pristine,
+                      hash_move_digest: hash_move_digest,
+                      static_eval: static_eval,
+                      is_check: false,
+                      killers: (MoveDigest::invalid(), MoveDigest::invalid()),
+                      current_move: Move::invalid(),
+                      excluded_move: Move::invalid(),
+                      null_move_allowed: !last_move.is_null(),
+                      was_pv: false,
+                  });
+    }
+
+    /// Pops the frame for the current node.
+    #[inline]
+    fn pop(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Removes the frame for the current node, and returns it.
+    #[inline]
+    fn take(&mut self) -> SearchFrame {
+        self.frames.pop().unwrap()
+    }
+
+    /// Puts back a frame removed by `take`.
+    #[inline]
+    fn put_back(&mut self, frame: SearchFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Returns the number of frames in the stack.
+    #[inline]
+    fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the ply (half-move) of the current node. The root node
+    /// is at ply `0`.
+    #[inline]
+    fn ply(&self) -> usize {
+        debug_assert!(!self.frames.is_empty());
+        self.frames.len() - 1
+    }
+
+    /// Returns the frame for the current node.
+    #[inline]
+    fn current(&self) -> &SearchFrame {
+        self.frames.last().unwrap()
+    }
+
+    /// Returns the frame for the current node as mutable.
+    #[inline]
+    fn current_mut(&mut self) -> &mut SearchFrame {
+        self.frames.last_mut().unwrap()
+    }
+}
+
+
+/// A triangular table of principal variations.
+///
+/// For each ply (half-move) in the current line of play, holds the
+/// principal variation (PV) found so far for the node at that
+/// ply. When a new best move is found, the PV for the node becomes
+/// the move, followed by the PV for the child node.
+struct PvTable {
+    lines: Vec<Vec<Move>>,
+}
+
+impl PvTable {
+    /// Creates a new (empty) instance.
+    fn new() -> PvTable {
+        let n = DEPTH_MAX as usize + 2;
+        PvTable { lines: (0..n).map(|ply| Vec::with_capacity(n - ply)).collect() }
+    }
+
+    /// Clears the PV for the node at a given ply.
+    #[inline]
+    fn clear(&mut self, ply: usize) {
+        if let Some(line) = self.lines.get_mut(ply) {
+            line.clear();
+        }
+    }
+
+    /// Sets the PV for the node at a given ply to the move `m`,
+    /// followed by the PV for the child node.
+    #[inline]
+    fn update(&mut self, ply: usize, m: Move) {
+        if ply + 1 < self.lines.len() {
+            let (head, tail) = self.lines.split_at_mut(ply + 1);
+            let line = &mut head[ply];
+            line.clear();
+            line.push(m);
+            line.extend_from_slice(&tail[0]);
+        }
+    }
+
+    /// Returns the PV for the node at a given ply.
+    #[inline]
+    fn get(&self, ply: usize) -> &[Move] {
+        &self.lines[ply]
+    }
 }
 
 
@@ -715,7 +1394,7 @@ struct NodeState {
 /// node, or any other earlier branch in the search tree with the same
 /// distance to the root position. The idea is to try that move early
 /// -- directly after the hash move and the winning captures.
-struct KillerTable {
+pub struct KillerTable {
     array: [KillerPair; DEPTH_MAX as usize],
 }
 
@@ -787,6 +1466,67 @@ impl KillerTable {
 }
 
 
+/// Holds history heuristic scores for every quiet move.
+///
+/// "History heuristic" is a dynamic move ordering method based on
+/// the number of cutoffs caused by a given move irrespectively from
+/// the position in which the move has been made. Every time a quiet
+/// move causes a beta cut-off, its score in the history table
+/// (indexed by side to move, origin square, and destination square --
+/// a "butterfly" table) is increased. The idea is to try the quiet
+/// moves with higher scores first.
+pub struct HistoryTable {
+    array: Vec<u32>,
+}
+
+impl HistoryTable {
+    /// Creates a new instance.
+    pub fn new() -> HistoryTable {
+        HistoryTable { array: vec![0; 2 * 64 * 64] }
+    }
+
+    /// Registers that the move `m`, played by the side `us`, caused a
+    /// beta cut-off at the specified search `depth`.
+    #[inline]
+    pub fn register(&mut self, us: Color, m: Move, depth: Depth) {
+        debug_assert!(us <= 1);
+        if m.captured_piece() < PIECE_NONE || m.move_type() == MOVE_PROMOTION || m.is_null() ||
+           depth <= 0 {
+            // Captures and promotions are ordered by other means.
+            return;
+        }
+        let i = Self::index(us, m);
+        self.array[i] += (depth as u32) * (depth as u32);
+
+        // Keep the scores from overflowing, giving more weight to
+        // the recent cut-offs.
+        if self.array[i] > HISTORY_SCORE_MAX {
+            for x in self.array.iter_mut() {
+                *x >>= 1;
+            }
+        }
+    }
+
+    /// Returns the history heuristic score for the move `m`, played
+    /// by the side `us`.
+    #[inline]
+    pub fn get(&self, us: Color, m: Move) -> u32 {
+        debug_assert!(us <= 1);
+        self.array[Self::index(us, m)]
+    }
+
+    #[inline]
+    fn index(us: Color, m: Move) -> usize {
+        (us << 12) | (m.orig_square() << 6) | m.dest_square()
+    }
+}
+
+
+/// When a score in the history table reaches this number, all
+/// scores are divided by two.
+const HISTORY_SCORE_MAX: u32 = 1 << 24;
+
+
 /// A killer move with its hit counter.
 #[derive(Clone, Copy)]
 struct Killer {
@@ -822,6 +1562,57 @@ impl Default for KillerPair {
 }
 
 
+/// A helper function. It increases/decreases a checkmate value by one
+/// half-move.
+///
+/// `value` should be the negated value of a child node. The returned
+/// value is from the point of view of the parent node.
+#[inline]
+fn adjust_mate_value(value: Value) -> Value {
+    if value < VALUE_EVAL_MIN - 1 {
+        value + 1
+    } else if value > VALUE_EVAL_MAX + 1 {
+        value - 1
+    } else {
+        value
+    }
+}
+
+
+/// A helper function. It returns the (alpha, beta) window with which
+/// a child node should be searched, given the window for the parent
+/// node.
+///
+/// Because checkmate values are adjusted by one on every half-move
+/// (see `adjust_mate_value`), checkmate bounds are shifted by one
+/// when passed to the child node. As a result, once a checkmate has
+/// been found, the window becomes narrower on every half-move, and
+/// longer lines are cut off by mate distance pruning.
+#[inline]
+fn child_window(alpha: Value, beta: Value) -> (Value, Value) {
+    debug_assert!(alpha < beta);
+
+    // Returns the biggest value `x`, for which
+    // `adjust_mate_value(x) <= v`. (Calculated as `isize` so as to
+    // avoid overflows.)
+    let unadjust = |v: Value| -> isize {
+        let v = v as isize;
+        if v > VALUE_EVAL_MAX as isize {
+            v + 1
+        } else if v < VALUE_EVAL_MIN as isize - 1 {
+            v - 1
+        } else {
+            v
+        }
+    };
+    let lower = max(-unadjust(beta - 1) - 1, VALUE_MIN as isize);
+    let upper = min(-unadjust(alpha), VALUE_MAX as isize);
+    let lower = min(lower, VALUE_MAX as isize - 1);
+    let upper = max(upper, lower + 1);
+    (lower as Value, upper as Value)
+}
+
+
 /// A helper function. It checks if the two supplied lists of moves
 /// contain the same moves, possibly in different order.
 fn contains_same_moves(list1: &Vec<Move>, list2: &Vec<Move>) -> bool {
@@ -835,8 +1626,9 @@ fn contains_same_moves(list1: &Vec<Move>, list2: &Vec<Move>) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use super::{SearchRunner, KillerTable};
+    use super::{SearchRunner, KillerTable, HistoryTable};
     use value::*;
+    use depth::*;
     use board::*;
     use search_node::*;
     use moves::*;
@@ -856,26 +1648,220 @@ mod tests {
                 .ok()
                 .unwrap();
         let mut moves = MoveStack::new();
-        let mut report = |_| false;
+        let mut report = |_, _, _| false;
         let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
         let value = search
-            .run(VALUE_MIN, VALUE_MAX, 1, Move::invalid())
+            .run(VALUE_MIN, VALUE_MAX, 1)
             .ok()
             .unwrap();
-        assert!(value < -300);
+        // The check extension lets even a 1 half-move search see the
+        // smothered mate that follows the queen sacrifice.
+        assert!(value > 300);
 
         let p = P::from_history("8/8/8/8/3q3k/7n/6PP/2Q2R1K b - - 0 1",
                                 &mut vec![].into_iter())
                 .ok()
                 .unwrap();
         let mut moves = MoveStack::new();
-        let mut report = |_| false;
+        let mut report = |_, _, _| false;
         let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
         let value = search
-            .run(VALUE_MIN, VALUE_MAX, 8, Move::invalid())
+            .run(VALUE_MIN, VALUE_MAX, 8)
             .ok()
             .unwrap();
         assert!(value > VALUE_EVAL_MAX);
+        let pv: Vec<String> = search.pv().iter().map(|m| m.notation()).collect();
+        assert_eq!(pv, vec!["d4g1", "f1g1", "h3f2"]);
+        let stats = search.tree_stats();
+        assert_eq!(stats.nodes + stats.qsearch_nodes, search.node_count());
+        assert!(stats.tt_hits > 0 && stats.tt_hits <= stats.tt_probes);
+        assert!(stats.beta_cutoffs[0] > 0);
+        assert!(stats.null_move_cutoffs <= stats.null_moves);
+        assert!(stats.reduction_researches <= stats.reductions);
+    }
+
+    #[test]
+    fn mate_distance_pruning() {
+        use super::{adjust_mate_value, child_window};
+        let values = [VALUE_MIN + 1,
+                      VALUE_MIN + 2,
+                      VALUE_EVAL_MIN - 2,
+                      VALUE_EVAL_MIN - 1,
+                      VALUE_EVAL_MIN,
+                      0,
+                      VALUE_EVAL_MAX,
+                      VALUE_EVAL_MAX + 1,
+                      VALUE_EVAL_MAX + 2,
+                      VALUE_MAX - 2,
+                      VALUE_MAX - 1];
+        for &alpha in values.iter() {
+            for &beta in values.iter().filter(|&&x| x > alpha) {
+                let (child_alpha, child_beta) = child_window(alpha, beta);
+                assert!(child_alpha < child_beta);
+                for &v in values.iter() {
+                    let parent_value = adjust_mate_value(-v);
+                    assert_eq!(v >= child_beta, parent_value <= alpha);
+                    assert_eq!(v <= child_alpha, parent_value >= beta);
+                }
+            }
+        }
+        assert_eq!(child_window(VALUE_MIN, VALUE_MAX), (VALUE_MIN, VALUE_MAX));
+
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let p = P::from_history("8/8/8/8/3q3k/7n/6PP/2Q2R1K b - - 0 1",
+                                &mut vec![].into_iter())
+                .ok()
+                .unwrap();
+        let mut moves = MoveStack::new();
+        let mut report = |_, _, _| false;
+        let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+        let value = search
+            .run(VALUE_MIN, VALUE_MAX, 10)
+            .ok()
+            .unwrap();
+        assert_eq!(value, VALUE_MAX - 3);
+    }
+
+    #[test]
+    fn node_limit() {
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let p = P::from_history("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                                &mut vec![].into_iter())
+                .ok()
+                .unwrap();
+        let mut moves = MoveStack::new();
+        let mut report = |_, _, _| false;
+        let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+        search.set_node_limit(1000);
+        assert!(search.run(VALUE_MIN, VALUE_MAX, 10).is_err());
+        assert!(search.node_count() >= 1000 && search.node_count() < 1100);
+    }
+
+    #[test]
+    fn termination_flag() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let p = P::from_history("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                                &mut vec![].into_iter())
+                .ok()
+                .unwrap();
+        let terminated = Arc::new(AtomicBool::new(false));
+        let flag = terminated.clone();
+        let mut moves = MoveStack::new();
+        let mut report = |nodes, _, _| {
+            if nodes >= 1000 {
+                flag.store(true, Ordering::Relaxed);
+            }
+            false
+        };
+        let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+        search.set_termination_flag(terminated);
+        assert!(search.run(VALUE_MIN, VALUE_MAX, 10).is_err());
+        let reported_nodes = search.reported_nodes;
+        assert!(reported_nodes >= 1000 && search.node_count() <= reported_nodes + 1);
+    }
+
+    #[test]
+    fn reverse_futility_pruning() {
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let p = P::from_history("4k3/8/8/8/8/8/3PPP2/Q3K3 w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let mut moves = MoveStack::new();
+        let mut report = |_, _, _| false;
+        {
+            let mut search = SearchRunner::new(p.clone(), &tt, &mut moves, &mut report);
+            assert_eq!(search.run(0, 1, 2).ok().unwrap(), 1);
+            assert_eq!(search.reported_nodes + search.unreported_nodes, 0);
+        }
+        {
+            let mut search = SearchRunner::new(p.clone(), &tt, &mut moves, &mut report);
+            assert!(search.run(VALUE_MIN, VALUE_MAX, 2).ok().unwrap() > 500);
+            assert!(search.reported_nodes + search.unreported_nodes > 0);
+        }
+        {
+            let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+            assert_eq!(search.run(0, 1, 5).ok().unwrap(), 1);
+            assert!(search.reported_nodes + search.unreported_nodes > 0);
+        }
+    }
+
+    #[test]
+    fn probcut() {
+        use evaluator::Evaluator;
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let mut moves = MoveStack::new();
+        let mut report = |_, _, _| false;
+        for &(fen, is_cut) in [("4k3/8/8/3q4/4P3/8/8/R3K3 w - - 0 1", true),
+                               ("4k3/8/8/3q4/8/4P3/8/R3K3 w - - 0 1", false),
+                               ("4k3/8/3p4/3q4/4P3/8/8/4K3 w - - 0 1", false)]
+                .iter() {
+            let p = P::from_history(fen, &mut vec![].into_iter()).ok().unwrap();
+            let hash = p.hash();
+            let static_eval = p.evaluator().evaluate(p.board());
+            let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+            search.stack.push(MoveDigest::invalid(), static_eval);
+            search.moves.open_frame(super::MOVES_PER_PLY);
+            assert_eq!(search.probcut(0, 6 * ONE_PLY, static_eval).ok().unwrap(), is_cut);
+            assert!(search.moves.list().is_empty());
+            assert_eq!(tt.probe(hash).is_some(), is_cut);
+            if is_cut {
+                assert_eq!(tt.probe(hash).unwrap().bound(), BOUND_LOWER);
+            }
+        }
+    }
+
+    #[test]
+    fn root_move_reports() {
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let p = P::from_history("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                                &mut vec![].into_iter())
+                .ok()
+                .unwrap();
+        let legal_moves = p.legal_moves();
+        let mut root_moves = vec![];
+        {
+            let mut moves = MoveStack::new();
+            let mut report = |_, _, current_move: Option<(Move, usize)>| {
+                if let Some(x) = current_move {
+                    if root_moves.last() != Some(&x) {
+                        root_moves.push(x);
+                    }
+                }
+                false
+            };
+            let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+            search.run(VALUE_MIN, VALUE_MAX, 2).ok().unwrap();
+        }
+        assert_eq!(root_moves.len(), 20);
+        for (i, &(m, n)) in root_moves.iter().enumerate() {
+            assert_eq!(n, i + 1);
+            assert!(legal_moves.contains(&m));
+        }
+    }
+
+    #[test]
+    fn tiny_ttable() {
+        // A table with only one bucket -- records are replaced all
+        // the time, and hash moves are often not pseudo-legal.
+        let tt = StdTtable::<StdTtableEntry>::new(Some(0));
+        for fen in ["8/8/8/8/3q3k/7n/6PP/2Q2R1K b - - 0 1",
+                    "5r2/8/8/4q1p1/3P4/k3P1P1/P2b1R1B/K4R2 w - - 0 1",
+                    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"]
+                    .iter() {
+            let p = P::from_history(fen, &mut vec![].into_iter()).ok().unwrap();
+            let mut moves = MoveStack::new();
+            let mut report = |_, _, _| false;
+            let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+            let value = search
+                .run(VALUE_MIN, VALUE_MAX, 5)
+                .ok()
+                .unwrap();
+            assert!(VALUE_MIN <= value && value <= VALUE_MAX);
+        }
+        assert_eq!(tt.rejected_records(), 0);
     }
 
     #[test]
@@ -904,4 +1890,149 @@ mod tests {
         }
         assert!(killers.get(1) == (MoveDigest::invalid(), MoveDigest::invalid()));
     }
+
+    #[test]
+    fn history() {
+        let mut history = HistoryTable::new();
+        let p = P::from_history("5r2/8/8/4q1p1/3P4/k3P1P1/P2b1R1B/K4R2 w - - 0 1",
+                                &mut vec![].into_iter())
+                .ok()
+                .unwrap();
+        let mut v = MoveStack::new();
+        p.generate_moves(&mut v);
+        let quiet: Vec<Move> = v.list()
+            .iter()
+            .cloned()
+            .filter(|m| m.captured_piece() == PIECE_NONE && m.move_type() != MOVE_PROMOTION)
+            .collect();
+        let capture = v.list()
+            .iter()
+            .cloned()
+            .find(|m| m.captured_piece() < PIECE_NONE)
+            .unwrap();
+        history.register(WHITE, quiet[0], 2);
+        history.register(WHITE, quiet[1], 3);
+        history.register(WHITE, capture, 5);
+        assert_eq!(history.get(WHITE, quiet[0]), 4);
+        assert_eq!(history.get(WHITE, quiet[1]), 9);
+        assert_eq!(history.get(BLACK, quiet[1]), 0);
+        assert_eq!(history.get(WHITE, capture), 0);
+        for _ in 0..3000 {
+            history.register(WHITE, quiet[1], 90);
+        }
+        assert!(history.get(WHITE, quiet[1]) <= super::HISTORY_SCORE_MAX);
+        assert!(history.get(WHITE, quiet[0]) < 4);
+    }
+
+    #[test]
+    fn former_pv_nodes() {
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let p = P::from_history("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let hash = p.hash();
+        let mut moves = MoveStack::new();
+        let mut report = |_, _, _| false;
+        let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+        assert!(search.run(VALUE_MIN, VALUE_MAX, 2).is_ok());
+        assert!(tt.probe(hash).unwrap().is_pv());
+        search.node_begin(-1, 0, 6 * ONE_PLY).ok().unwrap();
+        assert!(search.stack.current().was_pv);
+        search.node_end();
+        tt.clear();
+        search.node_begin(-1, 0, 6 * ONE_PLY).ok().unwrap();
+        assert!(!search.stack.current().was_pv);
+    }
+
+    #[test]
+    fn internal_iterative_deepening() {
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let p = P::from_history("5r2/8/8/4q1p1/3P4/k3P1P1/P2b1R1B/K4R2 w - - 0 1",
+                                &mut vec![].into_iter())
+                .ok()
+                .unwrap();
+        let mut moves = MoveStack::new();
+        let mut report = |_, _, _| false;
+        let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+        assert!(search
+                    .node_begin(VALUE_MIN, VALUE_MAX, 6 * ONE_PLY)
+                    .ok()
+                    .unwrap()
+                    .is_none());
+        assert!(search.stack.current().hash_move_digest == MoveDigest::invalid());
+        search
+            .internal_iterative_deepening(-1, 0, 6 * ONE_PLY)
+            .ok()
+            .unwrap();
+        assert!(search.stack.current().hash_move_digest == MoveDigest::invalid());
+        search
+            .internal_iterative_deepening(VALUE_MIN, VALUE_MAX, 6 * ONE_PLY)
+            .ok()
+            .unwrap();
+        assert!(search.stack.current().hash_move_digest != MoveDigest::invalid());
+        assert_eq!(search.stack.len(), 1);
+        assert_eq!(search.stack.ply(), 0);
+
+        // The principal variation is recorded for the current node.
+        let pv = search.pv().to_vec();
+        assert!(pv.len() >= 2 && pv.len() <= 6);
+        assert!(pv[0].digest() == search.stack.current().hash_move_digest);
+    }
+
+    #[test]
+    fn search_stack() {
+        use super::SearchStack;
+        let p = P::from_history("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let m = p.legal_moves()[0];
+        let mut stack = SearchStack::new();
+        stack.push(MoveDigest::invalid(), 0);
+        assert_eq!(stack.ply(), 0);
+        assert!(stack.current().null_move_allowed);
+        stack.current_mut().current_move = m;
+        stack.push(MoveDigest::invalid(), 0);
+        assert_eq!(stack.ply(), 1);
+        assert!(stack.current().null_move_allowed);
+        let frame = stack.take();
+        assert_eq!(stack.ply(), 0);
+        stack.put_back(frame);
+        assert_eq!(stack.ply(), 1);
+        stack.current_mut().current_move = p.null_move();
+        stack.push(MoveDigest::invalid(), 0);
+        assert!(!stack.current().null_move_allowed);
+        stack.pop();
+        stack.pop();
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack.current().current_move, m);
+    }
+
+    #[test]
+    fn extensions() {
+        use super::{EXTENSION_CHECK, EXTENSION_SINGULAR, EXTENSION_PAWN_PUSH, EXTENSIONS_MAX};
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let p = P::from_history("4k3/8/1P6/8/8/8/8/R3K3 w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let legal_moves = p.legal_moves();
+        let find = |notation| *legal_moves.iter().find(|m| m.notation() == notation).unwrap();
+        let (push, check, quiet) = (find("b6b7"), find("a1a8"), find("e1d1"));
+        let mut moves = MoveStack::new();
+        let mut report = |_, _, _| false;
+        let mut search = SearchRunner::new(p, &tt, &mut moves, &mut report);
+        let extension = |search: &mut SearchRunner<_, P>, m, singular_move, depth| {
+            assert!(search.position.do_move(m));
+            let e = search.extension(m, singular_move, depth);
+            search.position.undo_last_move();
+            e
+        };
+        assert_eq!(extension(&mut search, push, None, 5), Some(EXTENSION_PAWN_PUSH));
+        assert_eq!(extension(&mut search, check, None, 5), Some(EXTENSION_CHECK));
+        assert_eq!(extension(&mut search, quiet, None, 5), None);
+        assert_eq!(extension(&mut search, quiet, Some(quiet), 5),
+                   Some(EXTENSION_SINGULAR));
+        assert_eq!(extension(&mut search, check, None, DEPTH_MAX * ONE_PLY), None);
+        search.extensions[EXTENSION_CHECK] = EXTENSIONS_MAX[EXTENSION_CHECK];
+        assert_eq!(extension(&mut search, check, None, 5), None);
+    }
 }
@@ -1,11 +1,12 @@
 /// Implements `StdMoveGenerator`.
 
-use std::mem::uninitialized;
 use std::cell::Cell;
+use std::ops::Range;
 use uci::{SetOption, OptionDescription};
 use board::*;
 use squares::*;
 use moves::*;
+use value::Value;
 use evaluator::Evaluator;
 use move_generator::MoveGenerator;
 use bitsets::*;
@@ -30,16 +31,23 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
     type Evaluator = T;
 
     fn from_board(board: Board) -> Result<Self, IllegalBoard> {
-        let mut gen = StdMoveGenerator {
+        // Evaluators can not be created for illegal boards, so we
+        // use a placeholder evaluator to verify the board first.
+        let gen = StdMoveGenerator {
             geometry: BoardGeometry::get(),
             zobrist: ZobristArrays::get(),
             board: board,
-            evaluator: unsafe { uninitialized() },
+            evaluator: UnboundEvaluator,
             checkers: Cell::new(BB_ALL),
         };
         if gen.is_legal() {
-            gen.evaluator = T::new(gen.board());
-            Ok(gen)
+            Ok(StdMoveGenerator {
+                   geometry: gen.geometry,
+                   zobrist: gen.zobrist,
+                   evaluator: T::new(&gen.board),
+                   board: gen.board,
+                   checkers: gen.checkers,
+               })
         } else {
             Err(IllegalBoard)
         }
@@ -96,6 +104,24 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
         self.checkers() != 0
     }
 
+    /// Returns a bitboard with all enemy pieces and pawns that attack
+    /// the king of the side to move.
+    ///
+    /// The calculated bitboard is temporarily stored in move
+    /// generator's instance. In case another call to `checkers` is
+    /// made before any move was done/undone, `checkers` will return
+    /// the stored bitboard instead of re-calculating it, thus saving
+    /// time.
+    #[inline]
+    fn checkers(&self) -> Bitboard {
+        self.king_square_and_checkers().1
+    }
+
+    #[inline]
+    fn pinned(&self) -> Bitboard {
+        self.find_pinned(self.king_square())
+    }
+
     #[inline]
     fn evaluator(&self) -> &Self::Evaluator {
         &self.evaluator
@@ -113,100 +139,22 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
     ///
     /// **Note:** A pseudo-legal move is a move that is otherwise
     /// legal, except it might leave the king in check.
+    #[inline]
     fn generate_all<U: AddMove>(&self, moves: &mut U) {
-        let (king_square, checkers) = self.king_square_and_checkers();
-        let occupied_by_us = unsafe {
-            *self.board
-                 .pieces
-                 .color
-                 .get_unchecked(self.board.to_move)
-        };
-        let legal_dests = !occupied_by_us &
-                          match lsb(checkers) {
-            0 =>
-                // Not in check -- every move destination may be
-                // considered "covering".
-                BB_ALL,
-            x if x == checkers =>
-                // Single check -- calculate the check covering
-                // destination subset (the squares between the king
-                // and the checker). Notice that we must OR with "x"
-                // itself, because knights give check not lying on a
-                // line with the king.
-                x |
-                unsafe {
-                    *self.geometry
-                         .squares_between_including
-                         .get_unchecked(king_square)
-                         .get_unchecked(bsf(x))
-                },
-            _ =>
-                // Double check -- no covering moves.
-                0,
-        };
-
-        if legal_dests != 0 {
-            let pinned = self.find_pinned(king_square);
-
-            // Generate queen, rook, bishop, and knight moves.
-            for piece in QUEEN..PAWN {
-                let mut bb = self.board.pieces.piece_type[piece] & occupied_by_us;
-                while bb != 0 {
-                    let orig_square = bsf_reset(&mut bb);
-                    let piece_legal_dests = if 1 << orig_square & pinned == 0 {
-                        legal_dests
-                    } else {
-                        // The piece is pinned -- reduce the set of
-                        // legal destination to the squares on the
-                        // line of the pin.
-                        legal_dests & self.geometry.squares_at_line[king_square][orig_square]
-                    };
-                    self.add_piece_moves(piece, orig_square, piece_legal_dests, moves);
-                }
-            }
-
-            // Generate pawn moves.
-            {
-                let our_pawns = self.board.pieces.piece_type[PAWN] & occupied_by_us;
-                let mut pinned_pawns = our_pawns & pinned;
-                let pawn_legal_dests = if checkers & self.board.pieces.piece_type[PAWN] == 0 {
-                    legal_dests
-                } else {
-                    // We are in check from a pawn, therefore an
-                    // en-passant capture is legal too.
-                    legal_dests | self.enpassant_bb()
-                };
-
-                // Generate all moves with not-pinned pawns.
-                self.add_pawn_moves(our_pawns ^ pinned_pawns, pawn_legal_dests, false, moves);
-
-                // Generate pinned pawns' moves pawn by pawn, reducing
-                // the set of legal destination for each pinned pawn
-                // to the squares on the line of the pin.
-                while pinned_pawns != 0 {
-                    let pawn_square = bsf_reset(&mut pinned_pawns);
-                    let pawn_legal_dests = pawn_legal_dests &
-                                           self.geometry.squares_at_line[king_square][pawn_square];
-                    self.add_pawn_moves(1 << pawn_square, pawn_legal_dests, false, moves);
-                }
-            }
-        }
+        self.generate_moves(BB_ALL, moves);
+    }
 
-        // Generate king moves (pseudo-legal, possibly moving into check).
-        for side in 0..2 {
-            if self.can_castle(king_square, side) {
-                moves.add_move(Move::new(MOVE_CASTLING,
-                                         king_square,
-                                         [[C1, C8], [G1, G8]][side][self.board.to_move],
-                                         0,
-                                         PIECE_NONE,
-                                         KING,
-                                         self.board.castling_rights,
-                                         self.board.enpassant_file,
-                                         0));
-            }
-        }
-        self.add_piece_moves(KING, king_square, !occupied_by_us, moves);
+    /// Generates all legal moves.
+    ///
+    /// The moves are added to `moves`. Unlike `generate_all`, this
+    /// method calculates the squares attacked by the enemy (see
+    /// `Board::king_danger_squares`), and leaves out the king's moves
+    /// to those squares.
+    ///
+    /// The initial move score for all generated moves is `0`.
+    #[inline]
+    fn generate_legal<U: AddMove>(&self, moves: &mut U) {
+        self.generate_moves(!self.board.king_danger_squares(1 ^ self.board.to_move), moves);
     }
 
     /// Generates moves for the quiescence search.
@@ -281,12 +229,12 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
         {
             let our_pawns = self.board.pieces.piece_type[PAWN] & occupied_by_us;
             let mut pinned_pawns = our_pawns & pinned;
-            self.add_pawn_moves(our_pawns ^ pinned_pawns, pawn_dests, true, moves);
+            self.add_pawn_moves(our_pawns ^ pinned_pawns, pawn_dests, 0..1, moves);
             while pinned_pawns != 0 {
                 let pawn_square = bsf_reset(&mut pinned_pawns);
                 let pawn_dests = pawn_dests &
                                  self.geometry.squares_at_line[king_square][pawn_square];
-                self.add_pawn_moves(1 << pawn_square, pawn_dests, true, moves);
+                self.add_pawn_moves(1 << pawn_square, pawn_dests, 0..1, moves);
             }
         }
 
@@ -294,9 +242,67 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
         self.add_piece_moves(KING, king_square, occupied_by_them, moves);
     }
 
+    /// Generates the pseudo-legal moves that `generate_forcing`
+    /// leaves out.
+    ///
+    /// The moves are added to `moves`. If the king is in check, no
+    /// moves are generated. Otherwise, the generated moves are the
+    /// moves to empty squares that are not captures and not
+    /// promotions to queen, and the pawn promotions to pieces other
+    /// than queen. Some of the generated king's moves may be illegal
+    /// because the destination square is under attack.
+    ///
+    /// The initial move score for all generated moves is `0`.
+    fn generate_quiet<U: AddMove>(&self, moves: &mut U) {
+        let (king_square, checkers) = self.king_square_and_checkers();
+        if checkers != 0 {
+            return;
+        }
+        let pinned = self.find_pinned(king_square);
+        let occupied_by_us = unsafe {
+            *self.board
+                 .pieces
+                 .color
+                 .get_unchecked(self.board.to_move)
+        };
+        let empty = !self.board.occupied;
+
+        // Generate queen, rook, bishop, and knight moves.
+        for piece in QUEEN..PAWN {
+            let mut bb = self.board.pieces.piece_type[piece] & occupied_by_us;
+            while bb != 0 {
+                let orig_square = bsf_reset(&mut bb);
+                let mut dests = empty;
+                if 1 << orig_square & pinned != 0 {
+                    dests &= self.geometry.squares_at_line[king_square][orig_square];
+                }
+                self.add_piece_moves(piece, orig_square, dests, moves);
+            }
+        }
+
+        // Generate pawn moves. (Captures that promote to pieces other
+        // than queen are not tactical.)
+        {
+            let pawn_dests = empty & !self.enpassant_bb() | BB_PAWN_PROMOTION_RANKS;
+            let our_pawns = self.board.pieces.piece_type[PAWN] & occupied_by_us;
+            let mut pinned_pawns = our_pawns & pinned;
+            self.add_pawn_moves(our_pawns ^ pinned_pawns, pawn_dests, 1..4, moves);
+            while pinned_pawns != 0 {
+                let pawn_square = bsf_reset(&mut pinned_pawns);
+                let pawn_dests = pawn_dests &
+                                 self.geometry.squares_at_line[king_square][pawn_square];
+                self.add_pawn_moves(1 << pawn_square, pawn_dests, 1..4, moves);
+            }
+        }
+
+        // Generate king moves.
+        self.add_castling_moves(king_square, BB_ALL, moves);
+        self.add_piece_moves(KING, king_square, empty, moves);
+    }
+
     fn try_move_digest(&self, move_digest: MoveDigest) -> Option<Move> {
         // We will use `generated_move` to assert that our result is correct.
-        let mut generated_move = unsafe { uninitialized() };
+        let mut generated_move = None;
 
         // The purpose of `try_move_digest` is to check if a move is
         // pseudo-legal, without spending time to generate all
@@ -304,7 +310,6 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
         // performace, the whole complex logic of this method could be
         // substituted with the next few lines:
         if cfg!(debug_assertions) {
-            generated_move = None;
             let mut move_stack = Vec::new();
             self.generate_all(&mut move_stack);
             while let Some(m) = move_stack.pop() {
@@ -345,8 +350,7 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
                               PIECE_NONE,
                               KING,
                               self.board.castling_rights,
-                              self.board.enpassant_file,
-                              0);
+                              self.board.enpassant_file);
             debug_assert_eq!(generated_move, Some(m));
             return Some(m);
         }
@@ -412,19 +416,13 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
                 pseudo_legal_dests |= enpassant_bb;
             }
 
-            unsafe {
-                let mut dest_sets: [Bitboard; 4] = uninitialized();
-                calc_pawn_dest_sets(self.board.to_move,
-                                    occupied_by_us,
-                                    *self.board
-                                         .pieces
-                                         .color
-                                         .get_unchecked(1 ^ self.board.to_move),
-                                    enpassant_bb,
-                                    orig_square_bb,
-                                    &mut dest_sets);
-                pseudo_legal_dests &= dest_sets[0] | dest_sets[1] | dest_sets[2] | dest_sets[3];
-            }
+            let occupied_by_them = self.board.pieces.color[1 ^ self.board.to_move];
+            let (dest_sets, _) = calc_pawn_dest_sets(self.board.to_move,
+                                                     occupied_by_us,
+                                                     occupied_by_them,
+                                                     enpassant_bb,
+                                                     orig_square_bb);
+            pseudo_legal_dests &= dest_sets[0] | dest_sets[1] | dest_sets[2] | dest_sets[3];
             if pseudo_legal_dests & dest_square_bb == 0 {
                 debug_assert!(generated_move.is_none());
                 return None;
@@ -474,8 +472,7 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
                           captured_piece,
                           piece,
                           self.board.castling_rights,
-                          self.board.enpassant_file,
-                          0);
+                          self.board.enpassant_file);
         debug_assert_eq!(generated_move, Some(m));
         Some(m)
     }
@@ -490,12 +487,11 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
                   PIECE_NONE,
                   KING,
                   self.board.castling_rights,
-                  self.board.enpassant_file,
-                  0)
+                  self.board.enpassant_file)
     }
 
     fn do_move(&mut self, m: Move) -> Option<u64> {
-        let mut old_hash: u64 = unsafe { uninitialized() };
+        let mut old_hash = 0;
         let mut h = 0;
         let us = self.board.to_move;
         let them = 1 ^ us;
@@ -509,16 +505,8 @@ impl<T: Evaluator> MoveGenerator for StdMoveGenerator<T> {
         if cfg!(debug_assertions) {
             // Assert that `m` could be generated by `null_move` or
             // `generate_all`.
-            assert!({
-                        m.is_null() && played_piece == KING
-                    } ||
-                    {
-                        let mut m1 = m;
-                        let mut m2 = self.try_move_digest(m.digest()).unwrap();
-                        m1.set_score(0);
-                        m2.set_score(0);
-                        m1 == m2
-                    });
+            assert!(m.is_null() && played_piece == KING ||
+                    Some(m) == self.try_move_digest(m.digest()));
 
             // Initialize `old_hash`, which will be used to assert
             // that the returned value (`h`) is calculated correctly.
@@ -713,7 +701,7 @@ impl<T: Evaluator> SetOption for StdMoveGenerator<T> {
         T::options()
     }
 
-    fn set_option(name: &str, value: &str) {
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
         T::set_option(name, value)
     }
 }
@@ -816,6 +804,117 @@ impl<T: Evaluator> StdMoveGenerator<T> {
         }
     }
 
+    /// A helper method for `generate_all` and `generate_legal`. It
+    /// generates all legal moves, possibly including some king's moves
+    /// into check. King's moves to squares that are not within the
+    /// `king_dests` set are not generated.
+    fn generate_moves<U: AddMove>(&self, king_dests: Bitboard, moves: &mut U) {
+        let (king_square, checkers) = self.king_square_and_checkers();
+        let occupied_by_us = unsafe {
+            *self.board
+                 .pieces
+                 .color
+                 .get_unchecked(self.board.to_move)
+        };
+        let legal_dests = !occupied_by_us &
+                          match lsb(checkers) {
+            0 =>
+                // Not in check -- every move destination may be
+                // considered "covering".
+                BB_ALL,
+            x if x == checkers =>
+                // Single check -- calculate the check covering
+                // destination subset (the squares between the king
+                // and the checker). Notice that we must OR with "x"
+                // itself, because knights give check not lying on a
+                // line with the king.
+                x |
+                unsafe {
+                    *self.geometry
+                         .squares_between_including
+                         .get_unchecked(king_square)
+                         .get_unchecked(bsf(x))
+                },
+            _ =>
+                // Double check -- no covering moves.
+                0,
+        };
+
+        if legal_dests != 0 {
+            let pinned = self.find_pinned(king_square);
+
+            // Generate queen, rook, bishop, and knight moves.
+            for piece in QUEEN..PAWN {
+                let mut bb = self.board.pieces.piece_type[piece] & occupied_by_us;
+                while bb != 0 {
+                    let orig_square = bsf_reset(&mut bb);
+                    let piece_legal_dests = if 1 << orig_square & pinned == 0 {
+                        legal_dests
+                    } else {
+                        // The piece is pinned -- reduce the set of
+                        // legal destination to the squares on the
+                        // line of the pin.
+                        legal_dests & self.geometry.squares_at_line[king_square][orig_square]
+                    };
+                    self.add_piece_moves(piece, orig_square, piece_legal_dests, moves);
+                }
+            }
+
+            // Generate pawn moves.
+            {
+                let our_pawns = self.board.pieces.piece_type[PAWN] & occupied_by_us;
+                let mut pinned_pawns = our_pawns & pinned;
+                let pawn_legal_dests = if checkers & self.board.pieces.piece_type[PAWN] == 0 {
+                    legal_dests
+                } else {
+                    // We are in check from a pawn, therefore an
+                    // en-passant capture is legal too.
+                    legal_dests | self.enpassant_bb()
+                };
+
+                // Generate all moves with not-pinned pawns.
+                self.add_pawn_moves(our_pawns ^ pinned_pawns, pawn_legal_dests, 0..4, moves);
+
+                // Generate pinned pawns' moves pawn by pawn, reducing
+                // the set of legal destination for each pinned pawn
+                // to the squares on the line of the pin.
+                while pinned_pawns != 0 {
+                    let pawn_square = bsf_reset(&mut pinned_pawns);
+                    let pawn_legal_dests = pawn_legal_dests &
+                                           self.geometry.squares_at_line[king_square][pawn_square];
+                    self.add_pawn_moves(1 << pawn_square, pawn_legal_dests, 0..4, moves);
+                }
+            }
+        }
+
+        // Generate king moves (pseudo-legal, possibly moving into check).
+        self.add_castling_moves(king_square, king_dests, moves);
+        self.add_piece_moves(KING, king_square, !occupied_by_us & king_dests, moves);
+    }
+
+    /// A helper method. It adds the pseudo-legal castling moves for
+    /// which king's destination square is within the `king_dests` set
+    /// to `moves`. `king_square` should be the square that the king
+    /// of the side to move occupies.
+    fn add_castling_moves<U: AddMove>(&self,
+                                      king_square: Square,
+                                      king_dests: Bitboard,
+                                      moves: &mut U) {
+        for side in 0..2 {
+            let dest_square = [[C1, C8], [G1, G8]][side][self.board.to_move];
+            if 1 << dest_square & king_dests != 0 && self.can_castle(king_square, side) {
+                moves.add_move(Move::new(MOVE_CASTLING,
+                                         king_square,
+                                         dest_square,
+                                         0,
+                                         PIECE_NONE,
+                                         KING,
+                                         self.board.castling_rights,
+                                         self.board.enpassant_file));
+            }
+        }
+    }
+
     /// A helper method. It finds all squares attacked by `piece` from
     /// square `orig_square`, and for each square that is within the
     /// `legal_dests` set adds a new move to `moves`. `piece` must not
@@ -846,41 +945,30 @@ impl<T: Evaluator> StdMoveGenerator<T> {
                                      captured_piece,
                                      piece,
                                      self.board.castling_rights,
-                                     self.board.enpassant_file,
-                                     0));
+                                     self.board.enpassant_file));
         }
     }
 
     /// A helper method. It adds all pseudo-legal moves by the set of
     /// pawns given by `pawns` to `moves`, ensuring that all
-    /// destination squares are within the `legal_dests` set. When
-    /// `only_queen_promotions` is `true`, promotions to pieces other
-    /// that queen will not be added to `moves`.
+    /// destination squares are within the `legal_dests` set. Only
+    /// promotions to the pieces within the `promotions` range (`0` is
+    /// queen, `3` is knight) will be added to `moves`.
     fn add_pawn_moves<U: AddMove>(&self,
                                   pawns: Bitboard,
                                   legal_dests: Bitboard,
-                                  only_queen_promotions: bool,
+                                  promotions: Range<usize>,
                                   moves: &mut U) {
         debug_assert!(pawns & !self.board.pieces.piece_type[PAWN] == 0);
         debug_assert!(pawns & !self.board.pieces.color[self.board.to_move] == 0);
 
-        let mut dest_sets: [Bitboard; 4];
+        let us = self.board.to_move;
         let enpassant_bb = self.enpassant_bb();
-        let shifts = unsafe {
-            dest_sets = uninitialized();
-            calc_pawn_dest_sets(self.board.to_move,
-                                *self.board
-                                     .pieces
-                                     .color
-                                     .get_unchecked(self.board.to_move),
-                                *self.board
-                                     .pieces
-                                     .color
-                                     .get_unchecked(1 ^ self.board.to_move),
-                                enpassant_bb,
-                                pawns,
-                                &mut dest_sets)
-        };
+        let (dest_sets, shifts) = calc_pawn_dest_sets(us,
+                                                      self.board.pieces.color[us],
+                                                      self.board.pieces.color[1 ^ us],
+                                                      enpassant_bb,
+                                                      pawns);
 
         // Process each pawn move sub-type (push, double push, west
         // capture, east capture).
@@ -906,14 +994,13 @@ impl<T: Evaluator> StdMoveGenerator<T> {
                                                      PAWN,
                                                      PAWN,
                                                      self.board.castling_rights,
-                                                     self.board.enpassant_file,
-                                                     0));
+                                                     self.board.enpassant_file));
                         }
                     }
 
                     // pawn promotion
                     x if x & BB_PAWN_PROMOTION_RANKS != 0 => {
-                        for p in 0..4 {
+                        for p in promotions.clone() {
                             moves.add_move(Move::new(MOVE_PROMOTION,
                                                      orig_square,
                                                      dest_square,
@@ -921,11 +1008,7 @@ impl<T: Evaluator> StdMoveGenerator<T> {
                                                      captured_piece,
                                                      PAWN,
                                                      self.board.castling_rights,
-                                                     self.board.enpassant_file,
-                                                     0));
-                            if only_queen_promotions {
-                                break;
-                            }
+                                                     self.board.enpassant_file));
                         }
                     }
 
@@ -938,8 +1021,7 @@ impl<T: Evaluator> StdMoveGenerator<T> {
                                                  captured_piece,
                                                  PAWN,
                                                  self.board.castling_rights,
-                                                 self.board.enpassant_file,
-                                                 0));
+                                                 self.board.enpassant_file));
                     }
                 }
             }
@@ -1010,29 +1092,6 @@ impl<T: Evaluator> StdMoveGenerator<T> {
             })
     }
 
-    /// Returns a bitboard with all enemy pieces and pawns that attack
-    /// the king of the side to move.
-    ///
-    /// The calculated bitboard is temporarily stored in move
-    /// generator's instance. In case another call to `checkers` is
-    /// made before any move was done/undone, `checkers` will return
-    /// the stored bitboard instead of re-calculating it, thus saving
-    /// time.
-    #[inline]
-    fn checkers(&self) -> Bitboard {
-        if self.checkers.get() == BB_ALL {
-            self.checkers
-                .set(self.attacks_to(self.king_square()) &
-                     unsafe {
-                         *self.board
-                              .pieces
-                              .color
-                              .get_unchecked(1 ^ self.board.to_move)
-                     });
-        }
-        self.checkers.get()
-    }
-
     /// A helper method. It returns the square that the king of the
     /// side to move occupies, and its checker. Needed only for
     /// performance reasons.
@@ -1159,6 +1218,28 @@ impl<T: Evaluator> StdMoveGenerator<T> {
 }
 
 
+/// A placeholder evaluator, used by `StdMoveGenerator::from_board`
+/// to verify the board before the real evaluator is created.
+#[derive(Clone)]
+struct UnboundEvaluator;
+
+impl SetOption for UnboundEvaluator {}
+
+impl Evaluator for UnboundEvaluator {
+    fn new(_: &Board) -> UnboundEvaluator {
+        UnboundEvaluator
+    }
+
+    fn evaluate(&self, _: &Board) -> Value {
+        0
+    }
+
+    fn is_zugzwangy(&self, _: &Board) -> bool {
+        false
+    }
+}
+
+
 /// Pawn move sub-type -- a single push.
 const PAWN_PUSH: usize = 0;
 
@@ -1196,8 +1277,8 @@ const BB_CASTLING_ROOK_MOVEMENT: [[Bitboard; 2]; 2] = [[1 << A1 | 1 << D1, 1 <<
 
 
 /// A helper function. It calculates the pseudo-legal destinations
-/// for a given set of `pawns`, and writes them to the supplied
-/// `dest_sets` array.
+/// for a given set of `pawns`, and returns them in a `dest_sets`
+/// array, together with the pawn shift array for `us`.
 ///
 /// `dest_sets` is indexed by the sub-type of the pawn move: 0) push,
 /// 1) double push, 2) west capture, 3) east capture. The benefit of
@@ -1209,9 +1290,8 @@ fn calc_pawn_dest_sets(us: Color,
                        occupied_by_us: Bitboard,
                        occupied_by_them: Bitboard,
                        enpassant_bb: Bitboard,
-                       pawns: Bitboard,
-                       dest_sets: &mut [Bitboard; 4])
-                       -> &'static [isize; 4] {
+                       pawns: Bitboard)
+                       -> ([Bitboard; 4], &'static [isize; 4]) {
     debug_assert!(us <= 1);
     debug_assert!(pawns & !occupied_by_us == 0);
     debug_assert!(occupied_by_us & occupied_by_them == 0);
@@ -1224,6 +1304,7 @@ fn calc_pawn_dest_sets(us: Color,
                                                !(BB_FILE_H | BB_RANK_1 | BB_RANK_8)];
     let shifts: &[isize; 4] = unsafe { PAWN_MOVE_SHIFTS.get_unchecked(us) };
     let capture_targets = occupied_by_them | enpassant_bb;
+    let mut dest_sets = [0; 4];
     for i in 0..4 {
         dest_sets[i] = gen_shift(pawns & LEGITIMATE_ORIGINS[i], shifts[i]) &
                        (capture_targets ^ PUSHING_TARGETS[i]) &
@@ -1235,7 +1316,7 @@ fn calc_pawn_dest_sets(us: Color,
     dest_sets[PAWN_DOUBLE_PUSH] &= gen_shift(dest_sets[PAWN_PUSH], shifts[PAWN_PUSH]);
 
     // For convenience, return a reference to the pawn shift array for `us`.
-    shifts
+    (dest_sets, shifts)
 }
 
 
@@ -1558,6 +1639,65 @@ mod tests {
         s.clear_all();
     }
 
+    #[test]
+    fn generate_quiet() {
+        use utils::BENCH_POSITIONS;
+        let mut fens = vec!["k6r/P7/8/6p1/6pP/8/8/7K b - h3 0 1",
+                            "k7/8/8/4Pp2/4K3/8/8/8 w - f6 0 1",
+                            "1r2k3/P7/8/8/8/8/8/R3K2R w KQ - 0 1",
+                            "4k3/8/8/8/8/8/4r3/R3K2R w KQ - 0 1"];
+        fens.extend(BENCH_POSITIONS.iter());
+        for fen in fens {
+            let b = P::from_fen(fen).ok().unwrap();
+            let mut all = vec![];
+            let mut staged = vec![];
+            b.generate_all(&mut all);
+            b.generate_forcing(false, &mut staged);
+            let forcing_count = staged.len();
+            b.generate_quiet(&mut staged);
+            assert!(staged[forcing_count..].iter().all(|m| !m.is_tactical()));
+            all.sort();
+            staged.sort();
+            assert_eq!(all, staged);
+        }
+    }
+
+    #[test]
+    fn generate_legal() {
+        use utils::BENCH_POSITIONS;
+        let mut fens = vec!["k6r/P7/8/6p1/6pP/8/8/7K b - h3 0 1",
+                            "k7/8/8/4Pp2/4K3/8/8/8 w - f6 0 1",
+                            "4k3/8/8/8/8/8/4r3/R3K2R w KQ - 0 1",
+                            "4k3/8/8/8/8/8/6r1/R3K2R w KQ - 0 1",
+                            "8/8/8/8/8/2k5/8/r3K3 w - - 0 1",
+                            "4k3/8/8/8/1b6/8/8/R3K2R w KQ - 0 1"];
+        fens.extend(BENCH_POSITIONS.iter());
+        for fen in fens {
+            let mut b = P::from_fen(fen).ok().unwrap();
+            let mut all = vec![];
+            let mut legal = vec![];
+            b.generate_all(&mut all);
+            b.generate_legal(&mut legal);
+            all.retain(|&m| if b.do_move(m).is_some() {
+                           b.undo_move(m);
+                           true
+                       } else {
+                           false
+                       });
+            all.sort();
+            legal.sort();
+            assert_eq!(all, legal);
+        }
+
+        // The king can not step back on the line of the checking rook.
+        let b = P::from_fen("8/8/8/8/8/2k5/8/r3K3 w - - 0 1").ok().unwrap();
+        let mut legal = vec![];
+        b.generate_legal(&mut legal);
+        legal.sort();
+        assert_eq!(legal.iter().map(|m| m.dest_square()).collect::<Vec<_>>(),
+                   vec![E2, F2]);
+    }
+
     #[test]
     fn null_move() {
         let mut s = MoveStack::new();
@@ -2,6 +2,8 @@
 
 use std::cell::UnsafeCell;
 use std::marker::PhantomData;
+use std::cmp::{min, max};
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
 use uci::{SetOption, OptionDescription};
 use board::*;
 use value::*;
@@ -10,7 +12,25 @@ use moves::*;
 use evaluator::Evaluator;
 use qsearch::{Qsearch, QsearchParams, QsearchResult};
 use move_generator::MoveGenerator;
-use utils::MoveStack;
+use utils::{MoveStack, EvalCache};
+
+
+/// The value of the "Qsearch Check Plies" option.
+static CHECK_PLIES: AtomicIsize = AtomicIsize::new(1);
+
+/// The value of the "Eval Cache Size" option (in Kbytes).
+static EVAL_CACHE_SIZE: AtomicUsize = AtomicUsize::new(256);
+
+/// Incremented each time an option is set.
+///
+/// Setting an option may change the static evaluation of
+/// positions. When this happens, the evaluation caches become stale
+/// and must be re-created.
+static EVAL_CACHE_EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+thread_local!(
+    static EVAL_CACHE: UnsafeCell<(usize, EvalCache)> = UnsafeCell::new((0, EvalCache::new(0)))
+);
 
 
 /// Implements the `QsearchResult` trait.
@@ -18,6 +38,7 @@ use utils::MoveStack;
 pub struct StdQsearchResult {
     value: Value,
     searched_nodes: u64,
+    depth: Depth,
 }
 
 impl QsearchResult for StdQsearchResult {
@@ -27,6 +48,7 @@ impl QsearchResult for StdQsearchResult {
         StdQsearchResult {
             value: value,
             searched_nodes: searched_nodes,
+            depth: 0,
         }
     }
 
@@ -39,6 +61,11 @@ impl QsearchResult for StdQsearchResult {
     fn searched_nodes(&self) -> u64 {
         self.searched_nodes
     }
+
+    #[inline]
+    fn depth(&self) -> Depth {
+        self.depth
+    }
 }
 
 
@@ -47,6 +74,15 @@ impl QsearchResult for StdQsearchResult {
 /// Performs classical quiescence search with stand pat, delta
 /// pruning, static exchange evaluation, check evasions, limited
 /// checks and recaptures.
+///
+/// Quiet moves that give check are tried only during the first few
+/// plies of the quiescence search. The number of plies is set by the
+/// "Qsearch Check Plies" option.
+///
+/// Each thread has its own evaluation cache (see `EvalCache`), so
+/// that static evaluations of transposed quiet positions are not
+/// recomputed. The size of the cache is set by the "Eval Cache Size"
+/// option (in Kbytes). Setting it to zero disables the cache.
 pub struct StdQsearch<T: MoveGenerator> {
     phantom: PhantomData<T>,
 }
@@ -64,46 +100,107 @@ impl<T: MoveGenerator> Qsearch for StdQsearch<T> {
         thread_local!(
             static MOVE_STACK: UnsafeCell<MoveStack> = UnsafeCell::new(MoveStack::new())
         );
+        EVAL_CACHE.with(|c| unsafe {
+            // Re-create the evaluation cache if it is stale. (Epoch
+            // `0` denotes a cache that has never been created.)
+            let c = &mut *c.get();
+            let epoch = EVAL_CACHE_EPOCH.load(Ordering::Relaxed) + 1;
+            if c.0 != epoch {
+                *c = (epoch, EvalCache::new(EVAL_CACHE_SIZE.load(Ordering::Relaxed)));
+            }
+        });
         let mut searched_nodes = 0;
+        let mut reached_ply = -params.depth;
         let value = MOVE_STACK.with(|s| unsafe {
             qsearch(params.position,
                     params.lower_bound,
                     params.upper_bound,
                     params.static_eval,
-                    0,
+                    params.recapture_squares,
                     -params.depth,
+                    CHECK_PLIES.load(Ordering::Relaxed) as Depth,
                     &mut *s.get(),
-                    &mut searched_nodes)
+                    &mut searched_nodes,
+                    &mut reached_ply)
         });
-        StdQsearchResult::new(value, searched_nodes)
+        debug_assert!(VALUE_EVAL_MIN <= value && value <= VALUE_EVAL_MAX);
+        StdQsearchResult {
+            value: value,
+            searched_nodes: searched_nodes,
+            depth: reached_ply + params.depth,
+        }
     }
 }
 
 impl<T: MoveGenerator> SetOption for StdQsearch<T> {
     fn options() -> Vec<(&'static str, OptionDescription)> {
-        T::options()
+        let mut options = vec![("Qsearch Check Plies",
+                                OptionDescription::Spin {
+                                    min: 0,
+                                    max: 4,
+                                    default: 1,
+                                }),
+                               ("Eval Cache Size",
+                                OptionDescription::Spin {
+                                    min: 0,
+                                    max: 65536,
+                                    default: 256,
+                                })];
+        options.extend(T::options());
+        options
     }
 
-    fn set_option(name: &str, value: &str) {
-        T::set_option(name, value)
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "Qsearch Check Plies" => {
+                if let Ok(v) = value.parse::<isize>() {
+                    CHECK_PLIES.store(min(max(v, 0), 4), Ordering::Relaxed);
+                }
+            }
+            "Eval Cache Size" => {
+                if let Ok(v) = value.parse::<usize>() {
+                    EVAL_CACHE_SIZE.store(min(v, 65536), Ordering::Relaxed);
+                }
+            }
+            _ => try!(T::set_option(name, value)),
+        }
+        EVAL_CACHE_EPOCH.fetch_add(1, Ordering::Relaxed);
+        Ok(())
     }
 }
 
 
+/// Returns position's static evaluation, consulting the evaluation
+/// cache first.
+#[inline]
+fn evaluate_cached<T: MoveGenerator>(position: &T) -> Value {
+    let hash = position.hash();
+    EVAL_CACHE.with(|c| unsafe {
+        let cache = &mut (*c.get()).1;
+        cache.probe(hash).unwrap_or_else(|| {
+            let v = position.evaluator().evaluate(position.board());
+            cache.store(hash, v);
+            v
+        })
+    })
+}
+
+
 /// A classical recursive quiescence search implementation.
 fn qsearch<T: MoveGenerator>(position: &mut T,
                              mut lower_bound: Value, // alpha
                              upper_bound: Value, // beta
                              mut stand_pat: Value, // position's static evaluation
                              mut recapture_squares: Bitboard,
-                             ply: i8, // the reached `qsearch` depth
+                             ply: Depth, // the reached `qsearch` depth
+                             check_plies: Depth, // generate checks while `ply < check_plies`
                              move_stack: &mut MoveStack,
-                             searched_nodes: &mut u64)
+                             searched_nodes: &mut u64,
+                             reached_ply: &mut Depth) // the deepest reached `qsearch` depth
                              -> Value {
     debug_assert!(lower_bound < upper_bound);
     debug_assert!(stand_pat == VALUE_UNKNOWN ||
                   stand_pat == position.evaluator().evaluate(position.board()));
-    const PIECE_VALUES: [Value; 8] = [10000, 975, 500, 325, 325, 100, 0, 0];
 
     let is_check = position.is_check();
 
@@ -119,7 +216,7 @@ fn qsearch<T: MoveGenerator>(position: &mut T,
         // Position's static evaluation is useless when in check.
         stand_pat = lower_bound;
     } else if stand_pat == VALUE_UNKNOWN {
-        stand_pat = position.evaluator().evaluate(position.board());
+        stand_pat = evaluate_cached(position);
     }
     if stand_pat >= upper_bound {
         return stand_pat;
@@ -131,9 +228,9 @@ fn qsearch<T: MoveGenerator>(position: &mut T,
                                    (PIECE_VALUES[KNIGHT] - 4 * PIECE_VALUES[PAWN] / 3) as isize;
 
     // Generate all forcing moves. (Include checks only during the
-    // first ply.)
+    // first `check_plies` plies.)
     move_stack.save();
-    position.generate_forcing(ply <= 0, move_stack);
+    position.generate_forcing(ply < check_plies, move_stack);
 
     // Consider the generated moves one by one. See if any of them
     // can raise the lower bound.
@@ -184,14 +281,17 @@ fn qsearch<T: MoveGenerator>(position: &mut T,
 
             // Recursively call `qsearch`.
             *searched_nodes += 1;
+            *reached_ply = max(*reached_ply, ply + 1);
             let value = -qsearch(position,
                                  -upper_bound,
                                  -lower_bound,
                                  VALUE_UNKNOWN,
                                  recapture_squares ^ dest_square_bb,
                                  ply + 1,
+                                 check_plies,
                                  move_stack,
-                                 searched_nodes);
+                                 searched_nodes,
+                                 reached_ply);
             position.undo_move(m);
 
             // Update the lower bound.
@@ -242,41 +342,105 @@ mod tests {
         let fen = "8/8/8/8/6k1/6P1/8/6K1 b - - 0 1";
         let board = Board::from_fen(fen).ok().unwrap();
         let mut p = P::from_board(board).ok().unwrap();
-        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, &mut s, &mut 0).abs() <= d);
+        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut 0).abs()
+                    <= d);
+
+        let mut reached_ply = 0;
+        qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut reached_ply);
+        assert_eq!(reached_ply, 1);
 
         let fen = "8/8/8/8/6k1/6P1/8/5bK1 b - - 0 1";
         let board = Board::from_fen(fen).ok().unwrap();
         let mut p = P::from_board(board).ok().unwrap();
-        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, &mut s, &mut 0) > 225 - d);
+        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut 0)
+                    > 225 - d);
 
         let fen = "8/8/8/8/5pkp/6P1/5P1P/6K1 b - - 0 1";
         let board = Board::from_fen(fen).ok().unwrap();
         let mut p = P::from_board(board).ok().unwrap();
-        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, &mut s, &mut 0).abs() <= d);
+        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut 0).abs()
+                    <= d);
 
         let fen = "8/8/8/8/5pkp/6P1/5PKP/8 b - - 0 1";
         let board = Board::from_fen(fen).ok().unwrap();
         let mut p = P::from_board(board).ok().unwrap();
-        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, &mut s, &mut 0) <= -100 + d);
+        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut 0)
+                    <= -100 + d);
 
         let fen = "r1bqkbnr/pppp2pp/2n2p2/4p3/2N1P2B/3P1N2/PPP2PPP/R2QKB1R w - - 5 1";
         let board = Board::from_fen(fen).ok().unwrap();
         let mut p = P::from_board(board).ok().unwrap();
-        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, &mut s, &mut 0).abs() <= d);
+        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut 0).abs()
+                    <= d);
 
         let fen = "r1bqkbnr/pppp2pp/2n2p2/4N3/4P2B/3P1N2/PPP2PPP/R2QKB1R b - - 5 1";
         let board = Board::from_fen(fen).ok().unwrap();
         let mut p = P::from_board(board).ok().unwrap();
-        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, &mut s, &mut 0) <= -100 + d);
+        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut 0)
+                    <= -100 + d);
 
         let fen = "rn2kbnr/ppppqppp/8/4p3/2N1P1b1/3P1N2/PPP2PPP/R1BKQB1R w - - 5 1";
         let board = Board::from_fen(fen).ok().unwrap();
         let mut p = P::from_board(board).ok().unwrap();
-        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, &mut s, &mut 0).abs() <= d);
+        assert!(qsearch(&mut p, -1000, 1000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut 0).abs()
+                    <= d);
 
         let fen = "8/8/8/8/8/7k/7q/7K w - - 0 1";
         let board = Board::from_fen(fen).ok().unwrap();
         let mut p = P::from_board(board).ok().unwrap();
-        assert!(qsearch(&mut p, -10000, 10000, VALUE_UNKNOWN, 0, 0, &mut s, &mut 0) <= -10000);
+        assert!(qsearch(&mut p, -10000, 10000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut 0)
+                    <= -10000);
+    }
+
+    #[test]
+    fn recapture_squares() {
+        use super::qsearch;
+        use squares::*;
+        let mut s = MoveStack::new();
+        let fen = "3qk3/8/8/3N4/4P3/8/8/4K3 b - - 0 1";
+        let board = Board::from_fen(fen).ok().unwrap();
+        let mut p = P::from_board(board).ok().unwrap();
+        let mut n1 = 0;
+        let mut n2 = 0;
+        qsearch(&mut p, -10000, 10000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut n1, &mut 0);
+        qsearch(&mut p, -10000, 10000, VALUE_UNKNOWN, 1 << D5, 0, 1, &mut s, &mut n2, &mut 0);
+        assert!(n2 > n1);
+    }
+
+    #[test]
+    fn check_plies() {
+        use super::qsearch;
+        let mut s = MoveStack::new();
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        let board = Board::from_fen(fen).ok().unwrap();
+        let mut p = P::from_board(board).ok().unwrap();
+        assert!(qsearch(&mut p, -10000, 10000, VALUE_UNKNOWN, 0, 0, 0, &mut s, &mut 0, &mut 0)
+                    < 1000);
+        assert!(qsearch(&mut p, -10000, 10000, VALUE_UNKNOWN, 0, 0, 1, &mut s, &mut 0, &mut 0)
+                    >= 10000);
+
+        let fen = "6k1/8/5K2/8/8/8/8/1Q6 w - - 0 1";
+        let board = Board::from_fen(fen).ok().unwrap();
+        let mut p = P::from_board(board).ok().unwrap();
+        assert!(qsearch(&mut p, -10000, 10000, VALUE_UNKNOWN, 0, 0, 2, &mut s, &mut 0, &mut 0)
+                    < 10000);
+        assert!(qsearch(&mut p, -10000, 10000, VALUE_UNKNOWN, 0, 0, 3, &mut s, &mut 0, &mut 0)
+                    >= 10000);
+    }
+
+    #[test]
+    fn evaluate_cached() {
+        use super::{evaluate_cached, EVAL_CACHE};
+        use evaluator::Evaluator;
+        use utils::EvalCache;
+        let fen = "8/8/8/8/5pkp/6P1/5P1P/6K1 b - - 0 1";
+        let board = Board::from_fen(fen).ok().unwrap();
+        let p = P::from_board(board).ok().unwrap();
+        let v = p.evaluator().evaluate(p.board());
+        EVAL_CACHE.with(|c| unsafe { (*c.get()).1 = EvalCache::new(1) });
+        EVAL_CACHE.with(|c| unsafe { assert_eq!((*c.get()).1.probe(p.hash()), None) });
+        assert_eq!(evaluate_cached(&p), v);
+        EVAL_CACHE.with(|c| unsafe { assert_eq!((*c.get()).1.probe(p.hash()), Some(v)) });
+        assert_eq!(evaluate_cached(&p), v);
     }
 }
@@ -1,18 +1,37 @@
 //! Implements `StdSearchNode`.
 
-use std::cmp::min;
+use std::fmt;
+use std::cmp::{min, max};
 use std::cell::UnsafeCell;
 use std::hash::Hasher;
+use std::sync::atomic::{AtomicIsize, AtomicUsize, AtomicBool, Ordering};
 use std::collections::hash_map::DefaultHasher;
 use uci::{SetOption, OptionDescription};
-use board::{Board, IllegalBoard};
+use board::{Board, Bitboard, IllegalBoard, WHITE};
 use value::*;
 use depth::*;
 use qsearch::{Qsearch, QsearchParams, QsearchResult};
 use moves::{Move, MoveDigest, AddMove};
 use move_generator::MoveGenerator;
 use search_node::SearchNode;
-use utils::{ZobristArrays, parse_fen};
+use utils::{ZobristArrays, parse_fen, format_fen};
+
+
+/// The value of the "Contempt" option (centipawns).
+static CONTEMPT: AtomicIsize = AtomicIsize::new(0);
+
+/// The value of the "UCI_AnalyseMode" option.
+static ANALYSE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// The value of the "Draw Dither" option.
+static DRAW_DITHER: AtomicBool = AtomicBool::new(false);
+
+/// The value of the "Random Seed" option.
+static RANDOM_SEED: AtomicUsize = AtomicUsize::new(0);
+
+/// Draw scores are dithered only at or beyond this distance from the
+/// root (half-moves).
+const DRAW_DITHER_MIN_PLY: usize = 4;
 
 
 /// Contains information about a position.
@@ -29,6 +48,24 @@ struct PositionInfo {
 
 
 /// Implements the `SearchNode` trait.
+///
+/// Draws are not necessarily evaluated as `0`. The "Contempt" option
+/// makes the engine regard draws as a loss of some centipawns for the
+/// side that it plays at the root, and a gain of the same number of
+/// centipawns for the opponent. Contempt is ignored when the
+/// "UCI_AnalyseMode" option is set.
+///
+/// When the "Draw Dither" option is set, draw scores deep in the tree
+/// are dithered by ±1 centipawn. The dither is a pseudo-random
+/// function of the position and the root position, so it does not
+/// change between the iterations of a search, but it prevents the
+/// engine from oscillating between equivalent shuffling lines. Draw
+/// scores close to the root are never dithered. The option is off by
+/// default, because dead drawn positions may get reported as `±1`
+/// instead of `0`, and because they become much more expensive to
+/// search to a big depth. Different values for the "Random Seed"
+/// option give different dithers. Changes of the option take effect
+/// when a position is declared as root.
 pub struct StdSearchNode<T: Qsearch> {
     zobrist: &'static ZobristArrays,
     position: UnsafeCell<T::MoveGenerator>,
@@ -39,11 +76,17 @@ pub struct StdSearchNode<T: Qsearch> {
     /// The count of half-moves since the beginning of the game.
     halfmove_count: u16,
 
-    /// `true` if the position is deemed as a draw by repetition or
-    /// because 50 moves have been played without capturing a piece or
-    /// advancing a pawn.
+    /// `true` if the position is deemed as a draw by repetition, by
+    /// insufficient material, or because 50 moves have been played
+    /// without capturing a piece or advancing a pawn.
     repeated_or_rule50: bool,
 
+    /// `true` if there is insufficient material at the root position.
+    /// (Then this is true for every position that can be reached from
+    /// the root, because without pawns the material on the board can
+    /// only decrease.) It is set by `declare_as_root`.
+    insufficient_material_at_root: bool,
+
     /// The hash value for the underlying `Board` instance.
     board_hash: u64,
 
@@ -52,18 +95,33 @@ pub struct StdSearchNode<T: Qsearch> {
     /// detect repeated positions.
     encountered_boards: Vec<u64>,
 
+    /// The hash values for the boards that had occurred since the
+    /// last irreversible move, up to the root position (excluding
+    /// it). Unlike `encountered_boards`, this list is not modified by
+    /// `declare_as_root`. It is needed only by `history_hashes`.
+    root_history: Vec<u64>,
+
     /// A collective hash value for the set of boards that had
     /// occurred at least twice before the root position (the earliest
     /// position in `state_stack`), and can still be reached by
     /// playing moves from the root position. An empty set has a hash
     /// of `0`. We use this value when we generate position's hash.
     repeated_boards_hash: u64,
+
+    /// The seed for the draw dither. It is set by `declare_as_root`.
+    dither_seed: u64,
+
+    /// Whether draw scores are dithered. It is set by
+    /// `declare_as_root`, from the "Draw Dither" option.
+    dither_draws: bool,
 }
 
 
 impl<T: Qsearch> SearchNode for StdSearchNode<T> {
     type Evaluator = <<T as Qsearch>::MoveGenerator as MoveGenerator>::Evaluator;
 
+    type MoveGenerator = T::MoveGenerator;
+
     type QsearchResult = T::QsearchResult;
 
     fn from_history(fen: &str, moves: &mut Iterator<Item = &str>) -> Result<Self, IllegalBoard> {
@@ -107,10 +165,12 @@ impl<T: Qsearch> SearchNode for StdSearchNode<T> {
             // practical advantage that we get two separate records in
             // the transposition table for the first and the second
             // occurrence of the same position. (The second occurrence
-            // being deemed as a draw.)
-            1
+            // being deemed as a draw.) Because of the contempt, draws
+            // may not be worth `0`, so we blend the value of the draw
+            // into the hash.
+            (self.draw_value() as u16 as u64) << 1 | 1
         } else {
-            let hash = if self.root_is_reachable() {
+            let mut hash = if self.root_is_reachable() {
                 // If the repeated positions that occured before the
                 // root postition are still reachable, we blend their
                 // collective hash into current position's hash.
@@ -118,6 +178,13 @@ impl<T: Qsearch> SearchNode for StdSearchNode<T> {
             } else {
                 self.board_hash
             };
+            let white_draw_value = self.white_draw_value();
+            if white_draw_value != 0 {
+                // The values of all positions that lead to a draw
+                // depend on the contempt, so we blend it into the
+                // returned hash.
+                hash ^= (white_draw_value as u64).wrapping_mul(0x9e3779b97f4a7c15);
+            }
             let halfmove_clock = self.state().halfmove_clock;
             if halfmove_clock >= 70 {
                 // If `halfmove_clock` is close to rule-50, we blend
@@ -139,6 +206,18 @@ impl<T: Qsearch> SearchNode for StdSearchNode<T> {
         self.state().halfmove_clock
     }
 
+    fn history_hashes(&self) -> Vec<u64> {
+        let root_halfmove_clock = self.state_stack[0].halfmove_clock as usize;
+        let mut history: Vec<u64> = self.root_history
+            .iter()
+            .chain(self.encountered_boards[root_halfmove_clock..].iter())
+            .cloned()
+            .collect();
+        history.push(self.board_hash);
+        let n = min(history.len(), self.halfmove_clock() as usize + 1);
+        history.split_off(history.len() - n)
+    }
+
     #[inline]
     fn fullmove_number(&self) -> u16 {
         1 + (self.halfmove_count >> 1)
@@ -149,6 +228,16 @@ impl<T: Qsearch> SearchNode for StdSearchNode<T> {
         self.position().is_check()
     }
 
+    #[inline]
+    fn checkers(&self) -> Bitboard {
+        self.position().checkers()
+    }
+
+    #[inline]
+    fn pinned(&self) -> Bitboard {
+        self.position().pinned()
+    }
+
     #[inline]
     fn evaluator(&self) -> &Self::Evaluator {
         self.position().evaluator()
@@ -157,7 +246,7 @@ impl<T: Qsearch> SearchNode for StdSearchNode<T> {
     #[inline]
     fn evaluate_final(&self) -> Value {
         if self.repeated_or_rule50 || !self.is_check() {
-            0
+            self.draw_value()
         } else {
             VALUE_MIN
         }
@@ -180,14 +269,24 @@ impl<T: Qsearch> SearchNode for StdSearchNode<T> {
         debug_assert!(upper_bound <= VALUE_MAX);
         debug_assert!(lower_bound < upper_bound);
         if self.repeated_or_rule50 {
-            Self::QsearchResult::new(0, 0)
+            Self::QsearchResult::new(self.draw_value(), 0)
         } else {
+            // Always consider recapturing the piece moved by the last
+            // move played in the main search.
+            let last_move = self.last_move();
+            let recapture_squares = if last_move.is_null() ||
+                                       last_move.digest() == MoveDigest::invalid() {
+                0
+            } else {
+                1 << last_move.dest_square()
+            };
             T::qsearch(QsearchParams {
                            position: unsafe { self.position_mut() },
                            depth: depth,
                            lower_bound: lower_bound,
                            upper_bound: upper_bound,
                            static_eval: static_eval,
+                           recapture_squares: recapture_squares,
                        })
         }
     }
@@ -199,6 +298,20 @@ impl<T: Qsearch> SearchNode for StdSearchNode<T> {
         }
     }
 
+    #[inline]
+    fn generate_captures<U: AddMove>(&self, moves: &mut U) {
+        if !self.repeated_or_rule50 {
+            self.position().generate_forcing(false, moves);
+        }
+    }
+
+    #[inline]
+    fn generate_quiet_moves<U: AddMove>(&self, moves: &mut U) {
+        if !self.repeated_or_rule50 {
+            self.position().generate_quiet(moves);
+        }
+    }
+
     #[inline]
     fn try_move_digest(&self, move_digest: MoveDigest) -> Option<Move> {
         if self.repeated_or_rule50 {
@@ -241,6 +354,13 @@ impl<T: Qsearch> SearchNode for StdSearchNode<T> {
                     }
                 }
             };
+            if self.insufficient_material_at_root ||
+               halfmove_clock == 0 && self.board().is_insufficient_material() {
+                // Checkmate is not possible anymore. (The material on
+                // the board can change only after a capture or a
+                // promotion, which reset the halfmove clock.)
+                self.repeated_or_rule50 = true;
+            }
             self.halfmove_count += 1;
             self.encountered_boards.push(self.board_hash);
             self.board_hash ^= h;
@@ -293,6 +413,7 @@ impl<T: Qsearch> Clone for StdSearchNode<T> {
         StdSearchNode {
             position: UnsafeCell::new(self.position().clone()),
             encountered_boards: self.encountered_boards.clone(),
+            root_history: self.root_history.clone(),
             state_stack: self.state_stack.clone(),
             ..*self
         }
@@ -300,13 +421,66 @@ impl<T: Qsearch> Clone for StdSearchNode<T> {
 }
 
 
+impl<T: Qsearch> fmt::Display for StdSearchNode<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}", self.board().pieces));
+        writeln!(f,
+                 "{}",
+                 format_fen(self.board(), self.halfmove_clock(), self.fullmove_number()))
+    }
+}
+
+
+impl<T: Qsearch> fmt::Debug for StdSearchNode<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StdSearchNode")
+            .field("fen",
+                   &format_fen(self.board(), self.halfmove_clock(), self.fullmove_number()))
+            .field("hash", &format_args!("{:016x}", self.hash()))
+            .finish()
+    }
+}
+
+
 impl<T: Qsearch> SetOption for StdSearchNode<T> {
     fn options() -> Vec<(&'static str, OptionDescription)> {
-        T::options()
+        let mut options = vec![("Contempt",
+                                OptionDescription::Spin {
+                                    min: -100,
+                                    max: 100,
+                                    default: 0,
+                                }),
+                               ("UCI_AnalyseMode", OptionDescription::Check { default: false }),
+                               ("Draw Dither", OptionDescription::Check { default: false }),
+                               ("Random Seed",
+                                OptionDescription::Spin {
+                                    min: 0,
+                                    max: i32::MAX,
+                                    default: 0,
+                                })];
+        options.extend(T::options());
+        options
     }
 
-    fn set_option(name: &str, value: &str) {
-        T::set_option(name, value)
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "Contempt" => {
+                if let Ok(v) = value.parse::<isize>() {
+                    CONTEMPT.store(min(max(v, -100), 100), Ordering::Relaxed);
+                }
+            }
+            "UCI_AnalyseMode" => ANALYSE_MODE.store(value == "true", Ordering::Relaxed),
+            "Draw Dither" => DRAW_DITHER.store(value == "true", Ordering::Relaxed),
+            "Random Seed" => {
+                // The evaluator may have a random component too.
+                if let Ok(v) = value.parse::<usize>() {
+                    RANDOM_SEED.store(v, Ordering::Relaxed);
+                }
+                try!(T::set_option(name, value));
+            }
+            _ => try!(T::set_option(name, value)),
+        }
+        Ok(())
     }
 }
 
@@ -322,8 +496,12 @@ impl<T: Qsearch> StdSearchNode<T> {
                board_hash: gen.hash(),
                position: UnsafeCell::new(gen),
                repeated_or_rule50: false,
+               insufficient_material_at_root: false,
                repeated_boards_hash: 0,
+               dither_seed: 0,
+               dither_draws: false,
                encountered_boards: vec![0; halfmove_clock as usize],
+               root_history: vec![],
                state_stack: vec![PositionInfo {
                                      halfmove_clock: min(halfmove_clock, 99),
                                      last_move: Move::invalid(),
@@ -337,8 +515,11 @@ impl<T: Qsearch> StdSearchNode<T> {
         let state = *self.state();
 
         // The root position is never deemed as a draw due to
-        // repetition or rule-50.
+        // repetition, rule-50, or insufficient material. Its
+        // children, however, are deemed as draws if there is
+        // insufficient material already at the root.
         self.repeated_or_rule50 = false;
+        self.insufficient_material_at_root = self.board().is_insufficient_material();
 
         // Calculate the set of previously repeated, still reachable boards.
         let repeated_boards = {
@@ -347,6 +528,14 @@ impl<T: Qsearch> StdSearchNode<T> {
             self.encountered_boards = self.encountered_boards.split_off(last_irrev);
             self.encountered_boards.reserve(32);
 
+            // Remember the history. (Boards that had occurred before
+            // the initial position are unknown and have zero hashes.)
+            self.root_history = self.encountered_boards
+                .iter()
+                .cloned()
+                .filter(|&x| x != 0)
+                .collect();
+
             // Forget all encountered boards that occurred only once.
             set_non_repeated_values(&mut self.encountered_boards, 0)
         };
@@ -364,11 +553,65 @@ impl<T: Qsearch> StdSearchNode<T> {
             hasher.finish()
         };
 
+        // Seed the draw dither with the root position.
+        self.dither_seed = self.board_hash ^ self.repeated_boards_hash;
+        self.dither_draws = DRAW_DITHER.load(Ordering::Relaxed);
+
         // Forget all played moves.
         self.state_stack = vec![state];
         self.state_stack.reserve(32);
     }
 
+    /// Returns if the side to move is the side to move at the root
+    /// position (the earliest in `state_stack`).
+    #[inline]
+    fn is_root_side_to_move(&self) -> bool {
+        self.state_stack.len() & 1 == 1
+    }
+
+    /// Returns the value of a draw for the side to move.
+    #[inline]
+    fn draw_value(&self) -> Value {
+        let v = if self.board().to_move == WHITE {
+            self.white_draw_value()
+        } else {
+            -self.white_draw_value()
+        };
+        v + self.draw_dither()
+    }
+
+    /// Returns a pseudo-random dither (`-1` or `1`) for the
+    /// draw value of the current position.
+    ///
+    /// The dither is always `0` close to the root position, and when
+    /// the "Draw Dither" option is not set.
+    #[inline]
+    fn draw_dither(&self) -> Value {
+        if self.state_stack.len() <= DRAW_DITHER_MIN_PLY || !self.dither_draws {
+            return 0;
+        }
+        let seed = self.dither_seed ^ RANDOM_SEED.load(Ordering::Relaxed) as u64;
+        let x = (self.board_hash ^ seed).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        if x >> 63 == 0 { -1 } else { 1 }
+    }
+
+    /// Returns the value of a draw for white.
+    ///
+    /// Unless in analyse mode, the side to move at the root regards
+    /// a draw as a loss of "Contempt" centipawns.
+    #[inline]
+    fn white_draw_value(&self) -> Value {
+        if ANALYSE_MODE.load(Ordering::Relaxed) {
+            return 0;
+        }
+        let contempt = CONTEMPT.load(Ordering::Relaxed) as Value;
+        if (self.board().to_move == WHITE) == self.is_root_side_to_move() {
+            -contempt
+        } else {
+            contempt
+        }
+    }
+
     /// Returns if the root position (the earliest in `state_stack`)
     /// can be reached by playing moves from the current position.
     #[inline]
@@ -455,6 +698,46 @@ mod tests {
     use stock::{StdSearchNode, StdQsearch, StdMoveGenerator, SimpleEvaluator};
     type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
 
+    #[test]
+    fn history_hashes() {
+        let moves = vec!["e2e4", "e7e5", "g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut p = P::from_history("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                                    &mut moves.into_iter())
+            .ok()
+            .unwrap();
+        assert_eq!(p.ply_of_last_irreversible(), 2);
+        let h = p.history_hashes();
+        assert_eq!(h.len(), 5);
+        assert!(h[1] != h[3]);
+        let m = p.legal_moves().into_iter().find(|m| m.notation() == "g1f3").unwrap();
+        assert!(p.do_move(m));
+        let h = p.history_hashes();
+        assert_eq!(h.len(), 6);
+        assert_eq!(h[1], h[5]);
+        let m = p.legal_moves().into_iter().find(|m| m.notation() == "d7d5").unwrap();
+        assert!(p.do_move(m));
+        assert_eq!(p.history_hashes().len(), 1);
+        assert_eq!(p.ply_of_last_irreversible(), 8);
+
+        let p = P::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 10 30").ok().unwrap();
+        assert_eq!(p.history_hashes().len(), 1);
+        assert_eq!(p.ply_of_last_irreversible(), 48);
+    }
+
+    #[test]
+    fn checkers_and_pinned() {
+        use squares::*;
+        let mut p = P::from_fen("4k3/8/8/8/1b6/8/3N4/4K2R w K - 0 1").ok().unwrap();
+        assert_eq!(p.checkers(), 0);
+        assert_eq!(p.pinned(), 1 << D2);
+        assert!(!p.last_move_gave_check());
+        let m = p.legal_moves().into_iter().find(|m| m.notation() == "h1h8").unwrap();
+        assert!(p.do_move(m));
+        assert_eq!(p.checkers(), 1 << H8);
+        assert_eq!(p.pinned(), 0);
+        assert!(p.last_move_gave_check());
+    }
+
     #[test]
     fn is_legal() {
         assert!(P::from_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
@@ -626,6 +909,21 @@ mod tests {
             }
         }
         assert_eq!(p.evaluate_move(p.null_move()), 0);
+
+        for &(fen, notation, value) in
+            [("r6k/1P6/8/8/8/8/8/K7 w - - 0 1", "b7a8q", 1375),
+             ("k3n2r/3P4/8/8/8/8/8/K3Q3 w - - 0 1", "e1e8", 325),
+             ("3r3k/8/8/2Pp4/8/8/8/3RK3 w - d6 0 1", "c5d6", 100),
+             ("3rk3/8/8/8/2pP4/8/8/3R3K b - d3 0 1", "c4d3", 100)]
+                    .iter() {
+            let p = P::from_fen(fen).ok().unwrap();
+            p.generate_moves(&mut s);
+            while let Some(m) = s.pop() {
+                if m.notation() == notation {
+                    assert_eq!(p.evaluate_move(m), value);
+                }
+            }
+        }
     }
 
     #[test]
@@ -678,6 +976,60 @@ mod tests {
         assert_eq!(count, 4);
     }
 
+    #[test]
+    fn repetition_after_root() {
+        let fen = "7k/8/8/8/8/8/8/R6K w - - 0 1";
+        let shuffle = ["h1g1", "h8g8", "g1h1", "g8h8"];
+        let play = |p: &mut P, m: &str| {
+            let m = p.legal_moves().into_iter().find(|x| x.notation() == m).unwrap();
+            assert!(p.do_move(m));
+        };
+
+        // A single repetition inside the search tree is a draw.
+        let mut p = P::from_history(fen, &mut vec![].into_iter()).ok().unwrap();
+        for m in shuffle.iter() {
+            assert!(!p.legal_moves().is_empty());
+            play(&mut p, m);
+        }
+        assert!(p.legal_moves().is_empty());
+        assert_eq!(p.evaluate_final(), 0);
+
+        // Boards that occurred only once before the root are not
+        // deemed as repeated when they occur again.
+        let mut p = P::from_history(fen, &mut shuffle.iter().cloned()).ok().unwrap();
+        play(&mut p, "h1g1");
+        play(&mut p, "h8g8");
+        assert!(!p.legal_moves().is_empty());
+        play(&mut p, "g1h1");
+        play(&mut p, "g8h8");
+        assert!(p.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let mut p = P::from_history("8/8/8/3k4/8/8/4p3/4K3 w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        assert!(!p.is_insufficient_material());
+        let m = p.legal_moves().into_iter().find(|m| m.notation() == "e1e2").unwrap();
+        assert!(p.do_move(m));
+        assert!(p.is_insufficient_material());
+        assert!(p.legal_moves().is_empty());
+        assert_eq!(p.evaluate_final(), 0);
+        p.undo_last_move();
+        assert!(!p.legal_moves().is_empty());
+
+        let mut p = P::from_history("8/8/8/3k4/8/8/4B3/4K3 w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        assert!(p.is_insufficient_material());
+        assert!(!p.legal_moves().is_empty());
+        let m = p.legal_moves().into_iter().find(|m| m.notation() == "e2f3").unwrap();
+        assert!(p.do_move(m));
+        assert!(p.legal_moves().is_empty());
+        assert_eq!(p.evaluate_final(), 0);
+    }
+
     #[test]
     fn is_checkmate() {
         let p = P::from_fen("8/8/8/8/8/7K/8/5R1k b - - 0 1")
@@ -718,4 +1070,27 @@ mod tests {
         assert_eq!(p1.board_hash, p2.board_hash);
         assert!(p1.hash() != p3.hash());
     }
+
+    #[test]
+    fn draw_dither() {
+        use super::DRAW_DITHER_MIN_PLY;
+        let mut p = P::from_history("8/8/8/8/8/5k2/8/R4K2 w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+
+        // Setting the "Draw Dither" option here would disturb other
+        // tests, which are running in parallel.
+        p.dither_draws = true;
+        for (i, notation) in ["f1g1", "f3g3", "g1f1", "g3f3"].iter().enumerate() {
+            let m = p.legal_moves().into_iter().find(|m| m.notation() == *notation).unwrap();
+            assert!(p.do_move(m));
+            if i + 1 < DRAW_DITHER_MIN_PLY {
+                assert_eq!(p.evaluate_final(), 0);
+            } else {
+                assert_eq!(p.evaluate_final().abs(), 1);
+            }
+        }
+        p.dither_draws = false;
+        assert_eq!(p.evaluate_final(), 0);
+    }
 }
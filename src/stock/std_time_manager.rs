@@ -4,16 +4,25 @@ use std::sync::RwLock;
 use std::time::{SystemTime, Duration};
 use std::cmp::min;
 use board::*;
+use moves::Move;
 use depth::*;
 use value::*;
 use search::*;
 use ttable::Variation;
 use search_node::SearchNode;
+use evaluator::Evaluator;
+use qsearch::QsearchResult;
 use time_manager::{TimeManager, RemainingTime};
 use uci::{SetOption, OptionDescription};
 
 
 /// Implements the `TimeManager` trait.
+///
+/// The allotted time for the move is a soft limit -- the search
+/// continues to the depth that is expected to complete within the
+/// allotted time, but can be stopped earlier if the best move has not
+/// changed for several iterations. The search is never allowed to
+/// exceed the hard limit.
 pub struct StdTimeManager {
     started_at: SystemTime,
     depth: Depth,
@@ -22,6 +31,11 @@ pub struct StdTimeManager {
     hard_limit: f64,
     allotted_time: f64,
     must_play: bool,
+
+    // The best move from the last completed depth, and for how many
+    // consecutive depths it has been the best move.
+    best_move: Move,
+    best_move_stability: usize,
 }
 
 
@@ -49,6 +63,16 @@ impl<T> TimeManager<T> for StdTimeManager
         // move. Thinking longer that that would be reckless.
         let hard_limit = (t / n.sqrt() + inc).min(t - 1000.0);
 
+        // Give more time to tactical positions, and less time to
+        // quiet ones. `tactical_time_weight` determines how much the
+        // allotted time can deviate from the normal allotment.
+        let tactical_time_weight = option_as_f64("Tactical Time Weight") / 100.0;
+        let volatility = tactical_volatility(position,
+                                             option_as_f64("Volatility Check Weight"),
+                                             option_as_f64("Volatility Hanging Weight"),
+                                             option_as_f64("Volatility Divergence Weight"));
+        let volatility_factor = 1.0 + tactical_time_weight * (2.0 * volatility - 1.0);
+
         StdTimeManager {
             started_at: SystemTime::now(),
             depth: 0,
@@ -69,11 +93,13 @@ impl<T> TimeManager<T> for StdTimeManager
                 // heap. In reality we do not know how opponent's time
                 // will be spend, so we speculatively increase our
                 // time heap by 50%.
-                1.5 * time_heap / n
+                1.5 * volatility_factor * time_heap / n
             } else {
-                time_heap / n
+                volatility_factor * time_heap / n
             },
             must_play: false,
+            best_move: Move::invalid(),
+            best_move_stability: 0,
         }
     }
 
@@ -91,7 +117,8 @@ impl<T> TimeManager<T> for StdTimeManager
                     let t_pessimistic = t_next * AVG_SLOPE.read().unwrap().exp().sqrt();
                     let msg = format!("TARGET_DEPTH={}", target_depth);
                     search_instance.send_message(msg.as_str());
-                    is_finished = r.depth >= target_depth || t_pessimistic > self.hard_limit
+                    is_finished = r.depth >= target_depth || t_pessimistic > self.hard_limit ||
+                                  self.best_move_is_stable(r)
                 }
             }
             self.must_play = is_finished || elapsed_millis(&self.started_at) > self.hard_limit;
@@ -103,12 +130,62 @@ impl<T> TimeManager<T> for StdTimeManager
 
 impl SetOption for StdTimeManager {
     fn options() -> Vec<(&'static str, OptionDescription)> {
-        vec![("Ponder", OptionDescription::Check { default: false })]
+        vec![("Ponder", OptionDescription::Check { default: false }),
+             ("Tactical Time Weight",
+              OptionDescription::Spin {
+                  min: 0,
+                  max: 90,
+                  default: 25,
+              }),
+             ("Volatility Check Weight",
+              OptionDescription::Spin {
+                  min: 0,
+                  max: 100,
+                  default: 5,
+              }),
+             ("Volatility Hanging Weight",
+              OptionDescription::Spin {
+                  min: 0,
+                  max: 100,
+                  default: 20,
+              }),
+             ("Volatility Divergence Weight",
+              OptionDescription::Spin {
+                  min: 0,
+                  max: 100,
+                  default: 25,
+              })]
     }
 }
 
 
 impl StdTimeManager {
+    /// Registers the best move for a newly completed depth, and
+    /// returns if the best move has been stable long enough so that
+    /// the search can be stopped before the allotted time is used
+    /// up.
+    fn best_move_is_stable(&mut self, report: &SearchReport<Vec<Variation>>) -> bool {
+        // The best move must remain unchanged for this many
+        // consecutive depths.
+        const STABLE_DEPTHS: usize = 4;
+
+        // The fraction of the allotted time that must be used anyway.
+        const MIN_TIME_FRACTION: f64 = 0.4;
+
+        let best_move = match report.data.first().and_then(|v| v.moves.first()) {
+            Some(&m) => m,
+            None => return false,
+        };
+        if best_move == self.best_move {
+            self.best_move_stability += 1;
+        } else {
+            self.best_move = best_move;
+            self.best_move_stability = 1;
+        }
+        self.best_move_stability >= STABLE_DEPTHS &&
+        elapsed_millis(&self.started_at) >= MIN_TIME_FRACTION * self.allotted_time
+    }
+
     /// Guesses what target depth we will be able to reach, and how
     /// much time (milliseconds) it will take for the next search
     /// depth to complete.
@@ -177,6 +254,71 @@ lazy_static! {
 }
 
 
+/// Estimates how tactical (volatile) a position is.
+///
+/// Returns a number between `0.0` (a quiet position) and `1.0` (a
+/// very sharp position). Each available check adds `check_weight`
+/// percents, each hanging piece (for either side) adds
+/// `hanging_weight` percents, and each 100 centipawns of divergence
+/// between position's static evaluation and its quiescence search
+/// value add `divergence_weight` percents.
+fn tactical_volatility<T: SearchNode>(position: &T,
+                                      check_weight: f64,
+                                      hanging_weight: f64,
+                                      divergence_weight: f64)
+                                      -> f64 {
+    let mut p = position.clone();
+    let legal_moves = p.legal_moves();
+
+    // Count the available checks, and opponent's hanging pieces.
+    let mut checks = 0;
+    let mut hanging = 0;
+    for &m in legal_moves.iter() {
+        if m.captured_piece() < PIECE_NONE && p.evaluate_move(m) > 0 {
+            hanging += 1;
+        }
+        if p.do_move(m) {
+            if p.is_check() {
+                checks += 1;
+            }
+            p.undo_last_move();
+        }
+    }
+
+    // Count our hanging pieces.
+    if !p.is_check() {
+        let null_move = p.null_move();
+        if p.do_move(null_move) {
+            hanging += p.legal_moves()
+                .iter()
+                .filter(|&&m| m.captured_piece() < PIECE_NONE && p.evaluate_move(m) > 0)
+                .count();
+            p.undo_last_move();
+        }
+    }
+
+    // Calculate the divergence between the static evaluation and
+    // the quiescence search value.
+    let divergence = if p.is_check() || legal_moves.is_empty() {
+        0
+    } else {
+        let static_eval = p.evaluator().evaluate(p.board());
+        let qsearch_value = p.qsearch(0, VALUE_EVAL_MIN, VALUE_EVAL_MAX, static_eval).value();
+        (qsearch_value as isize - static_eval as isize).abs()
+    };
+
+    let volatility = check_weight * checks as f64 + hanging_weight * hanging as f64 +
+                     divergence_weight * divergence as f64 / 100.0;
+    (volatility / 100.0).max(0.0).min(1.0)
+}
+
+
+/// Returns the value of a numeric configuration option.
+fn option_as_f64(name: &'static str) -> f64 {
+    ::get_option(name).parse().unwrap_or(0.0)
+}
+
+
 /// Calculates elapsed milliseconds since a given time.
 fn elapsed_millis(since: &SystemTime) -> f64 {
     let d = since.elapsed().unwrap_or(Duration::from_millis(0));
@@ -209,4 +351,76 @@ mod tests {
         let y = slope * x + intercept;
         assert!(4.99 < y && y < 5.01);
     }
+
+    #[test]
+    fn best_move_is_stable() {
+        use std::time::SystemTime;
+        use board::*;
+        use moves::*;
+        use squares::*;
+        use value::*;
+        use ttable::*;
+        use search::SearchReport;
+        use super::StdTimeManager;
+        let mut tm = StdTimeManager {
+            started_at: SystemTime::now(),
+            depth: 0,
+            value: VALUE_UNKNOWN,
+            data_points: vec![],
+            hard_limit: 0.0,
+            allotted_time: 0.0,
+            must_play: false,
+            best_move: Move::invalid(),
+            best_move_stability: 0,
+        };
+        let m1 = Move::new(MOVE_NORMAL, E2, E4, 0, PIECE_NONE, PAWN, CastlingRights::new(0), 8);
+        let m2 = Move::new(MOVE_NORMAL, D2, D4, 0, PIECE_NONE, PAWN, CastlingRights::new(0), 8);
+        let report = |m: Move| {
+            SearchReport {
+                search_id: 0,
+                searched_nodes: 0,
+                depth: 1,
+                value: 0,
+                data: vec![Variation {
+                               moves: vec![m],
+                               value: 0,
+                               bound: BOUND_EXACT,
+                           }],
+                done: false,
+                termination: None,
+                details: Default::default(),
+            }
+        };
+        assert!(!tm.best_move_is_stable(&report(m1)));
+        assert!(!tm.best_move_is_stable(&report(m1)));
+        assert!(!tm.best_move_is_stable(&report(m2)));
+        assert!(!tm.best_move_is_stable(&report(m2)));
+        assert!(!tm.best_move_is_stable(&report(m2)));
+        assert!(tm.best_move_is_stable(&report(m2)));
+        tm.allotted_time = 1e9;
+        assert!(!tm.best_move_is_stable(&report(m2)));
+    }
+
+    #[test]
+    fn tactical_volatility() {
+        use super::tactical_volatility;
+        use search_node::SearchNode;
+        use stock::{SimpleEvaluator, StdMoveGenerator, StdQsearch, StdSearchNode};
+        type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+        let quiet = P::from_history("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                                    &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let sharp = P::from_history("r1bqk2r/pppp1ppp/2n2n2/2b1p1N1/2B1P3/8/PPPP1PPP/RNBQK2R w \
+                                     KQkq - 0 1",
+                                    &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let v_quiet = tactical_volatility(&quiet, 5.0, 20.0, 25.0);
+        let v_sharp = tactical_volatility(&sharp, 5.0, 20.0, 25.0);
+        assert_eq!(v_quiet, 0.0);
+        assert!(v_sharp > v_quiet);
+        assert!(v_sharp <= 1.0);
+        assert_eq!(tactical_volatility(&sharp, 0.0, 0.0, 0.0), 0.0);
+    }
 }
@@ -5,7 +5,6 @@ use libc::c_void;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::marker::PhantomData;
 use std::isize;
-use std::cell::Cell;
 use std::cmp::max;
 use std::mem;
 use ttable::*;
@@ -16,11 +15,15 @@ use moves::MoveDigest;
 ///
 /// Consists of a transposition table entry plus the highest 32 bits
 /// of the key. The key is split into two `u16` values to allow more
-/// flexible alignment.
+/// flexible alignment. When the "tt-verify" feature is enabled, the
+/// lowest 32 bits of the key are stored too, so that key collisions
+/// can be detected.
 #[derive(Copy, Clone)]
 struct Record<T: TtableEntry> {
     key: (u16, u16),
     data: T,
+    #[cfg(feature = "tt-verify")]
+    check: u32,
 }
 
 
@@ -122,13 +125,53 @@ impl<R> Drop for Bucket<R> {
 }
 
 
+/// Determines how `StdTtable` chooses which record to replace.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReplacementPolicy {
+    /// The least important record in the bucket is replaced. Records
+    /// from previous searches are always replaced first.
+    LeastImportant = 0,
+
+    /// Each bucket is split into two tiers. The first slot is
+    /// "depth-preferred" -- it keeps the most important record for
+    /// the current search, and is replaced only by more important
+    /// records or when its record is from a previous search. The
+    /// rest of the slots form an "always-replace" tier, to which the
+    /// records that did not make it into the first slot go. (A record
+    /// evicted from the first slot is moved to the second tier.)
+    TwoTier = 1,
+}
+
+
 /// Implements the `Ttable` trait.
 ///
 /// `StdTtable` provides a generic transposition table implementation
 /// that can efficiently pack in memory a wide range of transposition
 /// table entry types. The only condition is that `T` has a size
-/// between 6 and 16 bytes, and alignment requirements of 4 bytes or
-/// less.
+/// between 6 and 16 bytes (between 6 and 12 bytes with the
+/// "tt-verify" feature enabled), and alignment requirements of 4
+/// bytes or less.
+///
+/// Records are grouped in buckets. Each bucket occupies exactly one
+/// 64-byte cache line, and the table is aligned so that buckets never
+/// straddle two cache lines. Therefore, `Ttable::prefetch` needs to
+/// fetch a single cache line to bring in all the records for a key.
+///
+/// The table can be shared by many search threads without any
+/// external synchronization. Every bucket has a locking flag, which
+/// is set with an atomic compare-and-swap while the bucket is being
+/// read or written, so a probe never sees a half-written record.
+/// Because buckets are small and are held only for a few
+/// instructions, contention is negligible. (The XOR-validation trick
+/// used by many engines is not an option here, because the size of
+/// `T` is not fixed.) All other shared state is kept in atomics.
+///
+/// When the "tt-verify" feature is enabled, each record holds the
+/// whole 64-bit key. A successful probe for which the stored key
+/// differs from the probed key is counted as a key collision, and
+/// treated as a miss. This allows to measure the collision rate of
+/// the table (see `Ttable::key_collisions`), which is useful when
+/// the table is small.
 pub struct StdTtable<T: TtableEntry> {
     entries: PhantomData<T>,
 
@@ -140,7 +183,25 @@ pub struct StdTtable<T: TtableEntry> {
     /// searches will be replaced before records from the current
     /// search. The generation number is always between 1 and
     /// 31. Generation `0` is reserved for empty records.
-    generation: Cell<usize>,
+    generation: AtomicUsize,
+
+    /// The policy for choosing which record to replace.
+    ///
+    /// Holds `0` for `ReplacementPolicy::LeastImportant`, and `1`
+    /// for `ReplacementPolicy::TwoTier`.
+    replacement_policy: AtomicUsize,
+
+    /// The number of inconsistent records that have been rejected by
+    /// `probe`.
+    rejected_records: AtomicUsize,
+
+    /// The number of successful probes. (Counted only when the
+    /// "tt-verify" feature is enabled.)
+    verified_probes: AtomicUsize,
+
+    /// The number of key collisions detected by `probe`. (Counted
+    /// only when the "tt-verify" feature is enabled.)
+    key_collisions: AtomicUsize,
 
     /// The number of buckets in the table.
     ///
@@ -196,7 +257,11 @@ impl<T: TtableEntry> Ttable for StdTtable<T> {
 
         StdTtable {
             entries: PhantomData,
-            generation: Cell::new(1),
+            generation: AtomicUsize::new(1),
+            replacement_policy: AtomicUsize::new(ReplacementPolicy::TwoTier as usize),
+            rejected_records: AtomicUsize::new(0),
+            verified_probes: AtomicUsize::new(0),
+            key_collisions: AtomicUsize::new(0),
             bucket_count: bucket_count,
             alloc_ptr: alloc_ptr,
             table_ptr: table_ptr,
@@ -209,20 +274,21 @@ impl<T: TtableEntry> Ttable for StdTtable<T> {
         loop {
             // Increment the generation number (with wrapping).
             self.generation
-                .set(match self.generation.get() {
-                         n @ 1...30 => n + 1,
-                         31 => 1,
-                         _ => unreachable!(),
-                     });
-            debug_assert!(self.generation.get() > 0);
-            debug_assert!(self.generation.get() < 32);
+                .store(match self.generation() {
+                           n @ 1...30 => n + 1,
+                           31 => 1,
+                           _ => unreachable!(),
+                       },
+                       Ordering::Relaxed);
+            debug_assert!(self.generation() > 0);
+            debug_assert!(self.generation() < 32);
 
             // Count how many staled records from this generation
             // there are among the first `N` buckets.
             let mut staled = 0;
             for bucket in self.buckets().take(N) {
                 for slot in 0..Bucket::<Record<T>>::len() {
-                    if bucket.get_generation(slot) == self.generation.get() {
+                    if bucket.get_generation(slot) == self.generation() {
                         staled += 1;
                     }
                 }
@@ -241,11 +307,27 @@ impl<T: TtableEntry> Ttable for StdTtable<T> {
     #[inline]
     fn store(&self, key: u64, mut data: Self::Entry) {
         let bucket = self.bucket(key);
+        #[cfg(feature = "tt-verify")]
+        let check = key as u32;
         let key = chop_key(key);
 
+        // PV records from the current generation are protected.
+        let current_generation = self.generation();
+        let is_protected = |record: &Record<T>, generation| {
+            generation == current_generation && record.data.is_pv()
+        };
+
+        // With the two-tier policy, the first slot is not a candidate
+        // for replacement, unless it is empty or has the same key.
+        let first_replaceable_slot = match self.replacement_policy() {
+            ReplacementPolicy::LeastImportant => 0,
+            ReplacementPolicy::TwoTier => 1,
+        };
+
         // Choose a bucket slot to which to write the data.
         let mut replace_slot = 0;
         let mut replace_score = isize::MAX;
+        let mut is_new_key = true;
         for slot in 0..Bucket::<Record<T>>::len() {
             let record = unsafe { &mut *bucket.get(slot) };
             let generation = bucket.get_generation(slot);
@@ -253,15 +335,18 @@ impl<T: TtableEntry> Ttable for StdTtable<T> {
             // Use this slot if it is empty.
             if generation == 0 {
                 replace_slot = slot;
+                is_new_key = false;
                 break;
             }
 
             // Use this slot if it contains an old record for the same key.
             if record.key == key {
                 if record.data.bound() == BOUND_EXACT &&
-                   record.data.importance() > data.importance() {
+                   (record.data.importance() > data.importance() ||
+                    is_protected(record, generation) && !data.is_pv()) {
                     // Keep the old record if we are certain that it
-                    // is more important than the new one.
+                    // is more important than the new one, or if it is
+                    // a protected PV record.
                     //
                     // **Note:** We do not keep old records with
                     // inexact bounds because they can be useless,
@@ -273,16 +358,27 @@ impl<T: TtableEntry> Ttable for StdTtable<T> {
                     data = data.set_move_digest(record.data.move_digest());
                 }
                 replace_slot = slot;
+                is_new_key = false;
                 break;
             }
+            if slot < first_replaceable_slot {
+                continue;
+            }
 
             // Calculate the score for the record in this slot. The
             // replaced record will be the one with the lowest score.
             let mut score = record.data.importance() as isize;
-            if generation == self.generation.get() {
+            if generation == self.generation() {
                 // Positions from the current generation are always
                 // scored higher than positions from older generations.
                 score += 1 << 16;
+
+                // PV records from the current generation are not
+                // replaced by non-PV records, unless there is no
+                // other choice.
+                if is_protected(record, generation) && !data.is_pv() {
+                    score += 1 << 15;
+                }
             };
             if score < replace_score {
                 replace_slot = slot;
@@ -290,25 +386,74 @@ impl<T: TtableEntry> Ttable for StdTtable<T> {
             }
         }
 
+        // With the two-tier policy, a new record takes the first slot
+        // if it is more important than the record there, and the
+        // evicted record goes to the "always-replace" tier.
+        if is_new_key && first_replaceable_slot > 0 {
+            let generation = bucket.get_generation(0);
+            let record = unsafe { &mut *bucket.get(0) };
+            if generation != current_generation ||
+               data.importance() >= record.data.importance() &&
+               !(is_protected(record, generation) && !data.is_pv()) {
+                if generation == current_generation {
+                    unsafe {
+                        *bucket.get(replace_slot) = *record;
+                    }
+                    bucket.set_generation(replace_slot, current_generation);
+                }
+                replace_slot = 0;
+            }
+        }
+
         // Write the data to the chosen slot.
         unsafe {
             *bucket.get(replace_slot) = Record {
                 key: key,
                 data: data,
+                #[cfg(feature = "tt-verify")]
+                check: check,
             };
-            bucket.set_generation(replace_slot, self.generation.get());
+            bucket.set_generation(replace_slot, self.generation());
+        }
+    }
+
+    #[inline]
+    fn prefetch(&self, key: u64) {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            _mm_prefetch::<_MM_HINT_T0>(self.bucket_ptr(key) as *const i8);
         }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = key;
     }
 
     #[inline]
     fn probe(&self, key: u64) -> Option<Self::Entry> {
         let bucket = self.bucket(key);
+        #[cfg(feature = "tt-verify")]
+        let check = key as u32;
         let key = chop_key(key);
         for slot in 0..Bucket::<Record<T>>::len() {
             if bucket.get_generation(slot) != 0 {
                 let record = unsafe { &mut *bucket.get(slot) };
                 if record.key == key {
-                    bucket.set_generation(slot, self.generation.get());
+                    if !record.data.is_consistent() {
+                        // The record is garbage -- remove it.
+                        bucket.set_generation(slot, 0);
+                        self.rejected_records.fetch_add(1, Ordering::Relaxed);
+                        return None;
+                    }
+                    #[cfg(feature = "tt-verify")]
+                    {
+                        self.verified_probes.fetch_add(1, Ordering::Relaxed);
+                        if record.check != check {
+                            // The record is for another position.
+                            self.key_collisions.fetch_add(1, Ordering::Relaxed);
+                            return None;
+                        }
+                    }
+                    bucket.set_generation(slot, self.generation());
                     return Some(record.data);
                 }
             }
@@ -322,18 +467,73 @@ impl<T: TtableEntry> Ttable for StdTtable<T> {
                 bucket.set_generation(slot, 0);
             }
         }
-        self.generation.set(1);
+        self.generation.store(1, Ordering::Relaxed);
+    }
+
+    fn memory_usage(&self) -> MemoryUsage {
+        let slots = Bucket::<Record<T>>::len();
+        MemoryUsage {
+            bytes: (self.bucket_count + 1) * BUCKET_SIZE,
+            capacity: self.bucket_count * slots,
+            used: self.buckets()
+                .map(|b| (0..slots).filter(|&slot| b.get_generation(slot) != 0).count())
+                .sum(),
+        }
+    }
+
+    fn key_collisions(&self) -> Option<(usize, usize)> {
+        if cfg!(feature = "tt-verify") {
+            Some((self.verified_probes.load(Ordering::Relaxed),
+                  self.key_collisions.load(Ordering::Relaxed)))
+        } else {
+            None
+        }
     }
 }
 
 impl<T: TtableEntry> StdTtable<T> {
+    /// Returns the current replacement policy.
+    pub fn replacement_policy(&self) -> ReplacementPolicy {
+        match self.replacement_policy.load(Ordering::Relaxed) {
+            0 => ReplacementPolicy::LeastImportant,
+            _ => ReplacementPolicy::TwoTier,
+        }
+    }
+
+    /// Returns the number of inconsistent records that have been
+    /// rejected (and removed) by `probe`.
+    pub fn rejected_records(&self) -> usize {
+        self.rejected_records.load(Ordering::Relaxed)
+    }
+
+    /// Sets a new replacement policy.
+    ///
+    /// The default is `ReplacementPolicy::TwoTier`.
+    pub fn set_replacement_policy(&self, policy: ReplacementPolicy) {
+        self.replacement_policy.store(policy as usize, Ordering::Relaxed);
+    }
+
+    /// Returns the current generation number.
+    #[inline]
+    fn generation(&self) -> usize {
+        self.generation.load(Ordering::Relaxed)
+    }
+
     /// Returns the bucket for a given key.
     #[inline]
     fn bucket(&self, key: u64) -> Bucket<Record<T>> {
-        unsafe {
-            let byte_offset = (key as usize & (self.bucket_count - 1)) * BUCKET_SIZE;
-            Bucket::new(self.table_ptr.offset(byte_offset as isize))
-        }
+        unsafe { Bucket::new(self.bucket_ptr(key)) }
+    }
+
+    /// Returns a raw pointer to the beginning of the bucket for a
+    /// given key.
+    ///
+    /// Each bucket occupies exactly one cache line, so prefetching
+    /// the returned address brings in the whole bucket.
+    #[inline]
+    fn bucket_ptr(&self, key: u64) -> *mut c_void {
+        let byte_offset = (key as usize & (self.bucket_count - 1)) * BUCKET_SIZE;
+        unsafe { self.table_ptr.offset(byte_offset as isize) }
     }
 
     /// Returns an iterator over the buckets in the table.
@@ -445,13 +645,16 @@ mod tests {
             *record = Record {
                 key: (0, 0),
                 data: entry,
+                #[cfg(feature = "tt-verify")]
+                check: 0,
             };
             b.set_generation(0, 12);
             b.set_generation(1, 13);
             assert_eq!(record.data.depth(), 10);
             assert_eq!(b.get_generation(0), 12);
             assert_eq!(b.get_generation(1), 13);
-            assert_eq!(Bucket::<Record<StdTtableEntry>>::len(), 5);
+            assert_eq!(Bucket::<Record<StdTtableEntry>>::len(),
+                       if cfg!(feature = "tt-verify") { 3 } else { 5 });
             libc::free(p);
         }
     }
@@ -461,11 +664,14 @@ mod tests {
         unsafe {
             let p = libc::calloc(1, 64);
             let b = Bucket::<Record<StdTtableEntry>>::new(p);
-            let mut record = b.get(4).as_mut().unwrap();
+            let slot = Bucket::<Record<StdTtableEntry>>::len() - 1;
+            let mut record = b.get(slot).as_mut().unwrap();
             let entry = StdTtableEntry::new(0, BOUND_NONE, 10);
             *record = Record {
                 key: (0, 0),
                 data: entry,
+                #[cfg(feature = "tt-verify")]
+                check: 0,
             };
             b.set_generation(0, 12);
             b.set_generation(1, 12);
@@ -477,6 +683,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn depth_limits() {
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let depths = vec![DEPTH_MIN, -1, 0, 63, 64, 95, 96, DEPTH_MAX];
+        for (key, depth) in depths.into_iter().enumerate() {
+            let entry = StdTtableEntry::new(0, BOUND_EXACT, depth);
+            assert_eq!(entry.depth(), depth);
+            assert!(entry.is_consistent());
+            tt.store(key as u64, entry);
+            assert_eq!(tt.probe(key as u64).unwrap().depth(), depth);
+        }
+        assert!(DEPTH_MAX >= 127);
+    }
+
     #[test]
     fn store_and_probe() {
         let tt = StdTtable::<StdTtableEntry>::new(None);
@@ -504,15 +724,256 @@ mod tests {
         assert!(tt.probe(1).is_some());
     }
 
+    #[test]
+    fn pv_protection() {
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let pv_entry = StdTtableEntry::new(0, BOUND_EXACT, 1).set_pv_flag(true);
+        assert!(pv_entry.is_pv());
+        assert_eq!(pv_entry.bound(), BOUND_EXACT);
+        assert!(!pv_entry.set_pv_flag(false).is_pv());
+
+        // All these keys go to the same bucket.
+        tt.store(1 << 32, pv_entry);
+        for i in 2..20 {
+            tt.store(i << 32, StdTtableEntry::new(0, BOUND_LOWER, 10 + i as Depth));
+        }
+        assert!(tt.probe(1 << 32).unwrap().is_pv());
+        tt.store(1 << 32, StdTtableEntry::new(0, BOUND_LOWER, 20));
+        assert!(tt.probe(1 << 32).unwrap().is_pv());
+        tt.store(1 << 32, StdTtableEntry::new(5, BOUND_EXACT, 2).set_pv_flag(true));
+        assert_eq!(tt.probe(1 << 32).unwrap().value(), 5);
+
+        // PV records from previous searches are not protected.
+        tt.new_search();
+        for i in 2..20 {
+            tt.store(i << 32, StdTtableEntry::new(0, BOUND_LOWER, 10 + i as Depth));
+        }
+        assert!(tt.probe(1 << 32).is_none());
+    }
+
+    #[test]
+    fn probe_with_clock() {
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let entry = StdTtableEntry::new(300, BOUND_LOWER, 10)
+            .set_move_digest(MoveDigest::new(0xcc70))
+            .set_static_eval(25)
+            .set_pv_flag(true);
+        tt.store(1, entry);
+        assert_eq!(tt.probe_with_clock(1, 0).unwrap().bound(), BOUND_LOWER);
+        assert_eq!(tt.probe_with_clock(1, 89).unwrap().bound(), BOUND_LOWER);
+
+        // The fifty-move rule is within the search horizon.
+        let e = tt.probe_with_clock(1, 90).unwrap();
+        assert_eq!(e.bound(), BOUND_NONE);
+        assert_eq!(e.depth(), 10);
+        assert_eq!(e.move_digest(), MoveDigest::new(0xcc70));
+        assert_eq!(e.static_eval(), 25);
+        assert!(e.is_pv());
+        assert_eq!(tt.probe(1).unwrap().bound(), BOUND_LOWER);
+        assert!(tt.probe_with_clock(4, 90).is_none());
+
+        // Draws, and bounds that a draw can not violate, are kept.
+        tt.store(2, StdTtableEntry::new(-300, BOUND_EXACT, 10));
+        assert_eq!(tt.probe_with_clock(2, 90).unwrap().bound(), BOUND_LOWER);
+        tt.store(3, StdTtableEntry::new(0, BOUND_EXACT, 10));
+        assert_eq!(tt.probe_with_clock(3, 99).unwrap().bound(), BOUND_EXACT);
+    }
+
+    #[test]
+    fn replacement_policy() {
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        assert_eq!(tt.replacement_policy(), ReplacementPolicy::TwoTier);
+
+        // The deepest record survives a flood of shallow records.
+        tt.store(1 << 32, StdTtableEntry::new(0, BOUND_LOWER, 30));
+        for i in 2..50 {
+            tt.store(i << 32, StdTtableEntry::new(0, BOUND_LOWER, 1));
+        }
+        assert_eq!(tt.probe(1 << 32).unwrap().depth(), 30);
+
+        // A deeper record takes the first slot, and the evicted
+        // record goes to the second tier.
+        tt.store(50 << 32, StdTtableEntry::new(0, BOUND_LOWER, 40));
+        assert_eq!(tt.probe(50 << 32).unwrap().depth(), 40);
+        assert_eq!(tt.probe(1 << 32).unwrap().depth(), 30);
+        tt.store(51 << 32, StdTtableEntry::new(0, BOUND_LOWER, 1));
+        assert!(tt.probe(50 << 32).is_some());
+
+        // Deep records from previous searches do not crowd out fresh data.
+        tt.new_search();
+        tt.store(52 << 32, StdTtableEntry::new(0, BOUND_LOWER, 1));
+        assert!(tt.probe(52 << 32).is_some());
+
+        // With the other policy, the least important record is replaced.
+        tt.clear();
+        tt.set_replacement_policy(ReplacementPolicy::LeastImportant);
+        for i in 1..20 {
+            tt.store(i << 32, StdTtableEntry::new(0, BOUND_LOWER, i as Depth));
+        }
+        assert!(tt.probe(1 << 32).is_none());
+        assert!(tt.probe(19 << 32).is_some());
+    }
+
+    #[test]
+    fn inconsistent_records() {
+        use rand::{Rng, thread_rng};
+        let mut rng = thread_rng();
+
+        // The table has only one bucket.
+        let tt = StdTtable::<StdTtableEntry>::new(Some(0));
+        tt.store(1, StdTtableEntry::new(0, BOUND_EXACT, 5).set_static_eval(VALUE_MAX));
+        assert!(tt.probe(1).is_none());
+        assert_eq!(tt.rejected_records(), 1);
+        assert!(tt.probe(1).is_none());
+        assert_eq!(tt.rejected_records(), 1);
+
+        // A record with a corrupted move digest (castling e1e4).
+        let digest = MoveDigest::new(2 << 14 | 4 << 8 | 28 << 2);
+        tt.store(2, StdTtableEntry::new(0, BOUND_EXACT, 5).set_move_digest(digest));
+        assert!(tt.probe(2).is_none());
+        assert_eq!(tt.rejected_records(), 2);
+
+        // Only consistent records are returned.
+        for i in 0..10000 {
+            let key = rng.gen_range(0, 8) << 32;
+            let static_eval = if i % 10 == 0 {
+                VALUE_EVAL_MAX + 1
+            } else {
+                rng.gen_range(VALUE_EVAL_MIN, VALUE_EVAL_MAX)
+            };
+            let bound = rng.gen_range(0, 4);
+            let depth = rng.gen_range(DEPTH_MIN, DEPTH_MAX);
+            tt.store(key,
+                     StdTtableEntry::new(rng.gen_range(VALUE_MIN, VALUE_MAX), bound, depth)
+                         .set_static_eval(static_eval));
+            if let Some(e) = tt.probe(key) {
+                assert!(e.is_consistent());
+            }
+        }
+        assert!(tt.rejected_records() > 2);
+    }
+
     #[test]
     fn new_search() {
         let tt = StdTtable::<StdTtableEntry>::new(None);
-        assert_eq!(tt.generation.get(), 1);
+        assert_eq!(tt.generation(), 1);
         tt.new_search();
-        assert_eq!(tt.generation.get(), 2);
+        assert_eq!(tt.generation(), 2);
         for _ in 3..34 {
             tt.new_search();
         }
-        assert_eq!(tt.generation.get(), 2);
+        assert_eq!(tt.generation(), 2);
+    }
+
+    #[test]
+    fn memory_usage() {
+        let tt = StdTtable::<StdTtableEntry>::new(Some(1));
+        let usage = tt.memory_usage();
+        assert!(usage.bytes > 1024 * 1024);
+        assert_eq!(usage.capacity, 1024 * 1024 / 64 * Bucket::<Record<StdTtableEntry>>::len());
+        assert_eq!(usage.used, 0);
+        for i in 0..100 {
+            tt.store(i << 32 | i, StdTtableEntry::new(0, 0, 1));
+        }
+        assert_eq!(tt.memory_usage().used, 100);
+        assert_eq!((usage + tt.memory_usage()).used, 100);
+        tt.clear();
+        assert_eq!(tt.memory_usage().used, 0);
+    }
+
+    #[test]
+    fn key_collisions() {
+        let tt = StdTtable::<StdTtableEntry>::new(Some(1));
+        tt.store(1, StdTtableEntry::new(0, 0, 1));
+        assert!(tt.probe(1).is_some());
+
+        // The same bucket and the same highest 32 bits.
+        let other_key = 1 | 1 << 30;
+        if cfg!(feature = "tt-verify") {
+            assert!(tt.probe(other_key).is_none());
+            assert_eq!(tt.key_collisions(), Some((2, 1)));
+        } else {
+            assert!(tt.probe(other_key).is_some());
+            assert_eq!(tt.key_collisions(), None);
+        }
+    }
+
+    #[test]
+    fn bucket_alignment() {
+        let tt = StdTtable::<StdTtableEntry>::new(Some(1));
+        for key in 0..100u64 {
+            let addr = tt.bucket_ptr(key.wrapping_mul(0x9e3779b97f4a7c15)) as usize;
+            assert_eq!(addr % BUCKET_SIZE, 0);
+            assert!(addr >= tt.table_ptr as usize);
+            assert!(addr < tt.table_ptr as usize + tt.bucket_count * BUCKET_SIZE);
+        }
+        tt.prefetch(1);
+        tt.store(1, StdTtableEntry::new(0, 0, 1));
+        tt.prefetch(1);
+        assert!(tt.probe(1).is_some());
+    }
+
+    #[test]
+    fn extract_variations() {
+        use search_node::SearchNode;
+        use stock::{DefaultSearchNode, SimpleEvaluator};
+
+        let tt = StdTtable::<StdTtableEntry>::new(None);
+        let fen = "8/8/8/8/3q3k/7n/6PP/2Q2R1K b - - 0 1";
+        let mut p = DefaultSearchNode::<SimpleEvaluator>::from_history(fen,
+                                                                       &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let moves = p.legal_moves();
+        let entry = StdTtableEntry::new(20, BOUND_EXACT, 5).set_move_digest(moves[0].digest());
+        tt.store(p.hash(), entry);
+        for &(i, value, depth) in [(0, -20, 4), (1, -10, 4), (2, 5, 4), (3, -50, 1)].iter() {
+            assert!(p.do_move(moves[i]));
+            tt.store(p.hash(), StdTtableEntry::new(value, BOUND_EXACT, depth));
+            p.undo_last_move();
+        }
+        let variations = tt.extract_variations(&p, 5, 10);
+        assert_eq!(variations.len(), 3);
+        for (v, &(i, value)) in variations.iter().zip([(0, 20), (1, 10), (2, -5)].iter()) {
+            assert_eq!(v.moves, vec![moves[i]]);
+            assert_eq!(v.value, value);
+            assert_eq!(v.bound, BOUND_EXACT);
+        }
+        assert_eq!(tt.extract_variations(&p, 5, 2).len(), 2);
+        assert_eq!(tt.extract_variations(&p, 2, 10).len(), 4);
+        assert!(tt.extract_variations(&p, 5, 0).is_empty());
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        // With a 1 MB table the lowest 14 bits of the key select the
+        // bucket, so these keys can not collide.
+        let key = |i: u64| i << 32 | i & 0x3fff;
+        let tt = Arc::new(StdTtable::<StdTtableEntry>::new(Some(1)));
+        let threads: Vec<_> = (0..4u64)
+            .map(|t| {
+                let tt = tt.clone();
+                thread::spawn(move || for n in 0..20000u64 {
+                    let i = (n * 7 + t * 5003) % 20000;
+                    let data = StdTtableEntry::new((i % 1000) as Value, 0, (i % 100) as Depth);
+                    tt.store(key(i), data);
+                    if let Some(e) = tt.probe(key((i * 13) % 20000)) {
+                        assert_eq!(e.value(), ((i * 13) % 20000 % 1000) as Value);
+                    }
+                })
+            })
+            .collect();
+        for handle in threads {
+            handle.join().unwrap();
+        }
+        for i in 0..20000 {
+            if let Some(e) = tt.probe(key(i)) {
+                assert_eq!(e.value(), (i % 1000) as Value);
+                assert_eq!(e.depth(), (i % 100) as Depth);
+            }
+        }
     }
 }
@@ -10,8 +10,14 @@ use moves::MoveDigest;
 #[derive(Copy, Clone, Debug)]
 pub struct StdTtableEntry {
     value: Value,
-    bound: BoundType,
-    depth: Depth,
+
+    // The lowest 2 bits hold the bound type, the third bit holds the
+    // PV flag.
+    flags: u8,
+
+    // The depth is stored as an offset from `DEPTH_MIN`, so that it
+    // fits in one byte.
+    depth: u8,
     move_digest: MoveDigest,
     static_eval: Value,
 }
@@ -24,8 +30,8 @@ impl TtableEntry for StdTtableEntry {
         debug_assert!(DEPTH_MIN <= depth && depth <= DEPTH_MAX);
         StdTtableEntry {
             value: value,
-            bound: bound,
-            depth: depth,
+            flags: bound,
+            depth: (depth - DEPTH_MIN) as u8,
             move_digest: MoveDigest::invalid(),
             static_eval: VALUE_UNKNOWN,
         }
@@ -38,12 +44,12 @@ impl TtableEntry for StdTtableEntry {
 
     #[inline]
     fn bound(&self) -> BoundType {
-        self.bound
+        self.flags & BOUND_EXACT
     }
 
     #[inline]
     fn depth(&self) -> Depth {
-        self.depth
+        self.depth as Depth + DEPTH_MIN
     }
 
     #[inline]
@@ -73,4 +79,27 @@ impl TtableEntry for StdTtableEntry {
             ..self
         }
     }
+
+    /// Consumes the instance and returns a new instance with updated
+    /// PV flag.
+    #[inline]
+    fn set_pv_flag(self, is_pv: bool) -> Self {
+        Self {
+            flags: if is_pv {
+                self.flags | PV_FLAG
+            } else {
+                self.flags & !PV_FLAG
+            },
+            ..self
+        }
+    }
+
+    #[inline]
+    fn is_pv(&self) -> bool {
+        self.flags & PV_FLAG != 0
+    }
 }
+
+
+/// The bit in `StdTtableEntry::flags` that holds the PV flag.
+const PV_FLAG: u8 = 0b100;
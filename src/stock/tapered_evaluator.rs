@@ -0,0 +1,370 @@
+//! Implements `TaperedEvaluator` and `TaperedMaterial`.
+
+use uci::{SetOption, OptionDescription};
+use moves::*;
+use board::*;
+use value::*;
+use evaluator::*;
+use bitsets::*;
+
+
+/// An evaluator that interpolates the middlegame and endgame scores
+/// of a tapered evaluation term by the current game phase.
+///
+/// The game phase is updated incrementally on each played and taken
+/// back move.
+///
+/// Before the interpolation, the endgame score is scaled down in
+/// endgames that are hard to win even with extra material (see
+/// `endgame_scale`).
+#[derive(Clone)]
+pub struct TaperedEvaluator<T: TaperedTerm> {
+    term: T,
+
+    // The game phase, not capped at `PHASE_MAX`. (Promotions may
+    // bring more pieces to the board than there were initially.)
+    phase: i16,
+}
+
+impl<T: TaperedTerm> TaperedEvaluator<T> {
+    /// Returns the current game phase.
+    ///
+    /// The returned value is between `0` (a pure endgame) and
+    /// `PHASE_MAX` (a full-material middlegame).
+    #[inline]
+    pub fn phase(&self) -> u8 {
+        if self.phase > PHASE_MAX as i16 {
+            PHASE_MAX
+        } else {
+            self.phase as u8
+        }
+    }
+
+    /// Returns a reference to the evaluation term.
+    #[inline]
+    pub fn term(&self) -> &T {
+        &self.term
+    }
+}
+
+impl<T: TaperedTerm> SetOption for TaperedEvaluator<T> {
+    fn options() -> Vec<(&'static str, OptionDescription)> {
+        T::options()
+    }
+
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        T::set_option(name, value)
+    }
+}
+
+impl<T: TaperedTerm> Evaluator for TaperedEvaluator<T> {
+    fn new(position: &Board) -> TaperedEvaluator<T> {
+        let mut phase = 0;
+        for piece in QUEEN..PAWN {
+            phase += PHASE_WEIGHTS[piece] as i16 *
+                     pop_count(position.pieces.piece_type[piece]) as i16;
+        }
+        TaperedEvaluator {
+            term: T::new(position),
+            phase: phase,
+        }
+    }
+
+    #[inline]
+    fn evaluate(&self, position: &Board) -> Value {
+        let score = self.term.score(position);
+        scale(score, endgame_scale(position, score)).interpolate(self.phase())
+    }
+
+    fn evaluate_explained(&self, position: &Board) -> Vec<EvalTerm> {
+        let phase = self.phase();
+        let factor = endgame_scale(position, self.term.score(position));
+        self.term
+            .explain(position)
+            .into_iter()
+            .map(|(name, white, black)| {
+                     EvalTerm {
+                         name: name,
+                         white: scale(white, factor).interpolate(phase),
+                         black: scale(black, factor).interpolate(phase),
+                     }
+                 })
+            .collect()
+    }
+
+    #[inline]
+    fn is_zugzwangy(&self, position: &Board) -> bool {
+        // The side to move has only its king and pawns.
+        let pieces = position.pieces.color[position.to_move] &
+                     !(position.pieces.piece_type[KING] | position.pieces.piece_type[PAWN]);
+        pieces == 0
+    }
+
+    fn export(kind: &str) -> Option<String> {
+        T::export(kind)
+    }
+
+    #[inline]
+    fn will_do_move(&mut self, position: &Board, m: Move) {
+        self.term.will_do_move(position, m);
+    }
+
+    #[inline]
+    fn done_move(&mut self, position: &Board, m: Move) {
+        self.phase += game_phase_delta(m);
+        self.term.done_move(position, m);
+    }
+
+    #[inline]
+    fn will_undo_move(&mut self, position: &Board, m: Move) {
+        self.term.will_undo_move(position, m);
+    }
+
+    #[inline]
+    fn undone_move(&mut self, position: &Board, m: Move) {
+        self.phase -= game_phase_delta(m);
+        self.term.undone_move(position, m);
+    }
+}
+
+
+/// A tapered evaluation term that counts the available material.
+#[derive(Clone)]
+pub struct TaperedMaterial {
+    material: TaperedScore,
+}
+
+impl SetOption for TaperedMaterial {}
+
+impl TaperedTerm for TaperedMaterial {
+    fn new(position: &Board) -> TaperedMaterial {
+        let us = position.to_move;
+        let them = 1 ^ us;
+        let color = position.pieces.color;
+        let mut material = TaperedScore::default();
+        for piece in QUEEN..PIECE_NONE {
+            let occupied = position.pieces.piece_type[piece];
+            let count_us = pop_count(occupied & color[us]) as i32;
+            let count_them = pop_count(occupied & color[them]) as i32;
+            material += TaperedScore::new(MG_PIECE_VALUES[piece] * (count_us - count_them),
+                                          EG_PIECE_VALUES[piece] * (count_us - count_them));
+        }
+        TaperedMaterial { material: material }
+    }
+
+    #[inline]
+    fn score(&self, _: &Board) -> TaperedScore {
+        self.material
+    }
+
+    fn explain(&self, position: &Board) -> Vec<(&'static str, TaperedScore, TaperedScore)> {
+        let material = |color: Color| {
+            let mut score = TaperedScore::default();
+            for piece in QUEEN..PIECE_NONE {
+                let count = pop_count(position.pieces.piece_type[piece] &
+                                      position.pieces.color[color]) as i32;
+                score += TaperedScore::new(MG_PIECE_VALUES[piece] * count,
+                                           EG_PIECE_VALUES[piece] * count);
+            }
+            score
+        };
+        vec![("Material", material(WHITE), material(BLACK))]
+    }
+
+    #[inline]
+    fn done_move(&mut self, _: &Board, m: Move) {
+        self.material = -(self.material + gained_material(m));
+    }
+
+    #[inline]
+    fn undone_move(&mut self, _: &Board, m: Move) {
+        self.material = -self.material - gained_material(m);
+    }
+}
+
+
+/// The endgame scale factor that leaves the endgame score unchanged.
+pub const ENDGAME_SCALE_MAX: i32 = 64;
+
+
+/// Returns the factor by which the endgame score should be scaled.
+///
+/// `score` is the unscaled score, from the point of view of the side
+/// to move. Its sign tells which side is stronger. The returned value
+/// is between `0` and `ENDGAME_SCALE_MAX`. The following endgames are
+/// scaled down:
+///
+/// * Bishops of opposite colors, with no other pieces. Such endgames
+///   are often drawn even with two extra pawns.
+///
+/// * The stronger side has no pawns, and its extra material is worth
+///   less than a rook. (One extra minor piece is not enough to give
+///   mate.) A lone queen against a rook or a minor piece is an
+///   exception -- it usually wins.
+pub fn endgame_scale(position: &Board, score: TaperedScore) -> i32 {
+    let stronger = if score.eg >= 0 {
+        position.to_move
+    } else {
+        1 ^ position.to_move
+    };
+    let pieces = &position.pieces;
+    let pawns = pieces.piece_type[PAWN];
+    let bishops = pieces.piece_type[BISHOP];
+    if pieces.color[stronger] & pawns == 0 {
+        let material = |color: Color| {
+            (QUEEN..PAWN)
+                .map(|p| {
+                         SCALING_PIECE_UNITS[p] *
+                         pop_count(pieces.piece_type[p] & pieces.color[color]) as i32
+                     })
+                .sum::<i32>()
+        };
+        let queen_wins = pieces.piece_type[QUEEN] & pieces.color[stronger] != 0 &&
+                         material(1 ^ stronger) <= SCALING_PIECE_UNITS[ROOK];
+        if !queen_wins &&
+           material(stronger) - material(1 ^ stronger) < SCALING_PIECE_UNITS[ROOK] {
+            return ENDGAME_SCALE_MAX / 8;
+        }
+    }
+    let others = pieces.piece_type[QUEEN] | pieces.piece_type[ROOK] | pieces.piece_type[KNIGHT];
+    if others == 0 && pop_count(bishops & pieces.color[WHITE]) == 1 &&
+       pop_count(bishops & pieces.color[BLACK]) == 1 &&
+       pop_count(bishops & BB_DARK_SQUARES) == 1 {
+        return ENDGAME_SCALE_MAX / 2;
+    }
+    ENDGAME_SCALE_MAX
+}
+
+
+/// Rough piece values (in pawns) used to decide if the extra material
+/// is enough to win.
+const SCALING_PIECE_UNITS: [i32; 8] = [0, 9, 5, 3, 3, 1, 0, 0];
+
+
+#[inline]
+fn scale(score: TaperedScore, factor: i32) -> TaperedScore {
+    TaperedScore::new(score.mg, score.eg * factor / ENDGAME_SCALE_MAX)
+}
+
+
+const MG_PIECE_VALUES: [i32; 8] = [0, 1025, 477, 365, 337, 82, 0, 0];
+
+const EG_PIECE_VALUES: [i32; 8] = [0, 936, 512, 297, 281, 94, 0, 0];
+
+
+#[inline]
+fn gained_material(m: Move) -> TaperedScore {
+    let captured = m.captured_piece();
+    let mut gained = TaperedScore::new(MG_PIECE_VALUES[captured], EG_PIECE_VALUES[captured]);
+    if m.move_type() == MOVE_PROMOTION {
+        let promoted = Move::piece_from_aux_data(m.aux_data());
+        gained += TaperedScore::new(MG_PIECE_VALUES[promoted] - MG_PIECE_VALUES[PAWN],
+                                    EG_PIECE_VALUES[promoted] - EG_PIECE_VALUES[PAWN]);
+    }
+    gained
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use move_generator::*;
+    use stock::StdMoveGenerator;
+    use utils::MoveStack;
+
+    type E = TaperedEvaluator<TaperedMaterial>;
+
+    #[test]
+    fn interpolate() {
+        let s = TaperedScore::new(100, 200);
+        assert_eq!(s.interpolate(PHASE_MAX), 100);
+        assert_eq!(s.interpolate(0), 200);
+        assert_eq!(s.interpolate(PHASE_MAX / 2), 150);
+        assert_eq!(s.interpolate(PHASE_MAX + 10), 100);
+        assert_eq!(TaperedScore::new(100000, 100000).interpolate(0), VALUE_EVAL_MAX);
+        assert_eq!(-s + s, TaperedScore::default());
+    }
+
+    #[test]
+    fn phase() {
+        let b = Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+            .ok()
+            .unwrap();
+        assert_eq!(game_phase(&b), PHASE_MAX);
+        assert_eq!(E::new(&b).phase(), PHASE_MAX);
+        let b = Board::from_fen("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1").ok().unwrap();
+        assert_eq!(game_phase(&b), 0);
+        assert!(E::new(&b).is_zugzwangy(&b));
+        let b = Board::from_fen("4k3/r7/8/8/8/8/8/1N2K3 w - - 0 1").ok().unwrap();
+        assert_eq!(game_phase(&b), 3);
+    }
+
+    #[test]
+    fn evaluate_explained() {
+        use stock::TaperedPst;
+        type F = TaperedEvaluator<(TaperedMaterial, TaperedPst)>;
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "4k3/r7/8/8/8/8/8/1N2K3 b - - 0 1",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"]
+                    .iter() {
+            let b = Board::from_fen(fen).ok().unwrap();
+            let e = F::new(&b);
+            let terms = e.evaluate_explained(&b);
+            assert_eq!(terms.iter().map(|t| t.name).collect::<Vec<_>>(),
+                       vec!["Material", "Piece-Square"]);
+            let total: i32 = terms.iter().map(|t| t.white as i32 - t.black as i32).sum();
+            let v = e.evaluate(&b) as i32;
+            let v = if b.to_move == WHITE { v } else { -v };
+            assert!((total - v).abs() <= 2, "{}: {} != {}", fen, total, v);
+        }
+        let b = Board::from_fen("4k3/r7/8/8/8/8/8/1N2K3 b - - 0 1").ok().unwrap();
+        let terms = E::new(&b).evaluate_explained(&b);
+        assert!(terms[0].black > terms[0].white);
+    }
+
+    #[test]
+    fn endgame_scale() {
+        let s = TaperedScore::new(0, 100);
+        for &(fen, scale) in [("4k3/8/8/8/8/8/8/4K3 w - - 0 1", 8),
+                              ("4kb2/8/8/8/8/8/PP6/2B1K3 w - - 0 1", 64),
+                              ("4k1b1/8/8/8/8/8/PP6/2B1K3 w - - 0 1", 32),
+                              ("4k1b1/8/8/8/8/8/PP6/2B1K2R w - - 0 1", 64),
+                              ("4k3/8/8/8/8/8/P7/4K3 w - - 0 1", 64),
+                              ("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", 8),
+                              ("4k3/8/8/8/8/8/8/2BNK3 w - - 0 1", 64),
+                              ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", 64),
+                              ("4k3/7b/8/8/8/8/8/R3K3 w - - 0 1", 8),
+                              ("4k3/7r/8/8/8/8/8/Q3K3 w - - 0 1", 64),
+                              ("4k3/7n/8/8/8/8/8/Q3K3 w - - 0 1", 64),
+                              ("4k3/7q/8/8/8/8/8/Q3K3 w - - 0 1", 8)]
+                                 .iter() {
+            let b = Board::from_fen(fen).ok().unwrap();
+            assert_eq!(super::endgame_scale(&b, s), scale, "{}", fen);
+        }
+        let b = Board::from_fen("4k1b1/8/8/8/8/8/PP6/2B1K3 w - - 0 1").ok().unwrap();
+        let e = E::new(&b);
+        let score = e.term().score(&b);
+        assert!(score.eg > 0);
+        assert_eq!(e.evaluate(&b), scale(score, 32).interpolate(e.phase()));
+    }
+
+    #[test]
+    fn incremental_update() {
+        let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        let mut g = StdMoveGenerator::<E>::from_board(Board::from_fen(fen).ok().unwrap())
+            .ok()
+            .unwrap();
+        let mut s = MoveStack::new();
+        g.generate_all(&mut s);
+        while let Some(m) = s.pop() {
+            if g.do_move(m).is_some() {
+                let e = E::new(g.board());
+                assert_eq!(g.evaluator().phase(), e.phase());
+                assert_eq!(g.evaluator().evaluate(g.board()), e.evaluate(g.board()));
+                g.undo_move(m);
+            }
+        }
+        let e = E::new(g.board());
+        assert_eq!(g.evaluator().evaluate(g.board()), e.evaluate(g.board()));
+    }
+}
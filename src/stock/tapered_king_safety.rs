@@ -0,0 +1,187 @@
+//! Implements `TaperedKingSafety`.
+
+use std::cmp::min;
+use std::sync::atomic::AtomicIsize;
+use uci::{SetOption, OptionDescription};
+use board::*;
+use bitsets::*;
+use evaluator::*;
+use utils::{BoardGeometry, Tunable};
+
+
+/// A tapered evaluation term that penalizes attacks on the king.
+///
+/// The king zone consists of the king's square and the squares
+/// adjacent to it. Each enemy piece (other than the king and pawns)
+/// that attacks squares in the king zone adds its attack weight for
+/// each attacked square to the danger. When at least two pieces take
+/// part in the attack, the penalty grows with the square of the
+/// danger, so that coordinated attacks are penalized much more than
+/// lone raids. The penalty is applied only to the middlegame score,
+/// because in the endgame the king is rather an attacker than a
+/// target.
+///
+/// The attack weights and the penalty scale can be tuned at runtime
+/// (see `Tunable`).
+#[derive(Clone)]
+pub struct TaperedKingSafety;
+
+impl SetOption for TaperedKingSafety {
+    fn options() -> Vec<(&'static str, OptionDescription)> {
+        Tunable::options(&tunable_params())
+    }
+
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        Tunable::set_option(&tunable_params(), name, value);
+        Ok(())
+    }
+}
+
+impl TaperedTerm for TaperedKingSafety {
+    fn new(_: &Board) -> TaperedKingSafety {
+        TaperedKingSafety
+    }
+
+    #[inline]
+    fn score(&self, position: &Board) -> TaperedScore {
+        let us = position.to_move;
+        TaperedScore::new(king_danger(position, 1 ^ us) - king_danger(position, us), 0)
+    }
+
+    fn explain(&self, position: &Board) -> Vec<(&'static str, TaperedScore, TaperedScore)> {
+        vec![("King Safety",
+              TaperedScore::new(-king_danger(position, WHITE), 0),
+              TaperedScore::new(-king_danger(position, BLACK), 0))]
+    }
+}
+
+
+/// The maximal penalty for an attacked king.
+const KING_DANGER_MAX: i32 = 500;
+
+
+/// The weight of a queen attack on a king zone square.
+static QUEEN_ATTACK_WEIGHT: Tunable = Tunable {
+    name: "Tune Queen Attack Weight",
+    min: 0,
+    max: 20,
+    default: 5,
+    value: AtomicIsize::new(5),
+};
+
+
+/// The weight of a rook attack on a king zone square.
+static ROOK_ATTACK_WEIGHT: Tunable = Tunable {
+    name: "Tune Rook Attack Weight",
+    min: 0,
+    max: 20,
+    default: 3,
+    value: AtomicIsize::new(3),
+};
+
+
+/// The weight of a bishop attack on a king zone square.
+static BISHOP_ATTACK_WEIGHT: Tunable = Tunable {
+    name: "Tune Bishop Attack Weight",
+    min: 0,
+    max: 20,
+    default: 2,
+    value: AtomicIsize::new(2),
+};
+
+
+/// The weight of a knight attack on a king zone square.
+static KNIGHT_ATTACK_WEIGHT: Tunable = Tunable {
+    name: "Tune Knight Attack Weight",
+    min: 0,
+    max: 20,
+    default: 2,
+    value: AtomicIsize::new(2),
+};
+
+
+/// The penalty for an attacked king is the square of the danger,
+/// multiplied by this number, and divided by 256.
+static KING_DANGER_SCALE: Tunable = Tunable {
+    name: "Tune King Danger Scale",
+    min: 0,
+    max: 256,
+    default: 32,
+    value: AtomicIsize::new(32),
+};
+
+
+/// Returns the evaluation parameters that can be tuned at runtime.
+fn tunable_params() -> [&'static Tunable; 5] {
+    [&QUEEN_ATTACK_WEIGHT,
+     &ROOK_ATTACK_WEIGHT,
+     &BISHOP_ATTACK_WEIGHT,
+     &KNIGHT_ATTACK_WEIGHT,
+     &KING_DANGER_SCALE]
+}
+
+
+/// A helper function. It returns the penalty for the attacks on the
+/// king of a given color.
+fn king_danger(position: &Board, color: Color) -> i32 {
+    let geometry = BoardGeometry::get();
+    let king = position.pieces.color[color] & position.pieces.piece_type[KING];
+    if king == 0 {
+        return 0;
+    }
+    let king_square = bsf(king);
+    let zone = king | geometry.attacks_from(KING, king_square, 0);
+    let weights = [0,
+                   QUEEN_ATTACK_WEIGHT.get(),
+                   ROOK_ATTACK_WEIGHT.get(),
+                   BISHOP_ATTACK_WEIGHT.get(),
+                   KNIGHT_ATTACK_WEIGHT.get()];
+    let mut attackers = 0;
+    let mut danger = 0;
+    for (piece, &weight) in weights.iter().enumerate().skip(QUEEN) {
+        for square in position.pieces_of(1 ^ color, piece) {
+            let attacks = geometry.attacks_from(piece, square, position.occupied) & zone;
+            if attacks != 0 {
+                attackers += 1;
+                danger += weight * pop_count(attacks) as isize;
+            }
+        }
+    }
+    if attackers < 2 {
+        return 0;
+    }
+    min(danger * danger * KING_DANGER_SCALE.get() / 256,
+        KING_DANGER_MAX as isize) as i32
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stock::{TaperedEvaluator, TaperedMaterial};
+
+    type E = TaperedEvaluator<(TaperedMaterial, TaperedKingSafety)>;
+
+    #[test]
+    fn king_danger() {
+        // A lone attacker is not dangerous.
+        let b = Board::from_fen("6k1/5ppp/8/7Q/8/8/5PPP/6K1 w - - 0 1").ok().unwrap();
+        assert_eq!(super::king_danger(&b, BLACK), 0);
+
+        // A queen and a knight are.
+        let b = Board::from_fen("6k1/5ppp/8/6NQ/8/8/5PPP/6K1 w - - 0 1").ok().unwrap();
+        let d = super::king_danger(&b, BLACK);
+        assert!(d > 0);
+        assert_eq!(super::king_danger(&b, WHITE), 0);
+        let e = E::new(&b);
+        let terms = e.evaluate_explained(&b);
+        assert_eq!(terms[1].name, "King Safety");
+        assert_eq!(terms[1].black, -TaperedScore::new(d, 0).interpolate(e.phase()));
+
+        // The score is symmetric.
+        let m = Board::from_fen("6k1/5ppp/8/8/6nq/8/5PPP/6K1 b - - 0 1").ok().unwrap();
+        assert_eq!(E::new(&b).evaluate(&b), E::new(&m).evaluate(&m));
+        let b_black = Board::from_fen("6k1/5ppp/8/6NQ/8/8/5PPP/6K1 b - - 0 1").ok().unwrap();
+        assert_eq!(E::new(&b).evaluate(&b), -E::new(&b_black).evaluate(&b_black));
+    }
+}
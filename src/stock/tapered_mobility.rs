@@ -0,0 +1,99 @@
+//! Implements `TaperedMobility`.
+
+use uci::SetOption;
+use board::*;
+use bitsets::*;
+use evaluator::*;
+use utils::BoardGeometry;
+
+
+/// A tapered evaluation term that rewards mobile pieces.
+///
+/// The mobility of a piece (other than the king and pawns) is the
+/// number of its safe destination squares -- the squares that it
+/// attacks, which are not occupied by friendly pieces, and are not
+/// attacked by enemy pawns. Each piece gets a bonus for each safe
+/// square above a baseline, and a penalty for each safe square below
+/// it. Bishops and knights profit most from being mobile in the
+/// middlegame, rooks and queens -- in the endgame.
+#[derive(Clone)]
+pub struct TaperedMobility;
+
+impl SetOption for TaperedMobility {}
+
+impl TaperedTerm for TaperedMobility {
+    fn new(_: &Board) -> TaperedMobility {
+        TaperedMobility
+    }
+
+    #[inline]
+    fn score(&self, position: &Board) -> TaperedScore {
+        let us = position.to_move;
+        mobility_score(position, us) - mobility_score(position, 1 ^ us)
+    }
+
+    fn explain(&self, position: &Board) -> Vec<(&'static str, TaperedScore, TaperedScore)> {
+        vec![("Mobility",
+              mobility_score(position, WHITE),
+              mobility_score(position, BLACK))]
+    }
+}
+
+
+/// The middlegame and endgame bonuses for each safe square, indexed
+/// by piece type.
+const MOBILITY_WEIGHT: [(i32, i32); 6] = [(0, 0), (1, 2), (2, 4), (5, 5), (4, 4), (0, 0)];
+
+/// The number of safe squares for which a piece gets neither a bonus
+/// nor a penalty, indexed by piece type.
+const MOBILITY_BASELINE: [i32; 6] = [0, 14, 7, 7, 4, 0];
+
+
+/// A helper function. It returns the mobility score for the pieces
+/// of a given color, from this color's point of view.
+fn mobility_score(position: &Board, color: Color) -> TaperedScore {
+    let geometry = BoardGeometry::get();
+    let safe_squares = !position.pieces.color[color] & !position.pawn_attacks(1 ^ color);
+    let mut score = TaperedScore::default();
+    for piece in QUEEN..PAWN {
+        let (mg, eg) = MOBILITY_WEIGHT[piece];
+        for square in position.pieces_of(color, piece) {
+            let attacks = geometry.attacks_from(piece, square, position.occupied);
+            let mobility = pop_count(attacks & safe_squares) as i32 - MOBILITY_BASELINE[piece];
+            score += TaperedScore::new(mg * mobility, eg * mobility);
+        }
+    }
+    score
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stock::{TaperedEvaluator, TaperedMaterial};
+
+    type E = TaperedEvaluator<(TaperedMaterial, TaperedMobility)>;
+
+    #[test]
+    fn mobility() {
+        // The knight on "d4" has 8 safe squares, on "a1" -- only 2.
+        let b = Board::from_fen("4k3/8/8/8/3N4/8/8/N3K3 w - - 0 1").ok().unwrap();
+        let (mg, eg) = MOBILITY_WEIGHT[KNIGHT];
+        assert_eq!(mobility_score(&b, WHITE),
+                   TaperedScore::new(mg * (8 + 2 - 8), eg * (8 + 2 - 8)));
+
+        // Squares attacked by enemy pawns are not safe.
+        let p = Board::from_fen("4k3/8/2p1p3/8/3N4/8/8/N3K3 w - - 0 1").ok().unwrap();
+        assert!(mobility_score(&p, WHITE).mg < mobility_score(&b, WHITE).mg);
+        assert_eq!(mobility_score(&p, WHITE),
+                   TaperedScore::new(mg * (6 + 2 - 8), eg * (6 + 2 - 8)));
+
+        // The score is symmetric.
+        let m = Board::from_fen("n3k3/8/8/3n4/8/8/8/4K3 b - - 0 1").ok().unwrap();
+        assert_eq!(E::new(&b).evaluate(&b), E::new(&m).evaluate(&m));
+        let e = E::new(&b);
+        let terms = e.evaluate_explained(&b);
+        assert_eq!(terms[1].name, "Mobility");
+        assert_eq!(terms[1].black, 0);
+    }
+}
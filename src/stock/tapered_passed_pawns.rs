@@ -0,0 +1,149 @@
+//! Implements `TaperedPassedPawns`.
+
+use std::cmp::max;
+use uci::SetOption;
+use board::*;
+use bitsets::*;
+use evaluator::*;
+use utils::BoardGeometry;
+
+
+/// A tapered evaluation term that rewards passed pawns.
+///
+/// A pawn is passed when no enemy pawn can stop it from advancing --
+/// that is, there are no enemy pawns in front of it, on its own file
+/// or on the adjacent files. Passed pawns get a bonus that grows
+/// rapidly with their rank. The bonus is reduced when the square in
+/// front of the pawn is occupied by an enemy piece (the pawn is
+/// blockaded), and increased when the pawn is defended by a friendly
+/// pawn. In the endgame, the bonus is also adjusted by how close the
+/// kings are to the square in front of the pawn -- the enemy king
+/// should be far, our king should be near.
+#[derive(Clone)]
+pub struct TaperedPassedPawns;
+
+impl SetOption for TaperedPassedPawns {}
+
+impl TaperedTerm for TaperedPassedPawns {
+    fn new(_: &Board) -> TaperedPassedPawns {
+        TaperedPassedPawns
+    }
+
+    #[inline]
+    fn score(&self, position: &Board) -> TaperedScore {
+        let us = position.to_move;
+        passed_pawns_score(position, us) - passed_pawns_score(position, 1 ^ us)
+    }
+
+    fn explain(&self, position: &Board) -> Vec<(&'static str, TaperedScore, TaperedScore)> {
+        vec![("Passed Pawns",
+              passed_pawns_score(position, WHITE),
+              passed_pawns_score(position, BLACK))]
+    }
+}
+
+
+/// Middlegame bonuses for passed pawns, indexed by rank (from the
+/// pawn owner's point of view).
+const MG_PASSED_PAWN_BONUS: [i32; 8] = [0, 5, 10, 15, 25, 40, 60, 0];
+
+/// Endgame bonuses for passed pawns, indexed by rank (from the pawn
+/// owner's point of view).
+const EG_PASSED_PAWN_BONUS: [i32; 8] = [0, 10, 15, 25, 45, 75, 120, 0];
+
+/// The bonus for a passed pawn defended by a friendly pawn.
+const SUPPORTED_PASSED_PAWN_BONUS: (i32, i32) = (5, 10);
+
+/// The king proximity adjustment (see `passed_pawns_score`) is
+/// multiplied by this number, indexed by rank (from the pawn owner's
+/// point of view).
+const KING_PROXIMITY_WEIGHT: [i32; 8] = [0, 0, 0, 1, 2, 3, 4, 0];
+
+
+/// A helper function. It returns the score for the passed pawns of
+/// a given color, from this color's point of view.
+fn passed_pawns_score(position: &Board, color: Color) -> TaperedScore {
+    let geometry = BoardGeometry::get();
+    let pawns = position.pieces.piece_type[PAWN];
+    let our_pawns = pawns & position.pieces.color[color];
+    let their_pawns = pawns & position.pieces.color[1 ^ color];
+    let their_pieces = position.pieces.color[1 ^ color];
+    let kings = position.pieces.piece_type[KING];
+    let our_king = kings & position.pieces.color[color];
+    let their_king = kings & position.pieces.color[1 ^ color];
+    let mut score = TaperedScore::default();
+    for square in Squares::new(our_pawns) {
+        let file = Board::file(square);
+        let (rank, stop_square, front) = if color == WHITE {
+            (Board::rank(square), square + 8, !0 << (square - file + 8))
+        } else {
+            (7 - Board::rank(square), square - 8, (1 << (square - file)) - 1)
+        };
+        let files = bb_file(square) | (bb_file(square) & !BB_FILE_A) >> 1 |
+                    (bb_file(square) & !BB_FILE_H) << 1;
+        if their_pawns & files & front != 0 {
+            continue;
+        }
+        let mut bonus = TaperedScore::new(MG_PASSED_PAWN_BONUS[rank],
+                                          EG_PASSED_PAWN_BONUS[rank]);
+        if their_pieces & 1 << stop_square != 0 {
+            bonus = TaperedScore::new(bonus.mg / 2, bonus.eg / 2);
+        }
+        if geometry.pawn_attacks[1 ^ color][square] & our_pawns != 0 {
+            bonus += TaperedScore::new(SUPPORTED_PASSED_PAWN_BONUS.0,
+                                       SUPPORTED_PASSED_PAWN_BONUS.1);
+        }
+        if our_king != 0 && their_king != 0 {
+            let proximity = 5 * distance(bsf(their_king), stop_square) -
+                            2 * distance(bsf(our_king), stop_square);
+            bonus += TaperedScore::new(0, proximity * KING_PROXIMITY_WEIGHT[rank]);
+        }
+        score += bonus;
+    }
+    score
+}
+
+
+/// A helper function. It returns the number of king moves between
+/// two squares.
+#[inline]
+fn distance(a: Square, b: Square) -> i32 {
+    let files = (Board::file(a) as i32 - Board::file(b) as i32).abs();
+    let ranks = (Board::rank(a) as i32 - Board::rank(b) as i32).abs();
+    max(files, ranks)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stock::{TaperedEvaluator, TaperedMaterial};
+
+    type E = TaperedEvaluator<(TaperedMaterial, TaperedPassedPawns)>;
+
+    #[test]
+    fn passed_pawns() {
+        // "e5" is passed, "a2" is not.
+        let b = Board::from_fen("4k3/p7/8/4P3/8/8/P7/4K3 w - - 0 1").ok().unwrap();
+        let white = passed_pawns_score(&b, WHITE);
+        assert!(white.mg > 0 && white.eg > 0);
+        assert_eq!(white.mg, MG_PASSED_PAWN_BONUS[4]);
+        assert_eq!(passed_pawns_score(&b, BLACK), TaperedScore::default());
+
+        // A blockaded pawn gets less, a supported pawn gets more.
+        let blockaded = Board::from_fen("4k3/p7/4n3/4P3/8/8/P7/4K3 w - - 0 1").ok().unwrap();
+        assert!(passed_pawns_score(&blockaded, WHITE).mg < white.mg);
+        let supported = Board::from_fen("4k3/p7/8/4P3/3P4/8/8/4K3 w - - 0 1").ok().unwrap();
+        assert_eq!(passed_pawns_score(&supported, WHITE).mg,
+                   white.mg + SUPPORTED_PASSED_PAWN_BONUS.0 + MG_PASSED_PAWN_BONUS[3]);
+
+        // The enemy king should be far away.
+        let near = Board::from_fen("8/p3k3/8/4P3/8/8/P7/4K3 w - - 0 1").ok().unwrap();
+        let far = Board::from_fen("8/p7/k7/4P3/8/8/P7/4K3 w - - 0 1").ok().unwrap();
+        assert!(passed_pawns_score(&near, WHITE).eg < passed_pawns_score(&far, WHITE).eg);
+
+        // The score is symmetric.
+        let m = Board::from_fen("4k3/p7/8/8/4p3/8/P7/4K3 b - - 0 1").ok().unwrap();
+        assert_eq!(E::new(&b).evaluate(&b), E::new(&m).evaluate(&m));
+    }
+}
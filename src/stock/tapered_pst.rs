@@ -0,0 +1,485 @@
+//! Implements `TaperedPst` and `PieceSquareTables`.
+
+use std::fs::File;
+use std::io::Read;
+use std::sync::{Arc, RwLock};
+use uci::{SetOption, OptionDescription};
+use moves::*;
+use board::*;
+use evaluator::*;
+
+
+/// Middlegame and endgame piece-square tables for all piece types.
+///
+/// A piece-square table assigns a bonus (or a penalty) to a piece
+/// standing on a given square. The tables are given from white's
+/// point of view, and are indexed by piece type and square. (For
+/// black pieces the tables are mirrored vertically.)
+///
+/// # Text format
+///
+/// Piece-square tables can be loaded from (and exported to) a simple
+/// text format. The text consists of sections. Each section starts
+/// with a header line that contains a piece type ("king", "queen",
+/// "rook", "bishop", "knight", or "pawn") and a phase ("mg" or "eg"),
+/// followed by 8 lines with 8 integers each -- the bonuses for the
+/// squares on the ranks from 8 to 1, and the files from A to H. Empty
+/// lines and lines starting with "#" are ignored. The tables for
+/// missing sections are taken from the defaults.
+///
+/// ```text
+/// # A king that hurries to the center in the endgame.
+/// king eg
+/// -40 -30 -20 -10 -10 -20 -30 -40
+/// -30 -20 -10   0   0 -10 -20 -30
+/// ...
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PieceSquareTables {
+    /// The middlegame tables.
+    pub mg: [[i32; 64]; 6],
+
+    /// The endgame tables.
+    pub eg: [[i32; 64]; 6],
+}
+
+impl Default for PieceSquareTables {
+    fn default() -> PieceSquareTables {
+        let zeros = PieceSquareTables {
+            mg: [[0; 64]; 6],
+            eg: [[0; 64]; 6],
+        };
+        parse_text(DEFAULT_PSTS, zeros).unwrap()
+    }
+}
+
+impl PieceSquareTables {
+    /// Parses piece-square tables from the text format.
+    ///
+    /// Returns `None` if the text is malformed.
+    pub fn from_text(s: &str) -> Option<PieceSquareTables> {
+        parse_text(s, PieceSquareTables::default())
+    }
+
+    /// Loads piece-square tables from a file in the text format.
+    ///
+    /// Returns `None` if the file can not be read, or is malformed.
+    pub fn from_file(path: &str) -> Option<PieceSquareTables> {
+        let mut s = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut s)) {
+            Ok(_) => PieceSquareTables::from_text(&s),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns the tables in the text format.
+    pub fn to_text(&self) -> String {
+        let mut s = String::new();
+        for piece in KING..PIECE_NONE {
+            for &(phase, table) in [("mg", &self.mg[piece]), ("eg", &self.eg[piece])].iter() {
+                s.push_str(&format!("{} {}\n", PIECE_NAMES[piece], phase));
+                for rank in (0..8).rev() {
+                    let row: Vec<String> = (0..8)
+                        .map(|file| format!("{:4}", table[Board::square(file, rank)]))
+                        .collect();
+                    s.push_str(&row.join(" "));
+                    s.push('\n');
+                }
+            }
+        }
+        s
+    }
+
+    /// Returns the middlegame and endgame bonuses for a piece of a
+    /// given color on a given square.
+    #[inline]
+    pub fn get(&self, color: Color, piece: PieceType, square: Square) -> TaperedScore {
+        let square = if color == WHITE { square } else { square ^ 0b111000 };
+        TaperedScore::new(self.mg[piece][square], self.eg[piece][square])
+    }
+
+    /// Returns the piece-square tables currently used by `TaperedPst`.
+    pub fn current() -> Arc<PieceSquareTables> {
+        CURRENT_PSTS.read().unwrap().clone()
+    }
+
+    /// Replaces the piece-square tables used by `TaperedPst`.
+    ///
+    /// This affects only the `TaperedPst` instances created after the
+    /// call. It is useful for tuning.
+    pub fn set_current(psts: PieceSquareTables) {
+        *CURRENT_PSTS.write().unwrap() = Arc::new(psts);
+    }
+}
+
+
+/// A tapered evaluation term that sums up piece-square table bonuses.
+///
+/// The tables are taken from `PieceSquareTables::current()` when the
+/// term is created. They can be loaded from a file in the text format
+/// with the "PST File" option (an empty string means the embedded
+/// defaults).
+#[derive(Clone)]
+pub struct TaperedPst {
+    psts: Arc<PieceSquareTables>,
+
+    // The score from white's point of view.
+    score: TaperedScore,
+}
+
+impl SetOption for TaperedPst {
+    fn options() -> Vec<(&'static str, OptionDescription)> {
+        vec![("PST File", OptionDescription::String { default: String::new() })]
+    }
+
+    fn set_option(name: &str, value: &str) -> Result<(), String> {
+        if name == "PST File" {
+            let value = value.trim();
+            if value.is_empty() || value == "<empty>" {
+                PieceSquareTables::set_current(PieceSquareTables::default());
+            } else if let Some(psts) = PieceSquareTables::from_file(value) {
+                PieceSquareTables::set_current(psts);
+            } else {
+                return Err(format!("can not load piece-square tables from {}", value));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TaperedTerm for TaperedPst {
+    fn new(position: &Board) -> TaperedPst {
+        let psts = PieceSquareTables::current();
+        let mut score = TaperedScore::default();
+        for (color, piece, square) in position.all_pieces() {
+            if color == WHITE {
+                score += psts.get(color, piece, square);
+            } else {
+                score -= psts.get(color, piece, square);
+            }
+        }
+        TaperedPst {
+            psts: psts,
+            score: score,
+        }
+    }
+
+    #[inline]
+    fn score(&self, position: &Board) -> TaperedScore {
+        if position.to_move == WHITE {
+            self.score
+        } else {
+            -self.score
+        }
+    }
+
+    fn explain(&self, position: &Board) -> Vec<(&'static str, TaperedScore, TaperedScore)> {
+        let mut scores = [TaperedScore::default(); 2];
+        for (color, piece, square) in position.all_pieces() {
+            scores[color] += self.psts.get(color, piece, square);
+        }
+        vec![("Piece-Square", scores[WHITE], scores[BLACK])]
+    }
+
+    /// Exports the current piece-square tables (`kind` is "psts").
+    fn export(kind: &str) -> Option<String> {
+        if kind == "psts" {
+            Some(PieceSquareTables::current().to_text())
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn done_move(&mut self, position: &Board, m: Move) {
+        let delta = self.delta(1 ^ position.to_move, m);
+        self.score += delta;
+    }
+
+    #[inline]
+    fn undone_move(&mut self, position: &Board, m: Move) {
+        let delta = self.delta(position.to_move, m);
+        self.score -= delta;
+    }
+}
+
+impl TaperedPst {
+    /// Returns how the score (from white's point of view) changes
+    /// when `us` plays a given move.
+    fn delta(&self, us: Color, m: Move) -> TaperedScore {
+        if m.is_null() {
+            return TaperedScore::default();
+        }
+        let them = 1 ^ us;
+        let psts = &*self.psts;
+        let piece = m.played_piece();
+        let orig_square = m.orig_square();
+        let dest_square = m.dest_square();
+        let mut delta = -psts.get(us, piece, orig_square);
+        match m.move_type() {
+            MOVE_PROMOTION => {
+                delta += psts.get(us, Move::piece_from_aux_data(m.aux_data()), dest_square);
+            }
+            MOVE_CASTLING => {
+                delta += psts.get(us, piece, dest_square);
+                let rank = Board::rank(orig_square);
+                let (rook_orig, rook_dest) = if Board::file(dest_square) > Board::file(orig_square) {
+                    (Board::square(7, rank), Board::square(5, rank))
+                } else {
+                    (Board::square(0, rank), Board::square(3, rank))
+                };
+                delta += psts.get(us, ROOK, rook_dest) - psts.get(us, ROOK, rook_orig);
+            }
+            _ => delta += psts.get(us, piece, dest_square),
+        }
+        let captured_piece = m.captured_piece();
+        if captured_piece < PIECE_NONE {
+            let captured_square = if m.move_type() == MOVE_ENPASSANT {
+                Board::square(Board::file(dest_square), Board::rank(orig_square))
+            } else {
+                dest_square
+            };
+            delta += psts.get(them, captured_piece, captured_square);
+        }
+        if us == WHITE { delta } else { -delta }
+    }
+}
+
+
+/// A helper function. It parses piece-square tables in the text
+/// format, taking missing tables from `psts`.
+fn parse_text(s: &str, mut psts: PieceSquareTables) -> Option<PieceSquareTables> {
+    let mut lines = s.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+    while let Some(header) = lines.next() {
+        let words: Vec<&str> = header.split_whitespace().collect();
+        if words.len() != 2 {
+            return None;
+        }
+        let piece = match PIECE_NAMES.iter().position(|&x| x == words[0]) {
+            Some(x) => x,
+            None => return None,
+        };
+        let table = match words[1] {
+            "mg" => &mut psts.mg[piece],
+            "eg" => &mut psts.eg[piece],
+            _ => return None,
+        };
+        for rank in (0..8).rev() {
+            let values: Vec<i32> = match lines.next().map(|l| {
+                l.split_whitespace().map(|x| x.parse()).collect()
+            }) {
+                Some(Ok(x)) => x,
+                _ => return None,
+            };
+            if values.len() != 8 {
+                return None;
+            }
+            for (file, v) in values.into_iter().enumerate() {
+                table[Board::square(file, rank)] = v;
+            }
+        }
+    }
+    Some(psts)
+}
+
+
+lazy_static! {
+    static ref CURRENT_PSTS: RwLock<Arc<PieceSquareTables>> =
+        RwLock::new(Arc::new(PieceSquareTables::default()));
+}
+
+
+// Tests that change the current piece-square tables must hold this
+// lock, so that they do not disturb other tests.
+#[cfg(test)]
+lazy_static! {
+    pub static ref CURRENT_PSTS_TEST_LOCK: ::std::sync::Mutex<()> = ::std::sync::Mutex::new(());
+}
+
+
+const PIECE_NAMES: [&'static str; 6] = ["king", "queen", "rook", "bishop", "knight", "pawn"];
+
+
+/// The embedded default piece-square tables.
+const DEFAULT_PSTS: &'static str = "\
+king mg\n\
+ -55  -60  -60  -60  -60  -60  -60  -55\n\
+ -55  -60  -60  -60  -60  -60  -60  -55\n\
+ -55  -60  -60  -60  -60  -60  -60  -55\n\
+ -55  -60  -60  -60  -60  -60  -60  -55\n\
+ -45  -50  -50  -50  -50  -50  -50  -45\n\
+ -35  -40  -40  -40  -40  -40  -40  -35\n\
+  10   10   -5  -10  -10   -5   10   10\n\
+  20   30   10    0    0   10   30   20\n\
+king eg\n\
+ -42  -30  -18   -6   -6  -18  -30  -42\n\
+ -30  -18   -6    6    6   -6  -18  -30\n\
+ -18   -6    6   18   18    6   -6  -18\n\
+  -6    6   18   30   30   18    6   -6\n\
+  -6    6   18   30   30   18    6   -6\n\
+ -18   -6    6   18   18    6   -6  -18\n\
+ -30  -18   -6    6    6   -6  -18  -30\n\
+ -42  -30  -18   -6   -6  -18  -30  -42\n\
+queen mg\n\
+ -13  -10   -7   -4   -4   -7  -10  -13\n\
+ -10   -7   -4   -1   -1   -4   -7  -10\n\
+  -7   -4   -1    2    2   -1   -4   -7\n\
+  -4   -1    2    5    5    2   -1   -4\n\
+  -4   -1    2    5    5    2   -1   -4\n\
+  -7   -4   -1    2    2   -1   -4   -7\n\
+ -10   -7   -4   -1   -1   -4   -7  -10\n\
+  -8   -5   -2    1    1   -2   -5   -8\n\
+queen eg\n\
+ -15  -10   -5    0    0   -5  -10  -15\n\
+ -10   -5    0    5    5    0   -5  -10\n\
+  -5    0    5   10   10    5    0   -5\n\
+   0    5   10   15   15   10    5    0\n\
+   0    5   10   15   15   10    5    0\n\
+  -5    0    5   10   10    5    0   -5\n\
+ -10   -5    0    5    5    0   -5  -10\n\
+ -15  -10   -5    0    0   -5  -10  -15\n\
+rook mg\n\
+  -5    0    0    5    5    0    0   -5\n\
+  10   15   15   20   20   15   15   10\n\
+  -5    0    0    5    5    0    0   -5\n\
+  -5    0    0    5    5    0    0   -5\n\
+  -5    0    0    5    5    0    0   -5\n\
+  -5    0    0    5    5    0    0   -5\n\
+  -5    0    0    5    5    0    0   -5\n\
+   0    0    0    5    5    0    0    0\n\
+rook eg\n\
+   0    0    0    0    0    0    0    0\n\
+   5    5    5    5    5    5    5    5\n\
+   0    0    0    0    0    0    0    0\n\
+   0    0    0    0    0    0    0    0\n\
+   0    0    0    0    0    0    0    0\n\
+   0    0    0    0    0    0    0    0\n\
+   0    0    0    0    0    0    0    0\n\
+   0    0    0    0    0    0    0    0\n\
+bishop mg\n\
+ -20  -15  -10   -5   -5  -10  -15  -20\n\
+ -15  -10   -5    0    0   -5  -10  -15\n\
+ -10   -5    0    5    5    0   -5  -10\n\
+  -5    0    5   10   10    5    0   -5\n\
+  -5    0    5   10   10    5    0   -5\n\
+ -10   -5    0    5    5    0   -5  -10\n\
+ -15  -10   -5    0    0   -5  -10  -15\n\
+ -25  -20  -15  -10  -10  -15  -20  -25\n\
+bishop eg\n\
+ -14  -10   -6   -2   -2   -6  -10  -14\n\
+ -10   -6   -2    2    2   -2   -6  -10\n\
+  -6   -2    2    6    6    2   -2   -6\n\
+  -2    2    6   10   10    6    2   -2\n\
+  -2    2    6   10   10    6    2   -2\n\
+  -6   -2    2    6    6    2   -2   -6\n\
+ -10   -6   -2    2    2   -2   -6  -10\n\
+ -14  -10   -6   -2   -2   -6  -10  -14\n\
+knight mg\n\
+ -40  -30  -20  -10  -10  -20  -30  -40\n\
+ -30  -10    0   10   10    0  -10  -30\n\
+ -20    0   10   20   20   10    0  -20\n\
+ -10   10   20   30   30   20   10  -10\n\
+ -10   10   20   30   30   20   10  -10\n\
+ -20    0   10   20   20   10    0  -20\n\
+ -30  -10    0   10   10    0  -10  -30\n\
+ -40  -30  -20  -10  -10  -20  -30  -40\n\
+knight eg\n\
+ -28  -20  -12   -4   -4  -12  -20  -28\n\
+ -20  -12   -4    4    4   -4  -12  -20\n\
+ -12   -4    4   12   12    4   -4  -12\n\
+  -4    4   12   20   20   12    4   -4\n\
+  -4    4   12   20   20   12    4   -4\n\
+ -12   -4    4   12   12    4   -4  -12\n\
+ -20  -12   -4    4    4   -4  -12  -20\n\
+ -28  -20  -12   -4   -4  -12  -20  -28\n\
+pawn mg\n\
+   0    0    0    0    0    0    0    0\n\
+  25   25   30   35   35   30   25   25\n\
+  10   10   20   25   25   20   10   10\n\
+   5    5   15   25   25   15    5    5\n\
+   0    0   10   20   20   10    0    0\n\
+   0    0    0    5    5    0    0    0\n\
+   0    0    0  -10  -10    0    0    0\n\
+   0    0    0    0    0    0    0    0\n\
+pawn eg\n\
+   0    0    0    0    0    0    0    0\n\
+  50   50   50   50   50   50   50   50\n\
+  25   25   25   25   25   25   25   25\n\
+  10   10   10   10   10   10   10   10\n\
+   0    0    0    0    0    0    0    0\n\
+  -5   -5   -5   -5   -5   -5   -5   -5\n\
+   0    0    0    0    0    0    0    0\n\
+   0    0    0    0    0    0    0    0\n";
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use move_generator::*;
+    use stock::{StdMoveGenerator, TaperedEvaluator};
+    use utils::MoveStack;
+
+    type E = TaperedEvaluator<TaperedPst>;
+
+    #[test]
+    fn text_format() {
+        let psts = PieceSquareTables::default();
+        assert_eq!(PieceSquareTables::from_text(&psts.to_text()), Some(psts.clone()));
+        let changed = PieceSquareTables::from_text("# A comment.\n\
+                                                    pawn eg\n\
+                                                    0 0 0 0 0 0 0 0\n\
+                                                    1 1 1 1 1 1 1 1\n\
+                                                    0 0 0 0 0 0 0 0\n\
+                                                    0 0 0 0 0 0 0 0\n\
+                                                    0 0 0 0 0 0 0 0\n\n\
+                                                    0 0 0 0 0 0 0 0\n\
+                                                    0 0 0 0 0 0 0 0\n\
+                                                    0 0 0 0 0 0 0 9\n")
+            .unwrap();
+        assert_eq!(changed.mg, psts.mg);
+        assert_eq!(changed.eg[KING], psts.eg[KING]);
+        assert_eq!(changed.eg[PAWN][Board::square(0, 6)], 1);
+        assert_eq!(changed.eg[PAWN][Board::square(7, 0)], 9);
+        assert_eq!(changed.get(BLACK, PAWN, Board::square(7, 7)).eg, 9);
+        assert!(PieceSquareTables::from_text("pawn eg\n1 2 3").is_none());
+        assert!(PieceSquareTables::from_text("pawns mg").is_none());
+    }
+
+    #[test]
+    fn set_option() {
+        let _lock = CURRENT_PSTS_TEST_LOCK.lock();
+        let path = ::std::env::temp_dir().join("alcibiades-no-such-psts.txt");
+        let path = path.to_str().unwrap();
+        assert!(TaperedPst::set_option("PST File", path).is_err());
+        assert!(TaperedPst::set_option("PST File", "<empty>").is_ok());
+        assert_eq!(*PieceSquareTables::current(), PieceSquareTables::default());
+        assert!(TaperedPst::set_option("No Such Option", path).is_ok());
+        assert_eq!(E::export("psts"), Some(PieceSquareTables::default().to_text()));
+        assert_eq!(E::export("weights"), None);
+    }
+
+    #[test]
+    fn incremental_update() {
+        let _lock = CURRENT_PSTS_TEST_LOCK.lock();
+        for fen in ["r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                    "r3k2r/1P6/8/8/3pP3/2N5/8/R3K1NR b KQkq e3 0 1"]
+                    .iter() {
+            let mut g = StdMoveGenerator::<E>::from_board(Board::from_fen(fen).ok().unwrap())
+                .ok()
+                .unwrap();
+            let mut s = MoveStack::new();
+            g.generate_all(&mut s);
+            while let Some(m) = s.pop() {
+                if g.do_move(m).is_some() {
+                    let e = E::new(g.board());
+                    assert_eq!(g.evaluator().evaluate(g.board()), e.evaluate(g.board()));
+                    g.undo_move(m);
+                }
+            }
+            let e = E::new(g.board());
+            assert_eq!(g.evaluator().evaluate(g.board()), e.evaluate(g.board()));
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Implements Texel tuning of `PieceSquareTables`.
+
+use board::*;
+use evaluator::Evaluator;
+use stock::PieceSquareTables;
+
+
+/// A position with a known game result, used for tuning.
+#[derive(Clone, Debug)]
+pub struct TuningRecord {
+    /// The position.
+    pub board: Board,
+
+    /// The result of the game -- `1.0` if white won, `0.0` if black
+    /// won, and `0.5` if the game was drawn.
+    pub result: f64,
+}
+
+impl TuningRecord {
+    /// Parses a record from a line of text.
+    ///
+    /// The line should contain a position in Forsyth–Edwards notation
+    /// (the halfmove clock and the move number may be omitted),
+    /// followed by the result of the game -- "1-0", "0-1", "1/2-1/2",
+    /// or respectively "1.0", "0.0", "0.5". The result may be
+    /// enclosed in brackets or quotes, and may be preceded by "c9",
+    /// so that EPD records like `... c9 "1-0";` are also accepted.
+    /// Returns `None` if the line is malformed.
+    pub fn parse(line: &str) -> Option<TuningRecord> {
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.pop().map(|w| w.trim_matches(|c| "[]\";".contains(c))) {
+            Some("1-0") | Some("1.0") => 1.0,
+            Some("0-1") | Some("0.0") => 0.0,
+            Some("1/2-1/2") | Some("0.5") => 0.5,
+            _ => return None,
+        };
+        if words.last() == Some(&"c9") {
+            words.pop();
+        }
+        if words.len() == 4 {
+            words.extend_from_slice(&["0", "1"]);
+        }
+        Board::from_fen(&words.join(" ")).ok().map(|board| {
+                                                     TuningRecord {
+                                                         board: board,
+                                                         result: result,
+                                                     }
+                                                 })
+    }
+}
+
+
+/// Returns the mean squared difference between the game results and
+/// the results predicted by the evaluator `E`.
+///
+/// The predicted result for a position is `1 / (1 + 10^(-k * s /
+/// 400))`, where `s` is the static evaluation of the position in
+/// centipawns, from white's point of view.
+pub fn tuning_error<E: Evaluator>(records: &[TuningRecord], k: f64) -> f64 {
+    if records.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = records.iter().map(|r| squared_error::<E>(r, k)).sum();
+    sum / records.len() as f64
+}
+
+
+/// Returns the scaling constant `k` (see `tuning_error`) for which the
+/// evaluator `E` predicts the game results best.
+pub fn find_scaling_constant<E: Evaluator>(records: &[TuningRecord]) -> f64 {
+    let mut k = 1.0;
+    let mut step = 0.5;
+    let mut error = tuning_error::<E>(records, k);
+    while step > 0.001 {
+        let mut improved = false;
+        for &x in [k - step, k + step].iter() {
+            let e = tuning_error::<E>(records, x);
+            if x > 0.0 && e < error {
+                k = x;
+                error = e;
+                improved = true;
+                break;
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+    k
+}
+
+
+/// Tunes the piece-square tables used by `TaperedPst`, so that the
+/// evaluator `E` predicts the game results better.
+///
+/// This is the "Texel tuning" method: one by one, each middlegame and
+/// endgame bonus is increased (or decreased) by one, and the change
+/// is kept if it lowers `tuning_error`. The search starts from
+/// `PieceSquareTables::current()`, and continues until no change
+/// lowers the error, or `passes` passes over all the bonuses are
+/// done. After each pass `progress` is called with the number of
+/// the pass, the current error, and the tuned tables, and can stop
+/// the tuning by returning `false`. The tuned tables are returned,
+/// and also become the current tables.
+///
+/// Because piece-square tables are added to the value of the
+/// material, they can compensate for wrongly valued pieces too. Only
+/// evaluators that use `TaperedPst` can be tuned, and the positions
+/// should be "quiet" (with no pending captures), because their
+/// static evaluations are used as is.
+pub fn tune_psts<E, F>(records: &[TuningRecord], k: f64, passes: usize, mut progress: F)
+                       -> PieceSquareTables
+    where E: Evaluator,
+          F: FnMut(usize, f64, &PieceSquareTables) -> bool
+{
+    let mut psts = (*PieceSquareTables::current()).clone();
+    let mut errors: Vec<f64> = records.iter().map(|r| squared_error::<E>(r, k)).collect();
+    let mut total: f64 = errors.iter().sum();
+    let n = records.len().max(1) as f64;
+
+    // Only the records that have a given piece on a given square are
+    // affected by a change of its bonus.
+    let mut affected = vec![vec![]; 6 * 64];
+    for (i, r) in records.iter().enumerate() {
+        for (color, piece, square) in r.board.all_pieces() {
+            let square = if color == WHITE { square } else { square ^ 0b111000 };
+            affected[piece * 64 + square].push(i);
+        }
+    }
+
+    for pass in 1..passes + 1 {
+        let mut improved = false;
+        for piece in KING..PIECE_NONE {
+            for square in 0..64 {
+                let indexes = &affected[piece * 64 + square];
+                if indexes.is_empty() {
+                    continue;
+                }
+                for phase in 0..2 {
+                    for &delta in [1, -1].iter() {
+                        let mut candidate = psts.clone();
+                        if phase == 0 {
+                            candidate.mg[piece][square] += delta;
+                        } else {
+                            candidate.eg[piece][square] += delta;
+                        }
+                        PieceSquareTables::set_current(candidate.clone());
+                        let new_errors: Vec<f64> = indexes.iter()
+                            .map(|&i| squared_error::<E>(&records[i], k))
+                            .collect();
+                        let change: f64 = indexes.iter()
+                            .zip(new_errors.iter())
+                            .map(|(&i, &e)| e - errors[i])
+                            .sum();
+                        if change < 0.0 {
+                            for (&i, &e) in indexes.iter().zip(new_errors.iter()) {
+                                errors[i] = e;
+                            }
+                            total += change;
+                            psts = candidate;
+                            improved = true;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        PieceSquareTables::set_current(psts.clone());
+        if !progress(pass, total / n, &psts) || !improved {
+            break;
+        }
+    }
+    PieceSquareTables::set_current(psts.clone());
+    psts
+}
+
+
+#[inline]
+fn squared_error<E: Evaluator>(record: &TuningRecord, k: f64) -> f64 {
+    let board = &record.board;
+    let v = E::to_centipawns(E::new(board).evaluate(board)) as f64;
+    let s = if board.to_move == WHITE { v } else { -v };
+    let predicted = 1.0 / (1.0 + 10f64.powf(-k * s / 400.0));
+    (record.result - predicted).powi(2)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stock::{TaperedEvaluator, TaperedMaterial, TaperedPst};
+    use stock::tapered_pst::CURRENT_PSTS_TEST_LOCK;
+
+    type E = TaperedEvaluator<(TaperedMaterial, TaperedPst)>;
+
+    #[test]
+    fn parse() {
+        let r = TuningRecord::parse("8/8/8/8/8/8/4k3/4K3 w - - 0 1 [1/2-1/2]").unwrap();
+        assert_eq!(r.result, 0.5);
+        let r = TuningRecord::parse("8/8/8/8/8/8/4k3/4K3 b - - c9 \"1-0\";").unwrap();
+        assert_eq!(r.result, 1.0);
+        assert_eq!(r.board.to_move, BLACK);
+        assert_eq!(TuningRecord::parse("8/8/8/8/8/8/4k3/4K3 w - - 0.0").unwrap().result,
+                   0.0);
+        assert!(TuningRecord::parse("8/8/8/8/8/8/4k3/4K3 w - - 0 1").is_none());
+        assert!(TuningRecord::parse("8/8/8/4K3 w - - 1-0").is_none());
+        assert!(TuningRecord::parse("").is_none());
+    }
+
+    #[test]
+    fn tune() {
+        let _lock = CURRENT_PSTS_TEST_LOCK.lock();
+        // White wins every game with an extra knight on "d4", and
+        // draws with an extra knight on "a1".
+        let records: Vec<TuningRecord> = ["4k3/p7/8/8/3N4/8/P7/4K3 w - - 1-0",
+                                          "4k3/p7/8/8/3N4/8/P7/4K3 b - - 1-0",
+                                          "4k3/p7/8/8/8/8/P7/N3K3 w - - 1/2-1/2",
+                                          "4k3/p7/8/8/8/8/P7/N3K3 b - - 1/2-1/2"]
+            .iter()
+            .map(|s| TuningRecord::parse(s).unwrap())
+            .collect();
+        let k = 1.0;
+        let initial_error = tuning_error::<E>(&records, k);
+        let mut reported = vec![];
+        let psts = tune_psts::<E, _>(&records, k, 3, |pass, error, _| {
+            reported.push((pass, error));
+            true
+        });
+        assert_eq!(reported.len(), 3);
+        assert!(reported[2].1 < initial_error);
+        assert!((tuning_error::<E>(&records, k) - reported[2].1).abs() < 1e-9);
+        let default = PieceSquareTables::default();
+        assert!(psts.eg[KNIGHT][3 + 8 * 3] > default.eg[KNIGHT][3 + 8 * 3]);
+        assert!(psts.eg[KNIGHT][0] < default.eg[KNIGHT][0]);
+        assert_eq!(psts.mg[QUEEN], default.mg[QUEEN]);
+        PieceSquareTables::set_current(default);
+        assert!(find_scaling_constant::<E>(&records) > 0.0);
+    }
+}
@@ -1,6 +1,7 @@
 //! Defines types and traits related to transposition tables.
 
-use std::cmp::min;
+use std::cmp::{min, max};
+use std::ops::Add;
 use moves::{Move, MoveDigest};
 use value::*;
 use depth::*;
@@ -68,9 +69,86 @@ pub trait Ttable: Sync + Send + 'static {
     /// Probes for data by key.
     fn probe(&self, key: u64) -> Option<Self::Entry>;
 
+    /// Probes for data by key, for a position with a given rule-50
+    /// counter (see `SearchNode::halfmove_clock`).
+    ///
+    /// The values in the table are path dependent: repetitions and
+    /// the fifty-move rule can turn the value of a position into a
+    /// draw, depending on the line of play that led to it. Search
+    /// nodes take care of the most important cases by blending the
+    /// reachable repeated boards, and the rule-50 counter when it is
+    /// close to the limit, into their hashes (see
+    /// `SearchNode::hash`). Still, a win or a loss obtained with a
+    /// lower rule-50 counter is not reliable when the fifty-move rule
+    /// falls within the search horizon of the entry -- the real value
+    /// may be closer to a draw.
+    ///
+    /// In this case, the default implementation returns the entry
+    /// without the bound that a draw could violate (positive lower
+    /// bounds, and negative upper bounds). The move and the static
+    /// evaluation of the entry are kept. Searches should use this
+    /// method instead of `probe` when the value of the entry may
+    /// cause a cut-off.
+    fn probe_with_clock(&self, key: u64, halfmove_clock: u8) -> Option<Self::Entry> {
+        self.probe(key).map(|e| {
+            // The fifty-move rule kicks in after 100 half-moves.
+            if halfmove_clock as Depth + max(e.depth(), 0) < 100 {
+                return e;
+            }
+            let mut bound = e.bound();
+            if e.value() > 0 {
+                bound &= !BOUND_LOWER;
+            }
+            if e.value() < 0 {
+                bound &= !BOUND_UPPER;
+            }
+            if bound == e.bound() {
+                e
+            } else {
+                Self::Entry::new(e.value(), bound, e.depth())
+                    .set_move_digest(e.move_digest())
+                    .set_static_eval(e.static_eval())
+                    .set_pv_flag(e.is_pv())
+            }
+        })
+    }
+
+    /// Hints that `probe` or `store` will soon be called for a given
+    /// key.
+    ///
+    /// Implementations may use this to start fetching the memory that
+    /// holds the data for `key` into the CPU cache, so that the
+    /// following probe does not have to wait for it. The search
+    /// calls this method right after a move is played, before doing
+    /// any other work in the new position. The default implementation
+    /// does nothing.
+    #[inline]
+    fn prefetch(&self, key: u64) {
+        let _ = key;
+    }
+
     /// Removes all entries in the table.
     fn clear(&self);
 
+    /// Returns information about the memory used by the table.
+    ///
+    /// This may take some time, because the whole table may need to
+    /// be scanned. The default implementation reports no memory.
+    fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::default()
+    }
+
+    /// Returns the number of successful probes, and the number of
+    /// hash key collisions detected among them.
+    ///
+    /// A collision happens when a record that has been stored for one
+    /// position is found when probing for another position. Returns
+    /// `None` if the table can not detect collisions. (This is what
+    /// the default implementation does.)
+    fn key_collisions(&self) -> Option<(usize, usize)> {
+        None
+    }
+
     /// Extracts the principal variation for a given position.
     ///
     /// The principal variation (PV) is the sequence of moves that the
@@ -149,6 +227,94 @@ pub trait Ttable: Sync + Send + 'static {
             moves: moves,
         }
     }
+
+    /// Extracts up to `count` principal variations for a given
+    /// position, each one starting with a different move.
+    ///
+    /// The first variation is the one returned by `extract_pv`. The
+    /// next ones start with the other legal moves, for which the
+    /// table contains entries searched to a depth of at least
+    /// `depth - 1`, and are sorted by descending value. Moves that
+    /// already start an extracted variation are skipped. This makes
+    /// possible to show multi-PV output even when the search has not
+    /// completed an iteration yet.
+    fn extract_variations<T: SearchNode>(&self,
+                                         position: &T,
+                                         depth: Depth,
+                                         count: usize)
+                                         -> Vec<Variation> {
+        let mut variations = Vec::with_capacity(count);
+        if count == 0 {
+            return variations;
+        }
+        let pv = self.extract_pv(position);
+        if !pv.moves.is_empty() {
+            variations.push(pv);
+        }
+        let mut p = position.clone();
+        let mut others = vec![];
+        for m in p.legal_moves() {
+            if variations.iter().any(|v| v.moves[0] == m) || !p.do_move(m) {
+                continue;
+            }
+            match self.probe(p.hash()) {
+                Some(e) if e.bound() != BOUND_NONE && e.depth() >= depth - 1 => {
+                    // The value is from other side's perspective.
+                    let mut v = self.extract_pv(&p);
+                    v.moves.insert(0, m);
+                    v.value = -v.value;
+                    v.bound = match v.bound {
+                        BOUND_UPPER => BOUND_LOWER,
+                        BOUND_LOWER => BOUND_UPPER,
+                        b => b,
+                    };
+                    others.push(v);
+                }
+                _ => (),
+            }
+            p.undo_last_move();
+        }
+        others.sort_by_key(|v| -v.value);
+        let n = count - variations.len();
+        variations.extend(others.into_iter().take(n));
+        variations
+    }
+}
+
+
+/// Describes the memory used by an engine component (a transposition
+/// table or a cache).
+///
+/// Values for several components can be summed up with `+`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The number of allocated bytes.
+    pub bytes: usize,
+
+    /// The number of entries that can be stored.
+    pub capacity: usize,
+
+    /// The number of entries that are currently stored.
+    pub used: usize,
+}
+
+impl MemoryUsage {
+    /// Returns how full the component is, in permille (`0` to `1000`).
+    pub fn permille(&self) -> usize {
+        (self.used * 1000).checked_div(self.capacity).unwrap_or(0)
+    }
+}
+
+impl Add for MemoryUsage {
+    type Output = MemoryUsage;
+
+    fn add(self, other: MemoryUsage) -> MemoryUsage {
+        MemoryUsage {
+            bytes: self.bytes + other.bytes,
+            capacity: self.capacity + other.capacity,
+            used: self.used + other.used,
+        }
+    }
 }
 
 
@@ -199,6 +365,46 @@ pub trait TtableEntry: Copy + Send + 'static {
         VALUE_UNKNOWN
     }
 
+    /// Consumes the instance and returns a new instance with updated
+    /// PV flag.
+    ///
+    /// The PV flag marks entries for PV nodes (nodes searched with a
+    /// non-null window). Transposition tables may use it to protect
+    /// those entries from being replaced, which makes the principal
+    /// variation more stable.
+    ///
+    /// **Important note:** This method will do nothing if the
+    /// underlying memory structure has no bit allotted for the PV
+    /// flag.
+    #[allow(unused_variables)]
+    fn set_pv_flag(self, is_pv: bool) -> Self {
+        self
+    }
+
+    /// Returns `true` if the entry is for a PV node.
+    fn is_pv(&self) -> bool {
+        false
+    }
+
+    /// Returns if the entry is internally consistent.
+    ///
+    /// Transposition tables may use this method to detect garbage
+    /// (for example, entries corrupted by a race condition), so as to
+    /// not propagate it into the search. A consistent entry has its
+    /// value, bound type, depth, and static evaluation within their
+    /// legal ranges, and its move digest is well formed (see
+    /// `MoveDigest::is_well_formed`).
+    #[inline]
+    fn is_consistent(&self) -> bool {
+        let value = self.value();
+        let static_eval = self.static_eval();
+        VALUE_MIN <= value && value <= VALUE_MAX && self.bound() <= BOUND_EXACT &&
+        DEPTH_MIN <= self.depth() && self.depth() <= DEPTH_MAX &&
+        (static_eval == VALUE_UNKNOWN ||
+         VALUE_EVAL_MIN <= static_eval && static_eval <= VALUE_EVAL_MAX) &&
+        self.move_digest().is_well_formed()
+    }
+
     /// Returns the relative importance of the entry.
     ///
     /// Transposition tables may use this method to improve their
@@ -211,10 +417,10 @@ pub trait TtableEntry: Copy + Send + 'static {
     /// to a lower depth.
     #[inline]
     fn importance(&self) -> i16 {
-        let depth = self.depth() as i16;
+        let depth = self.depth();
         match self.bound() {
             BOUND_EXACT => depth + 1,
-            BOUND_NONE => DEPTH_MIN as i16 - 1,
+            BOUND_NONE => DEPTH_MIN - 1,
             _ => depth,
         }
     }
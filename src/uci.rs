@@ -12,8 +12,10 @@
 //!
 //! This module handles the low-level details of the UCI protocol. It
 //! only requires the programmer to define a type that implements the
-//! `UciEngine` trait. Then `run_engine` will handle the communication
-//! with the GUI all by itself.
+//! `UciEngine` trait. Then `run_engine_with_io` will handle the
+//! communication with the GUI all by itself. (If the GUI speaks the
+//! older "xboard" protocol instead, `run_engine_with_io` will notice
+//! this, and will hand the session over to `xboard::Server`.)
 
 use std::default::Default;
 use std::time::Duration;
@@ -22,6 +24,9 @@ use std::io;
 use std::io::{Write, BufWriter, BufRead, ErrorKind};
 use std::sync::mpsc::{channel, TryRecvError};
 use regex::Regex;
+use xboard;
+use io_log;
+use io_log::LogWriter;
 
 
 /// A command from the GUI to the engine.
@@ -57,6 +62,11 @@ enum UciCommand {
 
     /// Quit the program as soon as possible.
     Quit,
+
+    /// A non-standard command (for example "memory"), which is not
+    /// part of the UCI protocol, but is useful for debugging and
+    /// tuning.
+    Custom { name: String, params: String },
 }
 
 
@@ -180,6 +190,44 @@ pub enum OptionDescription {
 }
 
 
+impl OptionDescription {
+    /// Validates a new value for the option.
+    ///
+    /// Returns the value in its canonical form, or an error message
+    /// if the value is not acceptable. For check boxes, the canonical
+    /// form is either `"true"` or `"false"`. For combo boxes, it is
+    /// the matching item from the list. Spin box values must be
+    /// integers between `min` and `max` (inclusive). Buttons accept
+    /// any value, and their canonical value is an empty string.
+    pub fn validate(&self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+        match *self {
+            OptionDescription::Check { .. } => {
+                match value.to_lowercase().as_str() {
+                    x @ "true" | x @ "false" => Ok(x.to_string()),
+                    _ => Err(format!("\"{}\" is not a boolean", value)),
+                }
+            }
+            OptionDescription::Spin { min, max, .. } => {
+                match value.parse::<i32>() {
+                    Ok(v) if min <= v && v <= max => Ok(v.to_string()),
+                    Ok(_) => Err(format!("{} is out of range ({}..{})", value, min, max)),
+                    Err(_) => Err(format!("\"{}\" is not an integer", value)),
+                }
+            }
+            OptionDescription::Combo { ref list, .. } => {
+                list.iter()
+                    .find(|x| x.to_lowercase() == value.to_lowercase())
+                    .cloned()
+                    .ok_or_else(|| format!("\"{}\" is not in the list", value))
+            }
+            OptionDescription::String { .. } => Ok(value.to_string()),
+            OptionDescription::Button => Ok(String::new()),
+        }
+    }
+}
+
+
 /// A trait for announcing and changing configuration options.
 pub trait SetOption {
     /// Returns a list of supported configuration options (name and
@@ -195,19 +243,28 @@ pub trait SetOption {
     ///
     /// * `name` gives the name of the configuration option.
     ///
-    /// * `value` is the new value for the configuration option.
+    /// * `value` is the new value for the configuration option. It
+    ///   has already been validated against the option's description.
     ///
     /// Does nothing when called with unsupported configuration option
-    /// name.
+    /// name. Returns an error message if the value can not be
+    /// applied (for example, a file that can not be loaded). The
+    /// engine calls this method only between searches, so
+    /// implementations need not worry about a running search seeing
+    /// a half-updated state.
     #[allow(unused_variables)]
-    fn set_option(name: &str, value: &str) where Self: Sized {}
+    fn set_option(name: &str, value: &str) -> Result<(), String>
+        where Self: Sized
+    {
+        Ok(())
+    }
 }
 
 
 /// A trait for UCI-compatible chess engines.
 ///
-/// The methods in this trait, except the method `wait_for_reply`,
-/// must not block the current thread.
+/// The methods in this trait, except the methods `wait_for_reply` and
+/// `is_ready`, must not block the current thread.
 pub trait UciEngine {
     /// Returns the name of the engine.
     fn name() -> &'static str;
@@ -223,6 +280,12 @@ pub trait UciEngine {
     ///
     /// `tt_size_mb` is the preferred size of the transposition table
     /// in Mbytes.
+    ///
+    /// **Important note:** Implementations should not construct their
+    /// heavyweight components (the transposition table, the search
+    /// threads) here, because the options that affect their
+    /// construction may not have been received yet. Instead, they
+    /// should be constructed by `is_ready` or `go`.
     fn new(tt_size_mb: Option<usize>) -> Self;
 
     /// Sets a new value for a given configuration option.
@@ -240,9 +303,23 @@ pub trait UciEngine {
     /// notation. `moves` is an iterator over the moves played from
     /// the given position. The move format is long algebraic
     /// notation. Examples: `e2e4`, `e7e5`, `e1g1` (white short
-    /// castling), `e7e8q` (for promotion).
+    /// castling), `e7e8q` (for promotion). The moves have not been
+    /// validated, and the engine may choose to accept other move
+    /// formats too (SAN, for example).
+    ///
+    /// If the position is illegal, or some of the moves can not be
+    /// played, the engine should report which one is wrong (with an
+    /// `info string` message), and keep the previous position.
     fn position(&mut self, fen: &str, moves: &mut Iterator<Item = &str>);
 
+    /// Tells the engine to get ready for the next commands.
+    ///
+    /// This method is called when an "isready" command is received,
+    /// and it may block the current thread until the engine's
+    /// components are (re)constructed in accordance with the current
+    /// option values.
+    fn is_ready(&mut self);
+
     /// Tells the engine to start thinking.
     fn go(&mut self, params: &GoParams);
 
@@ -258,6 +335,26 @@ pub trait UciEngine {
     /// it is our turn to move.
     fn ponder_hit(&mut self);
 
+    /// Executes a non-standard command.
+    ///
+    /// `name` is the name of the command (for example "export"), and
+    /// `params` is the rest of the command line. The default
+    /// implementation ignores the command.
+    #[allow(unused_variables)]
+    fn custom_command(&mut self, name: &str, params: &str) {}
+
+    /// Returns whether the engine replies should be written as JSON
+    /// objects, instead of UCI commands.
+    ///
+    /// In JSON mode, each engine reply is written on its own line, as
+    /// an object with a `"type"` field (`"info"` or `"bestmove"`) --
+    /// for example, `{"type":"bestmove","bestmove":"e2e4"}`. The
+    /// replies to the handshake and to the "isready" command are not
+    /// affected. The default implementation returns `false`.
+    fn json_output(&self) -> bool {
+        false
+    }
+
     /// Waits for an engine reply, timing out after a specified
     /// duration or earlier.
     fn wait_for_reply(&mut self, duration: Duration) -> Option<EngineReply>;
@@ -270,14 +367,36 @@ pub trait UciEngine {
 }
 
 
-/// Serves UCI commands until a "quit" command is received.
+/// Serves UCI commands read from `reader`, writing the replies to
+/// `writer`, until a "quit" command is received.
 ///
-/// The current thread will block until the UCI session is closed.
+/// The current thread will block until the UCI session is closed. If
+/// the first command received is "xboard", CECP commands will be
+/// served instead. Passing a reader and a writer other than the
+/// standard input and output makes it possible to run an UCI session
+/// in-process (in tests, for example).
 ///
 /// Returns `Err` if the handshake was unsuccessful, or if an IO error
 /// occurred.
-pub fn run_engine<E: UciEngine>() -> io::Result<()> {
-    let mut server = try!(Server::<E>::wait_for_hanshake());
+pub fn run_engine_with_io<E, R, W>(reader: R, writer: W) -> io::Result<()>
+    where E: UciEngine,
+          R: BufRead + Send + 'static,
+          W: Write
+{
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^\s*xboard(?:\s|$)").unwrap();
+    }
+    let mut reader = reader;
+    let writer = LogWriter::new(writer);
+    let mut line = String::new();
+    if try!(reader.read_line(&mut line)) == 0 {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "EOF"));
+    }
+    io_log::log(io_log::RECEIVED, &line);
+    if RE.is_match(line.as_str()) {
+        return xboard::Server::<E, R, _>::new(reader, writer).serve();
+    }
+    let mut server = try!(Server::<E, R, _>::wait_for_hanshake(&line, reader, writer));
     server.serve()
 }
 
@@ -285,29 +404,29 @@ pub fn run_engine<E: UciEngine>() -> io::Result<()> {
 /// A UCI protocol server.
 ///
 /// Connects the engine to the GUI.
-struct Server<E: UciEngine> {
+struct Server<E: UciEngine, R: BufRead + Send + 'static, W: Write> {
     engine: Option<E>,
+    reader: Option<R>,
+    writer: BufWriter<W>,
 }
 
 
-impl<E: UciEngine> Server<E> {
-    /// Waits for UCI handshake from the GUI.
+impl<E, R, W> Server<E, R, W>
+    where E: UciEngine,
+          R: BufRead + Send + 'static,
+          W: Write
+{
+    /// Finalizes the UCI handshake with the GUI.
     ///
-    /// Will return `Err` if the handshake was unsuccessful, or if an
-    /// IO error has occurred. The current thread will be blocked
-    /// until the handshake is finalized.
-    pub fn wait_for_hanshake() -> io::Result<Self> {
+    /// `line` is the first line received from the GUI. Will return
+    /// `Err` if the handshake was unsuccessful, or if an IO error has
+    /// occurred.
+    pub fn wait_for_hanshake(line: &str, reader: R, writer: W) -> io::Result<Self> {
         lazy_static! {
             static ref RE: Regex = Regex::new(r"\buci(?:\s|$)").unwrap();
         }
-        let stdin = io::stdin();
-        let mut reader = stdin.lock();
-        let mut writer = BufWriter::new(io::stdout());
-        let mut line = String::new();
-        if try!(reader.read_line(&mut line)) == 0 {
-            return Err(io::Error::new(ErrorKind::UnexpectedEof, "EOF"));
-        }
-        if !RE.is_match(line.as_str()) {
+        let mut writer = BufWriter::new(writer);
+        if !RE.is_match(line) {
             return Err(io::Error::new(ErrorKind::Other, "unrecognized protocol"));
         }
         try!(write!(writer, "id name {}\n", E::name()));
@@ -341,7 +460,11 @@ impl<E: UciEngine> Server<E> {
         }
         try!(write!(writer, "uciok\n"));
         try!(writer.flush());
-        Ok(Server { engine: None })
+        Ok(Server {
+               engine: None,
+               reader: Some(reader),
+               writer: writer,
+           })
     }
 
     /// Blocks the current thread and serves UCI commands until a
@@ -349,18 +472,20 @@ impl<E: UciEngine> Server<E> {
     ///
     /// Will return `Err` if an IO error has occurred.
     pub fn serve(&mut self) -> io::Result<()> {
-        let mut writer = BufWriter::new(io::stdout());
+        let writer = &mut self.writer;
         let (tx, rx) = channel();
 
-        // Spawn a thread that reads from `stdin` and writes to `tx`.
+        // Spawn a thread that reads from `reader` and writes to `tx`.
+        let mut reader = self.reader.take().expect("the session is already served");
         let read_thread = spawn(move || -> io::Result<()> {
-            let stdin = io::stdin();
-            let mut reader = stdin.lock();
             let mut line = String::new();
             loop {
                 if let Ok(cmd) = match try!(reader.read_line(&mut line)) {
                        0 => return Err(io::Error::new(ErrorKind::UnexpectedEof, "EOF")),
-                       _ => parse_uci_command(line.as_str()),
+                       _ => {
+                           io_log::log(io_log::RECEIVED, &line);
+                           parse_uci_command(line.as_str())
+                       }
                    } {
                     if let UciCommand::Quit = cmd {
                         return Ok(());
@@ -381,20 +506,10 @@ impl<E: UciEngine> Server<E> {
                 let engine = if let Some(ref mut e) = self.engine {
                     e
                 } else {
-                    // Initialize the engine. (The UCI specification
-                    // states that the "Hash" "setoption" command
-                    // should be the first command passed to the
-                    // engine.)
-                    if let UciCommand::SetOption {
-                               ref name,
-                               ref value,
-                           } = cmd {
-                        if name == "Hash" {
-                            let hash_size_mb = value.parse::<usize>().ok();
-                            self.engine = Some(E::new(hash_size_mb));
-                            continue 'read_commands;
-                        }
-                    }
+                    // Initialize the engine. (This is cheap, because
+                    // the heavyweight components of the engine are
+                    // constructed later, when all "setoption" commands
+                    // have been received.)
                     self.engine = Some(E::new(None));
                     self.engine.as_mut().unwrap()
                 };
@@ -402,6 +517,7 @@ impl<E: UciEngine> Server<E> {
                 // Pass the received command to the engine.
                 match cmd {
                     UciCommand::IsReady => {
+                        engine.is_ready();
                         try!(write!(writer, "readyok\n"));
                         try!(writer.flush());
                     }
@@ -427,6 +543,9 @@ impl<E: UciEngine> Server<E> {
                     UciCommand::Go(params) => {
                         engine.go(&params);
                     }
+                    UciCommand::Custom { name, params } => {
+                        engine.custom_command(name.as_str(), params.as_str());
+                    }
                     UciCommand::Quit => unreachable!(),
                 }
             } // 'read_commands
@@ -436,26 +555,32 @@ impl<E: UciEngine> Server<E> {
                 let mut reply_count = 0;
                 while let Some(reply) = engine.wait_for_reply(Duration::from_millis(25)) {
                     reply_count += 1;
-                    match reply {
-                        EngineReply::BestMove {
-                            best_move,
-                            ponder_move,
-                        } => {
-                            try!(write!(writer,
-                                        "bestmove {}{}",
-                                        best_move,
-                                        match ponder_move {
-                                            None => "\n".to_string(),
-                                            Some(m) => format!(" ponder {}\n", m),
-                                        }))
+                    if engine.json_output() {
+                        if let Some(json) = format_json_reply(reply) {
+                            try!(write!(writer, "{}\n", json));
                         }
-                        EngineReply::Info(infos) => {
-                            if infos.len() > 0 {
-                                try!(write!(writer, "info"));
-                                for InfoItem { info_type, data } in infos {
-                                    try!(write!(writer, " {} {}", info_type, data));
+                    } else {
+                        match reply {
+                            EngineReply::BestMove {
+                                best_move,
+                                ponder_move,
+                            } => {
+                                try!(write!(writer,
+                                            "bestmove {}{}",
+                                            best_move,
+                                            match ponder_move {
+                                                None => "\n".to_string(),
+                                                Some(m) => format!(" ponder {}\n", m),
+                                            }))
+                            }
+                            EngineReply::Info(infos) => {
+                                if infos.len() > 0 {
+                                    try!(write!(writer, "info"));
+                                    for InfoItem { info_type, data } in infos {
+                                        try!(write!(writer, " {} {}", info_type, data));
+                                    }
+                                    try!(write!(writer, "\n"));
                                 }
-                                try!(write!(writer, "\n"));
                             }
                         }
                     }
@@ -482,6 +607,86 @@ impl<E: UciEngine> Server<E> {
 }
 
 
+/// Serializes an engine reply as a JSON object.
+///
+/// Numeric data is written as numbers, the "pv", "refutation", and
+/// "currline" items -- as arrays of moves, and the "score" item -- as
+/// an object (for example, `{"cp":35,"lowerbound":true}`). All other
+/// data is written as strings. Returns `None` for empty "info"
+/// replies.
+fn format_json_reply(reply: EngineReply) -> Option<String> {
+    let mut fields = vec![];
+    match reply {
+        EngineReply::BestMove {
+            best_move,
+            ponder_move,
+        } => {
+            fields.push(("type".to_string(), json_string("bestmove")));
+            fields.push(("bestmove".to_string(), json_string(&best_move)));
+            if let Some(m) = ponder_move {
+                fields.push(("ponder".to_string(), json_string(&m)));
+            }
+        }
+        EngineReply::Info(infos) => {
+            if infos.is_empty() {
+                return None;
+            }
+            fields.push(("type".to_string(), json_string("info")));
+            for InfoItem { info_type, data } in infos {
+                let value = match info_type.as_str() {
+                    "pv" | "refutation" | "currline" => {
+                        let moves: Vec<String> = data.split_whitespace().map(json_string).collect();
+                        format!("[{}]", moves.join(","))
+                    }
+                    "score" => {
+                        let mut items = vec![];
+                        let mut tokens = data.split_whitespace().peekable();
+                        while let Some(t) = tokens.next() {
+                            match tokens.peek().and_then(|x| x.parse::<i64>().ok()) {
+                                Some(n) => {
+                                    tokens.next();
+                                    items.push(format!("{}:{}", json_string(t), n));
+                                }
+                                None => items.push(format!("{}:true", json_string(t))),
+                            }
+                        }
+                        format!("{{{}}}", items.join(","))
+                    }
+                    _ => {
+                        match data.trim().parse::<i64>() {
+                            Ok(n) => n.to_string(),
+                            Err(_) => json_string(&data),
+                        }
+                    }
+                };
+                fields.push((info_type, value));
+            }
+        }
+    }
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|x| format!("{}:{}", json_string(&x.0), x.1))
+        .collect();
+    Some(format!("{{{}}}", fields.join(",")))
+}
+
+
+/// Returns a string literal in JavaScript Object Notation (JSON).
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+
 /// Represents a parse error.
 struct ParseError;
 
@@ -491,9 +696,19 @@ fn parse_uci_command(s: &str) -> Result<UciCommand, ParseError> {
         static ref RE: Regex = Regex::new(
             format!(r"\b({})\s*(?:\s(.*)|$)",
                     "setoption|isready|ucinewgame|\
-                     position|go|stop|ponderhit|quit",
+                     position|go|stop|ponderhit|quit|export|memory|eval",
             ).as_str()
         ).unwrap();
+        static ref RE_D: Regex = Regex::new(r"^\s*d(?:\s+(.*)|\s*$)").unwrap();
+    }
+
+    // "d" is too short to be looked for anywhere in the line, so it
+    // is recognized only as the first word.
+    if let Some(captures) = RE_D.captures(s) {
+        return Ok(UciCommand::Custom {
+                      name: "d".to_string(),
+                      params: captures.get(1).map_or("", |m| m.as_str()).trim().to_string(),
+                  });
     }
     if let Some(captures) = RE.captures(s) {
         let command_str = captures.get(1).unwrap().as_str();
@@ -507,6 +722,12 @@ fn parse_uci_command(s: &str) -> Result<UciCommand, ParseError> {
             "setoption" => parse_setoption_params(params_str),
             "position" => parse_position_params(params_str),
             "go" => parse_go_params(params_str),
+            "export" | "memory" | "eval" => {
+                Ok(UciCommand::Custom {
+                       name: command_str.to_string(),
+                       params: params_str.trim().to_string(),
+                   })
+            }
             _ => Err(ParseError),
         }
     } else {
@@ -536,9 +757,11 @@ fn parse_position_params(s: &str) -> Result<UciCommand, ParseError> {
     lazy_static! {
         static ref RE: Regex = Regex::new(
             format!(
-                r"^(?:fen\s+(?P<fen>{})|startpos)(?:\s+moves(?P<moves>{}))?\s*$",
+                r"^(?:fen\s+(?P<fen>{})|startpos)(?:\s+moves(?P<moves>(?:\s+(?:{}|{}))*))?\s*$",
                 r"[1-8KQRBNPkqrbnp/]+\s+[wb]\s+(?:[KQkq]{1,4}|-)\s+(?:[a-h][1-8]|-)\s+\d+\s+\d+",
-                r"(?:\s+[a-h][1-8][a-h][1-8][qrbn]?)*",  // a possibly empty list of moves
+                r"[a-h][1-8][a-h][1-8][qrbn]?",  // a move in long algebraic notation
+                // A move in Standard Algebraic Notation (SAN):
+                r"(?:[O0]-[O0](?:-[O0])?|[KQRBN]?[a-h]?[1-8]?x?[a-h][1-8](?:=?[QRBN])?)[+#]?[!?]*",
             ).as_str()
         ).unwrap();
     }
@@ -615,6 +838,34 @@ fn parse_go_params(s: &str) -> Result<UciCommand, ParseError> {
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn validate_option() {
+        use super::OptionDescription;
+        let check = OptionDescription::Check { default: false };
+        assert_eq!(check.validate("True").ok().unwrap(), "true");
+        assert_eq!(check.validate(" false").ok().unwrap(), "false");
+        assert!(check.validate("yes").is_err());
+        let spin = OptionDescription::Spin {
+            min: -5,
+            max: 10,
+            default: 0,
+        };
+        assert_eq!(spin.validate("-5").ok().unwrap(), "-5");
+        assert_eq!(spin.validate("10 ").ok().unwrap(), "10");
+        assert!(spin.validate("11").is_err());
+        assert!(spin.validate("1.5").is_err());
+        assert!(spin.validate("").is_err());
+        let combo = OptionDescription::Combo {
+            list: vec!["Off".to_string(), "On".to_string()],
+            default: "Off".to_string(),
+        };
+        assert_eq!(combo.validate("on").ok().unwrap(), "On");
+        assert!(combo.validate("Maybe").is_err());
+        let string = OptionDescription::String { default: String::new() };
+        assert_eq!(string.validate("/tmp/x y").ok().unwrap(), "/tmp/x y");
+        assert_eq!(OptionDescription::Button.validate("x").ok().unwrap(), "");
+    }
+
     #[test]
     fn parse_go_params() {
         use super::{parse_go_params, UciCommand};
@@ -738,7 +989,9 @@ mod tests {
                       "fen   8/8/8/8/8/8/8/k6K w - - 0 1  moves e2e4",
                       "fen   8/8/8/8/8/8/8/k6K   w   -  -  0  1    moves e2e4",
                       "fen   8/8/8/8/8/8/8/k6K w - - 0 1    moves",
-                      "fen   8/8/8/8/8/8/8/k6K w - - 0 1   "];
+                      "fen   8/8/8/8/8/8/8/k6K w - - 0 1   ",
+                      "startpos moves e4 e5 Nf3",
+                      "startpos moves e4!? e5 Nf3?! Nc6 Bb5!! a6?? O-O+!"];
         for (i, s) in params.iter().enumerate() {
             if let Some(UciCommand::Position { fen, moves }) = parse_position_params(s).ok() {
                 match i {
@@ -781,6 +1034,12 @@ mod tests {
                         assert_eq!(fen, "8/8/8/8/8/8/8/k6K w - - 0 1".to_string());
                         assert_eq!(moves.len(), 0);
                     }
+                    9 => {
+                        assert_eq!(moves.split_whitespace().count(), 3);
+                    }
+                    10 => {
+                        assert_eq!(moves.split_whitespace().count(), 7);
+                    }
                     _ => (),
                 }
             } else {
@@ -852,5 +1111,68 @@ mod tests {
                     UciCommand::Go(_) => true,
                     _ => false,
                 });
+        assert!(match parse_uci_command("export psts /tmp/x.txt ").ok().unwrap() {
+                    UciCommand::Custom { name, params } => {
+                        name == "export" && params == "psts /tmp/x.txt"
+                    }
+                    _ => false,
+                });
+        assert!(match parse_uci_command("memory").ok().unwrap() {
+                    UciCommand::Custom { name, params } => name == "memory" && params == "",
+                    _ => false,
+                });
+        assert!(match parse_uci_command("eval").ok().unwrap() {
+                    UciCommand::Custom { name, params } => name == "eval" && params == "",
+                    _ => false,
+                });
+        assert!(match parse_uci_command("d unicode").ok().unwrap() {
+                    UciCommand::Custom { name, params } => name == "d" && params == "unicode",
+                    _ => false,
+                });
+        assert!(match parse_uci_command("  d").ok().unwrap() {
+                    UciCommand::Custom { name, params } => name == "d" && params == "",
+                    _ => false,
+                });
+        assert!(parse_uci_command("dd").is_err());
+        assert!(match parse_uci_command("register later name d").ok() {
+                    Some(UciCommand::Custom { .. }) => false,
+                    _ => true,
+                });
+        assert!(match parse_uci_command("go depth 5").ok().unwrap() {
+                    UciCommand::Go(_) => true,
+                    _ => false,
+                });
+    }
+
+    #[test]
+    fn format_json_reply() {
+        use super::{format_json_reply, json_string, EngineReply, InfoItem};
+        let info = |items: &[(&str, &str)]| {
+            EngineReply::Info(items
+                                  .iter()
+                                  .map(|&(t, d)| {
+                                           InfoItem {
+                                               info_type: t.to_string(),
+                                               data: d.to_string(),
+                                           }
+                                       })
+                                  .collect())
+        };
+        assert_eq!(format_json_reply(info(&[("depth", "8"),
+                                            ("score", "cp -35 lowerbound"),
+                                            ("pv", "e2e4 e7e5")]))
+                           .unwrap(),
+                   concat!(r#"{"type":"info","depth":8,"#,
+                           r#""score":{"cp":-35,"lowerbound":true},"pv":["e2e4","e7e5"]}"#));
+        assert_eq!(format_json_reply(info(&[("string", "a \"b\"")])).unwrap(),
+                   r#"{"type":"info","string":"a \"b\""}"#);
+        assert!(format_json_reply(info(&[])).is_none());
+        assert_eq!(format_json_reply(EngineReply::BestMove {
+                                         best_move: "e2e4".to_string(),
+                                         ponder_move: Some("e7e5".to_string()),
+                                     })
+                           .unwrap(),
+                   r#"{"type":"bestmove","bestmove":"e2e4","ponder":"e7e5"}"#);
+        assert_eq!(json_string("\\\n"), r#""\\\u000a""#);
     }
 }
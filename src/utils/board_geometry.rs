@@ -7,6 +7,11 @@ use bitsets::*;
 
 /// Tables and methods useful for move generation and position
 /// evaluation.
+///
+/// All tables, including the "magic bitboards" attack tables used by
+/// `attacks_from`, are owned by the `BoardGeometry` object, which is
+/// created lazily by `get` the first time it is needed. Therefore,
+/// the tables can be accessed without any unsafe code.
 pub struct BoardGeometry {
     /// Contains bitboards with all squares lying at the line
     /// determined by two squares.
@@ -89,6 +94,22 @@ pub struct BoardGeometry {
     /// . . . . . . . .
     /// ```
     pub pawn_attacks: [[Bitboard; 64]; 2],
+
+    /// The attacking sets for all sliders (bishops and rooks), from
+    /// all origin squares, for all relevant board occupations.
+    slider_attacks: Vec<Bitboard>,
+
+    /// The attacking sets for a king from a given square.
+    king_attacks: [Bitboard; 64],
+
+    /// The attacking sets for a knight from a given square.
+    knight_attacks: [Bitboard; 64],
+
+    /// The "magic" slider maps for bishops, indexed by square.
+    bishop_map: [AttacksMagic; 64],
+
+    /// The "magic" slider maps for rooks, indexed by square.
+    rook_map: [AttacksMagic; 64],
 }
 
 
@@ -100,6 +121,11 @@ impl BoardGeometry {
             squares_between_including: [[0; 64]; 64],
             squares_behind_blocker: [[0; 64]; 64],
             pawn_attacks: [[0; 64]; 2],
+            slider_attacks: vec![0; SLIDER_ATTACKS_SIZE],
+            king_attacks: [0; 64],
+            knight_attacks: [0; 64],
+            bishop_map: [AttacksMagic::default(); 64],
+            rook_map: [AttacksMagic::default(); 64],
         };
 
         // Fill `bg.squares_at_line`.
@@ -144,7 +170,7 @@ impl BoardGeometry {
             }
         }
 
-        // Initialize the global attack tables.
+        // Initialize the attack tables.
         //
         // For every chess engine it is very important to be able to
         // very quickly find the attacking sets for all pieces, from
@@ -208,13 +234,16 @@ impl BoardGeometry {
         // possible. It requires one or two gaps inside the
         // consecutive N bits, to avoid collisions, blowing up the
         // table size.
-        unsafe {
-            init_king_attacks();
-            init_knight_attacks();
-            let bishop_attacks_size = init_slider_map(BISHOP, &mut BISHOP_MAP, 0, false);
-            let total_size = init_slider_map(ROOK, &mut ROOK_MAP, bishop_attacks_size, false);
-            assert!(total_size == SLIDER_ATTACKS_SIZE);
-        }
+        init_king_attacks(&mut bg.king_attacks);
+        init_knight_attacks(&mut bg.knight_attacks);
+        let bishop_attacks_size =
+            init_slider_map(BISHOP, &mut bg.bishop_map, &mut bg.slider_attacks, 0, false);
+        let total_size = init_slider_map(ROOK,
+                                         &mut bg.rook_map,
+                                         &mut bg.slider_attacks,
+                                         bishop_attacks_size,
+                                         false);
+        assert!(total_size == SLIDER_ATTACKS_SIZE);
 
         bg
     }
@@ -225,13 +254,10 @@ impl BoardGeometry {
     /// calls will return a reference to the same object. This is done
     /// in a thread-safe manner.
     pub fn get() -> &'static BoardGeometry {
-        use std::sync::{Once, ONCE_INIT};
-        static INIT_GEOMETRY: Once = ONCE_INIT;
-        static mut GEOMETRY: Option<BoardGeometry> = None;
-        unsafe {
-            INIT_GEOMETRY.call_once(|| { GEOMETRY = Some(BoardGeometry::new()); });
-            GEOMETRY.as_ref().unwrap()
+        lazy_static! {
+            static ref GEOMETRY: BoardGeometry = BoardGeometry::new();
         }
+        &GEOMETRY
     }
 
     /// Returns the set of squares that are attacked by a piece from a
@@ -250,16 +276,17 @@ impl BoardGeometry {
                         -> Bitboard {
         debug_assert!(piece < PAWN);
         debug_assert!(from_square <= 63);
+        let slider_attacks = &self.slider_attacks;
         unsafe {
             match piece {
                 QUEEN => {
-                    BISHOP_MAP[from_square].attacks(occupied) |
-                    ROOK_MAP[from_square].attacks(occupied)
+                    self.bishop_map[from_square].attacks(slider_attacks, occupied) |
+                    self.rook_map[from_square].attacks(slider_attacks, occupied)
                 }
-                ROOK => ROOK_MAP[from_square].attacks(occupied),
-                BISHOP => BISHOP_MAP[from_square].attacks(occupied),
-                KNIGHT => KNIGHT_ATTACKS[from_square],
-                _ => KING_ATTACKS[from_square],
+                ROOK => self.rook_map[from_square].attacks(slider_attacks, occupied),
+                BISHOP => self.bishop_map[from_square].attacks(slider_attacks, occupied),
+                KNIGHT => self.knight_attacks[from_square],
+                _ => self.king_attacks[from_square],
             }
         }
     }
@@ -278,46 +305,72 @@ impl BoardGeometry {
                                       -> Bitboard {
         debug_assert!(piece < PAWN);
         debug_assert!(from_square <= 63);
+        let slider_attacks = &self.slider_attacks;
         match piece {
             QUEEN => {
-                BISHOP_MAP.get_unchecked(from_square).attacks(occupied) |
-                ROOK_MAP.get_unchecked(from_square).attacks(occupied)
+                self.bishop_map.get_unchecked(from_square).attacks(slider_attacks, occupied) |
+                self.rook_map.get_unchecked(from_square).attacks(slider_attacks, occupied)
             }
-            ROOK => ROOK_MAP.get_unchecked(from_square).attacks(occupied),
-            BISHOP => BISHOP_MAP.get_unchecked(from_square).attacks(occupied),
-            KNIGHT => *KNIGHT_ATTACKS.get_unchecked(from_square),
-            _ => *KING_ATTACKS.get_unchecked(from_square),
+            ROOK => self.rook_map.get_unchecked(from_square).attacks(slider_attacks, occupied),
+            BISHOP => self.bishop_map.get_unchecked(from_square).attacks(slider_attacks, occupied),
+            KNIGHT => *self.knight_attacks.get_unchecked(from_square),
+            _ => *self.king_attacks.get_unchecked(from_square),
         }
     }
+
+    /// Returns a bitboard with all enemy pieces and pawns that attack
+    /// the king of the side to move.
+    pub fn checkers(&self, board: &Board) -> Bitboard {
+        let king_square = bsf(board.pieces.piece_type[KING] & board.pieces.color[board.to_move]);
+        let occupied = board.occupied;
+        let piece_type = &board.pieces.piece_type;
+        let attackers = (self.attacks_from(ROOK, king_square, occupied) &
+                         (piece_type[ROOK] | piece_type[QUEEN])) |
+                        (self.attacks_from(BISHOP, king_square, occupied) &
+                         (piece_type[BISHOP] | piece_type[QUEEN])) |
+                        (self.attacks_from(KNIGHT, king_square, occupied) & piece_type[KNIGHT]) |
+                        (self.attacks_from(KING, king_square, occupied) & piece_type[KING]) |
+                        (self.pawn_attacks[board.to_move][king_square] & piece_type[PAWN]);
+        attackers & board.pieces.color[1 ^ board.to_move]
+    }
+
+    /// Returns a bitboard with all pinned pieces and pawns belonging
+    /// to the side to move.
+    pub fn pinned(&self, board: &Board) -> Bitboard {
+        let king_square = bsf(board.pieces.piece_type[KING] & board.pieces.color[board.to_move]);
+        let occupied_by_them = board.pieces.color[1 ^ board.to_move];
+        let defenders = board.pieces.color[board.to_move] & !(1 << king_square);
+        let piece_type = &board.pieces.piece_type;
+
+        // Find the potential pinners, and then for each of them
+        // verify if there is exactly one defender between our king
+        // and the pinner.
+        let mut pinners = occupied_by_them &
+                          ((piece_type[QUEEN] | piece_type[ROOK]) &
+                           self.attacks_from(ROOK, king_square, occupied_by_them) |
+                           (piece_type[QUEEN] | piece_type[BISHOP]) &
+                           self.attacks_from(BISHOP, king_square, occupied_by_them));
+        let mut pinned = 0;
+        while pinners != 0 {
+            let pinner_square = bsf_reset(&mut pinners);
+            let bb = defenders & self.squares_between_including[king_square][pinner_square];
+            if lsb(bb) == bb {
+                pinned |= bb;
+            }
+        }
+        pinned
+    }
 }
 
 
-// Global attack tables (uninitialized).
+/// The total number of attacking sets for all sliders.
 const SLIDER_ATTACKS_SIZE: usize = 107648;
-static mut SLIDER_ATTACKS: [Bitboard; SLIDER_ATTACKS_SIZE] = [0; SLIDER_ATTACKS_SIZE];
-static mut KING_ATTACKS: [Bitboard; 64] = [0; 64];
-static mut KNIGHT_ATTACKS: [Bitboard; 64] = [0; 64];
-
-
-// Global slider maps (uninitialized).
-static mut BISHOP_MAP: [AttacksMagic; 64] = [AttacksMagic {
-    offset: 0,
-    mask: 0,
-    magic: 0,
-    shift: 0,
-}; 64];
-static mut ROOK_MAP: [AttacksMagic; 64] = [AttacksMagic {
-    offset: 0,
-    mask: 0,
-    magic: 0,
-    shift: 0,
-}; 64];
 
 
 /// An object that for a particular slider (bishop or rook) at a
 /// particular square, can "magically" find the corresponding attack
 /// set, for all possible board occupations.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 struct AttacksMagic {
     pub offset: usize,
     pub mask: Bitboard,
@@ -328,20 +381,23 @@ struct AttacksMagic {
 
 impl AttacksMagic {
     /// Returns the attack set for given board occupation.
+    ///
+    /// `slider_attacks` must be the table that has been initialized
+    /// together with this object.
     #[inline(always)]
-    pub unsafe fn attacks(&self, occupied: Bitboard) -> Bitboard {
+    pub unsafe fn attacks(&self, slider_attacks: &[Bitboard], occupied: Bitboard) -> Bitboard {
         let index = (self.magic.wrapping_mul(occupied & self.mask)) >> self.shift;
-        *SLIDER_ATTACKS.get_unchecked(self.offset.wrapping_add(index as usize))
+        *slider_attacks.get_unchecked(self.offset.wrapping_add(index as usize))
     }
 }
 
 
 /// A helper function for `init_magics`. It initializes knight's
 /// attacks table.
-unsafe fn init_knight_attacks() {
+fn init_knight_attacks(table: &mut [Bitboard; 64]) {
     let offsets = vec![(-1, -2), (-2, -1), (-2, 1), (-1, 2), (1, -2), (2, -1), (2, 1), (1, 2)];
 
-    for (i, attacks) in KNIGHT_ATTACKS.iter_mut().enumerate() {
+    for (i, attacks) in table.iter_mut().enumerate() {
         let (r, c) = ((i / 8) as isize, (i % 8) as isize);
 
         for &(dr, dc) in &offsets {
@@ -355,10 +411,10 @@ unsafe fn init_knight_attacks() {
 
 /// A helper function for `init_magics`. It initializes king's attacks
 /// table.
-unsafe fn init_king_attacks() {
+fn init_king_attacks(table: &mut [Bitboard; 64]) {
     let offsets = vec![(1, -1), (1, 0), (1, 1), (0, -1), (0, 1), (-1, -1), (-1, 0), (-1, 1)];
 
-    for (i, attacks) in KING_ATTACKS.iter_mut().enumerate() {
+    for (i, attacks) in table.iter_mut().enumerate() {
         let (r, c) = ((i / 8) as isize, (i % 8) as isize);
 
         for &(dr, dc) in &offsets {
@@ -372,11 +428,12 @@ unsafe fn init_king_attacks() {
 
 /// A helper function for `init_magics`. It initializes the look-up
 /// tables for a particular slider (bishop or rook).
-unsafe fn init_slider_map(piece: PieceType,
-                          piece_map: &mut [AttacksMagic; 64],
-                          mut offset: usize,
-                          from_scratch: bool)
-                          -> usize {
+fn init_slider_map(piece: PieceType,
+                   piece_map: &mut [AttacksMagic; 64],
+                   slider_attacks: &mut [Bitboard],
+                   mut offset: usize,
+                   from_scratch: bool)
+                   -> usize {
     assert!(piece == BISHOP || piece == ROOK);
     let mut rng = thread_rng();
 
@@ -445,7 +502,7 @@ unsafe fn init_slider_map(piece: PieceType,
                 shift: shift,
             };
             for (i, &att) in attacks.iter().enumerate() {
-                SLIDER_ATTACKS[offset + i] = att;
+                slider_attacks[offset + i] = att;
             }
             offset += size;
             break;
@@ -695,4 +752,32 @@ mod tests {
             assert_eq!(rook_attacks, bb_rook_attacks(square, occupied));
         }
     }
+
+    #[test]
+    fn checkers_and_pinned() {
+        let g = BoardGeometry::get();
+        let b = Board::from_fen("k2r4/3r4/3N4/5n2/qp1K2Pq/8/3PPR2/6b1 w - - 0 1").ok().unwrap();
+        assert_eq!(g.checkers(&b), 1 << F5);
+        assert_eq!(g.pinned(&b), 1 << F2 | 1 << D6 | 1 << G4);
+        let b = Board::from_fen("k7/8/8/8/8/8/8/K7 w - - 0 1").ok().unwrap();
+        assert_eq!(g.checkers(&b), 0);
+        assert_eq!(g.pinned(&b), 0);
+    }
+
+    #[test]
+    fn shared_instance() {
+        use std::thread;
+        let g = BoardGeometry::get();
+        let handles: Vec<_> = (0..4)
+            .map(|_| thread::spawn(|| BoardGeometry::get() as *const _ as usize))
+            .collect();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), g as *const _ as usize);
+        }
+        for square in 0..64 {
+            assert_eq!(g.attacks_from(ROOK, square, 0), bb_rook_attacks(square, 0));
+            assert_eq!(unsafe { g.attacks_from_unsafe(BISHOP, square, 0) },
+                       bb_bishop_attacks(square, 0));
+        }
+    }
 }
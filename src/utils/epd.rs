@@ -0,0 +1,205 @@
+//! Implements `EpdRecord` and `parse_epd`.
+
+use std::cmp::{min, max};
+use board::IllegalBoard;
+use moves::Move;
+use value::*;
+use super::notation::{parse_fen, parse_san};
+
+
+/// A parsed Extended Position Description (EPD) record.
+///
+/// EPD records are used by test suites (like "Win At Chess" or
+/// "Strategic Test Suite"). Each record describes a position, and
+/// carries a list of operations. Every operation consists of an
+/// opcode, followed by zero or more operands. The most commonly used
+/// opcodes are:
+///
+/// * `bm` -- the best moves (in SAN);
+/// * `am` -- the moves to avoid (in SAN);
+/// * `id` -- the position identifier;
+/// * `ce` -- the centipawn evaluation.
+#[derive(Clone, Debug)]
+pub struct EpdRecord {
+    /// The position in Forsyth–Edwards Notation (FEN).
+    ///
+    /// The halfmove clock and the fullmove number are taken from the
+    /// `hmvc` and `fmvn` operations, or are `0` and `1` if those
+    /// operations are missing.
+    pub fen: String,
+
+    /// The operations, as (opcode, operands) pairs.
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+
+impl EpdRecord {
+    /// Returns the operands of the first operation with the given
+    /// opcode, or `None` if there is no such operation.
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter().find(|x| x.0 == opcode).map(|x| x.1.as_slice())
+    }
+
+    /// Returns the position identifier (the `id` operation).
+    pub fn id(&self) -> Option<&str> {
+        self.operands("id").and_then(|x| x.first()).map(|x| x.as_str())
+    }
+
+    /// Returns the best moves (the `bm` operation).
+    ///
+    /// `legal_moves` should contain the legal moves in the
+    /// position. Operands that are not legal moves are ignored.
+    pub fn best_moves(&self, legal_moves: &[Move]) -> Vec<Move> {
+        self.moves("bm", legal_moves)
+    }
+
+    /// Returns the moves to avoid (the `am` operation).
+    ///
+    /// `legal_moves` should contain the legal moves in the
+    /// position. Operands that are not legal moves are ignored.
+    pub fn avoid_moves(&self, legal_moves: &[Move]) -> Vec<Move> {
+        self.moves("am", legal_moves)
+    }
+
+    /// Returns the centipawn evaluation (the `ce` operation).
+    ///
+    /// The value is from the point of view of the side to move.
+    pub fn centipawn_evaluation(&self) -> Option<Value> {
+        self.operands("ce")
+            .and_then(|x| x.first())
+            .and_then(|x| x.parse::<Value>().ok())
+            .map(|v| max(VALUE_MIN, min(v, VALUE_MAX)))
+    }
+
+    fn moves(&self, opcode: &str, legal_moves: &[Move]) -> Vec<Move> {
+        self.operands(opcode)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|x| parse_san(x, legal_moves).ok())
+            .collect()
+    }
+}
+
+
+/// Parses an Extended Position Description (EPD) record.
+///
+/// An EPD record consists of the first four fields of a FEN string
+/// (piece placement, active color, castling availability, and
+/// en-passant target square), followed by a list of operations. Each
+/// operation is terminated by a semicolon. Operands that contain
+/// spaces or semicolons are enclosed in double quotes.
+///
+/// ## Example:
+/// `r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - bm Bc5+; id "WAC.003";`
+pub fn parse_epd(s: &str) -> Result<EpdRecord, IllegalBoard> {
+    let s = s.trim();
+    let mut fields = vec![];
+    let mut rest = s;
+    for _ in 0..4 {
+        rest = &rest[rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len())..];
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return Err(IllegalBoard);
+        }
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    let operations = try!(parse_epd_operations(rest));
+    let operand = |opcode: &str, default: &'static str| {
+        operations
+            .iter()
+            .find(|x| x.0 == opcode)
+            .and_then(|x| x.1.first())
+            .map_or(default.to_string(), |x| x.clone())
+    };
+    let fen = format!("{} {} {}",
+                      fields.join(" "),
+                      operand("hmvc", "0"),
+                      operand("fmvn", "1"));
+    try!(parse_fen(&fen));
+    Ok(EpdRecord {
+           fen: fen,
+           operations: operations,
+       })
+}
+
+
+fn parse_epd_operations(s: &str) -> Result<Vec<(String, Vec<String>)>, IllegalBoard> {
+    let mut operations = vec![];
+    let mut tokens: Vec<String> = vec![];
+    let mut token = String::new();
+    let mut in_quotes = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                if in_quotes {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+                in_quotes = !in_quotes;
+            }
+            _ if in_quotes => token.push(c),
+            ';' => {
+                if !token.is_empty() {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+                if tokens.is_empty() {
+                    return Err(IllegalBoard);
+                }
+                let opcode = tokens.remove(0);
+                operations.push((opcode, tokens.clone()));
+                tokens.clear();
+            }
+            _ if c.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+            }
+            _ => token.push(c),
+        }
+    }
+    if in_quotes || !token.is_empty() || !tokens.is_empty() {
+        return Err(IllegalBoard);
+    }
+    Ok(operations)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use search_node::*;
+    use stock::{StdSearchNode, StdQsearch, StdMoveGenerator, SimpleEvaluator};
+    type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+
+    #[test]
+    fn parse_epd_record() {
+        let r = parse_epd("r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - \
+                           bm Bc5+; am Qf2 Nf3+; id \"WAC.003; test\"; ce -35;")
+            .ok()
+            .unwrap();
+        assert_eq!(r.fen, "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1");
+        assert_eq!(r.id(), Some("WAC.003; test"));
+        assert_eq!(r.centipawn_evaluation(), Some(-35));
+        assert_eq!(r.operands("am").unwrap().len(), 2);
+        assert!(r.operands("c0").is_none());
+        let p = P::from_history(&r.fen, &mut vec![].into_iter()).ok().unwrap();
+        let legal_moves = p.legal_moves();
+        let notation = |moves: Vec<Move>| -> Vec<String> {
+            moves.iter().map(|m| m.notation()).collect()
+        };
+        assert_eq!(notation(r.best_moves(&legal_moves)), vec!["f8c5"]);
+        assert_eq!(notation(r.avoid_moves(&legal_moves)), vec!["f6f2", "e5f3"]);
+
+        let r = parse_epd("8/8/8/8/8/8/8/K6k w - - hmvc 12; fmvn 40;").ok().unwrap();
+        assert_eq!(r.fen, "8/8/8/8/8/8/8/K6k w - - 12 40");
+        assert!(r.id().is_none());
+        assert!(parse_epd("8/8/8/8/8/8/8/K6k w - -").is_ok());
+        assert!(parse_epd("8/8/8/8/8/8/8/K6k w -").is_err());
+        assert!(parse_epd("8/8/8/8/8/8/8/K6k w - - bm Kb2").is_err());
+        assert!(parse_epd("8/8/8/8/8/8/8/K6k w - - id \"x;").is_err());
+        assert!(parse_epd("8/8/8/8/8/8/8/K6k w - - ;").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Implements `EvalCache`.
+
+use value::Value;
+
+
+/// A small hash table that remembers static evaluations.
+///
+/// Static evaluation is one of the hottest paths in the quiescence
+/// search, and the same quiet positions are reached again and again
+/// by transposition. `EvalCache` maps position hashes to their
+/// static evaluations, so that those need not be recomputed.
+///
+/// Each entry takes 8 bytes -- the highest 48 bits of the hash, and
+/// the evaluation. The entry for a given hash is selected by the
+/// lowest bits of the hash, and a new entry always replaces the old
+/// one. A cache with zero capacity never remembers anything.
+///
+/// # Examples:
+///
+/// ```rust
+/// # use alcibiades::utils::EvalCache;
+/// let mut cache = EvalCache::new(1024);
+/// assert_eq!(cache.probe(0x1234567812345678), None);
+/// cache.store(0x1234567812345678, -25);
+/// assert_eq!(cache.probe(0x1234567812345678), Some(-25));
+/// assert_eq!(cache.probe(0x8765432112345678), None);
+/// ```
+pub struct EvalCache {
+    entries: Vec<u64>,
+}
+
+
+impl EvalCache {
+    /// Creates a new (empty) instance.
+    ///
+    /// `size_kb` is the desired size in Kbytes. The actual number of
+    /// entries is rounded down to a power of 2.
+    pub fn new(size_kb: usize) -> EvalCache {
+        let n = size_kb * 1024 / 8;
+        let capacity = if n == 0 { 0 } else { 1 << (63 - (n as u64).leading_zeros()) };
+        EvalCache { entries: vec![0; capacity] }
+    }
+
+    /// Returns the number of entries that the cache can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the remembered static evaluation for a given hash, or
+    /// `None` if there is no such evaluation in the cache.
+    #[inline]
+    pub fn probe(&self, hash: u64) -> Option<Value> {
+        if let Some(&entry) = self.entries.get(self.index(hash)) {
+            if entry != 0 && entry & KEY_MASK == hash & KEY_MASK {
+                return Some(entry as u16 as Value);
+            }
+        }
+        None
+    }
+
+    /// Remembers the static evaluation for a given hash.
+    #[inline]
+    pub fn store(&mut self, hash: u64, value: Value) {
+        let index = self.index(hash);
+        if let Some(entry) = self.entries.get_mut(index) {
+            *entry = hash & KEY_MASK | value as u16 as u64;
+        }
+    }
+
+    /// Removes all entries from the cache.
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = 0;
+        }
+    }
+
+    #[inline]
+    fn index(&self, hash: u64) -> usize {
+        hash as usize & self.entries.len().wrapping_sub(1)
+    }
+}
+
+
+const KEY_MASK: u64 = !0xffff;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_and_probe() {
+        let mut cache = EvalCache::new(1);
+        assert_eq!(cache.capacity(), 128);
+        assert_eq!(cache.probe(0), None);
+        cache.store(1 << 20 | 5, 100);
+        cache.store(2 << 20 | 6, -100);
+        assert_eq!(cache.probe(1 << 20 | 5), Some(100));
+        assert_eq!(cache.probe(2 << 20 | 6), Some(-100));
+        assert_eq!(cache.probe(3 << 20 | 5), None);
+        cache.store(3 << 20 | 5, 0);
+        assert_eq!(cache.probe(3 << 20 | 5), Some(0));
+        assert_eq!(cache.probe(1 << 20 | 5), None);
+        cache.clear();
+        assert_eq!(cache.probe(2 << 20 | 6), None);
+
+        let mut cache = EvalCache::new(0);
+        assert_eq!(cache.capacity(), 0);
+        cache.store(1 << 20, 100);
+        assert_eq!(cache.probe(1 << 20), None);
+    }
+}
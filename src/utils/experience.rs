@@ -0,0 +1,204 @@
+//! Implements `ExperienceRecord` and `Experience`.
+
+use std::io;
+use std::io::{Read, Write, Seek, SeekFrom, ErrorKind};
+use std::collections::HashMap;
+use moves::MoveDigest;
+use value::Value;
+use depth::Depth;
+
+
+/// The first bytes of every experience file.
+const FILE_SIGNATURE: &'static [u8; 6] = b"ALCEX\x01";
+
+/// The length of a record in an experience file.
+const RECORD_LENGTH: usize = 14;
+
+
+/// What the engine has learned about a position from an earlier
+/// search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExperienceRecord {
+    /// The hash of the position.
+    pub hash: u64,
+
+    /// The depth of the search.
+    pub depth: Depth,
+
+    /// The value of the position, from the point of view of the side
+    /// to move.
+    pub value: Value,
+
+    /// The best move found.
+    pub move_digest: MoveDigest,
+}
+
+
+/// A collection of experience records, indexed by position hash.
+///
+/// Experience is stored in binary files, which start with a 6-byte
+/// signature, followed by 14-byte records (the hash, the depth, the
+/// value, and the move digest, all in little-endian byte order). New
+/// records are always appended at the end of the file (see
+/// `Experience::append`). When a file contains several records for
+/// the same position, the one from the deepest search wins, and on a
+/// tie -- the one that came last.
+#[derive(Clone, Debug, Default)]
+pub struct Experience {
+    records: HashMap<u64, ExperienceRecord>,
+}
+
+impl Experience {
+    /// Creates a new instance.
+    pub fn new() -> Experience {
+        Experience::default()
+    }
+
+    /// Reads all the records from a binary stream.
+    ///
+    /// An empty stream contains no records. Returns an error with
+    /// `ErrorKind::InvalidData` if the stream does not start with the
+    /// correct signature. A truncated record at the end of the stream
+    /// (the result of an interrupted write) is ignored.
+    pub fn load<R: Read>(mut reader: R) -> io::Result<Experience> {
+        let mut bytes = vec![];
+        try!(reader.read_to_end(&mut bytes));
+        let mut experience = Experience::new();
+        if bytes.is_empty() {
+            return Ok(experience);
+        }
+        if !bytes.starts_with(FILE_SIGNATURE) {
+            return Err(io::Error::new(ErrorKind::InvalidData, "invalid experience file"));
+        }
+        for chunk in bytes[FILE_SIGNATURE.len()..].chunks(RECORD_LENGTH) {
+            if chunk.len() == RECORD_LENGTH {
+                let u16_at = |i: usize| chunk[i] as u16 | (chunk[i + 1] as u16) << 8;
+                let hash = (0..4).fold(0, |x, i| x | (u16_at(2 * i) as u64) << (16 * i));
+                experience.insert(ExperienceRecord {
+                                      hash: hash,
+                                      depth: u16_at(8) as Depth,
+                                      value: u16_at(10) as Value,
+                                      move_digest: MoveDigest::new(u16_at(12)),
+                                  });
+            }
+        }
+        Ok(experience)
+    }
+
+    /// Appends records to a binary stream.
+    ///
+    /// The records are written at the end of the stream, overwriting
+    /// a truncated record if there is one. If the stream is empty, the
+    /// signature is written first. Returns an error with
+    /// `ErrorKind::InvalidData` if the stream is not empty, and does
+    /// not start with the correct signature.
+    pub fn append<W>(mut stream: W, records: &[ExperienceRecord]) -> io::Result<()>
+        where W: Read + Write + Seek
+    {
+        let mut bytes = vec![];
+        let end = try!(stream.seek(SeekFrom::End(0)));
+        let header_length = FILE_SIGNATURE.len() as u64;
+        if end == 0 {
+            bytes.extend_from_slice(FILE_SIGNATURE);
+        } else {
+            let mut signature = [0; 6];
+            try!(stream.seek(SeekFrom::Start(0)));
+            if end < header_length || stream.read_exact(&mut signature).is_err() ||
+               signature != *FILE_SIGNATURE {
+                return Err(io::Error::new(ErrorKind::InvalidData, "invalid experience file"));
+            }
+            let truncated = (end - header_length) % RECORD_LENGTH as u64;
+            try!(stream.seek(SeekFrom::Start(end - truncated)));
+        }
+        for r in records {
+            for x in &[r.hash as u16,
+                       (r.hash >> 16) as u16,
+                       (r.hash >> 32) as u16,
+                       (r.hash >> 48) as u16,
+                       r.depth as u16,
+                       r.value as u16,
+                       r.move_digest.value()] {
+                bytes.push(*x as u8);
+                bytes.push((*x >> 8) as u8);
+            }
+        }
+        try!(stream.write_all(&bytes));
+        stream.flush()
+    }
+
+    /// Adds a record, unless a record from a deeper search for the
+    /// same position already exists.
+    pub fn insert(&mut self, record: ExperienceRecord) {
+        let e = self.records.entry(record.hash).or_insert(record);
+        if e.depth <= record.depth {
+            *e = record;
+        }
+    }
+
+    /// Returns the record for a given position, if there is one.
+    pub fn get(&self, hash: u64) -> Option<&ExperienceRecord> {
+        self.records.get(&hash)
+    }
+
+    /// Returns the number of positions for which there are records.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns whether there are no records.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use moves::MoveDigest;
+    use super::*;
+
+    #[test]
+    fn load_and_append() {
+        let r1 = ExperienceRecord {
+            hash: 0x0123456789abcdef,
+            depth: 10,
+            value: -35,
+            move_digest: MoveDigest::new(0x1234),
+        };
+        let r2 = ExperienceRecord {
+            hash: 0xfedcba9876543210,
+            depth: 3,
+            value: 20000,
+            move_digest: MoveDigest::new(0xffff),
+        };
+        let shallow = ExperienceRecord { depth: 8, ..r1 };
+        let deep = ExperienceRecord { depth: 12, value: 5, ..r1 };
+        let mut file = Cursor::new(vec![]);
+        assert!(Experience::load(&file.get_ref()[..]).unwrap().is_empty());
+        Experience::append(&mut file, &[r1, r2]).unwrap();
+        assert_eq!(file.get_ref().len(), 6 + 2 * 14);
+        Experience::append(&mut file, &[shallow]).unwrap();
+        let e = Experience::load(&file.get_ref()[..]).unwrap();
+        assert_eq!(e.len(), 2);
+        assert_eq!(e.get(r1.hash), Some(&r1));
+        assert_eq!(e.get(r2.hash), Some(&r2));
+        assert_eq!(e.get(0), None);
+
+        // Truncated records are ignored (and overwritten by the next
+        // append), deeper records win.
+        file.get_mut().push(0);
+        let e = Experience::load(&file.get_ref()[..]).unwrap();
+        assert_eq!(e.len(), 2);
+        Experience::append(&mut file, &[deep]).unwrap();
+        assert_eq!(file.get_ref().len(), 6 + 4 * 14);
+        let e = Experience::load(&file.get_ref()[..]).unwrap();
+        assert_eq!(e.get(r1.hash), Some(&deep));
+
+        // Invalid signature.
+        assert!(Experience::load(&b"ALCGR\x01"[..]).is_err());
+        let mut file = Cursor::new(b"ALCGR\x01".to_vec());
+        assert!(Experience::append(&mut file, &[r1]).is_err());
+        assert_eq!(file.get_ref().len(), 6);
+    }
+}
@@ -0,0 +1,557 @@
+//! Implements `GameRecord`, `GameRecordWriter`, and `GameRecordReader`.
+
+use std::io;
+use std::io::{Read, Write, Seek, SeekFrom, ErrorKind};
+use regex::Regex;
+use board::{IllegalBoard, WHITE};
+use moves::MoveDigest;
+use value::*;
+use search_node::SearchNode;
+use super::notation::{parse_san, format_san};
+
+
+/// The FEN of the standard starting position.
+const START_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// The first bytes of every game record file.
+const FILE_SIGNATURE: &'static [u8; 6] = b"ALCGR\x01";
+
+
+/// The outcome of a game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    Unknown,
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    /// Returns the PGN notation for the result ("1-0", "0-1",
+    /// "1/2-1/2", or "*").
+    pub fn notation(&self) -> &'static str {
+        match *self {
+            GameResult::Unknown => "*",
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+
+    fn from_notation(s: &str) -> Option<GameResult> {
+        match s {
+            "*" => Some(GameResult::Unknown),
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> u8 {
+        match *self {
+            GameResult::Unknown => 0,
+            GameResult::WhiteWins => 1,
+            GameResult::BlackWins => 2,
+            GameResult::Draw => 3,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<GameResult> {
+        match code {
+            0 => Some(GameResult::Unknown),
+            1 => Some(GameResult::WhiteWins),
+            2 => Some(GameResult::BlackWins),
+            3 => Some(GameResult::Draw),
+            _ => None,
+        }
+    }
+}
+
+
+/// A compact record of a played game.
+///
+/// Every move is stored as a `MoveDigest`, together with the score
+/// that the engine assigned to the position before the move was
+/// played (from the point of view of the side to move, or
+/// `VALUE_UNKNOWN`). Game records are suitable for storing millions
+/// of self-play games (see `GameRecordWriter` and
+/// `GameRecordReader`), and can be converted to and from Portable
+/// Game Notation (PGN).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRecord {
+    /// The starting position in Forsyth–Edwards Notation.
+    pub fen: String,
+
+    /// The played moves.
+    pub moves: Vec<MoveDigest>,
+
+    /// The scores for the played moves (the same length as `moves`).
+    pub scores: Vec<Value>,
+
+    /// The outcome of the game.
+    pub result: GameResult,
+}
+
+impl GameRecord {
+    /// Creates a new record for a game starting from the given
+    /// position.
+    pub fn new(fen: &str) -> GameRecord {
+        GameRecord {
+            fen: fen.to_string(),
+            moves: vec![],
+            scores: vec![],
+            result: GameResult::Unknown,
+        }
+    }
+
+    /// Appends a played move and its score to the record.
+    pub fn push(&mut self, m: MoveDigest, score: Value) {
+        self.moves.push(m);
+        self.scores.push(score);
+    }
+
+    /// Converts the record to Portable Game Notation (PGN).
+    ///
+    /// Known scores are written as comments after the moves. Returns
+    /// `Err(IllegalBoard)` if the starting position or some of the
+    /// moves are illegal.
+    pub fn to_pgn<T: SearchNode>(&self) -> Result<String, IllegalBoard> {
+        let mut tokens = vec![];
+        let mut played = vec![];
+        for (i, &digest) in self.moves.iter().enumerate() {
+            let position = try!(replay::<T>(&self.fen, &played));
+            let m = try!(position
+                             .legal_moves()
+                             .into_iter()
+                             .find(|m| m.digest() == digest)
+                             .ok_or(IllegalBoard));
+            let fullmove_number = position.fullmove_number();
+            if position.board().to_move == WHITE {
+                tokens.push(format!("{}.", fullmove_number));
+            } else if i == 0 {
+                tokens.push(format!("{}...", fullmove_number));
+            }
+            tokens.push(format_san(&position, m));
+            match self.scores.get(i) {
+                Some(&v) if v != VALUE_UNKNOWN => tokens.push(format!("{{{}}}", v)),
+                _ => (),
+            }
+            played.push(m.notation());
+        }
+        tokens.push(self.result.notation().to_string());
+
+        let mut pgn = String::new();
+        if self.fen != START_FEN {
+            pgn.push_str(&format!("[FEN \"{}\"]\n[SetUp \"1\"]\n", self.fen));
+        }
+        pgn.push_str(&format!("[Result \"{}\"]\n\n", self.result.notation()));
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > 79 {
+                pgn.push('\n');
+                line_length = 0;
+            }
+            if line_length > 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+            pgn.push_str(&token);
+            line_length += token.len();
+        }
+        pgn.push('\n');
+        Ok(pgn)
+    }
+
+    /// Creates a record from a single game in Portable Game Notation
+    /// (PGN).
+    ///
+    /// Tag pairs other than "FEN" and "Result" are ignored. So are
+    /// move numbers, numeric annotation glyphs, and variations.
+    /// Comments that contain only an integer are taken for move
+    /// scores. Returns `Err(IllegalBoard)` if the starting position or
+    /// some of the moves are illegal.
+    pub fn from_pgn<T: SearchNode>(pgn: &str) -> Result<GameRecord, IllegalBoard> {
+        lazy_static! {
+            static ref TAG: Regex = Regex::new(r#"^\s*\[(\w+)\s+"([^"]*)"\]\s*$"#).unwrap();
+            static ref MOVE_NUMBER: Regex = Regex::new(r"^\d+\.*$").unwrap();
+        }
+        let mut record = GameRecord::new(START_FEN);
+        let mut movetext = String::new();
+        for line in pgn.lines() {
+            if let Some(captures) = TAG.captures(line) {
+                match &captures[1] {
+                    "FEN" => record.fen = captures[2].to_string(),
+                    "Result" => {
+                        record.result = GameResult::from_notation(&captures[2])
+                            .unwrap_or(GameResult::Unknown)
+                    }
+                    _ => (),
+                }
+            } else if !line.starts_with('%') {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+
+        let mut played = vec![];
+        let mut position = try!(replay::<T>(&record.fen, &played));
+        for token in tokenize_movetext(&movetext) {
+            match token {
+                PgnToken::Comment(s) => {
+                    if let (Some(score), Ok(v)) = (record.scores.last_mut(), s.trim().parse()) {
+                        *score = v;
+                    }
+                }
+                PgnToken::Symbol(s) => {
+                    if let Some(result) = GameResult::from_notation(&s) {
+                        record.result = result;
+                    } else if !MOVE_NUMBER.is_match(&s) && !s.starts_with('$') {
+                        let m = try!(parse_san(s.trim_start_matches(|c: char| {
+                                                                         c.is_digit(10) || c == '.'
+                                                                     }),
+                                               &position.legal_moves()));
+                        record.push(m.digest(), VALUE_UNKNOWN);
+                        played.push(m.notation());
+                        position = try!(replay::<T>(&record.fen, &played));
+                    }
+                }
+            }
+        }
+        Ok(record)
+    }
+}
+
+
+/// Writes game records to a binary stream.
+///
+/// # Binary format
+///
+/// The stream starts with the 6-byte signature `"ALCGR\x01"`, followed
+/// by the games one after another. Each game consists of a `u32`
+/// payload length, the payload, and a `u32` FNV-1a checksum of the
+/// payload. The payload contains: a `u8` result code (`0`) unknown,
+/// `1`) white wins, `2`) black wins, `3`) draw), a `u16` length of the
+/// starting position's FEN and the FEN itself, a `u16` number of
+/// moves, and then a `u16` move digest and an `i16` score for each
+/// move. All integers are little-endian.
+pub struct GameRecordWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> GameRecordWriter<W> {
+    /// Creates a new instance, writing the file signature to `writer`.
+    pub fn new(mut writer: W) -> io::Result<GameRecordWriter<W>> {
+        try!(writer.write_all(FILE_SIGNATURE));
+        Ok(GameRecordWriter { writer: writer })
+    }
+
+    /// Appends a game record to the stream.
+    pub fn write(&mut self, record: &GameRecord) -> io::Result<()> {
+        if record.fen.len() > 0xffff || record.moves.len() > 0xffff ||
+           record.scores.len() != record.moves.len() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "invalid game record"));
+        }
+        let mut payload = Vec::with_capacity(5 + record.fen.len() + 4 * record.moves.len());
+        payload.push(record.result.code());
+        push_u16(&mut payload, record.fen.len() as u16);
+        payload.extend_from_slice(record.fen.as_bytes());
+        push_u16(&mut payload, record.moves.len() as u16);
+        for (m, &score) in record.moves.iter().zip(record.scores.iter()) {
+            push_u16(&mut payload, m.value());
+            push_u16(&mut payload, score as u16);
+        }
+        let mut header = vec![];
+        push_u32(&mut header, payload.len() as u32);
+        let mut footer = vec![];
+        push_u32(&mut footer, checksum(&payload));
+        try!(self.writer.write_all(&header));
+        try!(self.writer.write_all(&payload));
+        self.writer.write_all(&footer)
+    }
+
+    /// Flushes the underlying stream and returns it.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+}
+
+
+/// Reads game records from a binary stream, in random order.
+///
+/// When created, the reader scans the stream once and builds an
+/// index of the games' offsets, so that each game can be read
+/// directly by its number. (See `GameRecordWriter` for the binary
+/// format.)
+pub struct GameRecordReader<R: Read + Seek> {
+    reader: R,
+    offsets: Vec<u64>,
+}
+
+impl<R: Read + Seek> GameRecordReader<R> {
+    /// Creates a new instance.
+    ///
+    /// Returns an error with `ErrorKind::InvalidData` if the stream
+    /// does not start with the correct signature, or the last game in
+    /// it is truncated.
+    pub fn new(mut reader: R) -> io::Result<GameRecordReader<R>> {
+        let end = try!(reader.seek(SeekFrom::End(0)));
+        try!(reader.seek(SeekFrom::Start(0)));
+        let mut signature = [0; 6];
+        try!(reader.read_exact(&mut signature));
+        if &signature != FILE_SIGNATURE {
+            return Err(invalid_data("not a game record file"));
+        }
+        let mut offsets = vec![];
+        let mut offset = signature.len() as u64;
+        while offset < end {
+            let length = try!(read_u32(&mut reader)) as u64;
+            offsets.push(offset);
+            offset += 4 + length + 4;
+            if offset > end {
+                return Err(invalid_data("truncated game record"));
+            }
+            try!(reader.seek(SeekFrom::Start(offset)));
+        }
+        Ok(GameRecordReader {
+               reader: reader,
+               offsets: offsets,
+           })
+    }
+
+    /// Returns the number of games in the stream.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns if there are no games in the stream.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Reads the game with the given number (starting from zero).
+    ///
+    /// Returns an error with `ErrorKind::InvalidData` if the game's
+    /// checksum does not match, or the game is malformed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn read(&mut self, index: usize) -> io::Result<GameRecord> {
+        try!(self.reader.seek(SeekFrom::Start(self.offsets[index])));
+        let length = try!(read_u32(&mut self.reader)) as usize;
+        let mut payload = vec![0; length];
+        try!(self.reader.read_exact(&mut payload));
+        if try!(read_u32(&mut self.reader)) != checksum(&payload) {
+            return Err(invalid_data("checksum mismatch"));
+        }
+        parse_payload(&payload).ok_or_else(|| invalid_data("malformed game record"))
+    }
+}
+
+
+/// Creates a search node from a starting position and the moves
+/// played so far.
+///
+/// The node is created anew each time so that it is never deemed a
+/// draw by repetition, which would hide its legal moves.
+fn replay<T: SearchNode>(fen: &str, played: &[String]) -> Result<T, IllegalBoard> {
+    T::from_history(fen, &mut played.iter().map(|s| s.as_str()))
+}
+
+
+enum PgnToken {
+    Symbol(String),
+    Comment(String),
+}
+
+
+/// Splits PGN movetext into symbols and comments, skipping
+/// variations and rest-of-line comments.
+fn tokenize_movetext(s: &str) -> Vec<PgnToken> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    let mut variation_level = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                if variation_level == 0 {
+                    tokens.push(PgnToken::Comment(comment));
+                }
+            }
+            ';' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => variation_level += 1,
+            ')' => variation_level -= 1,
+            c if c.is_whitespace() => (),
+            c => {
+                let mut symbol = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}();".contains(c) {
+                        break;
+                    }
+                    symbol.push(c);
+                    chars.next();
+                }
+                if variation_level == 0 {
+                    tokens.push(PgnToken::Symbol(symbol));
+                }
+            }
+        }
+    }
+    tokens
+}
+
+
+fn parse_payload(payload: &[u8]) -> Option<GameRecord> {
+    let mut bytes = payload.iter().cloned();
+    let result = match bytes.next().and_then(GameResult::from_code) {
+        Some(x) => x,
+        None => return None,
+    };
+    let fen_length = match read_u16_from(&mut bytes) {
+        Some(x) => x as usize,
+        None => return None,
+    };
+    let fen: Vec<u8> = bytes.by_ref().take(fen_length).collect();
+    let fen = match String::from_utf8(fen) {
+        Ok(ref x) if x.len() == fen_length => x.clone(),
+        _ => return None,
+    };
+    let mut record = GameRecord::new(&fen);
+    record.result = result;
+    let moves_count = match read_u16_from(&mut bytes) {
+        Some(x) => x,
+        None => return None,
+    };
+    for _ in 0..moves_count {
+        match (read_u16_from(&mut bytes), read_u16_from(&mut bytes)) {
+            (Some(m), Some(score)) => record.push(MoveDigest::new(m), score as Value),
+            _ => return None,
+        }
+    }
+    if bytes.next().is_some() {
+        return None;
+    }
+    Some(record)
+}
+
+
+/// Calculates the 32-bit FNV-1a hash of `bytes`.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |h, &b| (h ^ b as u32).wrapping_mul(0x01000193))
+}
+
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+
+fn push_u16(buf: &mut Vec<u8>, x: u16) {
+    buf.push(x as u8);
+    buf.push((x >> 8) as u8);
+}
+
+
+fn push_u32(buf: &mut Vec<u8>, x: u32) {
+    push_u16(buf, x as u16);
+    push_u16(buf, (x >> 16) as u16);
+}
+
+
+fn read_u16_from<I: Iterator<Item = u8>>(bytes: &mut I) -> Option<u16> {
+    match (bytes.next(), bytes.next()) {
+        (Some(lo), Some(hi)) => Some(lo as u16 | (hi as u16) << 8),
+        _ => None,
+    }
+}
+
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    try!(reader.read_exact(&mut buf));
+    Ok(buf.iter().rev().fold(0, |x, &b| x << 8 | b as u32))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use moves::MoveDigest;
+    use value::*;
+    use stock::{StdSearchNode, StdQsearch, StdMoveGenerator, SimpleEvaluator};
+    use super::*;
+    type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+
+    fn sample_records() -> Vec<GameRecord> {
+        let pgn = "[Event \"Test\"]\n[Result \"1-0\"]\n\n\
+                   1. e4 {20} e5 {-15} 2. Qh5 $2 Nc6 (2... g6) 3. Bc4 Nf6 ; oops\n\
+                   4. Qxf7# 1-0\n";
+        let mut records = vec![GameRecord::from_pgn::<P>(pgn).ok().unwrap()];
+        let mut r = GameRecord::new("8/8/8/4k3/8/8/3QK3/8 b - - 10 40");
+        r.push(MoveDigest::new(0), VALUE_UNKNOWN);
+        r.result = GameResult::Draw;
+        records.push(r);
+        records.push(GameRecord::new("8/8/8/4k3/8/8/3QK3/8 w - - 0 1"));
+        records
+    }
+
+    #[test]
+    fn pgn() {
+        let records = sample_records();
+        let r = &records[0];
+        assert_eq!(r.moves.len(), 7);
+        assert_eq!(&r.scores[..3], &[20, -15, VALUE_UNKNOWN]);
+        assert_eq!(r.result, GameResult::WhiteWins);
+        let pgn = r.to_pgn::<P>().ok().unwrap();
+        assert!(pgn.contains("1. e4 {20} e5 {-15} 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0"));
+        assert_eq!(&GameRecord::from_pgn::<P>(&pgn).ok().unwrap(), r);
+
+        let mut r = GameRecord::from_pgn::<P>("[FEN \"7k/8/6K1/8/8/8/8/R7 b - - 0 1\"]\n\
+                                               1... Kg8 2. Ra8# 1-0")
+            .ok()
+            .unwrap();
+        assert_eq!(r.moves.len(), 2);
+        assert!(r.to_pgn::<P>().ok().unwrap().contains("1... Kg8 2. Ra8# 1-0"));
+        r.moves[1] = r.moves[0];
+        assert!(r.to_pgn::<P>().is_err());
+        assert!(GameRecord::from_pgn::<P>("1. e4 e4").is_err());
+    }
+
+    #[test]
+    fn write_and_read() {
+        let records = sample_records();
+        let mut w = GameRecordWriter::new(Cursor::new(vec![])).ok().unwrap();
+        for r in records.iter() {
+            w.write(r).ok().unwrap();
+        }
+        let bytes = w.into_inner().ok().unwrap().into_inner();
+        let mut reader = GameRecordReader::new(Cursor::new(bytes.clone())).ok().unwrap();
+        assert_eq!(reader.len(), 3);
+        assert_eq!(&reader.read(2).ok().unwrap(), &records[2]);
+        assert_eq!(&reader.read(0).ok().unwrap(), &records[0]);
+        assert_eq!(&reader.read(1).ok().unwrap(), &records[1]);
+
+        // Corrupted data.
+        let mut corrupted = bytes.clone();
+        corrupted[20] ^= 1;
+        let mut reader = GameRecordReader::new(Cursor::new(corrupted)).ok().unwrap();
+        assert!(reader.read(0).is_err());
+        assert!(reader.read(1).is_ok());
+        let truncated = bytes[..bytes.len() - 1].to_vec();
+        assert!(GameRecordReader::new(Cursor::new(truncated)).is_err());
+        assert!(GameRecordReader::new(Cursor::new(vec![0; 6])).is_err());
+        let empty = GameRecordWriter::new(vec![]).ok().unwrap().into_inner().ok().unwrap();
+        assert!(GameRecordReader::new(Cursor::new(empty)).ok().unwrap().is_empty());
+    }
+}
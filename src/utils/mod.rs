@@ -4,46 +4,29 @@ mod board_geometry;
 mod zobrist_arrays;
 mod move_stack;
 mod notation;
+mod game_record;
+mod perft;
+mod bench_positions;
+mod epd;
+mod tunable;
+mod eval_cache;
+mod experience;
+mod opening_book;
+#[cfg(any(test, feature = "movegen-reference"))]
+mod reference_movegen;
 
 pub use self::board_geometry::BoardGeometry;
 pub use self::zobrist_arrays::ZobristArrays;
 pub use self::move_stack::MoveStack;
-pub use self::notation::parse_fen;
+pub use self::notation::{parse_fen, format_fen, parse_san, format_san};
+pub use self::game_record::{GameRecord, GameResult, GameRecordWriter, GameRecordReader};
+pub use self::perft::{perft, divide};
+pub use self::bench_positions::BENCH_POSITIONS;
+pub use self::epd::{EpdRecord, parse_epd};
+pub use self::tunable::Tunable;
+pub use self::eval_cache::EvalCache;
+pub use self::experience::{Experience, ExperienceRecord};
+pub use self::opening_book::OpeningBook;
+#[cfg(any(test, feature = "movegen-reference"))]
+pub use self::reference_movegen::{reference_moves, verify_move_generator, fuzz_move_generator};
 
-
-use depth::*;
-use move_generator::MoveGenerator;
-
-/// Performs move path enumeration.
-///
-/// `perft` is a debugging function to walk the move generation tree
-/// of strictly legal moves and count all the leaf nodes of a certain
-/// depth, which can be compared to predetermined values and used to
-/// isolate bugs. In perft, nodes are only counted at the end after
-/// the last `do_move`. Thus "higher" terminal nodes (e.g. mate or
-/// stalemate) are not counted. Perft ignores draws by repetition, by
-/// the fifty-move rule and by insufficient material. By recording the
-/// amount of time taken for each iteration, it's possible to compare
-/// the performance of different move generators or the same generator
-/// on different machines.
-pub fn perft<T: MoveGenerator>(position: &mut T, depth: Depth) -> u64 {
-    fn pft<T: MoveGenerator>(s: &mut MoveStack, p: &mut T, d: Depth) -> u64 {
-        if d <= 0 {
-            return 1;
-        }
-        let mut nodes = 0;
-        s.save();
-        p.generate_all(s);
-        while let Some(m) = s.pop() {
-            if p.do_move(m).is_some() {
-                nodes += pft(s, p, d - 1);
-                p.undo_move(m);
-            }
-        }
-        s.restore();
-        nodes
-    }
-
-    let mut s = MoveStack::new();
-    pft(&mut s, position, depth)
-}
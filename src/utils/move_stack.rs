@@ -1,10 +1,22 @@
 //! Implements `MoveStack`.
 
+use std::iter::Zip;
+use std::slice::IterMut;
 use moves::{Move, MoveDigest, AddMove};
 
 
 /// Stores a list of moves for each position in a given line of play.
 ///
+/// Each move in the stack has a move score, which is used to
+/// influence move ordering -- moves with higher scores are pulled
+/// first by `pull_best`. Move scores are kept in a separate array,
+/// parallel to the array of moves, so that `Move` can stay an
+/// immutable value type. Moves added with `push` (or `add_move`) get
+/// a score of zero.
+///
+/// Searchers open a new frame (`open_frame`) for each ply they enter,
+/// and close it (`close_frame`) when they leave the ply.
+///
 /// # Examples:
 ///
 /// ```rust
@@ -18,10 +30,10 @@ use moves::{Move, MoveDigest, AddMove};
 /// assert_eq!(s.list().len(), 2);
 /// let first_move = s.pull_best();
 /// assert_eq!(s.list().len(), 1);
-/// s.save();
+/// s.open_frame(64);
 /// assert_eq!(s.ply(), 1);
 /// assert_eq!(s.list().len(), 0);
-/// s.restore();
+/// s.close_frame();
 /// assert_eq!(s.ply(), 0);
 /// assert_eq!(s.list().len(), 1);
 /// let second_move = s.pull_best();
@@ -29,6 +41,7 @@ use moves::{Move, MoveDigest, AddMove};
 /// ```
 pub struct MoveStack {
     moves: Vec<Move>,
+    scores: Vec<u32>,
     savepoints: Vec<usize>,
     first_move_index: usize,
 }
@@ -48,6 +61,7 @@ impl MoveStack {
     pub fn new() -> MoveStack {
         MoveStack {
             moves: Vec::with_capacity(32 * 64),
+            scores: Vec::with_capacity(32 * 64),
             savepoints: Vec::with_capacity(32),
             first_move_index: 0,
         }
@@ -75,9 +89,36 @@ impl MoveStack {
     #[inline]
     pub fn restore(&mut self) {
         self.moves.truncate(self.first_move_index);
+        self.scores.truncate(self.first_move_index);
         self.first_move_index = self.savepoints.pop().unwrap();
     }
 
+    /// Opens a new ply frame, making sure that at least `capacity`
+    /// moves can be added to it without reallocation.
+    ///
+    /// This is the same as `save`, but also reserves capacity for the
+    /// moves that are about to be generated in the new frame. Each
+    /// call to `open_frame` must be paired with a call to
+    /// `close_frame`.
+    #[inline]
+    pub fn open_frame(&mut self, capacity: usize) {
+        self.save();
+        self.moves.reserve(capacity);
+        self.scores.reserve(capacity);
+    }
+
+    /// Closes the last opened ply frame.
+    ///
+    /// This is the same as `restore`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no open frames left.
+    #[inline]
+    pub fn close_frame(&mut self) {
+        self.restore();
+    }
+
     /// Returns the number of saved move lists.
     ///
     /// The number of saved move lists starts at zero. It is
@@ -92,21 +133,34 @@ impl MoveStack {
     #[inline]
     pub fn clear(&mut self) {
         self.moves.truncate(self.first_move_index);
+        self.scores.truncate(self.first_move_index);
     }
 
     /// Clears the current move list and deletes all saved move lists.
     #[inline]
     pub fn clear_all(&mut self) {
         self.moves.clear();
+        self.scores.clear();
         self.savepoints.clear();
         self.first_move_index = 0;
     }
 
     /// Appends a move to the end of the current move list.
+    ///
+    /// The move gets a score of zero.
     #[inline]
     pub fn push(&mut self, m: Move) {
+        self.push_scored(m, 0);
+    }
+
+    /// Appends a move with a given move score to the end of the
+    /// current move list.
+    #[inline]
+    pub fn push_scored(&mut self, m: Move, score: u32) {
         debug_assert!(self.moves.len() >= self.first_move_index);
+        debug_assert_eq!(self.moves.len(), self.scores.len());
         self.moves.push(m);
+        self.scores.push(score);
     }
 
     /// Removes the last move from the current move list and returns it.
@@ -116,6 +170,7 @@ impl MoveStack {
     pub fn pop(&mut self) -> Option<Move> {
         debug_assert!(self.moves.len() >= self.first_move_index);
         if self.moves.len() > self.first_move_index {
+            self.scores.pop();
             self.moves.pop()
         } else {
             None
@@ -133,15 +188,7 @@ impl MoveStack {
     /// Panics if there is no move at the given index.
     #[inline]
     pub fn pull(&mut self, index: usize) -> Move {
-        let last_move = *self.moves.last().unwrap();
-        let m;
-        {
-            let requested_slot = &mut self.moves[self.first_move_index + index];
-            m = *requested_slot;
-            *requested_slot = last_move;
-        }
-        self.moves.pop();
-        m
+        self.remove(self.first_move_index + index).0
     }
 
     /// Removes a specific move from the current move list and returns it.
@@ -154,60 +201,59 @@ impl MoveStack {
     #[inline]
     pub fn pull_move(&mut self, move_digest: MoveDigest) -> Option<Move> {
         debug_assert!(self.moves.len() >= self.first_move_index);
-
-        // The last move in `self.moves` will take the place of the
-        // removed move.
-        let last_move = if let Some(last) = self.moves.last() {
-            *last
+        if let Some(i) = self.list().iter().position(|m| m.digest() == move_digest) {
+            let index = self.first_move_index + i;
+            Some(self.remove(index).0)
         } else {
-            return None;
-        };
-
-        let m;
-        'moves: loop {
-            for curr in self.list_mut().iter_mut() {
-                if curr.digest() == move_digest {
-                    m = *curr;
-                    *curr = last_move;
-                    break 'moves;
-                }
-            }
-            return None;
+            None
         }
-        debug_assert!(!self.moves.is_empty());
-        self.moves.pop();
-        Some(m)
     }
 
-    /// Removes the move with the highest value from the current move
+    /// Removes the move with the highest score from the current move
     /// list and returns it.
     ///
     /// Best move's slot is taken by the last move in the current
     /// list, and the last slot is discarded. `None` is returned if
-    /// the current move list is empty.
+    /// the current move list is empty. Moves that have the same score
+    /// are compared with each other (see `Move`), so that MVV-LVA
+    /// move ordering is followed.
     #[inline]
     pub fn pull_best(&mut self) -> Option<Move> {
+        self.pull_best_scored().map(|(m, _)| m)
+    }
+
+    /// Removes the move with the highest score from the current move
+    /// list and returns it, together with its score.
+    ///
+    /// This is the same as `pull_best`, but also returns the score of
+    /// the pulled move. Each call scans the current move list once,
+    /// so pulling the moves one by one amounts to an in-place
+    /// selection sort that stops as soon as the caller stops pulling
+    /// (after a beta cut-off, for example). This is cheaper than
+    /// sorting the whole list, because usually only the first few
+    /// moves are tried.
+    #[inline]
+    pub fn pull_best_scored(&mut self) -> Option<(Move, u32)> {
         debug_assert!(self.moves.len() >= self.first_move_index);
-        let moves = &mut self.moves;
-        let n = moves.len();
+        debug_assert_eq!(self.moves.len(), self.scores.len());
+        let n = self.moves.len();
         if n > self.first_move_index {
             let last = n - 1;
+            let mut best = last;
             unsafe {
-                let mut best_move = *moves.get_unchecked(last);
-                let mut best = last;
+                let mut best_key = (*self.scores.get_unchecked(last),
+                                    *self.moves.get_unchecked(last));
                 let mut i = last;
                 while i > self.first_move_index {
                     i -= 1;
-                    let m = *moves.get_unchecked(i);
-                    if m > best_move {
-                        best_move = m;
+                    let key = (*self.scores.get_unchecked(i), *self.moves.get_unchecked(i));
+                    if key > best_key {
+                        best_key = key;
                         best = i;
                     }
                 }
-                *moves.get_unchecked_mut(best) = *moves.get_unchecked(last);
-                moves.pop();
-                return Some(best_move);
             }
+            return Some(self.remove(best));
         }
         None
     }
@@ -219,11 +265,49 @@ impl MoveStack {
         &self.moves[self.first_move_index..]
     }
 
-    /// Returns the current move list as a mutable slice.
+    /// Returns an iterator over the current move list that gives
+    /// mutable references to the moves and their scores.
+    ///
+    /// Moves and scores can be changed in place, but can not be
+    /// reordered separately, so that each move keeps its score.
     #[inline]
-    pub fn list_mut(&mut self) -> &mut [Move] {
+    pub fn iter_mut(&mut self) -> Zip<IterMut<Move>, IterMut<u32>> {
         debug_assert!(self.moves.len() >= self.first_move_index);
-        &mut self.moves[self.first_move_index..]
+        debug_assert_eq!(self.moves.len(), self.scores.len());
+        let moves = &mut self.moves[self.first_move_index..];
+        let scores = &mut self.scores[self.first_move_index..];
+        moves.iter_mut().zip(scores.iter_mut())
+    }
+
+    /// Returns the scores of the moves in the current move list as a
+    /// slice.
+    ///
+    /// The returned slice is parallel to the slice returned by
+    /// `list`: the score for `list()[i]` is `scores()[i]`.
+    #[inline]
+    pub fn scores(&self) -> &[u32] {
+        debug_assert!(self.scores.len() >= self.first_move_index);
+        &self.scores[self.first_move_index..]
+    }
+
+    /// Returns the scores of the moves in the current move list as a
+    /// mutable slice.
+    ///
+    /// The returned slice is parallel to the slice returned by
+    /// `list`: the score for `list()[i]` is `scores_mut()[i]`.
+    #[inline]
+    pub fn scores_mut(&mut self) -> &mut [u32] {
+        debug_assert!(self.scores.len() >= self.first_move_index);
+        &mut self.scores[self.first_move_index..]
+    }
+
+    /// A helper method. It removes the move at a given absolute
+    /// `index` and returns it together with its score. Move's slot is
+    /// taken by the last move.
+    #[inline]
+    fn remove(&mut self, index: usize) -> (Move, u32) {
+        debug_assert!(index >= self.first_move_index);
+        (self.moves.swap_remove(index), self.scores.swap_remove(index))
     }
 }
 
@@ -245,7 +329,7 @@ mod tests {
     #[test]
     fn move_stack() {
         let cr = CastlingRights::new(0);
-        let m = Move::new(MOVE_NORMAL, E2, E4, 0, PIECE_NONE, PAWN, cr, 8, 0);
+        let m = Move::new(MOVE_NORMAL, E2, E4, 0, PIECE_NONE, PAWN, cr, 8);
         let mut s = MoveStack::new();
         assert_eq!(s.ply(), 0);
         assert!(s.pull_best().is_none());
@@ -288,4 +372,58 @@ mod tests {
         assert_eq!(s.ply(), 0);
         assert_eq!(s.list().len(), 0);
     }
+
+    #[test]
+    fn move_stack_scores() {
+        let cr = CastlingRights::new(0);
+        let m1 = Move::new(MOVE_NORMAL, E2, E4, 0, PIECE_NONE, PAWN, cr, 8);
+        let m2 = Move::new(MOVE_NORMAL, D2, D4, 0, PIECE_NONE, PAWN, cr, 8);
+        let m3 = Move::new(MOVE_NORMAL, D2, D3, 0, PIECE_NONE, PAWN, cr, 8);
+        let mut s = MoveStack::new();
+        s.push(m1);
+        s.push_scored(m2, 10);
+        s.push_scored(m3, 5);
+        assert_eq!(s.scores(), &[0, 10, 5]);
+        s.scores_mut()[0] = 7;
+        assert_eq!(s.pull_best_scored(), Some((m2, 10)));
+        assert_eq!(s.list(), &[m1, m3]);
+        assert_eq!(s.scores(), &[7, 5]);
+        for (m, score) in s.iter_mut() {
+            if *m == m1 {
+                *score = 20;
+            }
+        }
+        assert_eq!(s.scores(), &[20, 5]);
+        assert_eq!(s.iter_mut().count(), 2);
+        assert_eq!(s.pull_move(m1.digest()), Some(m1));
+        assert_eq!(s.scores(), &[5]);
+        s.save();
+        s.push_scored(m1, 100);
+        s.restore();
+        assert_eq!(s.pull_best_scored(), Some((m3, 5)));
+        assert!(s.pull_best_scored().is_none());
+    }
+
+    #[test]
+    fn move_stack_frames() {
+        let cr = CastlingRights::new(0);
+        let m1 = Move::new(MOVE_NORMAL, E2, E4, 0, PIECE_NONE, PAWN, cr, 8);
+        let m2 = Move::new(MOVE_NORMAL, D2, D4, 0, PIECE_NONE, PAWN, cr, 8);
+        let m3 = Move::new(MOVE_NORMAL, D2, D3, 0, PIECE_NONE, PAWN, cr, 8);
+        let mut s = MoveStack::new();
+        s.push_scored(m1, 1);
+        s.open_frame(256);
+        assert_eq!(s.ply(), 1);
+        assert!(s.list().is_empty());
+        s.push_scored(m1, 3);
+        s.push_scored(m2, 7);
+        s.push_scored(m3, 3);
+        assert_eq!(s.list().len(), 3);
+        assert_eq!(s.pull_best_scored(), Some((m2, 7)));
+        assert_eq!(s.list().len(), 2);
+        s.close_frame();
+        assert_eq!(s.ply(), 0);
+        assert_eq!(s.list(), &[m1]);
+        assert_eq!(s.scores(), &[1]);
+    }
 }
@@ -1,10 +1,14 @@
-//! Implements Forsyth–Edwards Notation parsing.
+//! Implements Forsyth–Edwards Notation parsing and formatting.
 
 use regex::Regex;
 use board::*;
 use files::*;
 use ranks::*;
 
+mod san;
+
+pub use self::san::{parse_san, format_san};
+
 
 /// Parses Forsyth–Edwards Notation (FEN).
 ///
@@ -82,6 +86,67 @@ pub fn parse_fen(s: &str) -> Result<(Board, u8, u16), IllegalBoard> {
 }
 
 
+/// Returns the Forsyth–Edwards Notation (FEN) of a given position.
+///
+/// This is the inverse of `parse_fen`.
+pub fn format_fen(board: &Board, halfmove_clock: u8, fullmove_number: u16) -> String {
+    let mut placement = String::new();
+    for rank in (0..8).rev() {
+        let mut empty_squares = 0;
+        for file in 0..8 {
+            let bb = 1 << Board::square(file, rank);
+            match (KING..PIECE_NONE).find(|&p| board.pieces.piece_type[p] & bb != 0) {
+                Some(piece) => {
+                    if empty_squares > 0 {
+                        placement.push_str(&empty_squares.to_string());
+                        empty_squares = 0;
+                    }
+                    let c = b"kqrbnp"[piece] as char;
+                    placement.push(if board.pieces.color[WHITE] & bb != 0 {
+                                       c.to_ascii_uppercase()
+                                   } else {
+                                       c
+                                   });
+                }
+                None => empty_squares += 1,
+            }
+        }
+        if empty_squares > 0 {
+            placement.push_str(&empty_squares.to_string());
+        }
+        if rank > 0 {
+            placement.push('/');
+        }
+    }
+    let mut castling_rights: String = [(WHITE, KINGSIDE, 'K'),
+                                       (WHITE, QUEENSIDE, 'Q'),
+                                       (BLACK, KINGSIDE, 'k'),
+                                       (BLACK, QUEENSIDE, 'q')]
+            .iter()
+            .filter(|&&(color, side, _)| board.castling_rights.can_castle(color, side))
+            .map(|&(_, _, c)| c)
+            .collect();
+    if castling_rights.is_empty() {
+        castling_rights.push('-');
+    }
+    let enpassant_square = match board.enpassant_file {
+        file if file < 8 => {
+            format!("{}{}",
+                    (b'a' + file as u8) as char,
+                    if board.to_move == WHITE { 6 } else { 3 })
+        }
+        _ => "-".to_string(),
+    };
+    format!("{} {} {} {} {} {}",
+            placement,
+            if board.to_move == WHITE { "w" } else { "b" },
+            castling_rights,
+            enpassant_square,
+            halfmove_clock,
+            fullmove_number)
+}
+
+
 /// Parses square's algebraic notation (lowercase only).
 pub fn parse_square(s: &str) -> Result<Square, IllegalBoard> {
     lazy_static! {
@@ -238,4 +303,17 @@ mod tests {
         assert!(parse_fen("8/8/8/6k1/7P/8/8/6RK b - h3 0 1").is_ok());
         assert!(parse_fen("8/8/8/6k1/7P/8/8/7K b - h3 0 0").is_err());
     }
+
+    #[test]
+    fn format_fen_string() {
+        for fen in &["rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                     "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b Kq - 0 1",
+                     "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w Qk - 3 12",
+                     "8/8/8/6k1/7P/8/8/6RK b - h3 0 1",
+                     "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+                     "k7/8/8/8/8/8/8/7K w - - 49 140"] {
+            let (board, halfmove_clock, fullmove_number) = parse_fen(fen).ok().unwrap();
+            assert_eq!(format_fen(&board, halfmove_clock, fullmove_number), *fen);
+        }
+    }
 }
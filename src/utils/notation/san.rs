@@ -0,0 +1,232 @@
+//! Implements Standard Algebraic Notation (SAN) parsing and formatting.
+
+use regex::Regex;
+use board::*;
+use moves::*;
+use value::VALUE_MIN;
+use search_node::SearchNode;
+use files::*;
+use super::parse_square;
+
+
+/// Parses Standard Algebraic Notation (SAN).
+///
+/// `legal_moves` should contain all legal moves in the current
+/// position. Returns the move from `legal_moves` that `s` denotes, or
+/// `Err(IllegalBoard)` if `s` does not denote exactly one of them.
+///
+/// # Standard Algebraic Notation
+///
+/// Each move is described by the letter of the moved piece ("K",
+/// "Q", "R", "B", or "N" -- nothing for pawns), followed by the
+/// destination square. When more than one piece of the same type can
+/// move to the destination square, the file and/or the rank of the
+/// origin square are inserted between the letter and the destination
+/// square. Captures are marked with "x" before the destination
+/// square, and the promoted piece is given after "=". Castling is
+/// written as "O-O" (king-side) or "O-O-O" (queen-side). A "+" or "#"
+/// suffix marks check or checkmate.
+///
+/// This function is tolerant: the capture mark, the "=" sign, the
+/// check and checkmate suffixes, and the "!" and "?" annotations are
+/// optional. Zeros are accepted in castling ("0-0"). Superfluous
+/// disambiguation is allowed, but insufficient disambiguation is an
+/// error.
+///
+/// ## Examples:
+/// `e4`, `Nf3`, `exd5`, `Rae1`, `N5xd4+`, `e8=Q#`, `O-O-O`
+pub fn parse_san(s: &str, legal_moves: &[Move]) -> Result<Move, IllegalBoard> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            format!(
+                r"^(?:(?P<castling>{})|{}{})[+#]?[!?]*$",
+                r"[O0]-[O0](?:-[O0])?",
+                r"(?P<piece>[KQRBN])?(?P<file>[a-h])?(?P<rank>[1-8])?x?",
+                r"(?P<dest>[a-h][1-8])(?:=?(?P<promotion>[QRBN]))?",
+            ).as_str()
+        ).unwrap();
+    }
+    fn piece_type(s: &str) -> PieceType {
+        match s {
+            "K" => KING,
+            "Q" => QUEEN,
+            "R" => ROOK,
+            "B" => BISHOP,
+            "N" => KNIGHT,
+            _ => unreachable!(),
+        }
+    }
+
+    let captures = try!(RE.captures(s.trim()).ok_or(IllegalBoard));
+    let index = |name, first: u8| {
+        captures
+            .name(name)
+            .map(|x| (x.as_str().as_bytes()[0] - first) as usize)
+    };
+    let castling_file = captures
+        .name("castling")
+        .map(|x| if x.as_str().len() > 3 { FILE_C } else { FILE_G });
+    let piece = captures.name("piece").map_or(PAWN, |x| piece_type(x.as_str()));
+    let orig_file = index("file", b'a');
+    let orig_rank = index("rank", b'1');
+    let dest = captures.name("dest").map(|x| parse_square(x.as_str()).ok().unwrap());
+    let promotion = captures.name("promotion").map(|x| piece_type(x.as_str()));
+    let mut candidates = legal_moves.iter().filter(|m| match castling_file {
+        Some(file) => m.move_type() == MOVE_CASTLING && Board::file(m.dest_square()) == file,
+        None => {
+            m.move_type() != MOVE_CASTLING && m.played_piece() == piece &&
+            Some(m.dest_square()) == dest &&
+            orig_file.map_or(true, |x| x == Board::file(m.orig_square())) &&
+            orig_rank.map_or(true, |x| x == Board::rank(m.orig_square())) &&
+            promotion ==
+            if m.move_type() == MOVE_PROMOTION {
+                Some(Move::piece_from_aux_data(m.aux_data()))
+            } else {
+                None
+            }
+        }
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(m), None) => Ok(*m),
+        _ => Err(IllegalBoard),
+    }
+}
+
+
+/// Formats a move in Standard Algebraic Notation (SAN).
+///
+/// `m` must be a legal move in `position`. Only the minimal necessary
+/// disambiguation is added, and a "+" or "#" suffix is appended when
+/// the move gives check or checkmate. (See `parse_san` for details.)
+///
+/// ## Examples:
+/// `e4`, `Nf3`, `exd5`, `Rae1`, `N5xd4+`, `e8=Q#`, `O-O-O`
+pub fn format_san<T: SearchNode>(position: &T, m: Move) -> String {
+    const PIECE_LETTERS: [&'static str; 6] = ["K", "Q", "R", "B", "N", ""];
+    let orig_square = m.orig_square();
+    let dest_square = m.dest_square();
+    let notation = m.notation();
+    let mut san = if m.move_type() == MOVE_CASTLING {
+        if Board::file(dest_square) == FILE_C {
+            "O-O-O".to_string()
+        } else {
+            "O-O".to_string()
+        }
+    } else {
+        let piece = m.played_piece();
+        let is_capture = m.captured_piece() != PIECE_NONE;
+        let mut s = PIECE_LETTERS[piece].to_string();
+        if piece == PAWN {
+            if is_capture {
+                s.push_str(&notation[0..1]);
+            }
+        } else {
+            let rivals: Vec<Move> = position
+                .legal_moves()
+                .into_iter()
+                .filter(|x| {
+                            x.move_type() != MOVE_CASTLING && x.played_piece() == piece &&
+                            x.dest_square() == dest_square &&
+                            x.orig_square() != orig_square
+                        })
+                .collect();
+            if !rivals.is_empty() {
+                let file = Board::file(orig_square);
+                let rank = Board::rank(orig_square);
+                if rivals.iter().all(|x| Board::file(x.orig_square()) != file) {
+                    s.push_str(&notation[0..1]);
+                } else if rivals.iter().all(|x| Board::rank(x.orig_square()) != rank) {
+                    s.push_str(&notation[1..2]);
+                } else {
+                    s.push_str(&notation[0..2]);
+                }
+            }
+        }
+        if is_capture {
+            s.push('x');
+        }
+        s.push_str(&notation[2..4]);
+        if m.move_type() == MOVE_PROMOTION {
+            s.push('=');
+            s.push_str(PIECE_LETTERS[Move::piece_from_aux_data(m.aux_data())]);
+        }
+        s
+    };
+
+    // Play the move to see if it gives check or checkmate. (Note
+    // that in repeated positions no moves are generated, so we
+    // consult `evaluate_final` to tell checkmates from draws.)
+    let mut p = position.clone();
+    if p.do_move(m) && p.is_check() {
+        if p.legal_moves().is_empty() && p.evaluate_final() == VALUE_MIN {
+            san.push('#');
+        } else {
+            san.push('+');
+        }
+    }
+    san
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_san_string() {
+        use search_node::SearchNode;
+        use stock::{StdSearchNode, StdQsearch, StdMoveGenerator, SimpleEvaluator};
+        type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+        let p = P::from_history("r3k2r/1P6/8/8/8/2N5/8/R3K1NR w KQkq - 0 1",
+                                &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let moves = p.legal_moves();
+        let san = |s| parse_san(s, &moves).ok().map(|m| m.notation());
+        assert_eq!(san("O-O-O"), Some("e1c1".to_string()));
+        assert_eq!(san("0-0-0+"), Some("e1c1".to_string()));
+        assert_eq!(san("O-O"), None);
+        assert_eq!(san("Ne2"), None);
+        assert_eq!(san("Nge2"), Some("g1e2".to_string()));
+        assert_eq!(san("N1e2"), Some("g1e2".to_string()));
+        assert_eq!(san("Nc3e2"), Some("c3e2".to_string()));
+        assert_eq!(san("Nxe2"), None);
+        assert_eq!(san("Nb5!?"), Some("c3b5".to_string()));
+        assert_eq!(san("b8=Q+"), Some("b7b8q".to_string()));
+        assert_eq!(san("bxa8N"), Some("b7a8n".to_string()));
+        assert_eq!(san("b8"), None);
+        assert_eq!(san("e4"), None);
+        assert_eq!(san("Rxa8+"), Some("a1a8".to_string()));
+        assert_eq!(san("e2e4"), None);
+        assert_eq!(san("nb5"), None);
+    }
+
+    #[test]
+    fn format_san_string() {
+        use search_node::SearchNode;
+        use stock::{StdSearchNode, StdQsearch, StdMoveGenerator, SimpleEvaluator};
+        type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+        let p = P::from_history("r3k2r/1P6/8/8/8/2N5/8/R3K1NR w KQkq - 0 1",
+                                &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let moves = p.legal_moves();
+        for m in moves.iter() {
+            assert_eq!(parse_san(&format_san(&p, *m), &moves).ok(), Some(*m));
+        }
+        let san = |s| format_san(&p, parse_san(s, &moves).ok().unwrap());
+        assert_eq!(san("O-O-O"), "O-O-O");
+        assert_eq!(san("Nge2"), "Nge2");
+        assert_eq!(san("Nb5"), "Nb5");
+        assert_eq!(san("b8=Q"), "b8=Q+");
+        assert_eq!(san("bxa8N"), "bxa8=N");
+        assert_eq!(san("Rxa8"), "Rxa8+");
+        assert_eq!(san("Kf2"), "Kf2");
+
+        let p = P::from_history("7k/8/6K1/8/8/8/8/R7 w - - 0 1", &mut vec![].into_iter())
+            .ok()
+            .unwrap();
+        let moves = p.legal_moves();
+        assert_eq!(format_san(&p, parse_san("Ra8", &moves).ok().unwrap()), "Ra8#");
+    }
+}
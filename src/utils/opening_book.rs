@@ -0,0 +1,146 @@
+//! Implements `OpeningBook`.
+
+use std::io;
+use std::io::{BufRead, ErrorKind};
+use std::collections::HashMap;
+use board::Board;
+use search_node::SearchNode;
+use super::notation::format_fen;
+
+
+/// The starting position in Forsyth–Edwards notation.
+const START_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+
+/// A collection of opening lines.
+///
+/// Opening books are stored in text files. Each line of the file
+/// contains an opening line -- a sequence of moves in long algebraic
+/// notation, played from the starting position. Empty lines, and
+/// lines starting with "#" are ignored. For each position in the
+/// book, the book move is the move that is played in most of the
+/// opening lines (on a tie -- the one that came first).
+#[derive(Clone, Debug, Default)]
+pub struct OpeningBook {
+    // The moves played in each position, together with the number of
+    // lines in which they are played.
+    moves: HashMap<String, Vec<(String, u32)>>,
+}
+
+impl OpeningBook {
+    /// Creates a new instance.
+    pub fn new() -> OpeningBook {
+        OpeningBook::default()
+    }
+
+    /// Reads all the opening lines from a text stream.
+    ///
+    /// Returns an error with `ErrorKind::InvalidData` if an opening
+    /// line contains an illegal move.
+    pub fn load<N: SearchNode, R: BufRead>(reader: R) -> io::Result<OpeningBook> {
+        let mut book = OpeningBook::new();
+        for (n, line) in reader.lines().enumerate() {
+            let line = try!(line);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut position = N::from_history(START_FEN, &mut vec![].into_iter())
+                .ok()
+                .unwrap();
+            for notation in line.split_whitespace() {
+                match position.legal_moves().into_iter().find(|m| m.notation() == notation) {
+                    Some(m) => {
+                        book.insert(position.board(), notation);
+                        position.do_move(m);
+                    }
+                    None => {
+                        return Err(io::Error::new(ErrorKind::InvalidData,
+                                                  format!("illegal move {} on line {}",
+                                                          notation,
+                                                          n + 1)));
+                    }
+                }
+            }
+        }
+        Ok(book)
+    }
+
+    /// Adds a move (in long algebraic notation) played in a given
+    /// position.
+    pub fn insert(&mut self, board: &Board, notation: &str) {
+        let moves = self.moves.entry(key(board)).or_insert(vec![]);
+        if let Some(x) = moves.iter_mut().find(|x| x.0 == notation) {
+            x.1 += 1;
+            return;
+        }
+        moves.push((notation.to_string(), 1));
+    }
+
+    /// Returns the book move (in long algebraic notation) for a given
+    /// position, if there is one.
+    pub fn probe(&self, board: &Board) -> Option<&str> {
+        self.moves.get(&key(board)).and_then(|moves| {
+            moves.iter()
+                 .rev()
+                 .max_by_key(|x| x.1)
+                 .map(|x| x.0.as_str())
+        })
+    }
+
+    /// Returns the number of positions in the book.
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// Returns whether the book is empty.
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+}
+
+
+/// A helper function. It returns the key under which the moves for a
+/// given position are stored. (The halfmove clock and the move number
+/// do not matter.)
+fn key(board: &Board) -> String {
+    format_fen(board, 0, 1)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use search_node::SearchNode;
+    use stock::{StdSearchNode, StdQsearch, StdMoveGenerator, SimpleEvaluator};
+    type P = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+
+    #[test]
+    fn load_and_probe() {
+        let text = "# A small book.\n\
+                    e2e4 e7e5 g1f3\n\
+                    \n\
+                    d2d4 d7d5\n\
+                    e2e4 c7c5\n\
+                    d2d4 g8f6 c2c4\n";
+        let book = OpeningBook::load::<P, _>(text.as_bytes()).unwrap();
+        assert_eq!(book.len(), 5);
+        let position = |moves: &str| {
+            P::from_history(START_FEN, &mut moves.split_whitespace()).ok().unwrap()
+        };
+        assert_eq!(book.probe(position("").board()), Some("e2e4"));
+        assert_eq!(book.probe(position("e2e4").board()), Some("e7e5"));
+        assert_eq!(book.probe(position("d2d4 g8f6").board()), Some("c2c4"));
+        assert_eq!(book.probe(position("e2e4 e7e5 g1f3").board()), None);
+        assert_eq!(book.probe(position("g1f3").board()), None);
+
+        // Transpositions are recognized.
+        let mut book = OpeningBook::new();
+        book.insert(position("g1f3 g8f6 b1c3").board(), "b8c6");
+        assert_eq!(book.probe(position("b1c3 g8f6 g1f3").board()), Some("b8c6"));
+
+        // Illegal moves.
+        let e = OpeningBook::load::<P, _>("e2e4 e2e4\n".as_bytes()).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,85 @@
+//! Implements `perft` and `divide`.
+
+use depth::*;
+use moves::Move;
+use move_generator::MoveGenerator;
+use utils::MoveStack;
+
+
+/// Performs move path enumeration.
+///
+/// `perft` is a debugging function to walk the move generation tree
+/// of strictly legal moves and count all the leaf nodes of a certain
+/// depth, which can be compared to predetermined values and used to
+/// isolate bugs. In perft, nodes are only counted at the end after
+/// the last `do_move`. Thus "higher" terminal nodes (e.g. mate or
+/// stalemate) are not counted. Perft ignores draws by repetition, by
+/// the fifty-move rule and by insufficient material. By recording the
+/// amount of time taken for each iteration, it's possible to compare
+/// the performance of different move generators or the same generator
+/// on different machines.
+pub fn perft<T: MoveGenerator>(position: &mut T, depth: Depth) -> u64 {
+    fn pft<T: MoveGenerator>(s: &mut MoveStack, p: &mut T, d: Depth) -> u64 {
+        if d <= 0 {
+            return 1;
+        }
+        let mut nodes = 0;
+        s.save();
+        p.generate_all(s);
+        while let Some(m) = s.pop() {
+            if p.do_move(m).is_some() {
+                nodes += pft(s, p, d - 1);
+                p.undo_move(m);
+            }
+        }
+        s.restore();
+        nodes
+    }
+
+    let mut s = MoveStack::new();
+    pft(&mut s, position, depth)
+}
+
+
+/// Performs move path enumeration for each legal move separately.
+///
+/// Returns the legal moves in the position, each one paired with the
+/// number of leaf nodes of depth `depth` that it leads to. The sum of
+/// the counts equals `perft(position, depth)`. This is useful for
+/// finding the exact line in which a move generator is broken, by
+/// comparing the counts with those of a trusted implementation.
+pub fn divide<T: MoveGenerator>(position: &mut T, depth: Depth) -> Vec<(Move, u64)> {
+    let mut result = vec![];
+    if depth > 0 {
+        let mut s = MoveStack::new();
+        position.generate_all(&mut s);
+        for m in s.list().to_vec() {
+            if position.do_move(m).is_some() {
+                result.push((m, perft(position, depth - 1)));
+                position.undo_move(m);
+            }
+        }
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::Board;
+    use stock::{StdMoveGenerator, SimpleEvaluator};
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let board = Board::from_fen(fen).ok().unwrap();
+        let mut g = StdMoveGenerator::<SimpleEvaluator>::from_board(board).ok().unwrap();
+        assert_eq!(divide(&mut g, 0).len(), 0);
+        assert_eq!(divide(&mut g, 1).len(), 48);
+        let counts = divide(&mut g, 2);
+        assert_eq!(counts.len(), 48);
+        assert_eq!(counts.iter().map(|&(_, n)| n).sum::<u64>(), 2_039);
+        assert_eq!(counts.iter().find(|&&(m, _)| m.notation() == "e1g1").unwrap().1, 43);
+    }
+}
@@ -0,0 +1,393 @@
+//! Implements a simple reference move generator.
+
+use rand::{Rng, SeedableRng};
+use rand::isaac::Isaac64Rng;
+use board::*;
+use squares::*;
+use move_generator::MoveGenerator;
+use utils::MoveStack;
+
+
+/// A mailbox representation of the board. Each square contains
+/// either `None`, or the color and the type of the piece on it.
+type Mailbox = [Option<(Color, PieceType)>; 64];
+
+const KNIGHT_STEPS: [(isize, isize); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1),
+                                           (-2, 1), (-1, 2)];
+const KING_STEPS: [(isize, isize); 8] = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1),
+                                         (-1, 0), (-1, 1)];
+const ROOK_DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const BISHOP_DIRECTIONS: [(isize, isize); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+
+/// Returns all legal moves in `board`, in sorted coordinate notation.
+///
+/// This is a slow, but straightforward mailbox move generator. It
+/// does not share any code with the bitboard move generators, and
+/// therefore can be used as a reference to verify them. (See
+/// `verify_move_generator`.) Castling moves are written as king's
+/// moves (`e1g1` for example), and promotions have a lowercase
+/// suffix (`e7e8q` for example).
+pub fn reference_moves(board: &Board) -> Vec<String> {
+    let mailbox = mailbox(board);
+    let us = board.to_move;
+    let mut moves = vec![];
+    for orig in 0..64 {
+        let piece = match mailbox[orig] {
+            Some((color, piece)) if color == us => piece,
+            _ => continue,
+        };
+        let mut dests = vec![];
+        match piece {
+            KING => dests.extend(steps(&mailbox, orig, us, &KING_STEPS)),
+            QUEEN => {
+                dests.extend(slides(&mailbox, orig, us, &ROOK_DIRECTIONS));
+                dests.extend(slides(&mailbox, orig, us, &BISHOP_DIRECTIONS));
+            }
+            ROOK => dests.extend(slides(&mailbox, orig, us, &ROOK_DIRECTIONS)),
+            BISHOP => dests.extend(slides(&mailbox, orig, us, &BISHOP_DIRECTIONS)),
+            KNIGHT => dests.extend(steps(&mailbox, orig, us, &KNIGHT_STEPS)),
+            _ => {
+                // Pawn pushes and captures (including en-passant).
+                let (forward, start_rank, last_rank) = if us == WHITE {
+                    (1, 1, 7)
+                } else {
+                    (-1, 6, 0)
+                };
+                if let Some(dest) = offset(orig, 0, forward) {
+                    if mailbox[dest].is_none() {
+                        dests.push(dest);
+                        if Board::rank(orig) == start_rank {
+                            let dest = offset(dest, 0, forward).unwrap();
+                            if mailbox[dest].is_none() {
+                                dests.push(dest);
+                            }
+                        }
+                    }
+                }
+                for &file_step in &[-1, 1] {
+                    if let Some(dest) = offset(orig, file_step, forward) {
+                        match mailbox[dest] {
+                            Some((color, _)) if color != us => dests.push(dest),
+                            None if is_enpassant_square(board, dest) => dests.push(dest),
+                            _ => (),
+                        }
+                    }
+                }
+                for dest in dests {
+                    let mut b = mailbox;
+                    b[dest] = b[orig].take();
+                    if dest != orig && Board::file(dest) != Board::file(orig) &&
+                       mailbox[dest].is_none() {
+                        // En-passant capture.
+                        b[Board::square(Board::file(dest), Board::rank(orig))] = None;
+                    }
+                    if !is_attacked(&b, king_square(&b, us), 1 ^ us) {
+                        let notation = format!("{}{}", notation(orig), notation(dest));
+                        if Board::rank(dest) == last_rank {
+                            for suffix in &["q", "r", "b", "n"] {
+                                moves.push(format!("{}{}", notation, suffix));
+                            }
+                        } else {
+                            moves.push(notation);
+                        }
+                    }
+                }
+                continue;
+            }
+        }
+        for dest in dests {
+            let mut b = mailbox;
+            b[dest] = b[orig].take();
+            if !is_attacked(&b, king_square(&b, us), 1 ^ us) {
+                moves.push(format!("{}{}", notation(orig), notation(dest)));
+            }
+        }
+    }
+
+    // Castling.
+    let (king_orig, rank) = if us == WHITE { (E1, 0) } else { (E8, 7) };
+    if mailbox[king_orig] == Some((us, KING)) && !is_attacked(&mailbox, king_orig, 1 ^ us) {
+        for &(side, rook_file, empty_files, safe_files) in
+            &[(QUEENSIDE, 0, &[1, 2, 3][..], &[3, 2][..]),
+              (KINGSIDE, 7, &[5, 6][..], &[5, 6][..])] {
+            if board.castling_rights.can_castle(us, side) &&
+               mailbox[Board::square(rook_file, rank)] == Some((us, ROOK)) &&
+               empty_files.iter().all(|&f| mailbox[Board::square(f, rank)].is_none()) &&
+               safe_files.iter().all(|&f| !is_attacked(&mailbox, Board::square(f, rank), 1 ^ us)) {
+                let king_dest = Board::square(safe_files[1], rank);
+                moves.push(format!("{}{}", notation(king_orig), notation(king_dest)));
+            }
+        }
+    }
+
+    moves.sort();
+    moves
+}
+
+
+/// Verifies that the move generator generates exactly the legal moves
+/// that `reference_moves` finds.
+///
+/// The legal moves are obtained by calling `generate_all` and
+/// filtering out the moves that `do_move` rejects. On failure, an
+/// error message is returned that describes the missing and the
+/// superfluous moves.
+pub fn verify_move_generator<T: MoveGenerator>(position: &T) -> Result<(), String> {
+    let expected = reference_moves(position.board());
+    let generated = generated_moves(position);
+    if generated == expected {
+        return Ok(());
+    }
+    let missing: Vec<&str> = expected
+        .iter()
+        .filter(|m| !generated.contains(m))
+        .map(|m| m.as_str())
+        .collect();
+    let superfluous: Vec<&str> = generated
+        .iter()
+        .filter(|m| !expected.contains(m))
+        .map(|m| m.as_str())
+        .collect();
+    Err(format!("in \"{}\": missing moves [{}], superfluous moves [{}]",
+                position.board(),
+                missing.join(" "),
+                superfluous.join(" ")))
+}
+
+
+/// Plays random games from a given position, and verifies the move
+/// generator in every encountered position.
+///
+/// `games` random games with at most `plies` half-moves each are
+/// played. The games are a function of `seed`, so that a failure can
+/// be reproduced. Returns the number of verified positions, or an
+/// error message describing the first discrepancy and the seed. (See
+/// `verify_move_generator`.)
+pub fn fuzz_move_generator<T: MoveGenerator>(position: &T,
+                                             games: usize,
+                                             plies: usize,
+                                             seed: u64)
+                                             -> Result<usize, String> {
+    let mut rng: Isaac64Rng = SeedableRng::from_seed(&[seed][..]);
+    let mut count = 0;
+    let mut s = MoveStack::new();
+    for _ in 0..games {
+        let mut p = position.clone();
+        for _ in 0..plies + 1 {
+            try!(verify_move_generator(&p).map_err(|e| format!("{} (seed {})", e, seed)));
+            count += 1;
+            s.clear();
+            p.generate_all(&mut s);
+            let mut legal_moves = s.list().to_vec();
+            legal_moves.retain(|&m| {
+                                   let mut q = p.clone();
+                                   q.do_move(m).is_some()
+                               });
+            if legal_moves.is_empty() {
+                break;
+            }
+            let m = legal_moves[rng.gen_range(0, legal_moves.len())];
+            p.do_move(m);
+        }
+    }
+    Ok(count)
+}
+
+
+/// A helper function. It returns the legal moves generated by `position`,
+/// in sorted coordinate notation.
+fn generated_moves<T: MoveGenerator>(position: &T) -> Vec<String> {
+    let mut p = position.clone();
+    let mut s = MoveStack::new();
+    p.generate_all(&mut s);
+    let mut moves = vec![];
+    for m in s.list().to_vec() {
+        if p.do_move(m).is_some() {
+            moves.push(m.notation());
+            p.undo_move(m);
+        }
+    }
+    moves.sort();
+    moves
+}
+
+
+/// A helper function. It converts the board to mailbox representation.
+fn mailbox(board: &Board) -> Mailbox {
+    let mut mailbox = [None; 64];
+    for (square, x) in mailbox.iter_mut().enumerate() {
+        for color in 0..2 {
+            for piece in 0..6 {
+                if board.pieces.color[color] & board.pieces.piece_type[piece] & 1 << square != 0 {
+                    *x = Some((color, piece));
+                }
+            }
+        }
+    }
+    mailbox
+}
+
+
+/// A helper function. It returns the square that is `file_step` files
+/// and `rank_step` ranks away from `square`, if it is on the board.
+fn offset(square: Square, file_step: isize, rank_step: isize) -> Option<Square> {
+    let file = Board::file(square) as isize + file_step;
+    let rank = Board::rank(square) as isize + rank_step;
+    if 0 <= file && file < 8 && 0 <= rank && rank < 8 {
+        Some(Board::square(file as usize, rank as usize))
+    } else {
+        None
+    }
+}
+
+
+/// A helper function. It returns the squares to which a non-sliding
+/// piece can go, ignoring checks.
+fn steps(mailbox: &Mailbox, orig: Square, us: Color, steps: &[(isize, isize)]) -> Vec<Square> {
+    steps
+        .iter()
+        .filter_map(|&(f, r)| offset(orig, f, r))
+        .filter(|&dest| match mailbox[dest] {
+                    Some((color, _)) => color != us,
+                    None => true,
+                })
+        .collect()
+}
+
+
+/// A helper function. It returns the squares to which a sliding piece
+/// can go, ignoring checks.
+fn slides(mailbox: &Mailbox,
+          orig: Square,
+          us: Color,
+          directions: &[(isize, isize)])
+          -> Vec<Square> {
+    let mut dests = vec![];
+    for &(f, r) in directions {
+        let mut square = orig;
+        while let Some(dest) = offset(square, f, r) {
+            match mailbox[dest] {
+                Some((color, _)) => {
+                    if color != us {
+                        dests.push(dest);
+                    }
+                    break;
+                }
+                None => dests.push(dest),
+            }
+            square = dest;
+        }
+    }
+    dests
+}
+
+
+/// A helper function. It returns if `square` is attacked by a piece
+/// of color `attacker`.
+fn is_attacked(mailbox: &Mailbox, square: Square, attacker: Color) -> bool {
+    let is = |s: Option<Square>, pieces: &[PieceType]| match s.and_then(|s| mailbox[s]) {
+        Some((color, piece)) => color == attacker && pieces.contains(&piece),
+        None => false,
+    };
+    let pawn_rank_step = if attacker == WHITE { -1 } else { 1 };
+    if is(offset(square, -1, pawn_rank_step), &[PAWN]) ||
+       is(offset(square, 1, pawn_rank_step), &[PAWN]) ||
+       KNIGHT_STEPS.iter().any(|&(f, r)| is(offset(square, f, r), &[KNIGHT])) ||
+       KING_STEPS.iter().any(|&(f, r)| is(offset(square, f, r), &[KING])) {
+        return true;
+    }
+    for &(directions, pieces) in &[(&ROOK_DIRECTIONS, [ROOK, QUEEN]),
+                                   (&BISHOP_DIRECTIONS, [BISHOP, QUEEN])] {
+        for &(f, r) in directions.iter() {
+            let mut s = square;
+            while let Some(next) = offset(s, f, r) {
+                if mailbox[next].is_some() {
+                    if is(Some(next), &pieces) {
+                        return true;
+                    }
+                    break;
+                }
+                s = next;
+            }
+        }
+    }
+    false
+}
+
+
+/// A helper function. It returns the square of the king of color
+/// `us`.
+fn king_square(mailbox: &Mailbox, us: Color) -> Square {
+    (0..64).find(|&s| mailbox[s] == Some((us, KING))).unwrap()
+}
+
+
+/// A helper function. It returns if `square` is the square on which
+/// an en-passant capture can be made.
+fn is_enpassant_square(board: &Board, square: Square) -> bool {
+    let rank = if board.to_move == WHITE { 5 } else { 2 };
+    board.enpassant_file < 8 && square == Board::square(board.enpassant_file, rank)
+}
+
+
+/// A helper function. It returns the algebraic notation for `square`.
+fn notation(square: Square) -> String {
+    format!("{}{}",
+            ["a", "b", "c", "d", "e", "f", "g", "h"][Board::file(square)],
+            Board::rank(square) + 1)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::Board;
+    use move_generator::MoveGenerator;
+    use utils::BENCH_POSITIONS;
+    use stock::{StdMoveGenerator, SimpleEvaluator};
+    type P = StdMoveGenerator<SimpleEvaluator>;
+
+    fn position(fen: &str) -> P {
+        P::from_board(Board::from_fen(fen).ok().unwrap()).ok().unwrap()
+    }
+
+    #[test]
+    fn reference_move_counts() {
+        let count = |fen| reference_moves(&Board::from_fen(fen).ok().unwrap()).len();
+        assert_eq!(count("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), 20);
+        assert_eq!(count("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+                   48);
+        assert_eq!(count("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"), 14);
+        assert_eq!(count("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"), 6);
+    }
+
+    #[test]
+    fn verify_edge_cases() {
+        for fen in &["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                     "8/8/8/K2pP2q/8/8/8/7k w - d6 0 1",
+                     "8/8/8/3pP3/8/8/8/K6k w - d6 0 1",
+                     "4k3/8/8/2KpP3/8/8/8/8 w - d6 0 1",
+                     "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+                     "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1",
+                     "r3k2r/8/8/8/8/8/5q2/R3K2R w KQkq - 0 1",
+                     "r3k2r/8/8/8/8/8/8/R2rK2R w KQ - 0 1",
+                     "1r2k2r/8/8/8/8/8/8/R3K2R w KQk - 0 1",
+                     "r3k3/1P6/8/8/8/8/8/4K3 w q - 0 1",
+                     "4k3/8/8/8/8/8/6p1/4K2R b K - 0 1"] {
+            assert_eq!(verify_move_generator(&position(fen)), Ok(()));
+        }
+        for fen in BENCH_POSITIONS.iter() {
+            assert_eq!(verify_move_generator(&position(fen)), Ok(()));
+        }
+    }
+
+    #[test]
+    fn fuzz_random_games() {
+        let p = position("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert!(fuzz_move_generator(&p, 20, 200, 1).unwrap() > 20);
+        let p = position("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert!(fuzz_move_generator(&p, 20, 100, 2).unwrap() > 20);
+        assert_eq!(fuzz_move_generator(&p, 5, 50, 3), fuzz_move_generator(&p, 5, 50, 3));
+    }
+}
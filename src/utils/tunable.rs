@@ -0,0 +1,126 @@
+//! Implements `Tunable`.
+
+use std::cmp::{min, max};
+use std::sync::atomic::{AtomicIsize, Ordering};
+use uci::OptionDescription;
+
+
+/// A search (or evaluation) parameter that can be tuned at runtime.
+///
+/// Search algorithms and evaluation functions depend on many numeric
+/// constants (reductions, margins, weights) whose best values can
+/// only be found experimentally. When the "tune" feature is enabled,
+/// each `Tunable` becomes a configuration option, so that parameter
+/// tuning frameworks (like SPSA or CLOP) can adjust its value without
+/// recompiling the engine. The names of these options should start
+/// with "Tune", so that they can be easily told apart from the
+/// regular options. When the "tune" feature is disabled, `get`
+/// always returns the default value, and no options are announced.
+///
+/// # Example:
+///
+/// ```rust
+/// # use std::sync::atomic::AtomicIsize;
+/// # use alcibiades::utils::Tunable;
+/// static NULL_MOVE_REDUCTION: Tunable = Tunable {
+///     name: "Tune Null Move Reduction",
+///     min: 1,
+///     max: 6,
+///     default: 3,
+///     value: AtomicIsize::new(3),
+/// };
+///
+/// assert_eq!(NULL_MOVE_REDUCTION.get(), 3);
+/// ```
+pub struct Tunable {
+    /// The name of the configuration option.
+    pub name: &'static str,
+
+    /// The minimum value.
+    pub min: isize,
+
+    /// The maximum value.
+    pub max: isize,
+
+    /// The default value.
+    pub default: isize,
+
+    /// The current value. Must be initialized with the default value.
+    pub value: AtomicIsize,
+}
+
+
+impl Tunable {
+    /// Returns the current value.
+    #[inline]
+    pub fn get(&self) -> isize {
+        if cfg!(feature = "tune") {
+            self.value.load(Ordering::Relaxed)
+        } else {
+            self.default
+        }
+    }
+
+    /// Returns the descriptions of the configuration options for the
+    /// given parameters.
+    ///
+    /// Returns an empty list when the "tune" feature is disabled.
+    pub fn options(params: &[&Tunable]) -> Vec<(&'static str, OptionDescription)> {
+        if !cfg!(feature = "tune") {
+            return vec![];
+        }
+        params
+            .iter()
+            .map(|p| {
+                     (p.name,
+                      OptionDescription::Spin {
+                          min: p.min as i32,
+                          max: p.max as i32,
+                          default: p.default as i32,
+                      })
+                 })
+            .collect()
+    }
+
+    /// Sets the value of the parameter with the given name.
+    ///
+    /// Returns `false` if none of the given parameters has that name.
+    pub fn set_option(params: &[&Tunable], name: &str, value: &str) -> bool {
+        if let Some(p) = params.iter().find(|p| p.name == name) {
+            if let Ok(v) = value.parse::<isize>() {
+                p.value.store(min(max(v, p.min), p.max), Ordering::Relaxed);
+            }
+            return true;
+        }
+        false
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicIsize;
+
+    static PARAM: Tunable = Tunable {
+        name: "Tune Test",
+        min: -5,
+        max: 5,
+        default: 1,
+        value: AtomicIsize::new(1),
+    };
+
+    #[test]
+    fn set_option() {
+        assert_eq!(PARAM.get(), 1);
+        assert!(!Tunable::set_option(&[&PARAM], "Tune Other", "2"));
+        assert!(Tunable::set_option(&[&PARAM], "Tune Test", "100"));
+        if cfg!(feature = "tune") {
+            assert_eq!(PARAM.get(), 5);
+            assert_eq!(Tunable::options(&[&PARAM]).len(), 1);
+        } else {
+            assert_eq!(PARAM.get(), 1);
+            assert!(Tunable::options(&[&PARAM]).is_empty());
+        }
+    }
+}
@@ -84,12 +84,9 @@ impl ZobristArrays {
     /// calls will return a reference to the same object. This is done
     /// in a thread-safe manner.
     pub fn get() -> &'static ZobristArrays {
-        use std::sync::{Once, ONCE_INIT};
-        static INIT_ARRAYS: Once = ONCE_INIT;
-        static mut ARRAYS: Option<ZobristArrays> = None;
-        unsafe {
-            INIT_ARRAYS.call_once(|| { ARRAYS = Some(ZobristArrays::new()); });
-            ARRAYS.as_ref().unwrap()
+        lazy_static! {
+            static ref ARRAYS: ZobristArrays = ZobristArrays::new();
         }
+        &ARRAYS
     }
 }
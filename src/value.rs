@@ -1,7 +1,7 @@
 //! Defines the `Value` type and its related constants.
 
 
-/// Evaluation value in centipawns.
+/// Evaluation value (usually in centipawns).
 ///
 /// Positive values mean that the position is favorable for the side
 /// to move. Negative values mean the position is favorable for the
@@ -45,3 +45,50 @@ pub const VALUE_MAX: Value = ::std::i16::MAX;
 pub const VALUE_MIN: Value = -VALUE_MAX;
 pub const VALUE_EVAL_MAX: Value = 29999;
 pub const VALUE_EVAL_MIN: Value = -VALUE_EVAL_MAX;
+
+
+/// Returns the number of moves until an inevitable checkmate.
+///
+/// Returns `None` if `value` does not designate an inevitable
+/// checkmate. Otherwise returns the number of moves (not half-moves)
+/// until checkmate -- positive if the side to move will win,
+/// negative if it will lose, and zero if the side to move is
+/// checkmated. This is the number that UCI "score mate" expects.
+///
+/// # Examples:
+///
+/// ```rust
+/// # use alcibiades::*;
+/// assert_eq!(mate_distance(VALUE_MAX - 1), Some(1));
+/// assert_eq!(mate_distance(VALUE_MAX - 3), Some(2));
+/// assert_eq!(mate_distance(VALUE_MIN + 2), Some(-1));
+/// assert_eq!(mate_distance(VALUE_MIN), Some(0));
+/// assert_eq!(mate_distance(100), None);
+/// ```
+pub fn mate_distance(value: Value) -> Option<isize> {
+    match value {
+        VALUE_UNKNOWN => None,
+        v if v < VALUE_EVAL_MIN => Some((VALUE_MIN as isize - v as isize) / 2),
+        v if v > VALUE_EVAL_MAX => Some((VALUE_MAX as isize - v as isize + 1) / 2),
+        _ => None,
+    }
+}
+
+
+/// The material values of the pieces (indexed by piece type), on the
+/// scale used by static exchange evaluation and quiescence search.
+///
+/// On this scale a pawn is worth `100`, and the king is worth
+/// `10000` (more than all other pieces together). Note that
+/// evaluators are free to use other scales. (See
+/// `Evaluator::to_centipawns`.)
+pub const PIECE_VALUES: [Value; 8] = [10000, 975, 500, 325, 325, 100, 0, 0];
+
+
+/// The biggest absolute value in centipawns that can be reported to
+/// the GUI.
+///
+/// Bigger centipawn values (that do not designate an inevitable
+/// checkmate) are reported as `CENTIPAWNS_MAX` (or
+/// `-CENTIPAWNS_MAX`) bounds.
+pub const CENTIPAWNS_MAX: i32 = 9999;
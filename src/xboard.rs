@@ -0,0 +1,682 @@
+//! Implements the "Chess Engine Communication Protocol" (CECP).
+//!
+//! "Chess Engine Communication Protocol" (also known as the "xboard"
+//! protocol) is an older alternative to UCI, which is still spoken by
+//! some GUIs and tournament managers. Unlike UCI, in CECP the engine
+//! is responsible for keeping track of the game -- the GUI only
+//! informs it about the moves played by the opponent, and the engine
+//! decides by itself when it is its turn to move.
+//!
+//! This module translates CECP commands to calls to the methods of
+//! the `UciEngine` trait, so that any UCI-compatible engine can be
+//! driven by a CECP-speaking GUI as well. The protocol is selected
+//! automatically when the first command received is "xboard" (see
+//! `uci::run_engine_with_io`).
+
+use std::time::Duration;
+use std::thread::spawn;
+use std::io;
+use std::io::{Write, BufWriter, BufRead, ErrorKind};
+use std::sync::mpsc::{channel, TryRecvError};
+use regex::Regex;
+use io_log;
+use uci::{UciEngine, GoParams, EngineReply, InfoItem, OptionDescription};
+use search_node::SearchNode;
+use stock::{StdSearchNode, StdQsearch, StdMoveGenerator, SimpleEvaluator};
+
+
+/// The starting position in Forsyth–Edwards notation.
+const STARTPOS: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+
+/// The type used to keep track of the game, so that the moves
+/// received from the GUI can be validated. (The rules of the game are
+/// the same for all engines.)
+type Position = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+
+
+/// A command from the GUI to the engine.
+enum XboardCommand {
+    /// The GUI supports protocol version 2 (or higher), and expects
+    /// the engine to announce its features.
+    Protover,
+
+    /// Reset the board to the starting position, and leave force
+    /// mode. The engine should play black.
+    New,
+
+    /// Stop thinking, and do not play any moves until the next "go"
+    /// command. The moves received in force mode are only recorded.
+    Force,
+
+    /// Leave force mode, and start thinking for the side to move.
+    Go,
+
+    /// A move made by the opponent, in coordinate notation.
+    UserMove(String),
+
+    /// The time left on engine's clock in centiseconds.
+    Time(u64),
+
+    /// The time left on opponent's clock in centiseconds.
+    Otim(u64),
+
+    /// Set a conventional or incremental time control. The number of
+    /// moves per time control is `0` for incremental time controls.
+    /// (The initial time is ignored -- the time left on the clocks is
+    /// given by the "time" and "otim" commands.)
+    Level { moves_per_session: u64, increment_millis: u64 },
+
+    /// Think exactly that many milliseconds on each move.
+    St(u64),
+
+    /// Do not search deeper than that many half-moves.
+    Sd(u64),
+
+    /// Set up the position described in `fen`, with no moves played.
+    SetBoard(String),
+
+    /// The game has ended. The engine should stop thinking.
+    Result,
+
+    /// Reply with "pong" and the same argument, after all the
+    /// preceding commands have been processed.
+    Ping(String),
+
+    /// Turn the thinking output on or off.
+    Post(bool),
+
+    /// Change the value of some configuration option.
+    SetOption { name: String, value: String },
+
+    /// Quit the program as soon as possible.
+    Quit,
+}
+
+
+/// A CECP protocol server.
+///
+/// Connects the engine to the GUI, and keeps track of the game.
+pub struct Server<E: UciEngine, R: BufRead + Send + 'static, W: Write> {
+    engine: E,
+    reader: Option<R>,
+    writer: BufWriter<W>,
+
+    /// The starting position of the current game.
+    fen: String,
+
+    /// The moves played from the starting position.
+    moves: Vec<String>,
+
+    /// The current position.
+    position: Position,
+
+    /// Whether the engine is in force mode.
+    force: bool,
+
+    /// Whether the thinking output is turned on.
+    post: bool,
+
+    /// The time left on engine's clock in milliseconds.
+    engine_millis: Option<u64>,
+
+    /// The time left on opponent's clock in milliseconds.
+    opponent_millis: Option<u64>,
+
+    /// The number of moves per time control, and the increment per
+    /// move in milliseconds (set by the "level" command).
+    moves_per_session: Option<u64>,
+    increment_millis: Option<u64>,
+
+    /// The exact time per move in milliseconds (set by the "st"
+    /// command).
+    move_millis: Option<u64>,
+
+    /// The maximal search depth (set by the "sd" command).
+    depth: Option<u64>,
+
+    /// Whether the engine is thinking on its move.
+    is_thinking: bool,
+
+    /// The number of best moves from interrupted searches, that
+    /// should not be played.
+    discarded_moves: usize,
+}
+
+
+impl<E, R, W> Server<E, R, W>
+    where E: UciEngine,
+          R: BufRead + Send + 'static,
+          W: Write
+{
+    /// Creates a new server.
+    ///
+    /// The "xboard" command must have been already received.
+    pub fn new(reader: R, writer: W) -> Self {
+        Server {
+            engine: E::new(None),
+            reader: Some(reader),
+            writer: BufWriter::new(writer),
+            fen: STARTPOS.to_string(),
+            moves: vec![],
+            position: start_position(STARTPOS).unwrap(),
+            force: false,
+            post: false,
+            engine_millis: None,
+            opponent_millis: None,
+            moves_per_session: None,
+            increment_millis: None,
+            move_millis: None,
+            depth: None,
+            is_thinking: false,
+            discarded_moves: 0,
+        }
+    }
+
+    /// Blocks the current thread and serves CECP commands until a
+    /// "quit" command is received.
+    ///
+    /// Will return `Err` if an IO error has occurred.
+    pub fn serve(&mut self) -> io::Result<()> {
+        let (tx, rx) = channel();
+
+        // Spawn a thread that reads from `reader` and writes to `tx`.
+        let mut reader = self.reader.take().expect("the session is already served");
+        let read_thread = spawn(move || -> io::Result<()> {
+            let mut line = String::new();
+            loop {
+                if let Ok(cmd) = match try!(reader.read_line(&mut line)) {
+                       0 => return Err(io::Error::new(ErrorKind::UnexpectedEof, "EOF")),
+                       _ => {
+                           io_log::log(io_log::RECEIVED, &line);
+                           parse_xboard_command(line.as_str())
+                       }
+                   } {
+                    if let XboardCommand::Quit = cmd {
+                        return Ok(());
+                    }
+                    tx.send(cmd).unwrap();
+                }
+                line.clear();
+            }
+        });
+
+        'mainloop: loop {
+            // Try to receive commands from the GUI, and execute them.
+            while let Some(cmd) = match rx.try_recv() {
+                      Ok(cmd) => Some(cmd),
+                      Err(TryRecvError::Empty) => None,
+                      Err(TryRecvError::Disconnected) => break 'mainloop,
+                  } {
+                try!(self.execute(cmd));
+            }
+
+            // Wait for engine replies, fetch them to `stdout`.
+            let mut reply_count = 0;
+            while let Some(reply) = self.engine.wait_for_reply(Duration::from_millis(25)) {
+                reply_count += 1;
+                try!(self.forward(reply));
+                if reply_count >= 40 {
+                    // The engine is sending lots of replies, but we
+                    // should not forget to process GUI commands as
+                    // well.
+                    break;
+                }
+            }
+            try!(self.writer.flush());
+        } // 'mainloop
+
+        // End the CECP session.
+        self.engine.exit();
+        read_thread.join().unwrap()
+    }
+
+    /// Executes a command received from the GUI.
+    fn execute(&mut self, cmd: XboardCommand) -> io::Result<()> {
+        match cmd {
+            XboardCommand::Protover => try!(self.announce_features()),
+            XboardCommand::New => {
+                self.interrupt();
+                self.engine.new_game();
+                self.fen = STARTPOS.to_string();
+                self.moves.clear();
+                self.position = start_position(STARTPOS).unwrap();
+                self.force = false;
+                self.depth = None;
+            }
+            XboardCommand::Force | XboardCommand::Result => {
+                self.interrupt();
+                self.force = true;
+            }
+            XboardCommand::Go => {
+                self.interrupt();
+                self.force = false;
+                self.start_thinking();
+            }
+            XboardCommand::UserMove(m) => {
+                if !self.play_move(&m) {
+                    try!(write!(self.writer, "Illegal move: {}\n", m));
+                    return self.writer.flush();
+                }
+                self.interrupt();
+                self.moves.push(m);
+                if !self.force {
+                    self.start_thinking();
+                }
+            }
+            XboardCommand::Time(t) => self.engine_millis = Some(10 * t),
+            XboardCommand::Otim(t) => self.opponent_millis = Some(10 * t),
+            XboardCommand::Level { moves_per_session, increment_millis } => {
+                self.moves_per_session = Some(moves_per_session);
+                self.increment_millis = Some(increment_millis);
+                self.move_millis = None;
+            }
+            XboardCommand::St(t) => {
+                self.moves_per_session = None;
+                self.increment_millis = None;
+                self.move_millis = Some(t);
+            }
+            XboardCommand::Sd(depth) => self.depth = Some(depth),
+            XboardCommand::SetBoard(fen) => {
+                self.interrupt();
+                match start_position(&fen) {
+                    Some(position) => {
+                        self.fen = fen;
+                        self.moves.clear();
+                        self.position = position;
+                    }
+                    None => {
+                        try!(write!(self.writer, "tellusererror Illegal position\n"));
+                        try!(self.writer.flush());
+                    }
+                }
+            }
+            XboardCommand::Ping(n) => {
+                try!(write!(self.writer, "pong {}\n", n));
+                try!(self.writer.flush());
+            }
+            XboardCommand::Post(post) => self.post = post,
+            XboardCommand::SetOption { name, value } => {
+                let value = match E::options().into_iter().find(|x| x.0 == name) {
+                    Some((_, OptionDescription::Check { .. })) => {
+                        if value == "1" { "true" } else { "false" }.to_string()
+                    }
+                    _ => value,
+                };
+                self.engine.set_option(name.as_str(), value.as_str());
+            }
+            XboardCommand::Quit => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Forwards a reply from the engine to the GUI.
+    fn forward(&mut self, reply: EngineReply) -> io::Result<()> {
+        match reply {
+            EngineReply::BestMove { best_move, .. } => {
+                if self.discarded_moves > 0 {
+                    self.discarded_moves -= 1;
+                } else if self.is_thinking {
+                    self.is_thinking = false;
+                    if best_move != "0000" && self.play_move(&best_move) {
+                        try!(write!(self.writer, "move {}\n", best_move));
+                        self.moves.push(best_move);
+                    }
+                }
+            }
+            EngineReply::Info(infos) => {
+                // Lines starting with "#" are ignored by the GUI,
+                // but can be seen in its debug log.
+                if let Some(s) = infos.iter().find(|x| x.info_type == "string") {
+                    try!(write!(self.writer, "# {}\n", s.data));
+                } else if self.post {
+                    if let Some(line) = format_thinking_output(&infos) {
+                        try!(write!(self.writer, "{}\n", line));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the current position, and tells the engine to start
+    /// thinking on its move.
+    fn start_thinking(&mut self) {
+        let white_to_move = self.fen.split_whitespace().nth(1) != Some("b");
+        let (wtime, btime) = if white_to_move == (self.moves.len() & 1 == 0) {
+            (self.engine_millis, self.opponent_millis)
+        } else {
+            (self.opponent_millis, self.engine_millis)
+        };
+        let movestogo = self.moves_per_session.and_then(|n| if n > 0 {
+            // The number of moves that the side to move has played
+            // is one less than the fullmove number.
+            Some(n - (self.position.fullmove_number() as u64 - 1) % n)
+        } else {
+            None
+        });
+        let (wtime, btime) = if self.move_millis.is_some() {
+            (None, None)
+        } else {
+            (wtime, btime)
+        };
+        self.engine.position(self.fen.as_str(), &mut self.moves.iter().map(|m| m.as_str()));
+        self.engine.go(&GoParams {
+                            wtime: wtime,
+                            btime: btime,
+                            winc: self.increment_millis,
+                            binc: self.increment_millis,
+                            movestogo: movestogo,
+                            movetime: self.move_millis,
+                            depth: self.depth,
+                            ..GoParams::default()
+                        });
+        self.is_thinking = true;
+    }
+
+    /// Plays a move (in coordinate notation) in the current position.
+    ///
+    /// Returns `false` if the move is illegal.
+    fn play_move(&mut self, notation: &str) -> bool {
+        match self.position.legal_moves().into_iter().find(|m| m.notation() == notation) {
+            Some(m) => self.position.do_move(m),
+            None => false,
+        }
+    }
+
+    /// Stops engine's thinking, making sure that the best move found
+    /// will not be played.
+    fn interrupt(&mut self) {
+        if self.is_thinking {
+            self.engine.stop();
+            self.is_thinking = false;
+            self.discarded_moves += 1;
+        }
+    }
+
+    /// Replies to a "protover" command.
+    fn announce_features(&mut self) -> io::Result<()> {
+        try!(write!(self.writer, "feature done=0\n"));
+        try!(write!(self.writer,
+                    "feature myname=\"{}\" ping=1 setboard=1 usermove=1 playother=0 san=0 \
+                     time=1 draw=0 sigint=0 sigterm=0 reuse=1 analyze=0 colors=0 \
+                     variants=\"normal\"\n",
+                    E::name()));
+        for (name, description) in E::options() {
+            try!(write!(self.writer,
+                        "feature option=\"{} {}\"\n",
+                        name,
+                        match description {
+                            OptionDescription::Check { default } => {
+                                format!("-check {}", if default { 1 } else { 0 })
+                            }
+                            OptionDescription::Spin { default, min, max } => {
+                                format!("-spin {} {} {}", default, min, max)
+                            }
+                            OptionDescription::Combo { default, list } => {
+                                let items: Vec<String> = list.into_iter()
+                                    .map(|x| if x == default { format!("*{}", x) } else { x })
+                                    .collect();
+                                format!("-combo {}", items.join(" /// "))
+                            }
+                            OptionDescription::String { default } => {
+                                format!("-string {}", default)
+                            }
+                            OptionDescription::Button => "-button".to_string(),
+                        }));
+        }
+        try!(write!(self.writer, "feature done=1\n"));
+        self.writer.flush()
+    }
+}
+
+
+/// Converts an "info" reply that carries a principal variation to a
+/// line of CECP thinking output ("PLY SCORE TIME NODES PV").
+///
+/// Returns `None` if the reply carries no principal variation, or if
+/// the variation is not the best one.
+fn format_thinking_output(infos: &[InfoItem]) -> Option<String> {
+    let get = |info_type: &str| {
+        infos.iter().find(|x| x.info_type == info_type).map(|x| x.data.as_str())
+    };
+    if get("multipv").unwrap_or("1") != "1" {
+        return None;
+    }
+    let pv = match get("pv") {
+        Some(pv) => pv.trim(),
+        None => return None,
+    };
+    let mut score = get("score").unwrap_or("cp 0").split_whitespace();
+    let score = match (score.next(), score.next().and_then(|x| x.parse::<i64>().ok())) {
+        (Some("mate"), Some(n)) if n > 0 => 100000 + n,
+        (Some("mate"), Some(n)) => -100000 + n,
+        (_, Some(cp)) => cp,
+        _ => 0,
+    };
+    let number = |info_type: &str| get(info_type).and_then(|x| x.parse::<u64>().ok());
+    Some(format!("{} {} {} {} {}",
+                 number("depth").unwrap_or(0),
+                 score,
+                 number("time").unwrap_or(0) / 10,
+                 number("nodes").unwrap_or(0),
+                 pv))
+}
+
+
+/// A helper function. It returns the position described in `fen`, or
+/// `None` if the FEN is invalid.
+fn start_position(fen: &str) -> Option<Position> {
+    Position::from_history(fen, &mut vec![].into_iter()).ok()
+}
+
+
+/// Parses the time control given by a "level" command.
+///
+/// The initial time is given in minutes, or in minutes and seconds
+/// ("MIN:SEC"). The increment is given in seconds.
+fn parse_level(s: &str) -> Result<XboardCommand, ParseError> {
+    let params: Vec<&str> = s.split_whitespace().collect();
+    if params.len() != 3 {
+        return Err(ParseError);
+    }
+    let moves_per_session = try!(params[0].parse::<u64>().map_err(|_| ParseError));
+    let mut base = params[1].split(':');
+    for x in base.by_ref().take(2) {
+        try!(x.parse::<u64>().map_err(|_| ParseError));
+    }
+    if base.next().is_some() {
+        return Err(ParseError);
+    }
+    let increment = try!(params[2].parse::<f64>().map_err(|_| ParseError));
+    if increment < 0.0 {
+        return Err(ParseError);
+    }
+    Ok(XboardCommand::Level {
+           moves_per_session: moves_per_session,
+           increment_millis: (1000.0 * increment) as u64,
+       })
+}
+
+
+/// Represents a parse error.
+struct ParseError;
+
+
+fn parse_xboard_command(s: &str) -> Result<XboardCommand, ParseError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"^\s*(\S+)\s*(?:\s(.*?)\s*$|$)").unwrap();
+        static ref MOVE_RE: Regex = Regex::new(
+            r"^[a-h][1-8][a-h][1-8][qrbn]?$").unwrap();
+        static ref OPTION_RE: Regex = Regex::new(
+            r"^([^=]*[^=\s])\s*(?:=\s*(.*))?$").unwrap();
+    }
+    if let Some(captures) = RE.captures(s) {
+        let command_str = captures.get(1).unwrap().as_str();
+        let params_str = captures.get(2).map_or("", |m| m.as_str());
+        let number = || params_str.parse::<u64>().map_err(|_| ParseError);
+        match command_str {
+            "protover" => Ok(XboardCommand::Protover),
+            "new" => Ok(XboardCommand::New),
+            "force" => Ok(XboardCommand::Force),
+            "go" => Ok(XboardCommand::Go),
+            "result" => Ok(XboardCommand::Result),
+            "post" => Ok(XboardCommand::Post(true)),
+            "nopost" => Ok(XboardCommand::Post(false)),
+            "quit" => Ok(XboardCommand::Quit),
+            "ping" => Ok(XboardCommand::Ping(params_str.to_string())),
+            "time" => Ok(XboardCommand::Time(try!(number()))),
+            "otim" => Ok(XboardCommand::Otim(try!(number()))),
+            "level" => parse_level(params_str),
+            "st" => Ok(XboardCommand::St(1000 * try!(number()))),
+            "sd" => Ok(XboardCommand::Sd(try!(number()))),
+            "setboard" if !params_str.is_empty() => {
+                Ok(XboardCommand::SetBoard(params_str.to_string()))
+            }
+            "usermove" if MOVE_RE.is_match(params_str) => {
+                Ok(XboardCommand::UserMove(params_str.to_string()))
+            }
+            "option" => {
+                if let Some(captures) = OPTION_RE.captures(params_str) {
+                    Ok(XboardCommand::SetOption {
+                           name: captures.get(1).unwrap().as_str().to_string(),
+                           value: captures.get(2).map_or("", |m| m.as_str()).to_string(),
+                       })
+                } else {
+                    Err(ParseError)
+                }
+            }
+            // Version 1 of the protocol sends the moves without the
+            // "usermove" prefix.
+            m if params_str.is_empty() && MOVE_RE.is_match(m) => {
+                Ok(XboardCommand::UserMove(m.to_string()))
+            }
+            _ => Err(ParseError),
+        }
+    } else {
+        Err(ParseError)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_xboard_command, format_thinking_output, XboardCommand};
+    use uci::InfoItem;
+
+    #[test]
+    fn parse_command() {
+        assert!(match parse_xboard_command("protover 2").ok().unwrap() {
+                    XboardCommand::Protover => true,
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("  new  ").ok().unwrap() {
+                    XboardCommand::New => true,
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("usermove e7e8q").ok().unwrap() {
+                    XboardCommand::UserMove(m) => m == "e7e8q",
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("e2e4").ok().unwrap() {
+                    XboardCommand::UserMove(m) => m == "e2e4",
+                    _ => false,
+                });
+        assert!(parse_xboard_command("usermove e2").is_err());
+        assert!(match parse_xboard_command("time 1500").ok().unwrap() {
+                    XboardCommand::Time(t) => t == 1500,
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("otim 20").ok().unwrap() {
+                    XboardCommand::Otim(t) => t == 20,
+                    _ => false,
+                });
+        assert!(parse_xboard_command("time x").is_err());
+        assert!(match parse_xboard_command("level 40 5 0").ok().unwrap() {
+                    XboardCommand::Level { moves_per_session, increment_millis } => {
+                        moves_per_session == 40 && increment_millis == 0
+                    }
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("level 0 2:30 1.5").ok().unwrap() {
+                    XboardCommand::Level { moves_per_session, increment_millis } => {
+                        moves_per_session == 0 && increment_millis == 1500
+                    }
+                    _ => false,
+                });
+        assert!(parse_xboard_command("level 0 2:30").is_err());
+        assert!(parse_xboard_command("level 0 2:x 1").is_err());
+        assert!(match parse_xboard_command("st 30").ok().unwrap() {
+                    XboardCommand::St(t) => t == 30000,
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("sd 12").ok().unwrap() {
+                    XboardCommand::Sd(depth) => depth == 12,
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("setboard 8/8/8/8/8/8/8/K6k w - - 0 1")
+                          .ok()
+                          .unwrap() {
+                    XboardCommand::SetBoard(fen) => fen == "8/8/8/8/8/8/8/K6k w - - 0 1",
+                    _ => false,
+                });
+        assert!(parse_xboard_command("setboard").is_err());
+        assert!(match parse_xboard_command("result 1-0 {White mates}").ok().unwrap() {
+                    XboardCommand::Result => true,
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("ping 12").ok().unwrap() {
+                    XboardCommand::Ping(n) => n == "12",
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("nopost").ok().unwrap() {
+                    XboardCommand::Post(post) => !post,
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("option Move Overhead=50").ok().unwrap() {
+                    XboardCommand::SetOption { name, value } => {
+                        name == "Move Overhead" && value == "50"
+                    }
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("option Clear Hash").ok().unwrap() {
+                    XboardCommand::SetOption { name, value } => name == "Clear Hash" && value == "",
+                    _ => false,
+                });
+        assert!(match parse_xboard_command("quit").ok().unwrap() {
+                    XboardCommand::Quit => true,
+                    _ => false,
+                });
+        assert!(parse_xboard_command("accepted usermove").is_err());
+        assert!(parse_xboard_command("").is_err());
+    }
+
+    #[test]
+    fn thinking_output() {
+        let info = |items: &[(&str, &str)]| -> Vec<InfoItem> {
+            items
+                .iter()
+                .map(|&(t, d)| {
+                         InfoItem {
+                             info_type: t.to_string(),
+                             data: d.to_string(),
+                         }
+                     })
+                .collect()
+        };
+        assert_eq!(format_thinking_output(&info(&[("depth", "7"),
+                                                  ("multipv", "1"),
+                                                  ("score", "cp -35 upperbound"),
+                                                  ("time", "1234"),
+                                                  ("nodes", "5000"),
+                                                  ("pv", "e2e4 e7e5 ")])),
+                   Some("7 -35 123 5000 e2e4 e7e5".to_string()));
+        assert_eq!(format_thinking_output(&info(&[("depth", "3"),
+                                                  ("score", "mate -2"),
+                                                  ("pv", "h1h2")])),
+                   Some("3 -100002 0 0 h1h2".to_string()));
+        assert_eq!(format_thinking_output(&info(&[("multipv", "2"), ("pv", "h1h2")])), None);
+        assert_eq!(format_thinking_output(&info(&[("currmove", "h1h2")])), None);
+    }
+}
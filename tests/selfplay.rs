@@ -0,0 +1,949 @@
+//! Plays complete games through the UCI interface, with the engine
+//! playing against itself, and checks that the whole pipeline
+//! (command parsing, position setup, search, reporting) works. The
+//! other tests check individual engine features, each in a fresh
+//! session.
+
+extern crate alcibiades;
+#[macro_use]
+extern crate lazy_static;
+
+use std::io;
+use std::io::{Read, Write, BufReader};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use alcibiades::stock::*;
+use alcibiades::engine::run_uci_with_io;
+use alcibiades::*;
+
+type Ttable = StdTtable<StdTtableEntry>;
+type Node = StdSearchNode<StdQsearch<StdMoveGenerator<SimpleEvaluator>>>;
+type SearchExecutor = Deepening<SimpleSearch<Ttable, Node>>;
+
+const START_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const MAX_PLIES: usize = 120;
+const NODES_PER_MOVE: u64 = 2000;
+const INITIAL_CLOCK_MILLIS: i64 = 60_000;
+const INCREMENT_MILLIS: i64 = 1000;
+const REPLY_TIMEOUT: u64 = 60;
+
+
+/// Feeds lines sent over a channel to the engine's input.
+struct ChannelReader {
+    rx: Receiver<String>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.buffer.len() {
+            match self.rx.recv() {
+                Ok(line) => {
+                    self.buffer = line.into_bytes();
+                    self.position = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = try!((&self.buffer[self.position..]).read(buf));
+        self.position += n;
+        Ok(n)
+    }
+}
+
+
+/// Sends the lines written to the engine's output over a channel.
+struct ChannelWriter {
+    tx: Sender<String>,
+    buffer: Vec<u8>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while let Some(i) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..i + 1).collect();
+            let line = String::from_utf8(line).unwrap();
+            self.tx.send(line.trim().to_string()).ok();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+/// An in-process UCI session.
+///
+/// Only one engine can run at a time, so the sessions started by
+/// different tests take turns.
+struct Session {
+    commands: Sender<String>,
+    replies: Receiver<String>,
+    handle: Option<thread::JoinHandle<io::Result<()>>>,
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl Session {
+    fn start() -> Session {
+        let turn = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+        let (commands_tx, commands_rx) = channel();
+        let (replies_tx, replies_rx) = channel();
+        let reader = BufReader::new(ChannelReader {
+                                        rx: commands_rx,
+                                        buffer: vec![],
+                                        position: 0,
+                                    });
+        let writer = ChannelWriter {
+            tx: replies_tx,
+            buffer: vec![],
+        };
+        let handle = thread::spawn(move || {
+            run_uci_with_io::<SearchExecutor, StdTimeManager, _, _>("Test engine",
+                                                                    "Test author",
+                                                                    vec![],
+                                                                    reader,
+                                                                    writer)
+        });
+        Session {
+            commands: commands_tx,
+            replies: replies_rx,
+            handle: Some(handle),
+            _turn: turn,
+        }
+    }
+
+    /// Starts a session, and completes the UCI handshake.
+    fn start_uci() -> Session {
+        let session = Session::start();
+        session.send("uci");
+        session.expect("uciok");
+        session
+    }
+
+    fn send(&self, command: &str) {
+        self.commands.send(format!("{}\n", command)).unwrap();
+    }
+
+    /// Waits for a reply that starts with `prefix`, and returns it.
+    fn expect(&self, prefix: &str) -> String {
+        loop {
+            let reply = self.replies
+                .recv_timeout(Duration::from_secs(REPLY_TIMEOUT))
+                .expect("the engine does not respond");
+            if reply.starts_with(prefix) {
+                return reply;
+            }
+            assert!(reply.starts_with("info") || reply.starts_with("id") ||
+                    reply.starts_with("option"),
+                    "unexpected reply: {}",
+                    reply);
+        }
+    }
+
+    /// Verifies that no "bestmove" is received for some time.
+    fn expect_no_best_move(&self, millis: u64) {
+        let started_at = Instant::now();
+        while started_at.elapsed() < Duration::from_millis(millis) {
+            if let Ok(reply) = self.replies.recv_timeout(Duration::from_millis(10)) {
+                assert!(!reply.starts_with("bestmove"), "unexpected reply: {}", reply);
+            }
+        }
+    }
+
+    /// Waits for a "bestmove" reply, and returns the last "info depth"
+    /// reply received before it.
+    fn expect_last_info(&self) -> String {
+        let mut info = String::new();
+        loop {
+            let reply = self.expect("");
+            if reply.starts_with("info depth") {
+                info = reply;
+            } else if reply.starts_with("bestmove") {
+                return info;
+            }
+        }
+    }
+
+    fn quit(mut self) {
+        self.send("quit");
+        assert!(self.handle.take().unwrap().join().unwrap().is_ok());
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // If a check has failed, stop the engine anyway, so that the
+        // next session can be started.
+        if let Some(handle) = self.handle.take() {
+            self.send("quit");
+            handle.join().ok();
+        }
+    }
+}
+
+
+lazy_static! {
+    static ref SESSIONS: Mutex<()> = Mutex::new(());
+}
+
+
+/// Plays a game from the given position, and returns the result.
+fn play_game(session: &Session, fen: &str) -> &'static str {
+    let mut moves: Vec<String> = vec![];
+    let mut clocks = [INITIAL_CLOCK_MILLIS, INITIAL_CLOCK_MILLIS];
+    let mut encountered_boards = HashMap::new();
+    session.send("ucinewgame");
+    session.send("isready");
+    session.expect("readyok");
+    loop {
+        let node = Node::from_history(fen, &mut moves.iter().map(|m| m.as_str()))
+            .ok()
+            .expect("the game has an illegal move");
+        let board = node.board().clone();
+        let legal_moves: Vec<String> = node.legal_moves().iter().map(|m| m.notation()).collect();
+
+        // Check if the game has ended.
+        let repetitions = {
+            let count = encountered_boards.entry(format!("{:?}", board)).or_insert(0);
+            *count += 1;
+            *count
+        };
+        if legal_moves.is_empty() {
+            return match (node.is_check(), board.to_move) {
+                (false, _) => "1/2-1/2",
+                (true, WHITE) => "0-1",
+                (true, _) => "1-0",
+            };
+        }
+        if repetitions >= 3 || node.halfmove_clock() >= 100 ||
+           board.occupied.count_ones() == 2 || moves.len() >= MAX_PLIES {
+            return "1/2-1/2";
+        }
+
+        // Let the engine make a move.
+        let us = board.to_move;
+        session.send(&format!("position fen {} moves {}", fen, moves.join(" ")));
+        session.send(&format!("go wtime {} btime {} winc {} binc {} nodes {}",
+                              clocks[WHITE],
+                              clocks[BLACK],
+                              INCREMENT_MILLIS,
+                              INCREMENT_MILLIS,
+                              NODES_PER_MOVE));
+        let started_at = Instant::now();
+        let reply = session.expect("bestmove");
+        let elapsed = started_at.elapsed();
+        clocks[us] -= (1000 * elapsed.as_secs() + elapsed.subsec_nanos() as u64 / 1_000_000) as
+                      i64;
+        assert!(clocks[us] > 0, "the clock has been exceeded");
+        clocks[us] += INCREMENT_MILLIS;
+        let best_move = reply.split_whitespace().nth(1).unwrap().to_string();
+        assert!(legal_moves.contains(&best_move),
+                "illegal best move: {}",
+                best_move);
+        moves.push(best_move);
+    }
+}
+
+
+/// Resizes and clears the transposition table between searches, and
+/// checks its size and fill with the "memory" command.
+#[test]
+fn change_hash() {
+    let session = Session::start_uci();
+    let tt_memory = |commands: &[&str]| {
+        for command in commands {
+            session.send(command);
+        }
+        session.send("isready");
+        session.expect("readyok");
+        session.send("memory");
+        let reply = session.expect("info string memory tt bytes");
+        session.expect("info string memory total bytes");
+        reply
+    };
+    let search = || {
+        session.send("position startpos moves e2e4");
+        session.send("go depth 3");
+        let reply = session.expect("bestmove");
+        assert!(reply.split_whitespace().count() >= 2);
+    };
+    assert!(tt_memory(&["setoption name Hash value 1"]).contains(" bytes 1048640 entries 0 of "));
+    search();
+    assert!(!tt_memory(&[]).contains(" entries 0 of "));
+    assert!(tt_memory(&["setoption name Clear Hash"]).contains(" bytes 1048640 entries 0 of "));
+    search();
+    assert!(tt_memory(&["setoption name Hash value 4"]).contains(" bytes 4194368 entries 0 of "));
+    search();
+
+    // Invalid sizes are rejected, and the table stays the same.
+    session.send("setoption name Hash value 0");
+    session.expect("info string invalid value for Hash");
+    assert!(!tt_memory(&[]).contains(" entries 0 of "));
+    assert!(tt_memory(&["setoption name Clear Hash"]).contains(" bytes 4194368 entries 0 of "));
+    search();
+    session.quit();
+}
+
+
+/// Runs infinite analysis, and stops it.
+#[test]
+fn go_infinite() {
+    let session = Session::start_uci();
+    for fen in [START_FEN, "7k/8/6K1/8/8/8/8/R7 w - - 0 1"].iter() {
+        session.send(&format!("position fen {}", fen));
+        session.send("go infinite");
+        session.expect_no_best_move(500);
+        session.send("stop");
+        let reply = session.expect("bestmove");
+        let node = Node::from_history(fen, &mut vec![].into_iter()).ok().unwrap();
+        let best_move = reply.split_whitespace().nth(1).unwrap().to_string();
+        assert!(node.legal_moves().iter().any(|m| m.notation() == best_move));
+
+        // Superfluous "stop" commands are ignored.
+        session.send("stop");
+        session.send("isready");
+        session.expect("readyok");
+    }
+    session.quit();
+}
+
+
+/// Checks that the engine replies in time when the time is short.
+#[test]
+fn short_time() {
+    let session = Session::start_uci();
+    for go in ["go movetime 300", "go wtime 200 btime 200", "go movetime 5000 wtime 300 btime 300"]
+            .iter() {
+        session.send("position startpos");
+        session.send(go);
+        let started_at = Instant::now();
+        session.expect("bestmove");
+        assert!(started_at.elapsed() < Duration::from_millis(1000));
+    }
+    session.quit();
+}
+
+
+/// Checks that the engine tells why the search has ended.
+#[test]
+fn termination() {
+    let session = Session::start_uci();
+    for &(fen, reason) in [("7k/8/6K1/8/8/8/8/R7 w - - 0 1", "mate found"),
+                           ("k7/8/1K6/8/8/8/8/R7 b - - 0 1", "single move"),
+                           ("k7/2Q5/8/8/8/8/8/7K b - - 0 1", "draw forced"),
+                           (START_FEN, "depth reached")]
+                .iter() {
+        session.send(&format!("position fen {}", fen));
+        session.send("go depth 4");
+        session.expect(&format!("info string search ended: {}", reason));
+        session.expect("bestmove");
+    }
+    session.quit();
+}
+
+
+/// Checks that the selective search depth is reported, and that it
+/// is not lesser than the search depth.
+#[test]
+fn selective_depth() {
+    let session = Session::start_uci();
+    session.send("position startpos moves e2e4 e7e5 g1f3");
+    session.send("go depth 6");
+    let info = session.expect_last_info();
+    let seldepth: i32 = info.split_whitespace()
+        .skip_while(|&x| x != "seldepth")
+        .nth(1)
+        .expect("no seldepth")
+        .parse()
+        .unwrap();
+    assert!(seldepth >= 6, "{}", info);
+    session.quit();
+}
+
+
+/// Searches a simple position to the maximal depth.
+#[test]
+fn max_depth() {
+    let session = Session::start_uci();
+    session.send("position fen 8/5k2/8/8/8/8/5K2/8 w - - 0 1");
+    session.send("go depth 127");
+    session.expect("info depth 127");
+    session.expect("bestmove");
+    session.quit();
+}
+
+
+/// Checks that the "nodes" and "mate" limits of the "go" command are
+/// honored.
+#[test]
+fn search_limits() {
+    let session = Session::start_uci();
+    session.send("position startpos");
+    session.send("go nodes 5000");
+    session.expect("info string search ended: node limit reached");
+    session.expect("bestmove");
+    session.send("position fen 7k/8/6K1/8/8/8/8/R7 w - - 0 1");
+    session.send("go mate 1");
+    assert_eq!(session.expect("bestmove"), "bestmove a1a8");
+    session.quit();
+}
+
+
+/// Checks that forced checkmates are reported with "score mate".
+#[test]
+fn mate_score() {
+    let session = Session::start_uci();
+    for &(fen, score) in [("7k/8/6K1/8/8/8/8/R7 w - - 0 1", "score mate 1 "),
+                          ("k7/8/1K6/8/8/8/8/7R b - - 0 1", "score mate -1 ")]
+                .iter() {
+        session.send(&format!("position fen {}", fen));
+        session.send("go depth 4");
+        let info = session.expect_last_info();
+        assert!(info.contains(score), "{}", info);
+    }
+    session.quit();
+}
+
+
+/// Checks that draws are evaluated according to the "Contempt" and
+/// "UCI_AnalyseMode" options.
+#[test]
+fn contempt() {
+    let session = Session::start_uci();
+    for &(option, value, score) in [("Contempt", "30", "score cp -30"),
+                                    ("UCI_AnalyseMode", "true", "score cp 0"),
+                                    ("UCI_AnalyseMode", "false", "score cp -30"),
+                                    ("Contempt", "0", "score cp 0")]
+                .iter() {
+        session.send(&format!("setoption name {} value {}", option, value));
+        session.send("position fen k7/8/8/8/8/8/8/K6R w - - 99 80");
+        session.send("go depth 2");
+        let info = session.expect("info depth");
+        assert!(info.contains(score), "{}", info);
+        session.expect("bestmove");
+    }
+    session.quit();
+}
+
+
+/// Runs multi-PV searches, and checks that all lines are reported.
+#[test]
+fn multipv() {
+    let session = Session::start_uci();
+    let expect_line = |n: usize| {
+        let mut info = session.expect("info depth 6 ");
+        while !info.contains(&format!(" multipv {} ", n)) {
+            info = session.expect("info depth 6 ");
+        }
+    };
+    session.send("setoption name MultiPV value 3");
+    session.send("position startpos moves e2e4 e7e5");
+    session.send("go depth 6");
+    expect_line(3);
+    session.expect("bestmove");
+    session.send("go depth 6 searchmoves g1f3 d2d4");
+    expect_line(2);
+    session.expect("bestmove");
+    session.send("setoption name MultiPV value 1");
+    session.quit();
+}
+
+
+/// Checks that aspiration window and game tree statistics are shown
+/// when the "Debug Stats" option is on.
+#[test]
+fn debug_stats() {
+    let session = Session::start_uci();
+    session.send("setoption name Debug Stats value true");
+    session.send("ucinewgame");
+    session.send("position startpos");
+    session.send("go depth 6");
+    let reply = session.expect("info string aspiration depth");
+    assert!(reply.contains("re-searches"), "{}", reply);
+    session.expect("info string tree nodes");
+    session.expect("info string tree tt probes");
+    let reply = session.expect("info string tree beta cut-offs");
+    assert!(!reply.contains("cut-offs 0 "), "{}", reply);
+    session.expect("info string tree null moves");
+    session.expect("info string tree late move reductions");
+    session.expect("bestmove");
+    session.send("setoption name Debug Stats value false");
+    session.quit();
+}
+
+
+/// Checks that repeated requests are answered from the result cache.
+#[test]
+fn result_cache() {
+    let session = Session::start_uci();
+    session.send("setoption name Result Cache value true");
+    session.send("ucinewgame");
+    session.send("position startpos moves d2d4");
+    session.send("go depth 6");
+    let best_move = session.expect("bestmove");
+    session.send("position startpos moves d2d4");
+    session.send("go depth 5");
+    session.expect("info string reply from the result cache");
+    assert_eq!(session.expect("bestmove"), best_move);
+    session.send("go depth 7");
+    let reply = session.expect("");
+    assert!(!reply.contains("result cache"), "{}", reply);
+    session.expect("bestmove");
+    session.send("setoption name Result Cache value false");
+    session.quit();
+}
+
+
+/// Checks that identical searches give identical results when the
+/// "Deterministic" option is set.
+#[test]
+fn deterministic() {
+    let session = Session::start_uci();
+    session.send("setoption name Deterministic value true");
+    let mut results = vec![];
+    for _ in 0..2 {
+        session.send("ucinewgame");
+        session.send("position startpos moves e2e4 c7c5");
+        session.send("go wtime 600 btime 600 movetime 10");
+        let mut nodes = vec![];
+        loop {
+            let reply = session.expect("");
+            if reply.starts_with("bestmove") {
+                results.push((nodes, reply));
+                break;
+            }
+            let mut words = reply.split_whitespace().skip_while(|&x| x != "nodes");
+            nodes.extend(words.nth(1).map(String::from));
+        }
+    }
+    assert!(!results[0].0.is_empty());
+    assert_eq!(results[0], results[1]);
+    session.send("setoption name Deterministic value false");
+    session.quit();
+}
+
+
+/// Checks that the search depth is capped when the strength is
+/// limited, and that moves much worse than the best move are not
+/// played.
+#[test]
+fn strength_limiting() {
+    let session = Session::start_uci();
+    session.send("setoption name Skill Level value 0");
+    session.send("position startpos");
+    session.send("go depth 10");
+    loop {
+        let reply = session.expect("");
+        assert!(!reply.starts_with("info depth 2 "));
+        if reply.starts_with("bestmove") {
+            break;
+        }
+    }
+    session.send("position fen 7k/8/6K1/8/8/8/8/R7 w - - 0 1");
+    session.send("go depth 10");
+    assert_eq!(session.expect("bestmove"), "bestmove a1a8");
+    session.send("setoption name Skill Level value 20");
+    session.send("setoption name UCI_LimitStrength value true");
+    session.send("setoption name UCI_Elo value 800");
+    session.send("go depth 10");
+    assert_eq!(session.expect("bestmove"), "bestmove a1a8");
+    session.send("setoption name UCI_LimitStrength value false");
+    session.quit();
+}
+
+
+/// Checks that search results are appended to the "Experience File"
+/// at the end of each game, when learning is enabled.
+#[test]
+fn learning() {
+    let session = Session::start_uci();
+    let path = std::env::temp_dir().join("alcibiades-selfplay-experience.bin");
+    let path = path.to_str().unwrap();
+    std::fs::remove_file(path).ok();
+    session.send(&format!("setoption name Experience File value {}", path));
+    session.send("ucinewgame");
+    session.send("position startpos moves e2e4");
+    session.send("go depth 3");
+    session.expect("bestmove");
+    session.send("ucinewgame");
+    session.send("isready");
+    session.expect("readyok");
+    assert!(std::fs::metadata(path).is_err());
+    session.send("setoption name Learning value true");
+    session.send("position startpos moves e2e4");
+    session.send("go depth 3");
+    session.expect("bestmove");
+    session.send("position startpos moves e2e4 e7e5 g1f3");
+    session.send("go depth 3");
+    session.expect("bestmove");
+    session.send("ucinewgame");
+    session.send("isready");
+    session.expect("readyok");
+    assert_eq!(std::fs::metadata(path).unwrap().len(), 6 + 2 * 14);
+
+    // The records from the next game are appended to the file.
+    session.send("position startpos moves e2e4");
+    session.send("go depth 3");
+    session.expect("bestmove");
+    session.send("ucinewgame");
+    session.send("isready");
+    session.expect("readyok");
+    assert_eq!(std::fs::metadata(path).unwrap().len(), 6 + 3 * 14);
+
+    // Invalid files are reported.
+    std::fs::remove_file(path).ok();
+    let path = std::env::temp_dir().join("alcibiades-selfplay-invalid.bin");
+    let path = path.to_str().unwrap();
+    std::fs::File::create(path).unwrap().write_all(b"invalid").unwrap();
+    session.send(&format!("setoption name Experience File value {}", path));
+    session.send("go depth 1");
+    session.expect("info string can not read");
+    session.expect("bestmove");
+    session.send("ucinewgame");
+    session.expect("info string can not write");
+    session.send("setoption name Learning value false");
+    session.send("setoption name Experience File");
+    std::fs::remove_file(path).ok();
+    session.quit();
+}
+
+
+/// Checks that the engine replies are written as JSON objects when
+/// the "Output Format" option is "json".
+#[test]
+fn json_output() {
+    let session = Session::start_uci();
+    session.send("setoption name Output Format value json");
+    session.send("position startpos");
+    session.send("go depth 2");
+    let mut infos = 0;
+    loop {
+        let reply = session.expect("{");
+        if reply.starts_with("{\"type\":\"info\",\"depth\":2,") {
+            assert!(reply.contains("\"pv\":[\""));
+            infos += 1;
+        } else if reply.starts_with("{\"type\":\"bestmove\",\"bestmove\":\"") {
+            break;
+        }
+    }
+    assert!(infos > 0);
+    session.send("setoption name Output Format value uci");
+    session.send("go depth 1");
+    session.expect("bestmove");
+    session.quit();
+}
+
+
+/// Checks that the communication is logged when the "Log File"
+/// option is set.
+#[test]
+fn log_file() {
+    let session = Session::start_uci();
+    let path = std::env::temp_dir().join("alcibiades-selfplay-log.txt");
+    let path = path.to_str().unwrap();
+    std::fs::remove_file(path).ok();
+    session.send(&format!("setoption name Log File value {}", path));
+    session.send("isready");
+    session.expect("readyok");
+    session.send("position startpos");
+    session.send("go depth 2");
+    let best_move = session.expect("bestmove");
+    session.send("setoption name Log File");
+    session.send("isready");
+    session.expect("readyok");
+    let mut log = String::new();
+    std::fs::File::open(path).unwrap().read_to_string(&mut log).unwrap();
+    assert!(log.contains("<< go depth 2"));
+    assert!(log.contains("## search done: depth 2"));
+    assert!(log.contains(&format!(">> {}", best_move)));
+    std::fs::remove_file(path).ok();
+    session.quit();
+}
+
+
+/// Checks that illegal moves are diagnosed when the "Strict
+/// Legality" option is on.
+#[test]
+fn strict_legality() {
+    let session = Session::start_uci();
+    session.send("setoption name Strict Legality value true");
+    session.send("position startpos moves e2e4 e7e5 e1g1");
+    session.expect("info string position rejected: move 3 (e1g1) is illegal");
+    session.send("position startpos moves e2e4 e7e5 g1f3");
+    session.send("go depth 1");
+    session.expect("bestmove");
+    session.send("setoption name Strict Legality value false");
+    session.quit();
+}
+
+
+/// Checks that a rejected "position" command is diagnosed, and the
+/// previous position stays active.
+#[test]
+fn illegal_moves() {
+    let session = Session::start_uci();
+    session.send("position fen k7/8/8/8/8/8/8/7K w - - 0 1");
+    session.send("position startpos moves e2e4 e7e5 e1e3");
+    session.expect("info string position rejected: move 3 (e1e3) is illegal");
+    session.send("go depth 1");
+    assert!(session.expect("bestmove").starts_with("bestmove h1"));
+    session.quit();
+}
+
+
+/// Checks that draws by insufficient material are reported.
+#[test]
+fn insufficient_material() {
+    let session = Session::start_uci();
+    session.send("position fen 8/8/8/3k4/8/8/4p3/4K3 w - - 0 1 moves e1e2");
+    session.expect("info string draw by insufficient material");
+    session.send("go depth 5");
+    let info = session.expect_last_info();
+    assert!(info.contains(" score cp 0 "), "{}", info);
+    session.send("position fen 8/8/8/3k4/8/8/4B3/4K3 w - - 0 1");
+    session.send("go depth 5");
+    let info = session.expect_last_info();
+    assert!(info.contains(" score cp 0 "), "{}", info);
+    session.quit();
+}
+
+
+/// Checks that invalid "setoption" commands are reported and ignored.
+#[test]
+fn invalid_options() {
+    let session = Session::start_uci();
+    session.send("setoption name Contempt value 1000");
+    session.expect("info string invalid value for Contempt: 1000 is out of range");
+    session.send("setoption name Debug Stats value maybe");
+    session.expect("info string invalid value for Debug Stats");
+    session.send("setoption name No Such Option value 1");
+    session.expect("info string unknown option: No Such Option");
+    session.send("setoption name debug stats value TRUE");
+    session.send("setoption name Debug Stats value false");
+    session.send("isready");
+    session.expect("readyok");
+    session.quit();
+}
+
+
+/// Checks that the move currently searched at the root is shown
+/// during long searches.
+#[test]
+fn current_move() {
+    let session = Session::start_uci();
+    session.send("position startpos moves e2e4 e7e5");
+    session.send("go infinite");
+    let reply = session.expect("info currmove ");
+    assert!(reply.contains(" currmovenumber "), "{}", reply);
+    session.send("stop");
+    session.expect("bestmove");
+    session.quit();
+}
+
+
+/// Checks that the memory usage is reported on demand.
+#[test]
+fn memory_usage() {
+    let session = Session::start_uci();
+    session.send("setoption name Hash value 1");
+    session.send("isready");
+    session.expect("readyok");
+    session.send("memory");
+    let reply = session.expect("info string memory tt bytes");
+    assert!(reply.contains(" bytes 1048640 "), "{}", reply);
+    session.expect("info string memory result cache bytes");
+    session.expect("info string memory total bytes");
+    session.quit();
+}
+
+
+/// Checks that the breakdown of the static evaluation is printed on
+/// demand.
+#[test]
+fn eval_trace() {
+    let session = Session::start_uci();
+    session.send("position fen 4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+    session.send("eval");
+    session.expect("info string eval term");
+    let reply = session.expect("info string eval Material");
+    let values: Vec<i32> = reply
+        .split_whitespace()
+        .skip(4)
+        .map(|x| x.parse().unwrap())
+        .collect();
+    assert_eq!(values.len(), 3);
+    assert!(values[0] > values[1], "{}", reply);
+    assert_eq!(values[2], values[0] - values[1]);
+    session.expect("info string eval static");
+    session.quit();
+}
+
+
+/// Checks that the current position is displayed on demand.
+#[test]
+fn display() {
+    let session = Session::start_uci();
+    session.send("position startpos moves e2e4");
+    session.send("d coordinates flipped");
+    session.expect("info string 1 RNBKQBNR");
+    session.expect("info string   hgfedcba");
+    session.expect("info string fen rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq");
+    session.expect("info string key ");
+    session.send("d sideways");
+    session.expect("info string unknown display style: sideways");
+    session.quit();
+}
+
+
+/// Tries to export the piece-square tables. `SimpleEvaluator` does
+/// not use any, so there is nothing to export.
+#[test]
+fn export_psts() {
+    let session = Session::start_uci();
+    let path = std::env::temp_dir().join("alcibiades-selfplay-psts.txt");
+    let path = path.to_str().unwrap();
+    session.send("export psts");
+    session.expect("info string nothing to export: psts");
+    session.send(&format!("export psts {}", path));
+    session.expect("info string nothing to export: psts");
+    session.send("isready");
+    session.expect("readyok");
+    session.quit();
+}
+
+
+/// Checks that book moves are played without searching, and that the
+/// engine keeps working normally after leaving the book.
+#[test]
+fn opening_book() {
+    let session = Session::start_uci();
+    let path = std::env::temp_dir().join("alcibiades-selfplay-book.txt");
+    let path = path.to_str().unwrap();
+    std::fs::File::create(path).unwrap().write_all(b"e2e4 e7e5 g1f3\nd2d4 d7d5\n").unwrap();
+    session.send(&format!("setoption name Book File value {}", path));
+    session.send("ucinewgame");
+    session.send("position startpos");
+    session.send("go wtime 10000 btime 10000");
+    session.expect("info string book move e2e4");
+    assert_eq!(session.expect("bestmove"), "bestmove e2e4");
+    session.send("position startpos moves e2e4");
+    session.send("go wtime 10000 btime 10000");
+    assert_eq!(session.expect("bestmove"), "bestmove e7e5");
+    session.send("position startpos moves e2e4 e7e5 g1f3 b8c6");
+    session.send("go wtime 10000 btime 10000");
+    let info = session.expect_last_info();
+    assert!(info.contains(" pv "), "{}", info);
+
+    // The book is not used when the search is limited.
+    session.send("position startpos");
+    session.send("go depth 1");
+    assert!(session.expect_last_info().starts_with("info depth 1 "));
+
+    // Invalid files are reported.
+    std::fs::remove_file(path).ok();
+    let path = std::env::temp_dir().join("alcibiades-selfplay-invalid-book.txt");
+    let path = path.to_str().unwrap();
+    std::fs::File::create(path).unwrap().write_all(b"e2e4 e2e4\n").unwrap();
+    session.send(&format!("setoption name Book File value {}", path));
+    session.send("go wtime 10000 btime 10000");
+    session.expect("info string can not read");
+    session.expect("bestmove");
+    session.send("setoption name Book File");
+    session.send("ucinewgame");
+    std::fs::remove_file(path).ok();
+    session.quit();
+}
+
+
+/// Checks that search trace errors are reported, and do not stop the
+/// engine from playing.
+#[test]
+fn search_trace() {
+    let session = Session::start_uci();
+    let path = std::env::temp_dir().join("alcibiades-selfplay-trace.txt");
+    let path = path.to_str().unwrap();
+    std::fs::File::create(path).unwrap().write_all(b"search 0000000000000000 1\n").unwrap();
+    session.send("setoption name Search Trace Mode value Check");
+    session.send(&format!("setoption name Search Trace File value {}", path));
+    session.send("position startpos");
+    session.send("go depth 1");
+    session.expect("info string search trace diverges at line 1: ");
+    session.expect("bestmove");
+    let missing = std::env::temp_dir().join("alcibiades-selfplay-missing").join("trace.txt");
+    session.send(&format!("setoption name Search Trace File value {}", missing.to_str().unwrap()));
+    session.send("go depth 1");
+    session.expect("info string can not open the search trace file");
+    session.expect("bestmove");
+    session.send("setoption name Search Trace Mode value Off");
+    session.send("setoption name Search Trace File");
+    std::fs::remove_file(path).ok();
+    session.quit();
+}
+
+
+/// Plays a couple of moves over the "xboard" protocol.
+#[test]
+fn xboard() {
+    let session = Session::start();
+    let expect = |prefix: &str| -> String {
+        loop {
+            let reply = session
+                .replies
+                .recv_timeout(Duration::from_secs(REPLY_TIMEOUT))
+                .expect("the engine does not respond");
+            if reply.starts_with(prefix) {
+                return reply;
+            }
+        }
+    };
+    session.send("xboard");
+    session.send("protover 2");
+    assert!(expect("feature option=\"Hash ").contains("-spin"));
+    expect("feature done=1");
+    session.send("new");
+    session.send("post");
+    session.send("time 1000");
+    session.send("otim 1000");
+    session.send("usermove e2e5");
+    expect("Illegal move: e2e5");
+    session.send("usermove e2e4");
+    let reply = expect("move ");
+    assert!(reply.len() >= 9 && ["6", "5"].contains(&&reply[8..9]));
+    session.send("force");
+    session.send("setboard 7k/8/6K1/8/8/8/8/R7 w - - 0 1");
+    session.send("st 1");
+    session.send("sd 3");
+    session.send("go");
+    assert_eq!(expect("move "), "move a1a8");
+    session.send("result 1-0 {White mates}");
+    session.send("ping 7");
+    expect("pong 7");
+    session.quit();
+}
+
+
+/// Plays complete games, and checks that the engine plays legal moves
+/// in time until the games end.
+#[test]
+fn selfplay() {
+    let session = Session::start_uci();
+    session.send("setoption name Hash value 8");
+    session.send("isready");
+    session.expect("readyok");
+    for fen in [START_FEN,
+                "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+                "8/8/8/4k3/8/8/3QK3/8 w - - 0 1",
+                "8/5k2/8/8/8/8/5K2/8 w - - 0 1"]
+                .iter() {
+        let result = play_game(&session, fen);
+        assert!(["1-0", "0-1", "1/2-1/2"].contains(&result));
+    }
+    session.quit();
+}